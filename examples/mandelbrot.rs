@@ -33,7 +33,14 @@ fn main() {
 }
 
 fn run(dim: u32, iter_count: i32, thread_count: i32) {
-    let space = Arc::new(TreeObjectSpace::new());
+    // results larger than a megabyte are kept on disk until they are taken
+    let space = Arc::new(
+        TreeObjectSpace::builder()
+            .spill_to_disk(env::temp_dir().join("mandelbrot"), 1 << 20)
+            .build(),
+    );
+    // results are only ever taken by type, so none of their pixels need to be indexed
+    space.index_fields::<Vec<Pixel>>(&[]);
 
     // create worker threads
     for _ in 0..thread_count {
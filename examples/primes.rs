@@ -26,6 +26,8 @@ fn run(upper_lim: i64, thread_count: i64) {
     // setup. add 2 & 3 just because we can
     let mut n = 4;
     let space = Arc::new(TreeObjectSpace::new());
+    // workers may find the same prime
+    space.set_dedup::<i64>(true);
     space.write::<i64>(2);
     space.write::<i64>(3);
 
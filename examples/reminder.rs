@@ -12,8 +12,9 @@ use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-use chrono::{DateTime, Duration as ChronoDuration, NaiveDateTime, Utc};
-use object_space::{ObjectSpace, RangeLookupObjectSpace, TreeObjectSpace, ValueLookupObjectSpace};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use object_space::{Collation, RangeLookupObjectSpace, TreeObjectSpace, UpdateObjectSpace,
+                   ValueLookupObjectSpace};
 
 fn main() {
     let store = ReminderStore::new();
@@ -32,7 +33,7 @@ fn run(store: ReminderStore) {
 #[derive(Serialize, Deserialize, Debug)]
 struct Reminder {
     id: isize,
-    time: i64,
+    time: DateTime<Utc>,
     content: String,
 }
 
@@ -132,27 +133,39 @@ impl ReminderStore {
     }
 
     fn new() -> ReminderStore {
+        let space = TreeObjectSpace::new();
+        // times are compared as instants, rather than as strings
+        space.set_collation::<Reminder>("time", Collation::Timestamp);
         ReminderStore {
-            space: TreeObjectSpace::new(),
+            space: space,
             counter: AtomicIsize::new(0),
         }
     }
 
     fn add_reminder(&self, time: DateTime<Utc>, content: String) {
         let id = self.counter.fetch_add(1, Ordering::Relaxed);
-        self.space.write(Reminder {
+        self.write_reminder(Reminder {
             id: id,
-            time: time.timestamp(),
+            time: time,
             content: content,
         });
     }
 
+    // outdated reminders are kept for a day, then removed from the space
+    fn write_reminder(&self, reminder: Reminder) {
+        let expiry = reminder.time + ChronoDuration::days(1);
+        let ttl = (expiry - Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        self.space.write_with_ttl(reminder, ttl);
+    }
+
     fn get_reminder_until_time<'a>(
         &'a self,
         time: DateTime<Utc>,
     ) -> Box<Iterator<Item = Reminder> + 'a> {
         self.space
-            .read_all_by_range::<Reminder, _>("time", Utc::now().timestamp()..time.timestamp())
+            .read_all_by_range::<Reminder, _>("time", Utc::now()..time)
     }
 
     fn get_reminder_between_time<'a>(
@@ -161,17 +174,17 @@ impl ReminderStore {
         end_time: DateTime<Utc>,
     ) -> Box<Iterator<Item = Reminder> + 'a> {
         self.space
-            .read_all_by_range::<Reminder, _>("time", start_time.timestamp()..end_time.timestamp())
+            .read_all_by_range::<Reminder, _>("time", start_time..end_time)
     }
 
     fn get_all_todo_reminders<'a>(&'a self) -> Box<Iterator<Item = Reminder> + 'a> {
         self.space
-            .read_all_by_range::<Reminder, _>("time", Utc::now().timestamp()..)
+            .read_all_by_range::<Reminder, _>("time", Utc::now()..)
     }
 
     fn get_all_outdated_reminders<'a>(&'a self) -> Box<Iterator<Item = Reminder> + 'a> {
         self.space
-            .read_all_by_range::<Reminder, _>("time", ..Utc::now().timestamp())
+            .read_all_by_range::<Reminder, _>("time", ..Utc::now())
     }
 
     fn complete_reminder(&self, id: isize) -> Option<Reminder> {
@@ -179,39 +192,33 @@ impl ReminderStore {
     }
 
     fn edit_reminder_content(&self, id: isize, content: &str) {
-        match self.space.try_take_by_value::<Reminder>("id", &(id as i64)) {
-            Some(Reminder {
-                id: _,
-                time: rtime,
-                content: _,
-            }) => self.space.write(Reminder {
-                id: id,
-                time: rtime,
+        self.space
+            .update_by_value::<Reminder, _>("id", &(id as i64), |reminder| Reminder {
                 content: content.to_owned(),
-            }),
-            _ => {}
-        }
+                ..reminder
+            });
     }
 
     fn edit_reminder_time(&self, id: isize, time: DateTime<Utc>) {
+        // the lease depends on the time of the reminder, so it is written anew
         match self.space.try_take_by_value::<Reminder>("id", &(id as i64)) {
             Some(Reminder {
                 id: _,
                 time: _,
                 content: rcontent,
-            }) => self.space.write(Reminder {
+            }) => self.write_reminder(Reminder {
                 id: id,
-                time: time.timestamp(),
+                time: time,
                 content: rcontent,
             }),
             _ => {}
         }
     }
 
+    // structs are found in the order of the field, so the first one is the earliest to come
     fn get_next_reminder(&self) -> Option<Reminder> {
         self.space
-            .read_all_by_range::<Reminder, _>("time", Utc::now().timestamp()..)
-            .min_by_key(|r| r.time)
+            .try_read_by_range::<Reminder, _>("time", Utc::now()..)
     }
 }
 
@@ -222,7 +229,7 @@ impl fmt::Display for Reminder {
             "Reminder id: {}, content: {}, remind time: {}",
             self.id,
             self.content,
-            DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(self.time, 0), Utc)
+            self.time
         )
     }
 }
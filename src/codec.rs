@@ -0,0 +1,206 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use erased_serde;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{self, Value};
+
+#[cfg(feature = "bincode")]
+use bincode;
+#[cfg(feature = "msgpack")]
+use rmp_serde;
+
+/// How structs are serialized in the space.
+///
+/// Lookups work on the fields collected from a struct as it is written, whatever the codec,
+/// so the codec only changes the cost of writing and reading a struct, and the memory it takes.
+/// Features built on JSON documents, e.g: checkpoints, journals, `write_json` or dead letters,
+/// convert the structs from and to JSON, which requires a self-describing codec:
+/// they fail, or see no struct, with `Bincode` or a `Format` which is not.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Codec {
+    /// serde_json, the default.
+    #[default]
+    Json,
+    /// bincode, the fastest and most compact, but which does not support
+    /// `#[serde(flatten)]`, untagged enums or conversions from and to JSON.
+    #[cfg(feature = "bincode")]
+    Bincode,
+    /// MessagePack, with the names of fields, e.g: for large structs which are converted to JSON.
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    /// Any other serde data format, see `Format`.
+    Custom(&'static Format),
+}
+
+/// A serde data format structs could be serialized in, given to a space as `Codec::Custom`.
+///
+/// Structs are handed to the format, and read back from it, through the type-erased traits
+/// of `erased_serde`, which any serde serializer or deserializer could be wrapped in.
+///
+/// # Example
+///
+/// ```
+/// # extern crate erased_serde;
+/// # extern crate object_space;
+/// # extern crate serde_json;
+/// # use object_space::{Codec, CodecError, Format, ObjectSpace, TreeObjectSpace};
+/// #[derive(Debug)]
+/// struct PrettyJson;
+///
+/// impl Format for PrettyJson {
+///     fn encode(&self, obj: &erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+///         serde_json::to_vec_pretty(obj).map_err(CodecError::new)
+///     }
+///
+///     fn decode(
+///         &self,
+///         payload: &[u8],
+///         visit: &mut FnMut(&mut erased_serde::Deserializer) -> Result<(), erased_serde::Error>,
+///     ) -> Result<(), CodecError> {
+///         let mut deserializer = serde_json::Deserializer::from_slice(payload);
+///         visit(&mut <erased_serde::Deserializer>::erase(&mut deserializer)).map_err(CodecError::new)
+///     }
+/// }
+///
+/// # fn main() {
+/// let space = TreeObjectSpace::builder().codec(Codec::Custom(&PrettyJson)).build();
+/// space.write::<i64>(3);
+/// assert_eq!(space.try_take::<i64>(), Some(3));
+/// # }
+/// ```
+pub trait Format: fmt::Debug + Sync {
+    /// Serialize a struct.
+    fn encode(&self, obj: &erased_serde::Serialize) -> Result<Vec<u8>, CodecError>;
+
+    /// Hand a deserializer of `payload` to `visit`, which reads the struct from it.
+    fn decode(
+        &self,
+        payload: &[u8],
+        visit: &mut FnMut(&mut erased_serde::Deserializer) -> Result<(), erased_serde::Error>,
+    ) -> Result<(), CodecError>;
+
+    /// Whether payloads describe their own structure, so that they could be converted from and to JSON.
+    fn is_self_describing(&self) -> bool {
+        true
+    }
+}
+
+/// A struct of any size, which could be handed to a `Format` as an `erased_serde::Serialize`.
+struct Erased<'a, T: ?Sized + 'a>(&'a T);
+
+impl<'a, T> Serialize for Erased<'a, T>
+where
+    T: Serialize + ?Sized,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl Codec {
+    pub(crate) fn encode<T>(self, obj: &T) -> Result<Vec<u8>, CodecError>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(obj)?),
+            #[cfg(feature = "bincode")]
+            Codec::Bincode => bincode::serialize(obj).map_err(CodecError::new),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => rmp_serde::to_vec_named(obj).map_err(CodecError::new),
+            Codec::Custom(format) => format.encode(&Erased(obj)),
+        }
+    }
+
+    pub(crate) fn decode<T>(self, payload: &[u8]) -> Result<T, CodecError>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match self {
+            Codec::Json => Ok(serde_json::from_slice(payload)?),
+            #[cfg(feature = "bincode")]
+            Codec::Bincode => bincode::deserialize(payload).map_err(CodecError::new),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => rmp_serde::from_slice(payload).map_err(CodecError::new),
+            Codec::Custom(format) => {
+                let mut obj = None;
+                format.decode(payload, &mut |deserializer| {
+                    obj = Some(erased_serde::deserialize(deserializer)?);
+                    Ok(())
+                })?;
+                obj.ok_or_else(|| CodecError::new("the format did not read the struct"))
+            }
+        }
+    }
+
+    fn is_self_describing(self) -> bool {
+        match self {
+            #[cfg(feature = "bincode")]
+            Codec::Bincode => false,
+            Codec::Custom(format) => format.is_self_describing(),
+            _ => true,
+        }
+    }
+
+    /// Encode a JSON document as a struct, which the codec must be able to read back as any type.
+    pub(crate) fn encode_json(self, value: &Value) -> Result<Vec<u8>, CodecError> {
+        if !self.is_self_describing() {
+            return Err(CodecError::new(format!("{:?} could not encode a JSON document as a struct", self)));
+        }
+        self.encode(value)
+    }
+
+    /// Return a payload as JSON, borrowed if the codec is JSON.
+    pub(crate) fn json_bytes(self, payload: &[u8]) -> Result<Cow<[u8]>, CodecError> {
+        if let Codec::Json = self {
+            return Ok(Cow::Borrowed(payload));
+        }
+        Ok(Cow::Owned(serde_json::to_vec(&self.decode_json(payload)?)?))
+    }
+
+    pub(crate) fn decode_json(self, payload: &[u8]) -> Result<Value, CodecError> {
+        if !self.is_self_describing() {
+            return Err(CodecError::new(format!("{:?} payloads could not be read as JSON", self)));
+        }
+        self.decode(payload)
+    }
+}
+
+/// Why a struct could not be serialized or deserialized by a codec.
+#[derive(Debug)]
+pub struct CodecError(String);
+
+impl CodecError {
+    pub fn new<E: ToString>(error: E) -> Self {
+        CodecError(error.to_string())
+    }
+}
+
+impl From<io::Error> for CodecError {
+    fn from(error: io::Error) -> Self {
+        CodecError::new(error)
+    }
+}
+
+impl From<serde_json::Error> for CodecError {
+    fn from(error: serde_json::Error) -> Self {
+        CodecError::new(error)
+    }
+}
+
+impl From<erased_serde::Error> for CodecError {
+    fn from(error: erased_serde::Error) -> Self {
+        CodecError::new(error)
+    }
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for CodecError {}
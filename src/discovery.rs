@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde_json;
+use socket2::{Domain, Protocol, Socket, Type};
+
+use remote::{RemoteObjectSpace, SpaceServer};
+
+/// The multicast group spaces are announced on, unless another one is specified.
+pub const DISCOVERY_GROUP: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 255, 79, 83), 7879);
+
+// large enough for any announcement
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// A space announced by a `SpaceServer`, found by `RemoteObjectSpace::discover`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SpaceAddr {
+    /// The address to connect to, e.g: with `RemoteObjectSpace::connect`.
+    pub addr: SocketAddr,
+    /// The name the space was announced with.
+    pub name: String,
+}
+
+/// A message sent to the multicast group by a client, or to a client by a server.
+#[derive(Serialize, Deserialize)]
+enum Message {
+    /// Ask every server of the group to announce itself.
+    Probe,
+    /// The address a server listens on. An unspecified IP is that of the sender.
+    Announce { addr: SocketAddr, name: String },
+}
+
+impl SpaceServer {
+    /// Answer the clients looking for spaces on `DISCOVERY_GROUP` with the address of the server,
+    /// on a background thread. The call blocks forever unless receiving fails.
+    ///
+    /// Several servers, possibly on the same machine, could be announced on the same group.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{ObjectSpace, RemoteObjectSpace, SpaceServer};
+    /// let server = SpaceServer::bind("0.0.0.0:0").unwrap();
+    /// server.announce("tasks").unwrap();
+    /// server.spawn();
+    ///
+    /// let spaces = RemoteObjectSpace::discover(Duration::from_millis(200)).unwrap();
+    /// let tasks = spaces.iter().find(|space| space.name == "tasks").unwrap();
+    /// let space = RemoteObjectSpace::connect(tasks.addr).unwrap();
+    /// space.write::<i64>(3);
+    /// ```
+    pub fn announce(&self, name: &str) -> io::Result<JoinHandle<io::Result<()>>> {
+        self.announce_on(DISCOVERY_GROUP, name)
+    }
+
+    /// Answer the clients looking for spaces on the specified multicast group, like `announce`.
+    pub fn announce_on(&self, group: SocketAddrV4, name: &str) -> io::Result<JoinHandle<io::Result<()>>> {
+        let socket = join_group(group)?;
+        let announcement = serde_json::to_vec(&Message::Announce {
+            addr: self.local_addr()?,
+            name: name.to_owned(),
+        })?;
+        Ok(thread::spawn(move || {
+            let mut buf = vec![0; MAX_MESSAGE_LEN];
+            loop {
+                let (len, sender) = socket.recv_from(&mut buf)?;
+                // messages of other programs on the group are ignored
+                if let Ok(Message::Probe) = serde_json::from_slice(&buf[..len]) {
+                    socket.send_to(&announcement, sender)?;
+                }
+            }
+        }))
+    }
+}
+
+impl RemoteObjectSpace {
+    /// Return the spaces announced on `DISCOVERY_GROUP` which answer within the timeout.
+    pub fn discover(timeout: Duration) -> io::Result<Vec<SpaceAddr>> {
+        RemoteObjectSpace::discover_on(DISCOVERY_GROUP, timeout)
+    }
+
+    /// Return the spaces announced on the specified multicast group which answer within the timeout.
+    pub fn discover_on(group: SocketAddrV4, timeout: Duration) -> io::Result<Vec<SpaceAddr>> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.send_to(&serde_json::to_vec(&Message::Probe)?, group)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut spaces = Vec::new();
+        let mut seen = HashSet::new();
+        let mut buf = vec![0; MAX_MESSAGE_LEN];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(spaces);
+            }
+            socket.set_read_timeout(Some(deadline - now))?;
+            let (len, sender) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                    return Ok(spaces)
+                }
+                Err(e) => return Err(e),
+            };
+            if let Ok(Message::Announce { mut addr, name }) = serde_json::from_slice(&buf[..len]) {
+                if addr.ip().is_unspecified() {
+                    addr.set_ip(sender.ip());
+                }
+                let space = SpaceAddr { addr, name };
+                if seen.insert(space.clone()) {
+                    spaces.push(space);
+                }
+            }
+        }
+    }
+}
+
+/// Return a socket receiving the messages sent to the multicast group.
+fn join_group(group: SocketAddrV4) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // every server of the machine listens on the port of the group
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, group.port()).into())?;
+    socket.join_multicast_v4(group.ip(), &Ipv4Addr::UNSPECIFIED)?;
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use object_space::ObjectSpace;
+
+    use super::*;
+
+    #[test]
+    fn discovery() {
+        // a group of its own, so that spaces announced by other tests are not found
+        let group = SocketAddrV4::new(Ipv4Addr::new(239, 255, 79, 84), 47911);
+        let mut addrs = Vec::new();
+        for name in &["tasks", "results"] {
+            let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+            addrs.push(server.local_addr().unwrap());
+            server.announce_on(group, name).unwrap();
+            server.spawn();
+        }
+
+        let mut spaces = RemoteObjectSpace::discover_on(group, Duration::from_millis(200)).unwrap();
+        spaces.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            spaces,
+            vec![
+                SpaceAddr {
+                    addr: addrs[1],
+                    name: String::from("results"),
+                },
+                SpaceAddr {
+                    addr: addrs[0],
+                    name: String::from("tasks"),
+                },
+            ]
+        );
+        let tasks = RemoteObjectSpace::connect(spaces[1].addr).unwrap();
+        tasks.write::<i64>(3);
+        assert_eq!(RemoteObjectSpace::connect(addrs[0]).unwrap().try_take::<i64>(), Some(3));
+
+        let other = SocketAddrV4::new(Ipv4Addr::new(239, 255, 79, 84), 47912);
+        assert!(RemoteObjectSpace::discover_on(other, Duration::from_millis(50)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use entry::indexer::is_selected;
+use entry::record::{Field, Record};
+
+const FILTER_SIZE: usize = 4096;
+const HASH_COUNT: u64 = 3;
+
+/// A counting Bloom filter.
+///
+/// Each slot keeps a counter instead of a bit so that values could be removed
+/// when the corresponding struct is taken out of the space.
+/// A saturated counter is never decremented to avoid false negatives.
+pub struct BloomFilter {
+    counters: Vec<u8>,
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        BloomFilter {
+            counters: vec![0; FILTER_SIZE],
+        }
+    }
+}
+
+impl BloomFilter {
+    pub fn insert(&mut self, hash: u64) {
+        for slot in slots(hash) {
+            let counter = &mut self.counters[slot];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    pub fn remove(&mut self, hash: u64) {
+        for slot in slots(hash) {
+            let counter = &mut self.counters[slot];
+            if *counter > 0 && *counter < u8::MAX {
+                *counter -= 1;
+            }
+        }
+    }
+
+    pub fn may_contain(&self, hash: u64) -> bool {
+        slots(hash).all(|slot| self.counters[slot] > 0)
+    }
+}
+
+fn slots(hash: u64) -> impl Iterator<Item = usize> {
+    // double hashing: derive k slots from the two halves of the hash
+    let h1 = hash & 0xffff_ffff;
+    let h2 = (hash >> 32) | 1;
+    (0..HASH_COUNT).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % FILTER_SIZE as u64) as usize)
+}
+
+/// Hash the value of a field.
+/// Return None if the value is not a basic value (e.g: an array or null).
+pub fn hash_value(value: &Field) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    match *value {
+        Field::Float(f) => ('f', float_bits(f)).hash(&mut hasher),
+        Field::Int(i) => ('i', i).hash(&mut hasher),
+        Field::BigInt(i) => ('n', i).hash(&mut hasher),
+        Field::Bool(boolean) => ('b', boolean).hash(&mut hasher),
+        Field::Str(ref string) => ('s', string).hash(&mut hasher),
+        Field::Null | Field::Seq | Field::Bytes => return None,
+    }
+    Some(hasher.finish())
+}
+
+/// Return the bits of a float, with a single encoding for the values comparing equal
+/// (0.0 and -0.0) and for NaN, so that the filter gives no false negative.
+fn float_bits(f: f64) -> u64 {
+    if f == 0.0 {
+        0
+    } else if f.is_nan() {
+        ::std::f64::NAN.to_bits()
+    } else {
+        f.to_bits()
+    }
+}
+
+/// A Bloom filter for each flattened field of a type.
+#[derive(Default)]
+pub struct FieldFilters {
+    filters: HashMap<String, BloomFilter>,
+}
+
+impl FieldFilters {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&mut self, obj: &Record, selected: Option<&HashSet<String>>) {
+        for (field, val) in obj.indexed_fields().filter(|&(field, _)| is_selected(selected, field)) {
+            self.add_field(field, val);
+        }
+    }
+
+    pub fn remove(&mut self, obj: &Record, selected: Option<&HashSet<String>>) {
+        for (field, val) in obj.indexed_fields().filter(|&(field, _)| is_selected(selected, field)) {
+            self.remove_field(field, val);
+        }
+    }
+
+    /// Return false only if no struct has the specified value for the field.
+    pub fn may_contain(&self, field: &str, key: &Field) -> bool {
+        match (self.filters.get(field), hash_value(key)) {
+            (Some(filter), Some(hash)) => filter.may_contain(hash),
+            _ => true,
+        }
+    }
+
+    fn add_field(&mut self, field: &str, val: &Field) {
+        if let Some(hash) = hash_value(val) {
+            self.filters
+                .entry(field.to_owned())
+                .or_default()
+                .insert(hash);
+        }
+    }
+
+    fn remove_field(&mut self, field: &str, val: &Field) {
+        if let (Some(filter), Some(hash)) = (self.filters.get_mut(field), hash_value(val)) {
+            filter.remove(hash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+
+    use codec::Codec;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TestStruct {
+        count: i64,
+        name: String,
+    }
+
+    fn record(count: i64, name: &str) -> Record {
+        let obj = TestStruct {
+            count,
+            name: String::from(name),
+        };
+        Record::new(&obj, Codec::Json).unwrap()
+    }
+
+    #[test]
+    fn counting_filter() {
+        let mut filter = BloomFilter::default();
+        let hash = hash_value(&Field::Int(3)).unwrap();
+        assert!(!filter.may_contain(hash));
+        filter.insert(hash);
+        filter.insert(hash);
+        filter.remove(hash);
+        assert!(filter.may_contain(hash));
+        filter.remove(hash);
+        assert!(!filter.may_contain(hash));
+
+        // a saturated counter stays set whatever is removed
+        for _ in 0..300 {
+            filter.insert(hash);
+        }
+        for _ in 0..300 {
+            filter.remove(hash);
+        }
+        assert!(filter.may_contain(hash));
+    }
+
+    #[test]
+    fn hash_equal_values() {
+        assert_eq!(hash_value(&Field::Float(-0.0)), hash_value(&Field::Float(0.0)));
+        assert_eq!(
+            hash_value(&Field::Float(f64::NAN)),
+            hash_value(&Field::Float(-f64::NAN))
+        );
+        assert_ne!(hash_value(&Field::Float(1.0)), hash_value(&Field::Float(-1.0)));
+        // values of different kinds never share a hash
+        assert_ne!(hash_value(&Field::Int(1)), hash_value(&Field::Bool(true)));
+        assert_eq!(hash_value(&Field::Null), None);
+    }
+
+    #[test]
+    fn field_filters() {
+        let mut filters = FieldFilters::new();
+        let obj = record(3, "Tuan");
+        filters.add(&obj, None);
+        assert!(filters.may_contain("count", &Field::Int(3)));
+        assert!(filters.may_contain("name", &Field::Str(String::from("Tuan"))));
+        assert!(!filters.may_contain("name", &Field::Str(String::from("Duane"))));
+        // fields without a filter could hold anything
+        assert!(filters.may_contain("age", &Field::Int(3)));
+
+        filters.remove(&obj, None);
+        assert!(!filters.may_contain("count", &Field::Int(3)));
+
+        let mut selected = HashSet::new();
+        selected.insert(String::from("count"));
+        filters.add(&record(5, "Duane"), Some(&selected));
+        assert!(filters.may_contain("count", &Field::Int(5)));
+        assert!(!filters.may_contain("name", &Field::Str(String::from("Duane"))));
+    }
+}
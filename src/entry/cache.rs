@@ -0,0 +1,41 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A cache of deserialized structs, shared with readers through `Arc`.
+///
+/// Every struct in an entry has the same type, so a cached struct is always downcast successfully.
+#[derive(Default)]
+pub struct StructCache {
+    structs: Mutex<HashMap<u64, Arc<Any + Send + Sync>>>,
+}
+
+impl StructCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Return the cached struct at the index, deserializing it with `f` if it is not cached yet.
+    pub fn get_or_insert_with<T, F>(&self, index: u64, f: F) -> Option<Arc<T>>
+    where
+        T: Any + Send + Sync,
+        F: FnOnce() -> Option<T>,
+    {
+        let mut structs = self.structs.lock().unwrap();
+        if let Some(cached) = structs.get(&index) {
+            return cached.clone().downcast().ok();
+        }
+
+        let obj = Arc::new(f()?);
+        structs.insert(index, obj.clone());
+        Some(obj)
+    }
+
+    pub fn remove(&mut self, index: u64) {
+        self.structs.get_mut().unwrap().remove(&index);
+    }
+
+    pub fn clear(&mut self) {
+        self.structs.get_mut().unwrap().clear();
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// What writing a struct does when its type holds as many structs as its capacity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wait until a struct is removed, e.g: taken by a consumer. `try_write` fails instead.
+    Block,
+    /// Panic, or fail for `try_write`.
+    Reject,
+    /// Remove the oldest struct of the type to make room,
+    /// or the one the eviction policy of the type chooses, see `TreeObjectSpace::set_eviction`.
+    EvictOldest,
+}
+
+/// Which limit of an entry a struct could not be added within.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breach {
+    /// The entry holds as many structs as its capacity.
+    Capacity(usize),
+    /// The structs of the entry would take more memory than its quota, in bytes.
+    Quota(usize),
+}
+
+/// A limit on the structs of an entry, either on their number or on the memory they take,
+/// and what to do when it is reached.
+pub struct Capacity {
+    limit: usize,
+    overflow: Overflow,
+    room: Arc<Room>,
+}
+
+impl Capacity {
+    pub fn new(limit: usize, overflow: Overflow) -> Self {
+        Capacity {
+            limit,
+            overflow,
+            room: Arc::new(Room::default()),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub fn room(&self) -> &Arc<Room> {
+        &self.room
+    }
+}
+
+/// Where writers wait for structs to be removed from a full entry.
+///
+/// A writer registers with `block` while holding the write guard of the entry,
+/// so that a struct removed after it found the entry full always wakes it up.
+#[derive(Default)]
+pub struct Room {
+    // bumped every time room is made while writers are blocked
+    freed: Mutex<u64>,
+    cvar: Condvar,
+    blocked: AtomicUsize,
+}
+
+impl Room {
+    /// Register a writer about to wait, and return the value to pass to `wait`.
+    pub fn block(&self) -> u64 {
+        self.blocked.fetch_add(1, Ordering::SeqCst);
+        *self.freed.lock().unwrap()
+    }
+
+    /// Wait until room has been made since `block` returned `seen`.
+    pub fn wait(&self, seen: u64) {
+        let mut freed = self.freed.lock().unwrap();
+        while *freed == seen {
+            freed = self.cvar.wait(freed).unwrap();
+        }
+        self.blocked.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Wake up the blocked writers, if any.
+    pub fn notify(&self) {
+        if self.blocked.load(Ordering::SeqCst) > 0 {
+            let mut freed = self.freed.lock().unwrap();
+            *freed = freed.wrapping_add(1);
+            self.cvar.notify_all();
+        }
+    }
+}
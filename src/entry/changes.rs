@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+
+use entry::record::Record;
+
+/// The structs added to and removed from an entry since its last checkpoint.
+#[derive(Default)]
+pub struct ChangeLog {
+    added: IndexMap<u64, Arc<Record>>,
+    removed: Vec<Arc<Record>>,
+}
+
+impl ChangeLog {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&mut self, index: u64, obj: &Arc<Record>) {
+        self.added.insert(index, obj.clone());
+    }
+
+    /// A struct added and then removed since the last checkpoint is forgotten altogether.
+    pub fn remove(&mut self, index: u64, obj: &Arc<Record>) {
+        if self.added.swap_remove(&index).is_none() {
+            self.removed.push(obj.clone());
+        }
+    }
+
+    pub fn added<'a>(&'a self) -> Box<Iterator<Item = &'a Record> + 'a> {
+        Box::new(self.added.values().map(|obj| &**obj))
+    }
+
+    pub fn removed<'a>(&'a self) -> Box<Iterator<Item = &'a Record> + 'a> {
+        Box::new(self.removed.iter().map(|obj| &**obj))
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::ops::{Bound, RangeBounds};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, TimeZone, Utc};
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
+
+use entry::record::Field;
+use query::FieldValue;
+
+/// How the strings of a field are compared by lookups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Collation {
+    /// Strings are equal if they have the same characters. This is the default.
+    #[default]
+    Binary,
+    /// Strings are equal if they have the same characters, regardless of case.
+    CaseInsensitive,
+    /// Strings are RFC 3339 times and ints are Unix timestamps in seconds,
+    /// compared as the instants they denote, e.g: `2018-01-01T02:00:00+02:00`,
+    /// `2018-01-01T00:00:00Z` and `1514764800` are equal.
+    /// The field could then be looked up by `DateTime<Utc>` keys.
+    #[cfg(feature = "chrono")]
+    Timestamp,
+}
+
+impl Collation {
+    /// Return the form of a string which is stored in indices and compared by lookups.
+    pub fn normalize(self, s: &str) -> String {
+        match self {
+            Collation::Binary => s.to_owned(),
+            Collation::CaseInsensitive => s.to_lowercase(),
+            #[cfg(feature = "chrono")]
+            Collation::Timestamp => match DateTime::parse_from_rfc3339(s) {
+                Ok(time) => format_time(&time.with_timezone(&Utc)),
+                Err(_) => s.to_owned(),
+            },
+        }
+    }
+
+    pub fn normalize_field(self, field: &Field) -> Field {
+        match *field {
+            Field::Str(ref s) => Field::Str(self.normalize(s)),
+            #[cfg(feature = "chrono")]
+            Field::Int(seconds) if self == Collation::Timestamp => match Utc.timestamp_opt(seconds, 0).single() {
+                Some(time) => Field::Str(format_time(&time)),
+                None => field.clone(),
+            },
+            ref field => field.clone(),
+        }
+    }
+}
+
+/// Return the form of a time which is stored in indices, whose order is the order of the times,
+/// unlike RFC 3339 strings with different offsets or fractions of seconds.
+#[cfg(feature = "chrono")]
+pub fn format_time(time: &DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.9fZ").to_string()
+}
+
+/// A key which is normalized by the collation of the field it is compared to.
+pub trait Collate: Clone {
+    /// Return the normalized key, or None if the key is not a string.
+    fn collate(&self, _collation: Collation) -> Option<Self> {
+        None
+    }
+}
+
+impl Collate for String {
+    fn collate(&self, collation: Collation) -> Option<Self> {
+        Some(collation.normalize(self))
+    }
+}
+
+impl Collate for FieldValue {
+    fn collate(&self, collation: Collation) -> Option<Self> {
+        match *self {
+            FieldValue::Str(ref s) => Some(FieldValue::Str(collation.normalize(s))),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_collate {
+    ($($ty:ty)*) => {
+        $(
+            impl Collate for $ty {}
+        )*
+    };
+}
+
+impl_collate!{i64 u64 i128 bool f64}
+
+// times are converted to the form of `Collation::Timestamp` as they are looked up
+#[cfg(feature = "chrono")]
+impl Collate for DateTime<Utc> {}
+
+// uuids are looked up by their string, which every collation but `Timestamp` leaves as is
+#[cfg(feature = "uuid")]
+impl Collate for Uuid {}
+
+/// Return the key normalized by the collation of the field, or None if it is compared as is.
+pub fn collate_key<K: Collate>(collations: &HashMap<String, Collation>, field: &str, key: &K) -> Option<K> {
+    collations.get(field).and_then(|&collation| key.collate(collation))
+}
+
+/// Return the bounds of a range, normalized by the collation of the field.
+pub fn collate_range<K, R>(collations: &HashMap<String, Collation>, field: &str, range: &R) -> (Bound<K>, Bound<K>)
+where
+    K: Collate,
+    R: RangeBounds<K>,
+{
+    let convert = |bound: Bound<&K>| match bound {
+        Bound::Included(key) => Bound::Included(collate_key(collations, field, key).unwrap_or_else(|| key.clone())),
+        Bound::Excluded(key) => Bound::Excluded(collate_key(collations, field, key).unwrap_or_else(|| key.clone())),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (convert(range.start_bound()), convert(range.end_bound()))
+}
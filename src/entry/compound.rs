@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::iter::empty;
+
+use indexmap::IndexSet;
+use ordered_float::NotNaN;
+
+use entry::indexer::shrink_set;
+use entry::record::{Field, Record};
+
+/// The value of one of the fields of a compound index, ordered within its kind.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Key {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(NotNaN<f64>),
+    Str(String),
+}
+
+impl Key {
+    /// Return None if the value could not be indexed, e.g: an array.
+    fn from_field(field: &Field) -> Option<Self> {
+        match *field {
+            Field::Null => Some(Key::Null),
+            Field::Bool(b) => Some(Key::Bool(b)),
+            Field::Int(i) => Some(Key::Int(i128::from(i))),
+            Field::BigInt(i) => Some(Key::Int(i)),
+            Field::Float(f) => NotNaN::new(f).ok().map(Key::Float),
+            Field::Str(ref s) => Some(Key::Str(s.clone())),
+            Field::Seq | Field::Bytes => None,
+        }
+    }
+}
+
+/// An index over the values of several fields of a type at once,
+/// so that structs whose fields all equal some values are found in a single lookup.
+pub struct CompoundIndexer {
+    fields: Vec<String>,
+    map: BTreeMap<Vec<Key>, IndexSet<u64>>,
+}
+
+impl CompoundIndexer {
+    pub fn new(fields: &[&str]) -> Self {
+        CompoundIndexer {
+            fields: fields.iter().map(|&field| field.to_owned()).collect(),
+            map: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_on(&self, fields: &[&str]) -> bool {
+        self.fields.len() == fields.len() && self.fields.iter().zip(fields).all(|(a, b)| a == b)
+    }
+
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Release the memory held by the buckets beyond what their structs need,
+    /// and return an estimate of it in bytes.
+    pub fn compact(&mut self) -> usize {
+        self.map.values_mut().map(shrink_set).sum()
+    }
+
+    /// Structs missing any of the fields are not indexed.
+    pub fn add(&mut self, obj: &Record, index: u64) {
+        if let Some(key) = self.read_key(obj) {
+            self.map.entry(key).or_default().insert(index);
+        }
+    }
+
+    pub fn remove(&mut self, obj: &Record, index: u64) {
+        if let Some(key) = self.read_key(obj) {
+            let is_empty = match self.map.get_mut(&key) {
+                Some(set) => {
+                    set.remove(&index);
+                    set.is_empty()
+                }
+                None => false,
+            };
+            if is_empty {
+                self.map.remove(&key);
+            }
+        }
+    }
+
+    /// Return the indices of the structs whose fields equal the specified values,
+    /// given in the order of the fields of the index.
+    pub fn get_all_indices<'a>(&'a self, values: &[Field]) -> Box<Iterator<Item = u64> + 'a> {
+        let key: Option<Vec<Key>> = values.iter().map(Key::from_field).collect();
+        match key.and_then(|key| self.map.get(&key)) {
+            Some(set) => Box::new(set.iter().cloned()),
+            None => Box::new(empty()),
+        }
+    }
+
+    fn read_key(&self, obj: &Record) -> Option<Vec<Key>> {
+        self.fields
+            .iter()
+            .map(|field| obj.get(field).and_then(Key::from_field))
+            .collect()
+    }
+}
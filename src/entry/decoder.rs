@@ -0,0 +1,134 @@
+use std::mem;
+
+use serde_json::Value;
+
+use codec::CodecError;
+use entry::record::Record;
+
+/// A struct which could not be read as its type, e.g: because it was saved before the type gained a field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodeError {
+    /// The struct as a JSON document, which could be migrated by hand and written back.
+    pub payload: Value,
+    /// Why the struct could not be read.
+    pub error: String,
+}
+
+impl DecodeError {
+    pub fn new(record: &Record, error: &CodecError) -> Self {
+        DecodeError {
+            payload: record.to_json().unwrap_or(Value::Null),
+            error: error.to_string(),
+        }
+    }
+}
+
+/// What to do with a struct added to an entry.
+pub enum Admission {
+    /// Add the struct as is.
+    Keep,
+    /// Add a copy of the struct completed with the fields of the default value of its type instead.
+    Replace(Record),
+    /// Do not add the struct, which has been kept aside as a dead letter.
+    Reject,
+}
+
+// return why a struct could not be read as the type of the entry, if it could not
+type Check = Box<Fn(&Record) -> Result<(), DecodeError> + Send + Sync>;
+// complete a struct with the fields of the default value of the type, if that makes it readable
+type Fill = Box<Fn(&Record) -> Option<Record> + Send + Sync>;
+
+/// What an entry does with the structs added which could not be read as its type,
+/// instead of keeping them where lookups would skip them.
+#[derive(Default)]
+pub struct Decoder {
+    check: Option<Check>,
+    fill: Option<Fill>,
+    // keep the structs added which could not be read nor completed aside
+    keep: bool,
+    // the structs which could not be read, added or taken
+    dead_letters: Vec<DecodeError>,
+}
+
+impl Decoder {
+    pub fn set_check<F>(&mut self, check: F)
+    where
+        F: Fn(&Record) -> Result<(), DecodeError> + Send + Sync + 'static,
+    {
+        self.check = Some(Box::new(check));
+    }
+
+    pub fn set_fill<F>(&mut self, fill: F)
+    where
+        F: Fn(&Record) -> Option<Record> + Send + Sync + 'static,
+    {
+        self.fill = Some(Box::new(fill));
+    }
+
+    /// Keep the structs which could not be read aside from now on.
+    pub fn keep_dead_letters(&mut self) {
+        self.keep = true;
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.check.is_some()
+    }
+
+    /// Tell what to do with a struct added: keep it if it could be read,
+    /// replace it with a copy completed with the fields of the default value if that could be read,
+    /// or keep it aside as a dead letter.
+    pub fn admit(&mut self, obj: &Record) -> Admission {
+        let error = match self.check {
+            Some(ref check) => match check(obj) {
+                Ok(()) => return Admission::Keep,
+                Err(error) => error,
+            },
+            None => return Admission::Keep,
+        };
+        if let Some(filled) = self.fill.as_ref().and_then(|fill| fill(obj)) {
+            return Admission::Replace(filled);
+        }
+        if self.keep {
+            self.dead_letters.push(error);
+            Admission::Reject
+        } else {
+            // lookups skip the struct, as they do without a decoder
+            Admission::Keep
+        }
+    }
+
+    /// Keep aside a struct which could not be read, e.g: after it has been taken.
+    pub fn add_dead_letter(&mut self, error: DecodeError) {
+        self.dead_letters.push(error);
+    }
+
+    pub fn dead_letters(&self) -> &[DecodeError] {
+        &self.dead_letters
+    }
+
+    pub fn remove_dead_letter(&mut self, index: usize) -> Option<DecodeError> {
+        if index < self.dead_letters.len() {
+            Some(self.dead_letters.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn take_dead_letters(&mut self) -> Vec<DecodeError> {
+        mem::take(&mut self.dead_letters)
+    }
+}
+
+/// Add the fields of `default` missing from `value`, recursively, e.g: the fields a type gained.
+pub fn fill_missing_fields(value: &mut Value, default: &Value) {
+    if let (&mut Value::Object(ref mut fields), &Value::Object(ref defaults)) = (value, default) {
+        for (name, default) in defaults {
+            match fields.get_mut(name) {
+                Some(field) => fill_missing_fields(field, default),
+                None => {
+                    fields.insert(name.clone(), default.clone());
+                }
+            }
+        }
+    }
+}
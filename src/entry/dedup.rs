@@ -0,0 +1,42 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// The indices of the structs of an entry by the hash of their payload,
+/// to find a struct equal to one being added without comparing it with every struct.
+#[derive(Default)]
+pub struct PayloadIndex {
+    indices: HashMap<u64, Vec<u64>>,
+}
+
+impl PayloadIndex {
+    pub fn add(&mut self, payload: &[u8], index: u64) {
+        self.indices.entry(hash(payload)).or_default().push(index);
+    }
+
+    pub fn remove(&mut self, payload: &[u8], index: u64) {
+        let key = hash(payload);
+        if let Some(indices) = self.indices.get_mut(&key) {
+            indices.retain(|&i| i != index);
+            if indices.is_empty() {
+                self.indices.remove(&key);
+            }
+        }
+    }
+
+    /// Return the indices of the structs which may have the payload.
+    /// Payloads with the same hash are told apart by the caller.
+    pub fn candidates(&self, payload: &[u8]) -> &[u64] {
+        self.indices.get(&hash(payload)).map_or(&[], |indices| &indices[..])
+    }
+
+    pub fn clear(&mut self) {
+        self.indices.clear();
+    }
+}
+
+fn hash(payload: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    payload.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,97 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use entry::expiry::ExpiryQueue;
+
+/// Which structs of a type are evicted, see `TreeObjectSpace::set_eviction`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Eviction {
+    /// `Overflow::EvictOldest` removes the struct written the longest ago. This is the default.
+    #[default]
+    LeastRecentlyWritten,
+    /// `Overflow::EvictOldest` removes the struct read the longest ago, or written if it has not been read since.
+    LeastRecentlyRead,
+    /// The structs written longer ago than the duration are removed,
+    /// and `Overflow::EvictOldest` removes the struct written the longest ago.
+    OlderThan(Duration),
+}
+
+/// When the structs of an entry were written and last used, tracked once an eviction policy needs it.
+pub struct Recency {
+    // updated by lookups which only hold a read guard of the entry
+    used: Mutex<LastUse>,
+    // the structs by the time they were written
+    written: ExpiryQueue,
+}
+
+/// The order in which the structs were last read or written, by a tick increased on every use.
+#[derive(Default)]
+struct LastUse {
+    tick: u64,
+    ticks: HashMap<u64, u64>,
+    indices: BTreeMap<u64, u64>,
+}
+
+impl LastUse {
+    fn set(&mut self, index: u64) {
+        self.tick += 1;
+        if let Some(tick) = self.ticks.insert(index, self.tick) {
+            self.indices.remove(&tick);
+        }
+        self.indices.insert(self.tick, index);
+    }
+
+    fn remove(&mut self, index: u64) {
+        if let Some(tick) = self.ticks.remove(&index) {
+            self.indices.remove(&tick);
+        }
+    }
+}
+
+impl Recency {
+    pub fn new() -> Self {
+        Recency {
+            used: Mutex::new(LastUse::default()),
+            written: ExpiryQueue::new(),
+        }
+    }
+
+    pub fn add(&mut self, index: u64, now: Instant) {
+        self.written.add(index, now);
+        self.used.get_mut().unwrap().set(index);
+    }
+
+    pub fn remove(&mut self, index: u64) {
+        self.written.remove(index);
+        self.used.get_mut().unwrap().remove(index);
+    }
+
+    /// Record that the struct has just been read.
+    pub fn touch(&self, index: u64) {
+        let mut used = self.used.lock().unwrap();
+        if used.ticks.contains_key(&index) {
+            used.set(index);
+        }
+    }
+
+    /// Return the index of the struct read or written the longest ago, if any.
+    pub fn least_recently_used(&self) -> Option<u64> {
+        self.used.lock().unwrap().indices.values().next().cloned()
+    }
+
+    /// Return the index of the struct written the longest ago, if any.
+    pub fn least_recently_written(&self) -> Option<u64> {
+        self.written.next_index()
+    }
+
+    /// Return true if at least one struct was written at or before the specified time.
+    pub fn written_before(&self, time: Instant) -> bool {
+        self.written.has_expired(time)
+    }
+
+    /// Return the indices of all structs written at or before the specified time, and stop tracking when.
+    pub fn pop_written_before(&mut self, time: Instant) -> Vec<u64> {
+        self.written.pop_expired(time)
+    }
+}
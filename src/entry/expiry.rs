@@ -0,0 +1,65 @@
+use std::collections::{BTreeSet, HashMap};
+use std::time::Instant;
+
+/// The deadlines of structs which expire, ordered so that the next one to expire is found quickly.
+#[derive(Default)]
+pub struct ExpiryQueue {
+    queue: BTreeSet<(Instant, u64)>,
+    deadlines: HashMap<u64, Instant>,
+}
+
+impl ExpiryQueue {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&mut self, index: u64, deadline: Instant) {
+        self.queue.insert((deadline, index));
+        self.deadlines.insert(index, deadline);
+    }
+
+    pub fn remove(&mut self, index: u64) {
+        if let Some(deadline) = self.deadlines.remove(&index) {
+            self.queue.remove(&(deadline, index));
+        }
+    }
+
+    pub fn deadline(&self, index: u64) -> Option<Instant> {
+        self.deadlines.get(&index).cloned()
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.deadlines.clear();
+    }
+
+    /// Return the deadline of the next struct to expire, if any.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.queue.iter().next().map(|&(deadline, _)| deadline)
+    }
+
+    /// Return the index of the next struct to expire, if any.
+    pub fn next_index(&self) -> Option<u64> {
+        self.queue.iter().next().map(|&(_, index)| index)
+    }
+
+    /// Return true if at least one struct has expired at the specified time.
+    pub fn has_expired(&self, now: Instant) -> bool {
+        self.queue
+            .iter()
+            .next()
+            .is_some_and(|&(deadline, _)| deadline <= now)
+    }
+
+    /// Return the indices of all structs expired at the specified time, and stop tracking them.
+    pub fn pop_expired(&mut self, now: Instant) -> Vec<u64> {
+        let mut result = Vec::new();
+        while self.has_expired(now) {
+            let (deadline, index) = *self.queue.iter().next().unwrap();
+            self.queue.remove(&(deadline, index));
+            self.deadlines.remove(&index);
+            result.push(index);
+        }
+        result
+    }
+}
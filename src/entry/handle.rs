@@ -0,0 +1,17 @@
+/// Identifies a struct written to a space, among the structs of its type.
+///
+/// A handle refers to the same struct for as long as the struct stays in the space,
+/// even if it is equal to other structs. It is never reused for another struct of the same type.
+/// A struct replaced by an update, e.g: `update_by_value`, is a new struct with a new handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ObjectHandle(u64);
+
+impl ObjectHandle {
+    pub(crate) fn new(index: u64) -> Self {
+        ObjectHandle(index)
+    }
+
+    pub(crate) fn index(self) -> u64 {
+        self.0
+    }
+}
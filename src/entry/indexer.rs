@@ -1,78 +1,145 @@
 use std::collections::Bound;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
 use std::iter::empty;
+use std::mem;
 use std::ops::RangeBounds;
 
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
 use indexmap::IndexSet;
 use ordered_float::NotNaN;
-use serde_json::map::Map;
-use serde_json::value::Value;
-use serde_json::Number;
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
+#[cfg(feature = "chrono")]
+use entry::collation::format_time;
+use entry::record::{Field, Record};
+use entry::schema::FieldKind;
+use query::FieldValue;
+
+/// The order in which the values of a field are visited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// Smallest value first.
+    Ascending,
+    /// Largest value first.
+    Descending,
+}
 
+#[derive(Default)]
 pub enum ValueIndexer {
     FloatLeaf(BTreeMap<NotNaN<f64>, IndexSet<u64>>),
-    IntLeaf(BTreeMap<i64, IndexSet<u64>>),
+    IntLeaf(BTreeMap<i128, IndexSet<u64>>),
     BoolLeaf(BTreeMap<bool, IndexSet<u64>>),
     StringLeaf(BTreeMap<String, IndexSet<u64>>),
+    FloatHashLeaf(HashMap<NotNaN<f64>, IndexSet<u64>>),
+    IntHashLeaf(HashMap<i128, IndexSet<u64>>),
+    BoolHashLeaf(HashMap<bool, IndexSet<u64>>),
+    StringHashLeaf(HashMap<String, IndexSet<u64>>),
     VecLeaf(IndexSet<u64>),
-    Branch(HashMap<String, ValueIndexer>),
+    /// The indexers of the fields of a struct, and which structs have each field set to null.
+    Branch(HashMap<String, ValueIndexer>, HashMap<String, IndexSet<u64>>),
+    HashNull,
+    #[default]
     Null,
 }
 
-impl Default for ValueIndexer {
-    fn default() -> Self {
-        ValueIndexer::Null
-    }
-}
-
 impl ValueIndexer {
     pub fn new() -> Self {
         Default::default()
     }
 
-    pub fn add(&mut self, obj: Value, index: u64) {
-        match obj {
-            Value::Number(num) => self.add_value_by_num(num, index),
-            Value::Bool(boolean) => self.add_index(boolean, index),
-            Value::String(string) => self.add_index(string, index),
-            Value::Array(_) => self.add_value_by_array(index),
-            Value::Object(map) => self.add_value_by_object(map, index),
-            _ => (),
+    /// Index the fields of a struct, or only the selected ones if any.
+    pub fn add(&mut self, obj: &Record, index: u64, selected: Option<&HashSet<String>>) {
+        if let ValueIndexer::Null = *self {
+            *self = ValueIndexer::Branch(HashMap::new(), HashMap::new());
+        }
+
+        match *self {
+            ValueIndexer::Branch(ref mut hashmap, ref mut nulls) => for (key, val) in obj.indexed_fields() {
+                if !is_selected(selected, key) {
+                    continue;
+                }
+                if let Field::Null = *val {
+                    nulls.entry(key.to_owned()).or_default().insert(index);
+                    continue;
+                }
+                let sub_entry = hashmap.entry(key.to_owned()).or_insert(ValueIndexer::Null);
+                sub_entry.add_field(val, index);
+            },
+            _ => panic!("Incorrect data type! Found object."),
         }
     }
 
-    pub fn remove(&mut self, index: u64, value: &Value) {
-        match value {
-            Value::Number(num) => self.remove_by_num(num, index),
-            Value::Bool(boolean) => self.remove_index(boolean, index),
-            Value::String(string) => self.remove_index(string, index),
-            Value::Array(_) => self.remove_by_array(index),
-            Value::Object(map) => self.remove_by_object(map, index),
-            _ => (),
+    /// Mark a field as only ever queried by equality.
+    /// The field is indexed by a `HashMap` instead of a `BTreeMap`,
+    /// which makes value lookup O(1) but disallows range lookup.
+    pub fn set_hash_index(&mut self, field: &str) {
+        if let ValueIndexer::Null = *self {
+            *self = ValueIndexer::Branch(HashMap::new(), HashMap::new());
+        }
+        match *self {
+            ValueIndexer::Branch(ref mut hashmap, _) => hashmap
+                .entry(field.to_owned())
+                .or_insert(ValueIndexer::HashNull)
+                .convert_to_hash_leaf(),
+            _ => panic!("Incorrect data type! Found object."),
         }
     }
 
-    fn add_value_by_num(&mut self, num: Number, index: u64) {
-        // only parse as f64 if it is actually f64
-        // (e.g: accept '64.0' but not '64')
-        if num.is_f64() {
-            self.add_index(num.as_f64().unwrap(), index);
-        } else if let Some(i) = num.as_i64() {
-            self.add_index(i, index);
-        } else {
-            panic!("Not a number!");
+    fn convert_to_hash_leaf(&mut self) {
+        let indexer = match mem::replace(self, ValueIndexer::Null) {
+            ValueIndexer::Null | ValueIndexer::HashNull => ValueIndexer::HashNull,
+            ValueIndexer::FloatLeaf(map) => ValueIndexer::FloatHashLeaf(map.into_iter().collect()),
+            ValueIndexer::IntLeaf(map) => ValueIndexer::IntHashLeaf(map.into_iter().collect()),
+            ValueIndexer::BoolLeaf(map) => ValueIndexer::BoolHashLeaf(map.into_iter().collect()),
+            ValueIndexer::StringLeaf(map) => ValueIndexer::StringHashLeaf(map.into_iter().collect()),
+            ValueIndexer::Branch(..) | ValueIndexer::VecLeaf(_) => {
+                panic!("Only fields of basic types could be hash-indexed")
+            }
+            indexer => indexer,
+        };
+        *self = indexer;
+    }
+
+    pub fn remove(&mut self, index: u64, obj: &Record, selected: Option<&HashSet<String>>) {
+        if let ValueIndexer::Branch(ref mut hashmap, ref mut nulls) = *self {
+            for (key, val) in obj.indexed_fields() {
+                if !is_selected(selected, key) {
+                    continue;
+                }
+                if let Field::Null = *val {
+                    if let Some(set) = nulls.get_mut(key) {
+                        set.remove(&index);
+                    }
+                } else if let Some(indexer) = hashmap.get_mut(key) {
+                    indexer.remove_field(val, index);
+                }
+            }
         }
     }
 
-    fn remove_by_num(&mut self, num: &Number, index: u64) {
-        // only parse as f64 if it is actually f64
-        // (e.g: accept '64.0' but not '64')
-        if num.is_f64() {
-            self.remove_index(&num.as_f64().unwrap(), index);
-        } else if let Some(i) = num.as_i64() {
-            self.remove_index(&i, index);
-        } else {
-            panic!("Not a number!");
+    fn add_field(&mut self, value: &Field, index: u64) {
+        match *value {
+            Field::Int(i) => self.add_index(i128::from(i), index),
+            Field::BigInt(i) => self.add_index(i, index),
+            Field::Float(f) => self.add_index(f, index),
+            Field::Bool(boolean) => self.add_index(boolean, index),
+            Field::Str(ref string) => self.add_index(string.clone(), index),
+            Field::Seq => self.add_value_by_array(index),
+            Field::Null | Field::Bytes => (),
+        }
+    }
+
+    fn remove_field(&mut self, value: &Field, index: u64) {
+        match *value {
+            Field::Int(i) => self.remove_index(&i128::from(i), index),
+            Field::BigInt(i) => self.remove_index(&i, index),
+            Field::Float(f) => self.remove_index(&f, index),
+            Field::Bool(boolean) => self.remove_index(&boolean, index),
+            Field::Str(ref string) => self.remove_index(string, index),
+            Field::Seq => self.remove_by_array(index),
+            Field::Null | Field::Bytes => (),
         }
     }
 
@@ -97,36 +164,142 @@ impl ValueIndexer {
         }
     }
 
-    fn add_value_by_object(&mut self, map: Map<String, Value>, index: u64) {
-        if let ValueIndexer::Null = *self {
-            *self = ValueIndexer::Branch(HashMap::new());
+    /// Release the memory held by the buckets beyond what their structs need,
+    /// and return an estimate of it in bytes.
+    pub fn compact(&mut self) -> usize {
+        match *self {
+            ValueIndexer::Null | ValueIndexer::HashNull => 0,
+            ValueIndexer::FloatLeaf(ref mut map) => map.values_mut().map(shrink_set).sum(),
+            ValueIndexer::IntLeaf(ref mut map) => map.values_mut().map(shrink_set).sum(),
+            ValueIndexer::BoolLeaf(ref mut map) => map.values_mut().map(shrink_set).sum(),
+            ValueIndexer::StringLeaf(ref mut map) => map.values_mut().map(shrink_set).sum(),
+            ValueIndexer::FloatHashLeaf(ref mut map) => shrink_map(map) + map.values_mut().map(shrink_set).sum::<usize>(),
+            ValueIndexer::IntHashLeaf(ref mut map) => shrink_map(map) + map.values_mut().map(shrink_set).sum::<usize>(),
+            ValueIndexer::BoolHashLeaf(ref mut map) => shrink_map(map) + map.values_mut().map(shrink_set).sum::<usize>(),
+            ValueIndexer::StringHashLeaf(ref mut map) => {
+                shrink_map(map) + map.values_mut().map(shrink_set).sum::<usize>()
+            }
+            ValueIndexer::VecLeaf(ref mut set) => shrink_set(set),
+            ValueIndexer::Branch(ref mut field_map, ref mut nulls) => {
+                field_map.values_mut().map(ValueIndexer::compact).sum::<usize>()
+                    + nulls.values_mut().map(shrink_set).sum::<usize>()
+            }
         }
+    }
 
+    /// Return the indices of the structs having the field, by value of the field in the order,
+    /// and oldest first among structs with the same value.
+    pub fn get_all_indices_ordered<'a>(&'a self, field: &str, order: Order) -> Box<Iterator<Item = u64> + 'a> {
         match *self {
-            ValueIndexer::Branch(ref mut hashmap) => for (key, val) in map {
-                let sub_entry = hashmap.entry(key).or_insert(ValueIndexer::Null);
-                sub_entry.add(val, index);
-            },
-            _ => panic!("Incorrect data type! Found object."),
+            ValueIndexer::Null | ValueIndexer::HashNull => Box::new(empty()),
+            ValueIndexer::FloatLeaf(ref map) => ordered_indices(map, order),
+            ValueIndexer::IntLeaf(ref map) => ordered_indices(map, order),
+            ValueIndexer::BoolLeaf(ref map) => ordered_indices(map, order),
+            ValueIndexer::StringLeaf(ref map) => ordered_indices(map, order),
+            ValueIndexer::FloatHashLeaf(_)
+            | ValueIndexer::IntHashLeaf(_)
+            | ValueIndexer::BoolHashLeaf(_)
+            | ValueIndexer::StringHashLeaf(_) => panic!("Hash-indexed field does not support ordered lookup"),
+            ValueIndexer::VecLeaf(_) => panic!("Not correct type"),
+            ValueIndexer::Branch(ref field_map, _) => field_map
+                .get(field)
+                .map_or(Box::new(empty()), |entry| entry.get_all_indices_ordered("", order)),
         }
     }
 
-    fn remove_by_object(&mut self, map: &Map<String, Value>, index: u64) {
-        if let ValueIndexer::Null = *self {
-            *self = ValueIndexer::Branch(HashMap::new());
+    /// Return why the fields of a struct could not be indexed, if one of them holds another kind of value
+    /// than in the structs indexed before, e.g: a float where ints have been indexed.
+    pub fn conflict(&self, obj: &Record, selected: Option<&HashSet<String>>) -> Option<String> {
+        let field_map = match *self {
+            ValueIndexer::Branch(ref field_map, _) => field_map,
+            _ => return None,
+        };
+        obj.indexed_fields()
+            .filter(|&(key, _)| is_selected(selected, key))
+            .find_map(|(key, val)| {
+                let (indexed, _) = field_map.get(key)?.kind("")?;
+                let kind = FieldKind::of(val);
+                match kind {
+                    // neither is indexed as a value
+                    FieldKind::Unknown | FieldKind::Bytes => None,
+                    _ if indexed == FieldKind::Unknown || indexed == kind => None,
+                    _ => Some(format!("field `{}` holds {:?} values, not {:?}", key, indexed, kind)),
+                }
+            })
+    }
+
+    /// Return the fields indexed so far, with the kind of their values and whether they are hash-indexed.
+    pub fn fields(&self) -> BTreeMap<String, (FieldKind, bool)> {
+        match *self {
+            ValueIndexer::Branch(ref field_map, ref nulls) => field_map
+                .keys()
+                .chain(nulls.keys())
+                .filter_map(|field| self.kind(field).map(|kind| (field.clone(), kind)))
+                .collect(),
+            _ => BTreeMap::new(),
         }
+    }
 
+    /// Return the kind of the values indexed for the field and whether they are hash-indexed,
+    /// or None if no struct has had the field.
+    pub fn kind(&self, field: &str) -> Option<(FieldKind, bool)> {
         match *self {
-            ValueIndexer::Branch(ref mut hashmap) => for (key, val) in map {
-                hashmap
-                    .get_mut(key)
-                    .map(|indexer| indexer.remove(index, val));
+            ValueIndexer::Null => None,
+            ValueIndexer::HashNull => Some((FieldKind::Unknown, true)),
+            ValueIndexer::FloatLeaf(_) => Some((FieldKind::Float, false)),
+            ValueIndexer::IntLeaf(_) => Some((FieldKind::Int, false)),
+            ValueIndexer::BoolLeaf(_) => Some((FieldKind::Bool, false)),
+            ValueIndexer::StringLeaf(_) => Some((FieldKind::String, false)),
+            ValueIndexer::FloatHashLeaf(_) => Some((FieldKind::Float, true)),
+            ValueIndexer::IntHashLeaf(_) => Some((FieldKind::Int, true)),
+            ValueIndexer::BoolHashLeaf(_) => Some((FieldKind::Bool, true)),
+            ValueIndexer::StringHashLeaf(_) => Some((FieldKind::String, true)),
+            ValueIndexer::VecLeaf(_) => Some((FieldKind::Seq, false)),
+            ValueIndexer::Branch(ref field_map, ref nulls) => match field_map.get(field) {
+                Some(indexer) => indexer.kind(""),
+                None if nulls.contains_key(field) => Some((FieldKind::Unknown, false)),
+                None => None,
             },
-            _ => panic!("Incorrect data type! Found object."),
         }
     }
 }
 
+fn ordered_indices<K: Ord>(map: &BTreeMap<K, IndexSet<u64>>, order: Order) -> Box<Iterator<Item = u64> + '_> {
+    let sets: Box<Iterator<Item = &IndexSet<u64>>> = match order {
+        Order::Ascending => Box::new(map.values()),
+        Order::Descending => Box::new(map.values().rev()),
+    };
+    // a struct is found once, at the first of its elements in the order
+    let mut found = HashSet::new();
+    Box::new(
+        sets.flat_map(|set| set.iter().cloned())
+            .filter(move |i| found.insert(*i)),
+    )
+}
+
+/// Release the memory held by a bucket beyond what its structs need, and return an estimate of it in bytes.
+pub fn shrink_set(set: &mut IndexSet<u64>) -> usize {
+    let capacity = set.capacity();
+    set.shrink_to_fit();
+    // a struct takes its index and its hash, and a slot of the hash table
+    capacity.saturating_sub(set.capacity()) * mem::size_of::<(u64, u64, usize)>()
+}
+
+/// Release the memory held by a map beyond what its entries need, and return an estimate of it in bytes.
+pub fn shrink_map<K, V>(map: &mut HashMap<K, V>) -> usize
+where
+    K: Eq + Hash,
+{
+    let capacity = map.capacity();
+    map.shrink_to_fit();
+    capacity.saturating_sub(map.capacity()) * mem::size_of::<(K, V)>()
+}
+
+/// Return true if the field is indexed, given the fields selected for indexing if any.
+pub fn is_selected(selected: Option<&HashSet<String>>, field: &str) -> bool {
+    selected.is_none_or(|fields| fields.contains(field))
+}
+
 trait Indexer<T> {
     fn add_index(&mut self, field_value: T, index: u64);
 
@@ -134,12 +307,14 @@ trait Indexer<T> {
 }
 
 macro_rules! impl_indexer {
-    ($([$path:ident, $ty:ty])*) => {
+    ($([$path:ident, $hash_path:ident, $ty:ty])*) => {
         $(
             impl Indexer<$ty> for ValueIndexer {
                 fn add_index(&mut self, field_value: $ty, index: u64) {
-                    if let ValueIndexer::Null = *self {
-                        *self = ValueIndexer::$path(BTreeMap::new());
+                    match *self {
+                        ValueIndexer::Null => *self = ValueIndexer::$path(BTreeMap::new()),
+                        ValueIndexer::HashNull => *self = ValueIndexer::$hash_path(HashMap::new()),
+                        _ => (),
                     }
 
                     match *self {
@@ -147,14 +322,27 @@ macro_rules! impl_indexer {
                             let set = map.entry(field_value).or_insert(IndexSet::new());
                             set.insert(index);
                         }
+                        ValueIndexer::$hash_path(ref mut map) => {
+                            let set = map.entry(field_value).or_insert(IndexSet::new());
+                            set.insert(index);
+                        }
                         _ => panic!("Incorrect data type!"),
                     }
                 }
 
                 fn remove_index(&mut self, field_value: &$ty, index: u64) {
+                    // the bucket of a value is removed with its last struct,
+                    // so that long-lived entries do not accumulate the values of structs taken long ago
                     match *self {
                         ValueIndexer::$path(ref mut map) => {
-                            map.get_mut(field_value).map(|set| set.remove(&index));
+                            if map.get_mut(field_value).is_some_and(|set| set.remove(&index) && set.is_empty()) {
+                                map.remove(field_value);
+                            }
+                        }
+                        ValueIndexer::$hash_path(ref mut map) => {
+                            if map.get_mut(field_value).is_some_and(|set| set.remove(&index) && set.is_empty()) {
+                                map.remove(field_value);
+                            }
                         }
                         _ => panic!("Incorrect data type!"),
                     }
@@ -164,7 +352,12 @@ macro_rules! impl_indexer {
     };
 }
 
-impl_indexer!{[IntLeaf, i64] [StringLeaf, String] [BoolLeaf, bool] [FloatLeaf, NotNaN<f64>] }
+impl_indexer!{
+    [IntLeaf, IntHashLeaf, i128]
+    [StringLeaf, StringHashLeaf, String]
+    [BoolLeaf, BoolHashLeaf, bool]
+    [FloatLeaf, FloatHashLeaf, NotNaN<f64>]
+}
 
 impl Indexer<f64> for ValueIndexer {
     fn add_index(&mut self, field_value: f64, index: u64) {
@@ -193,14 +386,15 @@ pub trait ValueLookupIndexer<T> {
 }
 
 macro_rules! impl_value_lookup_indexer {
-    ($([$path:ident, $ty:ty])*) => {
+    ($([$path:ident, $hash_path:ident, $ty:ty])*) => {
         $(
             impl ValueLookupIndexer<$ty> for ValueIndexer {
                 fn get_index_by_value(&self, field: &str, key: &$ty) -> Option<u64> {
                     match *self {
-                        ValueIndexer::Null => None,
+                        ValueIndexer::Null | ValueIndexer::HashNull => None,
                         ValueIndexer::$path(ref map) => map.get(key).and_then(|set| set.get_index(0).map(|i| *i)),
-                        ValueIndexer::Branch(ref field_map) => field_map
+                        ValueIndexer::$hash_path(ref map) => map.get(key).and_then(|set| set.get_index(0).map(|i| *i)),
+                        ValueIndexer::Branch(ref field_map, _) => field_map
                             .get(field)
                             .and_then(|entry| entry.get_index_by_value("", key)),
                         _ => panic!("Not correct type"),
@@ -210,13 +404,18 @@ macro_rules! impl_value_lookup_indexer {
                 fn get_all_indices_by_value<'a>(&'a self, field: &str, key: &$ty)
                     -> Box<Iterator<Item = u64> + 'a> {
                     match *self {
-                        ValueIndexer::Null => Box::new(empty()),
+                        ValueIndexer::Null | ValueIndexer::HashNull => Box::new(empty()),
                         ValueIndexer::$path(ref map) => map
                             .get(key)
                             .map_or(
                                 Box::new(empty()), |set| Box::new(set.iter().cloned())
                             ),
-                        ValueIndexer::Branch(ref field_map) => field_map
+                        ValueIndexer::$hash_path(ref map) => map
+                            .get(key)
+                            .map_or(
+                                Box::new(empty()), |set| Box::new(set.iter().cloned())
+                            ),
+                        ValueIndexer::Branch(ref field_map, _) => field_map
                             .get(field)
                             .map_or(
                                 Box::new(empty()),
@@ -230,7 +429,12 @@ macro_rules! impl_value_lookup_indexer {
     };
 }
 
-impl_value_lookup_indexer!{ [IntLeaf, i64] [StringLeaf, String] [BoolLeaf, bool] [FloatLeaf, NotNaN<f64>] }
+impl_value_lookup_indexer!{
+    [IntLeaf, IntHashLeaf, i128]
+    [StringLeaf, StringHashLeaf, String]
+    [BoolLeaf, BoolHashLeaf, bool]
+    [FloatLeaf, FloatHashLeaf, NotNaN<f64>]
+}
 
 impl ValueLookupIndexer<f64> for ValueIndexer {
     fn get_index_by_value(&self, field: &str, key: &f64) -> Option<u64> {
@@ -252,6 +456,55 @@ impl ValueLookupIndexer<f64> for ValueIndexer {
     }
 }
 
+// uuids are stored as the strings they are serialized to
+#[cfg(feature = "uuid")]
+impl ValueLookupIndexer<Uuid> for ValueIndexer {
+    fn get_index_by_value(&self, field: &str, key: &Uuid) -> Option<u64> {
+        self.get_index_by_value(field, &key.to_string())
+    }
+
+    fn get_all_indices_by_value<'a>(
+        &'a self,
+        field: &str,
+        key: &Uuid,
+    ) -> Box<Iterator<Item = u64> + 'a> {
+        self.get_all_indices_by_value(field, &key.to_string())
+    }
+}
+
+impl ValueLookupIndexer<FieldValue> for ValueIndexer {
+    fn get_index_by_value(&self, field: &str, key: &FieldValue) -> Option<u64> {
+        match *key {
+            FieldValue::Null => self.get_all_indices_by_value(field, key).next(),
+            FieldValue::Bool(ref b) => self.get_index_by_value(field, b),
+            FieldValue::Int(ref i) => self.get_index_by_value(field, i),
+            FieldValue::BigInt(ref i) => self.get_index_by_value(field, i),
+            FieldValue::Float(ref f) => self.get_index_by_value(field, f),
+            FieldValue::Str(ref s) => self.get_index_by_value(field, s),
+        }
+    }
+
+    fn get_all_indices_by_value<'a>(
+        &'a self,
+        field: &str,
+        key: &FieldValue,
+    ) -> Box<Iterator<Item = u64> + 'a> {
+        match *key {
+            FieldValue::Null => match *self {
+                ValueIndexer::Branch(_, ref nulls) => nulls
+                    .get(field)
+                    .map_or(Box::new(empty()), |set| Box::new(set.iter().cloned())),
+                _ => Box::new(empty()),
+            },
+            FieldValue::Bool(ref b) => self.get_all_indices_by_value(field, b),
+            FieldValue::Int(ref i) => self.get_all_indices_by_value(field, i),
+            FieldValue::BigInt(ref i) => self.get_all_indices_by_value(field, i),
+            FieldValue::Float(ref f) => self.get_all_indices_by_value(field, f),
+            FieldValue::Str(ref s) => self.get_all_indices_by_value(field, s),
+        }
+    }
+}
+
 pub trait RangeLookupIndexer<T> {
     fn get_index_by_range<R>(&self, field: &str, range: R) -> Option<u64>
     where
@@ -264,10 +517,17 @@ pub trait RangeLookupIndexer<T> {
     ) -> Box<Iterator<Item = u64> + 'a>
     where
         R: RangeBounds<T>;
+
+    /// Return the number of distinct values of the field within the range, and the number of structs having them,
+    /// counting a struct once per element of an array within the range.
+    /// Return None if the field could not be looked up by range, e.g: because it is hash-indexed.
+    fn count_by_range<R>(&self, field: &str, range: R) -> Option<(usize, usize)>
+    where
+        R: RangeBounds<T>;
 }
 
 macro_rules! impl_range_lookup_indexer {
-    ($([$path:ident, $ty:ty])*) => {
+    ($([$path:ident, $hash_path:ident, $ty:ty])*) => {
         $(
             impl RangeLookupIndexer<$ty> for ValueIndexer {
                 fn get_index_by_range<R>(&self, field: &str, range: R) -> Option<u64>
@@ -275,7 +535,8 @@ macro_rules! impl_range_lookup_indexer {
                     R: RangeBounds<$ty> 
                 {
                     match *self {
-                        ValueIndexer::Null => None,
+                        ValueIndexer::Null | ValueIndexer::HashNull => None,
+                        ValueIndexer::$hash_path(_) => panic!("Hash-indexed field does not support range lookup"),
                         ValueIndexer::$path(ref map) => {
                             for (_, set) in map.range(range) {
                                 if let Some(i) = set.get_index(0) {
@@ -284,7 +545,7 @@ macro_rules! impl_range_lookup_indexer {
                             }
                             None
                         },
-                        ValueIndexer::Branch(ref field_map) => field_map
+                        ValueIndexer::Branch(ref field_map, _) => field_map
                             .get(field)
                             .and_then(|entry| entry.get_index_by_range::<_>("", range)),
                         _ => panic!("Not correct type"),
@@ -298,13 +559,19 @@ macro_rules! impl_range_lookup_indexer {
                 ) -> Box<Iterator<Item = u64> + 'a>
                 where R: RangeBounds<$ty> {
                     match *self {
-                        ValueIndexer::Null => Box::new(empty()),
-                        ValueIndexer::$path(ref map) => Box::new(
-                            map
-                                .range(range)
-                                .flat_map(|(_, set)| set.iter().cloned())
-                        ),
-                        ValueIndexer::Branch(ref field_map) => field_map
+                        ValueIndexer::Null | ValueIndexer::HashNull => Box::new(empty()),
+                        ValueIndexer::$hash_path(_) => panic!("Hash-indexed field does not support range lookup"),
+                        ValueIndexer::$path(ref map) => {
+                            // a struct is found once per element of an array within the range
+                            let mut found = HashSet::new();
+                            Box::new(
+                                map
+                                    .range(range)
+                                    .flat_map(|(_, set)| set.iter().cloned())
+                                    .filter(move |i| found.insert(*i))
+                            )
+                        },
+                        ValueIndexer::Branch(ref field_map, _) => field_map
                             .get(field)
                             .map_or(
                                 Box::new(empty()),
@@ -312,13 +579,84 @@ macro_rules! impl_range_lookup_indexer {
                             ),
                         _ => panic!("Not correct type"),
                     }
-                }                
+                }
+
+                fn count_by_range<R>(&self, field: &str, range: R) -> Option<(usize, usize)>
+                where
+                    R: RangeBounds<$ty>
+                {
+                    match *self {
+                        ValueIndexer::Null => Some((0, 0)),
+                        ValueIndexer::$path(ref map) => Some(
+                            map.range(range).fold((0, 0), |(buckets, structs), (_, set)| (buckets + 1, structs + set.len()))
+                        ),
+                        ValueIndexer::Branch(ref field_map, _) => field_map
+                            .get(field)
+                            .map_or(Some((0, 0)), |entry| entry.count_by_range::<_>("", range)),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_range_lookup_indexer!{
+    [IntLeaf, IntHashLeaf, i128]
+    [StringLeaf, StringHashLeaf, String]
+    [FloatLeaf, FloatHashLeaf, NotNaN<f64>]
+}
+
+// integer keys are all stored as i128, so that structs could be looked up by any integer type
+macro_rules! impl_int_lookup_indexer {
+    ($($ty:ty)*) => {
+        $(
+            impl ValueLookupIndexer<$ty> for ValueIndexer {
+                fn get_index_by_value(&self, field: &str, key: &$ty) -> Option<u64> {
+                    self.get_index_by_value(field, &i128::from(*key))
+                }
+
+                fn get_all_indices_by_value<'a>(&'a self, field: &str, key: &$ty)
+                    -> Box<Iterator<Item = u64> + 'a> {
+                    self.get_all_indices_by_value(field, &i128::from(*key))
+                }
+            }
+
+            impl RangeLookupIndexer<$ty> for ValueIndexer {
+                fn get_index_by_range<R>(&self, field: &str, range: R) -> Option<u64>
+                where
+                    R: RangeBounds<$ty>
+                {
+                    self.get_index_by_range(field, convert_int_range(range))
+                }
+
+                fn get_all_indices_by_range<'a, R>(
+                    &'a self,
+                    field: &str,
+                    range: R
+                ) -> Box<Iterator<Item = u64> + 'a>
+                where R: RangeBounds<$ty> {
+                    self.get_all_indices_by_range(field, convert_int_range(range))
+                }
+
+                fn count_by_range<R>(&self, field: &str, range: R) -> Option<(usize, usize)>
+                where
+                    R: RangeBounds<$ty>
+                {
+                    self.count_by_range(field, convert_int_range(range))
+                }
+            }
+
+            impl NearestLookupIndexer<$ty> for ValueIndexer {
+                fn get_index_nearest(&self, field: &str, target: &$ty) -> Option<u64> {
+                    self.get_index_nearest(field, &i128::from(*target))
+                }
             }
         )*
     };
 }
 
-impl_range_lookup_indexer!{ [IntLeaf, i64] [StringLeaf, String] [FloatLeaf, NotNaN<f64>] }
+impl_int_lookup_indexer!{i64 u64}
 
 impl RangeLookupIndexer<f64> for ValueIndexer {
     fn get_index_by_range<R>(&self, field: &str, range: R) -> Option<u64>
@@ -338,6 +676,117 @@ impl RangeLookupIndexer<f64> for ValueIndexer {
     {
         self.get_all_indices_by_range(field, convert_float_range(range))
     }
+
+    fn count_by_range<R>(&self, field: &str, range: R) -> Option<(usize, usize)>
+    where
+        R: RangeBounds<f64>,
+    {
+        self.count_by_range(field, convert_float_range(range))
+    }
+}
+
+// times are looked up in the form stored by `Collation::Timestamp`
+#[cfg(feature = "chrono")]
+impl RangeLookupIndexer<DateTime<Utc>> for ValueIndexer {
+    fn get_index_by_range<R>(&self, field: &str, range: R) -> Option<u64>
+    where
+        R: RangeBounds<DateTime<Utc>>,
+    {
+        self.get_index_by_range(field, convert_time_range(range))
+    }
+
+    fn get_all_indices_by_range<'a, R>(
+        &'a self,
+        field: &str,
+        range: R,
+    ) -> Box<Iterator<Item = u64> + 'a>
+    where
+        R: RangeBounds<DateTime<Utc>>,
+    {
+        self.get_all_indices_by_range(field, convert_time_range(range))
+    }
+
+    fn count_by_range<R>(&self, field: &str, range: R) -> Option<(usize, usize)>
+    where
+        R: RangeBounds<DateTime<Utc>>,
+    {
+        self.count_by_range(field, convert_time_range(range))
+    }
+}
+
+pub trait NearestLookupIndexer<T> {
+    fn get_index_nearest(&self, field: &str, target: &T) -> Option<u64>;
+}
+
+/// A key type whose values could be compared by their distance to a target.
+trait Distance: Ord + Copy {
+    /// Return true if `self` is at least as close to `target` as `other`.
+    fn closer_than(&self, other: &Self, target: &Self) -> bool;
+}
+
+impl Distance for i128 {
+    fn closer_than(&self, other: &Self, target: &Self) -> bool {
+        self.abs_diff(*target) <= other.abs_diff(*target)
+    }
+}
+
+impl Distance for NotNaN<f64> {
+    fn closer_than(&self, other: &Self, target: &Self) -> bool {
+        (**self - **target).abs() <= (**other - **target).abs()
+    }
+}
+
+fn nearest_index<K>(map: &BTreeMap<K, IndexSet<u64>>, target: &K) -> Option<u64>
+where
+    K: Distance,
+{
+    // buckets are removed with their last struct, so none is empty
+    let below = map.range(..=*target).next_back();
+    let above = map.range(*target..).next();
+    let (_, set) = match (below, above) {
+        (Some(low), Some(high)) => if low.0.closer_than(high.0, target) {
+            low
+        } else {
+            high
+        },
+        (Some(item), None) | (None, Some(item)) => item,
+        (None, None) => return None,
+    };
+    set.get_index(0).cloned()
+}
+
+macro_rules! impl_nearest_lookup_indexer {
+    ($([$path:ident, $hash_path:ident, $ty:ty])*) => {
+        $(
+            impl NearestLookupIndexer<$ty> for ValueIndexer {
+                fn get_index_nearest(&self, field: &str, target: &$ty) -> Option<u64> {
+                    match *self {
+                        ValueIndexer::Null | ValueIndexer::HashNull => None,
+                        ValueIndexer::$hash_path(_) => panic!("Hash-indexed field does not support nearest lookup"),
+                        ValueIndexer::$path(ref map) => nearest_index(map, target),
+                        ValueIndexer::Branch(ref field_map, _) => field_map
+                            .get(field)
+                            .and_then(|entry| entry.get_index_nearest("", target)),
+                        _ => panic!("Not correct type"),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_nearest_lookup_indexer!{
+    [IntLeaf, IntHashLeaf, i128]
+    [FloatLeaf, FloatHashLeaf, NotNaN<f64>]
+}
+
+impl NearestLookupIndexer<f64> for ValueIndexer {
+    fn get_index_nearest(&self, field: &str, target: &f64) -> Option<u64> {
+        self.get_index_nearest(
+            field,
+            &NotNaN::new(*target).expect("NaN value is not accepted"),
+        )
+    }
 }
 
 fn convert_float_bound(bound: Bound<&f64>) -> Bound<NotNaN<f64>> {
@@ -361,3 +810,154 @@ where
         convert_float_bound(range.end_bound()),
     )
 }
+
+fn convert_int_bound<T>(bound: Bound<&T>) -> Bound<i128>
+where
+    T: Copy + Into<i128>,
+{
+    match bound {
+        Bound::Included(value) => Bound::Included((*value).into()),
+        Bound::Excluded(value) => Bound::Excluded((*value).into()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+#[cfg(feature = "chrono")]
+fn convert_time_range<R>(range: R) -> (Bound<String>, Bound<String>)
+where
+    R: RangeBounds<DateTime<Utc>>,
+{
+    let convert = |bound: Bound<&DateTime<Utc>>| match bound {
+        Bound::Included(time) => Bound::Included(format_time(time)),
+        Bound::Excluded(time) => Bound::Excluded(format_time(time)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (convert(range.start_bound()), convert(range.end_bound()))
+}
+
+fn convert_int_range<T, R>(range: R) -> (Bound<i128>, Bound<i128>)
+where
+    T: Copy + Into<i128>,
+    R: RangeBounds<T>,
+{
+    (
+        convert_int_bound(range.start_bound()),
+        convert_int_bound(range.end_bound()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+
+    use codec::Codec;
+
+    use super::*;
+
+    fn record(value: Value) -> Record {
+        Record::from_json(&value, Codec::Json).unwrap()
+    }
+
+    fn indices<I: Iterator<Item = u64>>(iter: I) -> Vec<u64> {
+        let mut indices: Vec<u64> = iter.collect();
+        indices.sort();
+        indices
+    }
+
+    #[test]
+    fn value_lookup() {
+        let mut indexer = ValueIndexer::new();
+        let objs = [
+            record(serde_json::json!({ "count": 3, "name": "Tuan" })),
+            record(serde_json::json!({ "count": 5, "name": "Duane" })),
+            record(serde_json::json!({ "count": 3, "name": "Duane" })),
+        ];
+        for (index, obj) in objs.iter().enumerate() {
+            indexer.add(obj, index as u64, None);
+        }
+
+        assert_eq!(indexer.get_index_by_value("count", &3i64), Some(0));
+        assert_eq!(indices(indexer.get_all_indices_by_value("count", &3i64)), vec![0, 2]);
+        assert_eq!(
+            indices(indexer.get_all_indices_by_value("name", &String::from("Duane"))),
+            vec![1, 2]
+        );
+        assert_eq!(indexer.get_index_by_value("count", &4i64), None);
+        assert_eq!(indexer.get_index_by_value("age", &3i64), None);
+
+        // the bucket of a value goes away with its last struct
+        indexer.remove(0, &objs[0], None);
+        assert_eq!(indices(indexer.get_all_indices_by_value("count", &3i64)), vec![2]);
+        indexer.remove(2, &objs[2], None);
+        assert_eq!(indexer.get_index_by_value("count", &3i64), None);
+        match indexer {
+            ValueIndexer::Branch(ref field_map, _) => match field_map["count"] {
+                ValueIndexer::IntLeaf(ref map) => assert_eq!(map.len(), 1),
+                _ => panic!("count is not indexed as ints"),
+            },
+            _ => panic!("the indexer of a struct is not a branch"),
+        }
+    }
+
+    #[test]
+    fn range_lookup() {
+        let mut indexer = ValueIndexer::new();
+        for (index, count) in [5, 1, 3, 4].iter().enumerate() {
+            indexer.add(&record(serde_json::json!({ "count": count })), index as u64, None);
+        }
+
+        assert_eq!(indices(indexer.get_all_indices_by_range("count", 3i64..5)), vec![2, 3]);
+        assert_eq!(indexer.get_index_by_range("count", ..2i64), Some(1));
+        assert_eq!(indexer.get_index_by_range("count", 6i64..), None);
+        assert_eq!(
+            indexer.get_all_indices_ordered("count", Order::Descending).collect::<Vec<_>>(),
+            vec![0, 3, 2, 1]
+        );
+        assert_eq!(indexer.get_index_nearest("count", &7i64), Some(0));
+    }
+
+    #[test]
+    fn hash_index_and_nulls() {
+        let mut indexer = ValueIndexer::new();
+        indexer.set_hash_index("name");
+        indexer.add(&record(serde_json::json!({ "name": "Tuan", "owner": null })), 0, None);
+        indexer.add(&record(serde_json::json!({ "name": "Duane", "owner": "Tuan" })), 1, None);
+
+        assert_eq!(indexer.get_index_by_value("name", &String::from("Duane")), Some(1));
+        assert_eq!(indexer.kind("name"), Some((FieldKind::String, true)));
+        assert_eq!(indexer.kind("owner"), Some((FieldKind::String, false)));
+        assert_eq!(indexer.get_index_by_value("owner", &String::from("Tuan")), Some(1));
+        assert_eq!(indexer.kind("age"), None);
+    }
+
+    #[test]
+    fn selected_fields() {
+        let mut selected = HashSet::new();
+        selected.insert(String::from("count"));
+        let mut indexer = ValueIndexer::new();
+        indexer.add(&record(serde_json::json!({ "count": 3, "name": "Tuan" })), 0, Some(&selected));
+
+        assert_eq!(indexer.get_index_by_value("count", &3i64), Some(0));
+        assert_eq!(indexer.get_index_by_value("name", &String::from("Tuan")), None);
+        assert_eq!(indexer.fields().keys().collect::<Vec<_>>(), vec!["count"]);
+    }
+
+    #[test]
+    fn conflict() {
+        let mut indexer = ValueIndexer::new();
+        assert_eq!(indexer.conflict(&record(serde_json::json!({ "x": 1 })), None), None);
+        indexer.add(&record(serde_json::json!({ "x": 1, "label": "a", "owner": null })), 0, None);
+
+        assert_eq!(indexer.conflict(&record(serde_json::json!({ "x": 2, "label": "b" })), None), None);
+        assert!(indexer.conflict(&record(serde_json::json!({ "x": 1.5 })), None).unwrap().contains("`x`"));
+        assert!(indexer.conflict(&record(serde_json::json!({ "label": 2 })), None).is_some());
+        // a field only ever null, or not yet indexed, could hold anything
+        assert_eq!(indexer.conflict(&record(serde_json::json!({ "owner": 2, "age": true })), None), None);
+        assert_eq!(indexer.conflict(&record(serde_json::json!({ "x": null })), None), None);
+
+        // fields which are not indexed are not checked
+        let mut selected = HashSet::new();
+        selected.insert(String::from("label"));
+        assert_eq!(indexer.conflict(&record(serde_json::json!({ "x": 1.5 })), Some(&selected)), None);
+    }
+}
@@ -0,0 +1,366 @@
+use std::cmp::{max, Ordering};
+
+use ordered_float::NotNaN;
+
+use entry::record::{Field, Record};
+
+/// A key type usable as the bounds of an interval.
+pub trait IntervalKey: Ord + Copy {
+    fn from_field(field: &Field) -> Option<Self>;
+
+    fn tree(indexer: &IntervalIndexer) -> Option<&IntervalTree<Self>>;
+}
+
+impl IntervalKey for i64 {
+    fn from_field(field: &Field) -> Option<Self> {
+        match *field {
+            Field::Int(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    fn tree(indexer: &IntervalIndexer) -> Option<&IntervalTree<Self>> {
+        match indexer.tree {
+            IntervalTreeKind::Int(ref tree) => Some(tree),
+            IntervalTreeKind::Null => None,
+            _ => panic!("Not correct type"),
+        }
+    }
+}
+
+impl IntervalKey for NotNaN<f64> {
+    fn from_field(field: &Field) -> Option<Self> {
+        match *field {
+            Field::Float(f) => NotNaN::new(f).ok(),
+            _ => None,
+        }
+    }
+
+    fn tree(indexer: &IntervalIndexer) -> Option<&IntervalTree<Self>> {
+        match indexer.tree {
+            IntervalTreeKind::Float(ref tree) => Some(tree),
+            IntervalTreeKind::Null => None,
+            _ => panic!("Not correct type"),
+        }
+    }
+}
+
+/// A type whose values could be used to query an interval index.
+pub trait ToIntervalKey {
+    type Key: IntervalKey;
+
+    fn to_key(&self) -> Self::Key;
+}
+
+impl ToIntervalKey for i64 {
+    type Key = i64;
+
+    fn to_key(&self) -> i64 {
+        *self
+    }
+}
+
+impl ToIntervalKey for f64 {
+    type Key = NotNaN<f64>;
+
+    fn to_key(&self) -> NotNaN<f64> {
+        NotNaN::new(*self).expect("NaN value is not accepted")
+    }
+}
+
+/// Read the interval `start..end` stored in the two specified fields of a flattened struct.
+pub fn read_interval<K>(obj: &Record, fields: (&str, &str)) -> Option<(K, K)>
+where
+    K: IntervalKey,
+{
+    let start = obj.get(fields.0)?;
+    let end = obj.get(fields.1)?;
+    Some((K::from_field(start)?, K::from_field(end)?))
+}
+
+enum IntervalTreeKind {
+    Int(IntervalTree<i64>),
+    Float(IntervalTree<NotNaN<f64>>),
+    Null,
+}
+
+/// An interval index over a pair of fields of a type.
+pub struct IntervalIndexer {
+    start_field: String,
+    end_field: String,
+    tree: IntervalTreeKind,
+}
+
+impl IntervalIndexer {
+    pub fn new(start_field: &str, end_field: &str) -> Self {
+        IntervalIndexer {
+            start_field: start_field.to_owned(),
+            end_field: end_field.to_owned(),
+            tree: IntervalTreeKind::Null,
+        }
+    }
+
+    pub fn is_on(&self, fields: (&str, &str)) -> bool {
+        self.start_field == fields.0 && self.end_field == fields.1
+    }
+
+    pub fn fields(&self) -> (&str, &str) {
+        (&self.start_field, &self.end_field)
+    }
+
+    pub fn clear(&mut self) {
+        self.tree = IntervalTreeKind::Null;
+    }
+
+    pub fn add(&mut self, obj: &Record, index: u64) {
+        let fields = (self.start_field.as_str(), self.end_field.as_str());
+        let is_float = match obj.get(fields.0) {
+            Some(&Field::Float(_)) => true,
+            Some(&Field::Int(_)) => false,
+            _ => return,
+        };
+
+        if let IntervalTreeKind::Null = self.tree {
+            self.tree = if is_float {
+                IntervalTreeKind::Float(IntervalTree::new())
+            } else {
+                IntervalTreeKind::Int(IntervalTree::new())
+            };
+        }
+
+        match self.tree {
+            IntervalTreeKind::Int(ref mut tree) => {
+                let (start, end) = read_interval(obj, fields).expect("Incorrect data type!");
+                tree.insert(start, end, index);
+            }
+            IntervalTreeKind::Float(ref mut tree) => {
+                let (start, end) = read_interval(obj, fields).expect("Incorrect data type!");
+                tree.insert(start, end, index);
+            }
+            IntervalTreeKind::Null => (),
+        }
+    }
+
+    pub fn remove(&mut self, obj: &Record, index: u64) {
+        let fields = (self.start_field.as_str(), self.end_field.as_str());
+        match self.tree {
+            IntervalTreeKind::Int(ref mut tree) => {
+                if let Some((start, _)) = read_interval::<i64>(obj, fields) {
+                    tree.remove(start, index);
+                }
+            }
+            IntervalTreeKind::Float(ref mut tree) => {
+                if let Some((start, _)) = read_interval::<NotNaN<f64>>(obj, fields) {
+                    tree.remove(start, index);
+                }
+            }
+            IntervalTreeKind::Null => (),
+        }
+    }
+}
+
+type Link<K> = Option<Box<Node<K>>>;
+
+struct Node<K> {
+    start: K,
+    end: K,
+    index: u64,
+    max_end: K,
+    height: i32,
+    left: Link<K>,
+    right: Link<K>,
+}
+
+impl<K: IntervalKey> Node<K> {
+    fn new(start: K, end: K, index: u64) -> Self {
+        Node {
+            start,
+            end,
+            index,
+            max_end: end,
+            height: 1,
+            left: None,
+            right: None,
+        }
+    }
+
+    fn key_cmp(&self, start: K, index: u64) -> Ordering {
+        (start, index).cmp(&(self.start, self.index))
+    }
+
+    fn update(&mut self) {
+        self.height = 1 + max(height(&self.left), height(&self.right));
+        self.max_end = self.end;
+        for child in [&self.left, &self.right].iter() {
+            if let Some(ref node) = **child {
+                self.max_end = max(self.max_end, node.max_end);
+            }
+        }
+    }
+}
+
+fn height<K>(link: &Link<K>) -> i32 {
+    link.as_ref().map_or(0, |node| node.height)
+}
+
+fn rotate_left<K: IntervalKey>(mut node: Box<Node<K>>) -> Box<Node<K>> {
+    let mut right = node.right.take().expect("rotating without right child");
+    node.right = right.left.take();
+    node.update();
+    right.left = Some(node);
+    right.update();
+    right
+}
+
+fn rotate_right<K: IntervalKey>(mut node: Box<Node<K>>) -> Box<Node<K>> {
+    let mut left = node.left.take().expect("rotating without left child");
+    node.left = left.right.take();
+    node.update();
+    left.right = Some(node);
+    left.update();
+    left
+}
+
+fn balance<K: IntervalKey>(mut node: Box<Node<K>>) -> Box<Node<K>> {
+    node.update();
+    let factor = height(&node.left) - height(&node.right);
+    if factor > 1 {
+        let left = node.left.take().unwrap();
+        node.left = Some(if height(&left.left) < height(&left.right) {
+            rotate_left(left)
+        } else {
+            left
+        });
+        rotate_right(node)
+    } else if factor < -1 {
+        let right = node.right.take().unwrap();
+        node.right = Some(if height(&right.right) < height(&right.left) {
+            rotate_right(right)
+        } else {
+            right
+        });
+        rotate_left(node)
+    } else {
+        node
+    }
+}
+
+fn insert<K: IntervalKey>(link: Link<K>, start: K, end: K, index: u64) -> Box<Node<K>> {
+    match link {
+        None => Box::new(Node::new(start, end, index)),
+        Some(mut node) => {
+            match node.key_cmp(start, index) {
+                Ordering::Less => node.left = Some(insert(node.left.take(), start, end, index)),
+                Ordering::Greater => node.right = Some(insert(node.right.take(), start, end, index)),
+                Ordering::Equal => {
+                    node.end = end;
+                }
+            }
+            balance(node)
+        }
+    }
+}
+
+fn remove_min<K: IntervalKey>(mut node: Box<Node<K>>) -> (Link<K>, Box<Node<K>>) {
+    match node.left.take() {
+        None => (node.right.take(), node),
+        Some(left) => {
+            let (rest, min) = remove_min(left);
+            node.left = rest;
+            (Some(balance(node)), min)
+        }
+    }
+}
+
+fn remove<K: IntervalKey>(link: Link<K>, start: K, index: u64) -> Link<K> {
+    let mut node = link?;
+    match node.key_cmp(start, index) {
+        Ordering::Less => node.left = remove(node.left.take(), start, index),
+        Ordering::Greater => node.right = remove(node.right.take(), start, index),
+        Ordering::Equal => {
+            return match (node.left.take(), node.right.take()) {
+                (None, right) => right,
+                (left, None) => left,
+                (left, Some(right)) => {
+                    let (rest, mut min) = remove_min(right);
+                    min.left = left;
+                    min.right = rest;
+                    Some(balance(min))
+                }
+            }
+        }
+    }
+    Some(balance(node))
+}
+
+/// A dynamic interval tree: an AVL tree ordered by the start of intervals,
+/// with each node augmented by the maximum end in its subtree.
+///
+/// Intervals are half-open: an interval `start..end` contains `point`
+/// if `start <= point < end`.
+pub struct IntervalTree<K> {
+    root: Link<K>,
+}
+
+impl<K> Default for IntervalTree<K> {
+    fn default() -> Self {
+        IntervalTree { root: None }
+    }
+}
+
+impl<K: IntervalKey> IntervalTree<K> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, start: K, end: K, index: u64) {
+        self.root = Some(insert(self.root.take(), start, end, index));
+    }
+
+    pub fn remove(&mut self, start: K, index: u64) {
+        self.root = remove(self.root.take(), start, index);
+    }
+
+    /// Return the index of the interval with the lowest start containing the point.
+    pub fn get_index_covering(&self, point: K) -> Option<u64> {
+        first_covering(&self.root, point)
+    }
+
+    /// Return the indices of all intervals containing the point, ordered by their start.
+    pub fn get_all_indices_covering(&self, point: K) -> Vec<u64> {
+        let mut result = Vec::new();
+        all_covering(&self.root, point, &mut result);
+        result
+    }
+}
+
+fn first_covering<K: IntervalKey>(link: &Link<K>, point: K) -> Option<u64> {
+    let node = match *link {
+        Some(ref node) if node.max_end > point => node,
+        _ => return None,
+    };
+    first_covering(&node.left, point).or_else(|| {
+        if node.start > point {
+            None
+        } else if node.end > point {
+            Some(node.index)
+        } else {
+            first_covering(&node.right, point)
+        }
+    })
+}
+
+fn all_covering<K: IntervalKey>(link: &Link<K>, point: K, result: &mut Vec<u64>) {
+    if let Some(ref node) = *link {
+        if node.max_end <= point {
+            return;
+        }
+        all_covering(&node.left, point, result);
+        if node.start <= point {
+            if node.end > point {
+                result.push(node.index);
+            }
+            all_covering(&node.right, point, result);
+        }
+    }
+}
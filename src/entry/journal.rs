@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde_json::{self, Value};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Op {
+    Write,
+    Take,
+}
+
+/// A line of the journal.
+#[derive(Deserialize)]
+struct Line {
+    #[serde(rename = "type")]
+    type_name: String,
+    op: Op,
+    obj: Value,
+}
+
+/// An append-only log of the structs written to and taken from a space,
+/// with one JSON document per line.
+pub struct Journal {
+    file: Mutex<File>,
+    // the structs left in the journal when it was opened, by name of their type,
+    // until the type is first accessed
+    pending: Mutex<HashMap<String, Vec<Value>>>,
+}
+
+impl Journal {
+    /// Open the journal at the specified path, creating it if it does not exist,
+    /// and find out which structs it holds.
+    ///
+    /// A last line left half-written, e.g: by a crash, is discarded.
+    pub fn open(path: &Path) -> io::Result<Journal> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        let complete = content.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        if complete < content.len() {
+            file.set_len(complete as u64)?;
+        }
+
+        let mut lines = Vec::new();
+        for line in content[..complete].split(|&b| b == b'\n') {
+            if !line.is_empty() {
+                lines.push(serde_json::from_slice(line)?);
+            }
+        }
+        Ok(Journal {
+            file: Mutex::new(file),
+            pending: Mutex::new(replay(lines)),
+        })
+    }
+
+    /// Return the structs of a type left in the journal when it was opened, in the order they were written.
+    /// Subsequent calls for the same type return nothing.
+    pub fn take_pending(&self, type_name: &str) -> Vec<Value> {
+        self.pending
+            .lock()
+            .unwrap()
+            .remove(type_name)
+            .unwrap_or_default()
+    }
+
+    /// Append an operation on a struct, given its payload.
+    /// The line is handed to the operating system before returning.
+    pub fn append(&self, type_name: &str, op: &str, payload: &[u8]) -> io::Result<()> {
+        let mut line = Vec::new();
+        write!(
+            line,
+            "{{\"type\":{},\"op\":\"{}\",\"obj\":",
+            serde_json::to_string(type_name)?,
+            op
+        )?;
+        line.extend_from_slice(payload);
+        line.extend_from_slice(b"}\n");
+        // a single write, so that concurrent lines are never interleaved
+        self.file.lock().unwrap().write_all(&line)
+    }
+}
+
+/// Return the structs left by the operations of the journal, by name of their type.
+///
+/// Structs with the same payload could not be told apart, so a take removes any of them.
+fn replay(lines: Vec<Line>) -> HashMap<String, Vec<Value>> {
+    let mut written: HashMap<String, Vec<Option<Value>>> = HashMap::new();
+    let mut positions: HashMap<(String, Vec<u8>), Vec<usize>> = HashMap::new();
+    for line in lines {
+        let objs = written.entry(line.type_name.clone()).or_default();
+        // the payload written by the space and the one of the parsed value may order fields differently
+        let key = (line.type_name, serde_json::to_vec(&line.obj).unwrap());
+        match line.op {
+            Op::Write => {
+                positions.entry(key).or_default().push(objs.len());
+                objs.push(Some(line.obj));
+            }
+            Op::Take => {
+                if let Some(position) = positions.get_mut(&key).and_then(|found| found.pop()) {
+                    objs[position] = None;
+                }
+            }
+        }
+    }
+    written
+        .into_iter()
+        .map(|(type_name, objs)| (type_name, objs.into_iter().flatten().collect()))
+        .collect()
+}
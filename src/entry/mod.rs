@@ -1,18 +1,115 @@
-use std::borrow::Borrow;
-use std::ops::RangeBounds;
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::iter::empty;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
 use indexmap::IndexMap;
-use serde_json::value::Value;
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
 
+pub mod bloom;
+pub mod cache;
+pub mod capacity;
+pub mod changes;
+pub mod collation;
+pub mod compound;
+pub mod decoder;
+pub mod dedup;
+pub mod eviction;
+pub mod expiry;
+pub mod handle;
 pub mod indexer;
+pub mod interval;
+pub mod journal;
+pub mod pattern;
+pub mod policy;
+pub mod record;
+pub mod schema;
+pub mod snapshot;
+pub mod spill;
+pub mod spatial;
+pub mod stats;
+pub mod subscription;
+pub mod wait_queue;
 
-use entry::indexer::{RangeLookupIndexer, ValueIndexer, ValueLookupIndexer};
+use entry::bloom::FieldFilters;
+use entry::cache::StructCache;
+use entry::capacity::{Breach, Capacity, Overflow, Room};
+use entry::changes::ChangeLog;
+use entry::collation::{collate_key, collate_range, Collate, Collation};
+use entry::compound::CompoundIndexer;
+use entry::decoder::{Admission, Decoder};
+use entry::dedup::PayloadIndex;
+use entry::eviction::{Eviction, Recency};
+use entry::expiry::ExpiryQueue;
+use entry::journal::Journal;
+use entry::pattern::Pattern;
+use entry::policy::OrderingPolicy;
+use error::SpaceError;
+use helpers::sample_indices;
+use entry::record::{Field, Record};
+use entry::schema::{FieldKind, Schema};
+use entry::snapshot::Snapshot;
+use entry::stats::{Counters, HoldTime, QueryPlan, TypeInfo, TypeMetrics};
+use entry::subscription::Subscription;
+use entry::wait_queue::{Condition, Signal, Ticket, WaitQueue};
+use query::{FieldValue, Query, TypedBounds};
+use entry::interval::{read_interval, IntervalIndexer, IntervalKey, ToIntervalKey};
+use entry::spatial::{in_rect, read_point, SpatialIndexer};
+use entry::indexer::{
+    is_selected, shrink_map, NearestLookupIndexer, Order, RangeLookupIndexer, ValueIndexer, ValueLookupIndexer,
+};
 
 pub struct Entry {
     counter: u64,
-    value_map: IndexMap<u64, Arc<Value>>,
+    value_map: IndexMap<u64, Arc<Record>>,
+    policy: OrderingPolicy,
     indexer: ValueIndexer,
+    filters: FieldFilters,
+    hashed_fields: Vec<String>,
+    // None if every field is indexed
+    indexed_fields: Option<HashSet<String>>,
+    intervals: Vec<IntervalIndexer>,
+    spatial_indices: Vec<SpatialIndexer>,
+    compound_indices: Vec<CompoundIndexer>,
+    // the collations of the string fields not compared as is
+    collations: HashMap<String, Collation>,
+    ttl: Option<Duration>,
+    expiries: ExpiryQueue,
+    // the structs checked out, with their own deadline if any
+    checked_out: HashMap<u64, (Arc<Record>, Option<Instant>)>,
+    // the end of the lease of every struct checked out
+    checkouts: ExpiryQueue,
+    cache: StructCache,
+    schema: Option<Schema>,
+    strict: bool,
+    decoder: Decoder,
+    // None unless writing a struct equal to one in the entry is a no-op
+    dedup: Option<PayloadIndex>,
+    // None if the number of structs is unlimited
+    capacity: Option<Capacity>,
+    // None if the memory taken by the structs is unlimited
+    quota: Option<Capacity>,
+    // an estimate of the memory taken by the structs and their indices, in bytes
+    memory: usize,
+    eviction: Eviction,
+    // None unless the eviction policy needs to know when the structs were written or read
+    recency: Option<Recency>,
+    hold_times: BTreeMap<&'static str, HoldTime>,
+    counters: Counters,
+    // None until the first checkpoint of the entry
+    changes: Option<ChangeLog>,
+    // the journal of the space and the name of the type, if the space has a journal
+    journal: Option<(Arc<Journal>, &'static str)>,
+    subscriptions: Vec<Subscription>,
+    waiters: WaitQueue,
 }
 
 impl Entry {
@@ -20,130 +117,1400 @@ impl Entry {
         Entry {
             counter: 0,
             value_map: IndexMap::new(),
+            policy: OrderingPolicy::Unordered,
             indexer: ValueIndexer::new(),
+            filters: FieldFilters::new(),
+            hashed_fields: Vec::new(),
+            indexed_fields: None,
+            intervals: Vec::new(),
+            spatial_indices: Vec::new(),
+            compound_indices: Vec::new(),
+            collations: HashMap::new(),
+            ttl: None,
+            expiries: ExpiryQueue::new(),
+            checked_out: HashMap::new(),
+            checkouts: ExpiryQueue::new(),
+            cache: StructCache::new(),
+            schema: None,
+            strict: false,
+            decoder: Decoder::default(),
+            dedup: None,
+            capacity: None,
+            quota: None,
+            memory: 0,
+            eviction: Eviction::default(),
+            recency: None,
+            hold_times: BTreeMap::new(),
+            counters: Counters::default(),
+            changes: None,
+            journal: None,
+            subscriptions: Vec::new(),
+            waiters: WaitQueue::new(),
         }
     }
 
-    pub fn add(&mut self, obj: Value) {
-        self.add_value_to_list(Arc::new(obj.clone()));
-        self.indexer.add(obj, self.counter)
+    /// Set the lease given to every struct added afterward. `None` means structs never expire.
+    pub fn set_ttl(&mut self, ttl: Option<Duration>) {
+        self.ttl = ttl;
     }
 
-    pub fn get(&self) -> Option<Value> {
-        self.value_map.values().next().map(|arc| {
-            let val: &Value = arc.borrow();
-            val.clone()
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Return structs in the order of the policy from now on.
+    pub fn set_policy(&mut self, policy: OrderingPolicy) {
+        self.policy = policy;
+        if policy != OrderingPolicy::Unordered {
+            // structs removed so far may have been moved out of the order they were added in
+            self.value_map.sort_keys();
+        }
+        self.track_recency();
+    }
+
+    pub fn len(&self) -> usize {
+        self.value_map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.value_map.is_empty()
+    }
+
+    /// Return true if any index, strict schema, deduplication, memory quota or eviction policy has been declared
+    /// on the entry, or if its changes are tracked for checkpoints, appended to a journal, or subscribed to.
+    pub fn has_settings(&self) -> bool {
+        !self.hashed_fields.is_empty()
+            || self.indexed_fields.is_some()
+            || !self.intervals.is_empty()
+            || !self.spatial_indices.is_empty()
+            || !self.compound_indices.is_empty()
+            || !self.collations.is_empty()
+            || self.policy != OrderingPolicy::Unordered
+            || self.strict
+            || self.decoder.is_set()
+            || !self.decoder.dead_letters().is_empty()
+            || self.changes.is_some()
+            || self.journal.is_some()
+            || !self.subscriptions.is_empty()
+            || self.dedup.is_some()
+            || self.quota.is_some()
+            || self.eviction != Eviction::default()
+    }
+
+    /// Notify a subscription of the structs added from now on.
+    pub fn subscribe(&mut self, subscription: Subscription) {
+        self.subscriptions.push(subscription);
+    }
+
+    /// Wake up a blocked call when a struct satisfying the condition of its ticket is added.
+    /// The condition is normalized by the collations of the entry.
+    pub fn register_waiter(&mut self, condition: Condition) -> Arc<Ticket> {
+        let ticket = Ticket::new(condition.collate(&self.collations));
+        self.waiters.register(&ticket);
+        ticket
+    }
+
+    pub fn unregister_waiter(&mut self, ticket: &Arc<Ticket>) {
+        self.waiters.unregister(ticket);
+    }
+
+    /// Wake up every blocked call and writer waiting for room, e.g: because the space is closed.
+    pub fn wake_all(&self) {
+        self.waiters.wake_all();
+        for limit in self.capacity.iter().chain(self.quota.iter()) {
+            limit.room().notify();
+        }
+    }
+
+    /// Wake up a call waiting for structs of several types when a struct satisfying the condition is added,
+    /// or any struct if there is none. The condition is normalized by the collations of the entry.
+    pub fn register_signal(&mut self, signal: &Arc<Signal>, condition: Option<Condition>) {
+        let condition = condition.map(|condition| condition.collate(&self.collations));
+        self.waiters.register_signal(signal, condition);
+    }
+
+    pub fn unregister_signal(&mut self, signal: &Arc<Signal>) {
+        self.waiters.unregister_signal(signal);
+    }
+
+    /// Append the structs added and removed from now on to a journal.
+    pub fn set_journal(&mut self, journal: Arc<Journal>, type_name: &'static str) {
+        self.journal = Some((journal, type_name));
+    }
+
+    pub fn is_journaled(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    fn append_to_journal(&self, op: &str, obj: &Record) {
+        if let Some((ref journal, type_name)) = self.journal {
+            let payload = obj.json_payload().expect("struct cannot be journaled as JSON");
+            journal
+                .append(type_name, op, &payload)
+                .expect("cannot append to the journal");
+        }
+    }
+
+    /// Track the changes made from now on, discarding the changes tracked so far.
+    pub fn track_changes(&mut self) {
+        self.changes = Some(ChangeLog::new());
+    }
+
+    /// Return the changes tracked so far, if they are tracked, and track the changes made from now on.
+    pub fn take_changes(&mut self) -> Option<ChangeLog> {
+        self.changes.as_mut().map(|changes| mem::replace(changes, ChangeLog::new()))
+    }
+
+    /// Stop tracking changes, e.g: because they could not be saved.
+    pub fn forget_changes(&mut self) {
+        self.changes = None;
+    }
+
+    /// Reject structs whose shape differs from the schema captured on the first write.
+    pub fn set_strict_schema(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Make adding a struct equal to one in the entry a no-op from now on, or not.
+    /// Structs already equal to each other are left as is.
+    pub fn set_dedup(&mut self, dedup: bool) {
+        if !dedup {
+            self.dedup = None;
+        } else if self.dedup.is_none() {
+            let mut payloads = PayloadIndex::default();
+            for (&index, value) in &self.value_map {
+                payloads.add(&value.payload(), index);
+            }
+            self.dedup = Some(payloads);
+        }
+    }
+
+    /// Limit the number of structs of the entry from now on. Structs beyond the limit are left as is.
+    pub fn set_capacity(&mut self, limit: usize, overflow: Overflow) {
+        if let Some(previous) = self.capacity.replace(Capacity::new(limit, overflow)) {
+            // the writers waiting for room try again with the new capacity
+            previous.room().notify();
+        }
+        self.track_recency();
+    }
+
+    /// Return the maximum number of structs of the entry, and what to do when it is reached, if limited.
+    pub fn capacity(&self) -> Option<(usize, Overflow)> {
+        self.capacity.as_ref().map(|capacity| (capacity.limit(), capacity.overflow()))
+    }
+
+    /// Limit the memory taken by the structs of the entry and their indices from now on, in bytes.
+    /// Structs beyond the limit are left as is.
+    pub fn set_quota(&mut self, bytes: usize, overflow: Overflow) {
+        if let Some(previous) = self.quota.replace(Capacity::new(bytes, overflow)) {
+            // the writers waiting for room try again with the new quota
+            previous.room().notify();
+        }
+        self.track_recency();
+    }
+
+    /// Return the maximum memory taken by the structs of the entry, and what to do when it is reached, if limited.
+    pub fn quota(&self) -> Option<(usize, Overflow)> {
+        self.quota.as_ref().map(|quota| (quota.limit(), quota.overflow()))
+    }
+
+    /// Choose which structs are evicted from now on.
+    pub fn set_eviction(&mut self, eviction: Eviction) {
+        self.eviction = eviction;
+        self.track_recency();
+    }
+
+    /// Track when the structs were written and read if the eviction policy needs it,
+    /// or if the oldest unordered struct may be evicted, and stop tracking it otherwise.
+    /// The structs already in the entry are considered written, and read, in the order they were added in
+    /// when it is first needed.
+    fn track_recency(&mut self) {
+        let evicts = self.capacity
+            .iter()
+            .chain(self.quota.iter())
+            .any(|limit| limit.overflow() == Overflow::EvictOldest);
+        let needed = self.eviction != Eviction::LeastRecentlyWritten
+            || (evicts && self.policy == OrderingPolicy::Unordered);
+        if !needed {
+            self.recency = None;
+        } else if self.recency.is_none() {
+            let mut recency = Recency::new();
+            let now = Instant::now();
+            let mut indices: Vec<_> = self.value_map.keys().cloned().collect();
+            indices.sort();
+            for index in indices {
+                recency.add(index, now);
+            }
+            self.recency = Some(recency);
+        }
+    }
+
+    /// Return an estimate of the memory taken by the structs of the entry and their indices, in bytes.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Return the limit which adding the struct would exceed, and what to do about it, if any.
+    /// A duplicate needs no room, as adding it is a no-op.
+    pub fn breach(&self, obj: &Record) -> Option<(Breach, Overflow)> {
+        if self.find_equal(obj).is_some() {
+            return None;
+        }
+        if let Some((limit, overflow)) = self.capacity() {
+            if self.value_map.len() >= limit {
+                return Some((Breach::Capacity(limit), overflow));
+            }
+        }
+        if let Some((bytes, overflow)) = self.quota() {
+            if self.memory + self.footprint(obj) > bytes {
+                return Some((Breach::Quota(bytes), overflow));
+            }
+        }
+        None
+    }
+
+    /// Return true if the struct alone would take more memory than the quota of the entry.
+    pub fn exceeds_quota(&self, obj: &Record) -> bool {
+        self.quota().is_some_and(|(bytes, _)| self.footprint(obj) > bytes)
+    }
+
+    /// Return true if the struct could be added without exceeding the capacity and the quota of the entry,
+    /// after evicting the oldest structs if a limit is reached, evicts, and `evict` is true.
+    pub fn make_room(&mut self, obj: &Record, evict: bool) -> bool {
+        while let Some((_, overflow)) = self.breach(obj) {
+            // nothing is evicted for a struct which would not fit even on its own
+            if !evict || overflow != Overflow::EvictOldest || self.exceeds_quota(obj) {
+                return false;
+            }
+            let victim = match (self.eviction, self.recency.as_ref()) {
+                (Eviction::LeastRecentlyRead, Some(recency)) => recency.least_recently_used(),
+                // the structs are sorted by index
+                _ if self.policy != OrderingPolicy::Unordered => self.value_map.keys().next().cloned(),
+                (_, Some(recency)) => recency.least_recently_written(),
+                _ => None,
+            };
+            let index = match victim {
+                Some(index) => index,
+                // a limit of zero leaves no room at all
+                None => return false,
+            };
+            if let Some(val) = self.remove_value_from_index(&index) {
+                self.remove_from_index(index, &val);
+                self.counters.evicted += 1;
+            }
+        }
+        true
+    }
+
+    /// Register a writer about to wait for room in the entry, where adding a struct would exceed the limit,
+    /// and return where to wait along with the value to wait with.
+    pub fn block_writer(&self, breach: Breach) -> Option<(Arc<Room>, u64)> {
+        let limit = match breach {
+            Breach::Capacity(_) => self.capacity.as_ref(),
+            Breach::Quota(_) => self.quota.as_ref(),
+        };
+        limit.map(|limit| {
+            let room = limit.room().clone();
+            let seen = room.block();
+            (room, seen)
         })
     }
 
-    pub fn get_all<'a>(&'a self) -> Box<Iterator<Item = Value> + 'a> {
-        Box::new(self.value_map.values().map(|item| {
-            let val: &Value = item.borrow();
-            val.clone()
-        }))
+    /// Wake up the writers waiting for room, if a struct could be added.
+    fn notify_room(&self) {
+        if let Some(ref capacity) = self.capacity {
+            if self.value_map.len() < capacity.limit() {
+                capacity.room().notify();
+            }
+        }
+        // whether the struct of a writer fits is only known to the writer
+        if let Some(ref quota) = self.quota {
+            quota.room().notify();
+        }
     }
 
-    pub fn remove(&mut self) -> Option<Value> {
-        self.value_map.pop().map(|(key, value)| {
-            let val: &Value = value.borrow();
-            self.indexer.remove(key, val);
-            val.clone()
+    /// Return an estimate of the memory taken by a struct and its entries in the indices, in bytes.
+    fn footprint(&self, obj: &Record) -> usize {
+        // an index holds the value of every indexed field of the struct, and the index of the struct
+        let indexed: usize = obj
+            .indexed_fields()
+            .filter(|&(name, _)| is_selected(self.indexed_fields.as_ref(), name))
+            .map(|(_, field)| mem::size_of::<(Field, u64)>() + field.heap_size())
+            .sum();
+        mem::size_of::<(u64, Arc<Record>)>() + obj.memory() + indexed
+    }
+
+    /// Return the index of a struct equal to the specified one, if duplicates are suppressed.
+    fn find_equal(&self, obj: &Record) -> Option<u64> {
+        let payloads = self.dedup.as_ref()?;
+        let payload = obj.payload();
+        payloads.candidates(&payload).iter().cloned().find(|index| {
+            self.value_map
+                .get(index)
+                .is_some_and(|value| value.payload() == payload)
         })
     }
 
-    pub fn remove_all(&mut self) -> Vec<Value> {
-        let result = self.get_all().collect();
-        *self = Entry::new();
-        result
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    pub fn decoder(&self) -> &Decoder {
+        &self.decoder
+    }
+
+    pub fn decoder_mut(&mut self) -> &mut Decoder {
+        &mut self.decoder
+    }
+
+    /// Apply the decoder to the structs already added:
+    /// complete those which could not be read, or keep them aside as dead letters.
+    pub fn readmit_all(&mut self) {
+        let indices: Vec<u64> = self.value_map.keys().cloned().collect();
+        for index in indices {
+            let admission = match self.value_map.get(&index) {
+                Some(obj) => self.decoder.admit(obj),
+                None => continue,
+            };
+            match admission {
+                Admission::Keep => {}
+                Admission::Replace(filled) => {
+                    let deadline = self.expiries.deadline(index);
+                    self.remove_by_index(index);
+                    self.insert(index, Arc::new(filled), deadline);
+                }
+                Admission::Reject => {
+                    self.remove_by_index(index);
+                }
+            }
+        }
+    }
+
+    /// Record that the write guard of the entry has been held by an operation for some time.
+    pub fn record_hold_time(&mut self, operation: &'static str, held: Duration) {
+        self.hold_times.entry(operation).or_default().record(held);
+    }
+
+    pub fn hold_times(&self) -> &BTreeMap<&'static str, HoldTime> {
+        &self.hold_times
+    }
+
+    /// Record a lookup leaving the structs in the entry. Only a read guard is needed.
+    pub fn record_read(&self) {
+        self.counters.reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record how long an operation took, from requesting the write guard to releasing it.
+    pub fn record_latency(&mut self, operation: &'static str, latency: Duration) {
+        self.counters.latencies.entry(operation).or_default().record(latency);
+    }
+
+    /// Record that a blocking call started waiting for a struct.
+    pub fn wait_started(&mut self) {
+        self.counters.blocked += 1;
+    }
+
+    /// Record that a blocking call returned after the specified time.
+    pub fn wait_ended(&mut self, operation: &'static str, waited: Duration) {
+        // the call might have started waiting on an entry since collected
+        self.counters.blocked = self.counters.blocked.saturating_sub(1);
+        self.counters.waits.entry(operation).or_default().record(waited);
+    }
+
+    pub fn metrics(&self, type_name: &'static str) -> TypeMetrics {
+        let counters = &self.counters;
+        TypeMetrics {
+            type_name,
+            writes: counters.writes,
+            reads: counters.reads.load(Ordering::Relaxed),
+            takes: counters.removed - counters.expired - counters.evicted,
+            expired: counters.expired,
+            evicted: counters.evicted,
+            blocked: counters.blocked,
+            size: self.len(),
+            memory: self.memory,
+            latencies: counters.latencies.clone(),
+            waits: counters.waits.clone(),
+        }
+    }
+
+    /// Describe how a lookup by the field is answered,
+    /// given the number of values in the lookup and of structs having them, if it could be looked up.
+    fn plan(&self, field: &str, counts: Option<(usize, usize)>) -> QueryPlan {
+        let (kind, hashed) = match self.indexer.kind(field) {
+            Some((kind, hashed)) => (Some(kind), hashed),
+            None => (None, false),
+        };
+        let (buckets, candidates) = counts.unwrap_or((0, 0));
+        QueryPlan {
+            field: field.to_owned(),
+            indexed: is_selected(self.indexed_fields.as_ref(), field),
+            hashed,
+            kind,
+            buckets,
+            candidates,
+            total: self.len(),
+        }
+    }
+
+    /// Describe the structs of the entry and their indices.
+    pub fn info(&self, type_name: &'static str) -> TypeInfo {
+        let fields = self.indexer.fields();
+        let memory = self.memory;
+        TypeInfo {
+            type_name,
+            count: self.len(),
+            indexed_fields: fields.iter().map(|(name, &(kind, _))| (name.clone(), kind)).collect(),
+            hashed_fields: fields.into_iter().filter(|&(_, (_, hashed))| hashed).map(|(name, _)| name).collect(),
+            memory,
+        }
+    }
+
+    pub fn set_hash_index(&mut self, field: &str) {
+        self.indexer.set_hash_index(field);
+        if !self.hashed_fields.iter().any(|f| f == field) {
+            self.hashed_fields.push(field.to_owned());
+        }
+    }
+
+    /// Only index the selected fields, in addition to those selected before, and rebuild the indices.
+    pub fn set_indexed_fields(&mut self, fields: &[&str]) {
+        self.indexed_fields
+            .get_or_insert_with(HashSet::new)
+            .extend(fields.iter().map(|&field| field.to_owned()));
+        self.reindex();
+    }
+
+    /// Compare the strings of a field according to the collation, and rebuild the indices.
+    pub fn set_collation(&mut self, field: &str, collation: Collation) {
+        match collation {
+            Collation::Binary => self.collations.remove(field),
+            collation => self.collations.insert(field.to_owned(), collation),
+        };
+        self.reindex();
+    }
+
+    /// Return the key normalized by the collation of the field, or None if it is compared as is.
+    fn collate<K: Collate>(&self, field: &str, key: &K) -> Option<K> {
+        collate_key(&self.collations, field, key)
+    }
+
+    /// Return the indexer of the entry, after checking that the field is indexed.
+    fn indexer(&self, field: &str) -> &ValueIndexer {
+        if !is_selected(self.indexed_fields.as_ref(), field) {
+            panic!("{}", SpaceError::NotIndexed(field.to_owned()));
+        }
+        &self.indexer
+    }
+
+    /// Return why looking up the field by the keys would fail, e.g: because the field holds ints
+    /// and a key is a string. Range lookups also fail on hash-indexed fields.
+    pub fn check_lookup(&self, field: &str, keys: &[Field], range: bool) -> Result<(), SpaceError> {
+        if !is_selected(self.indexed_fields.as_ref(), field) {
+            return Err(SpaceError::NotIndexed(field.to_owned()));
+        }
+        if keys.iter().any(|key| matches!(*key, Field::Float(f) if f.is_nan())) {
+            return Err(SpaceError::NaN);
+        }
+        let (kind, hashed) = match self.indexer.kind(field) {
+            Some(found) => found,
+            // nothing could be known about the fields before the first write
+            None if self.schema.is_none() => return Ok(()),
+            // byte strings are never indexed, so no key could find them
+            None if self.schema.as_ref().and_then(|schema| schema.get(field)) == Some(FieldKind::Bytes) => {
+                (FieldKind::Bytes, false)
+            }
+            None => return Err(SpaceError::NoSuchField(field.to_owned())),
+        };
+        let mismatch = keys.iter().map(FieldKind::of).find(|&key| {
+            key != FieldKind::Unknown && kind != FieldKind::Unknown && key != kind
+        });
+        if let Some(key) = mismatch {
+            return Err(SpaceError::WrongType {
+                field: field.to_owned(),
+                kind,
+                key,
+            });
+        }
+        if range && hashed {
+            return Err(SpaceError::HashIndexed(field.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Return why looking up the field by the range would fail, see `check_lookup`.
+    pub fn check_range_lookup<U, R>(&self, field: &str, range: &R) -> Result<(), SpaceError>
+    where
+        U: Clone + Into<Field>,
+        R: RangeBounds<U>,
+    {
+        let keys: Vec<Field> = [range.start_bound(), range.end_bound()]
+            .iter()
+            .filter_map(|bound| match *bound {
+                Bound::Included(key) | Bound::Excluded(key) => Some(key.clone().into()),
+                Bound::Unbounded => None,
+            })
+            .collect();
+        self.check_lookup(field, &keys, true)
+    }
+
+    /// Return why looking up the structs satisfying the query would fail, see `check_lookup`.
+    pub fn check_query(&self, query: &Query) -> Result<(), SpaceError> {
+        match *query {
+            Query::Eq(ref field, ref value) => self.check_lookup(field, &[value.to_field()], false),
+            Query::Range(ref field, ref start, ref end) => {
+                let keys: Vec<Field> = [start, end]
+                    .iter()
+                    .filter_map(|bound| match **bound {
+                        Bound::Included(ref key) | Bound::Excluded(ref key) => Some(key.to_field()),
+                        Bound::Unbounded => None,
+                    })
+                    .collect();
+                self.check_lookup(field, &keys, true)
+            }
+            Query::And(ref queries) | Query::Or(ref queries) => {
+                queries.iter().try_for_each(|query| self.check_query(query))
+            }
+        }
+    }
+
+    /// Rebuild the indices whose keys depend on the indexed fields and their collations.
+    fn reindex(&mut self) {
+        self.reset_indexer();
+        for indexer in &mut self.compound_indices {
+            indexer.clear();
+        }
+        let values: Vec<_> = self.value_map.iter().map(|(index, value)| (*index, value.clone())).collect();
+        // the memory of a struct depends on the fields indexed
+        self.memory = 0;
+        for (index, value) in values {
+            self.memory += self.footprint(&value);
+            let collated = self.collated(&value);
+            self.add_to_indices(index, collated.as_ref().unwrap_or(&value));
+        }
+    }
+
+    /// Return a copy of the struct with its fields normalized by their collations,
+    /// or None if the entry has no collation.
+    fn collated(&self, obj: &Record) -> Option<Record> {
+        if self.collations.is_empty() {
+            None
+        } else {
+            Some(obj.collate(&self.collations))
+        }
+    }
+
+    /// Index a struct by its collated fields.
+    fn add_to_indices(&mut self, index: u64, obj: &Record) {
+        self.filters.add(obj, self.indexed_fields.as_ref());
+        for indexer in &mut self.compound_indices {
+            indexer.add(obj, index);
+        }
+        self.indexer.add(obj, index, self.indexed_fields.as_ref());
+    }
+
+    fn remove_from_indices(&mut self, index: u64, obj: &Record) {
+        self.filters.remove(obj, self.indexed_fields.as_ref());
+        for indexer in &mut self.compound_indices {
+            indexer.remove(obj, index);
+        }
+        self.indexer.remove(index, obj, self.indexed_fields.as_ref());
+    }
+
+    /// Discard the indexer and the filters, keeping the fields declared as hash-indexed.
+    fn reset_indexer(&mut self) {
+        self.indexer = ValueIndexer::new();
+        self.filters = FieldFilters::new();
+        for field in &self.hashed_fields {
+            self.indexer.set_hash_index(field);
+        }
+    }
+
+    /// Maintain an interval index over the pair of fields `(start, end)`.
+    pub fn set_interval_index(&mut self, fields: (&str, &str)) {
+        if self.intervals.iter().any(|indexer| indexer.is_on(fields)) {
+            return;
+        }
+
+        let mut indexer = IntervalIndexer::new(fields.0, fields.1);
+        for (index, value) in &self.value_map {
+            indexer.add(value, *index);
+        }
+        self.intervals.push(indexer);
+    }
+
+    /// Maintain a 2D index over the pair of numeric fields `(x, y)`.
+    pub fn set_spatial_index(&mut self, fields: (&str, &str)) {
+        if self.spatial_indices.iter().any(|indexer| indexer.is_on(fields)) {
+            return;
+        }
+
+        let mut indexer = SpatialIndexer::new(fields.0, fields.1);
+        for (index, value) in &self.value_map {
+            indexer.add(value, *index);
+        }
+        self.spatial_indices.push(indexer);
+    }
+
+    /// Maintain an index over the values of several fields at once.
+    pub fn set_compound_index(&mut self, fields: &[&str]) {
+        if self.compound_indices.iter().any(|indexer| indexer.is_on(fields)) {
+            return;
+        }
+
+        let mut indexer = CompoundIndexer::new(fields);
+        for (index, value) in &self.value_map {
+            let collated = self.collated(value);
+            indexer.add(collated.as_ref().unwrap_or(value), *index);
+        }
+        self.compound_indices.push(indexer);
+    }
+
+    /// Add a struct and return its index, or return why it does not fit the schema in strict mode.
+    pub fn add(&mut self, obj: Record) -> Result<u64, String> {
+        let deadline = self.ttl.map(|ttl| Instant::now() + ttl);
+        self.add_with_deadline(obj, deadline)
+    }
+
+    /// Add a struct with its own lease, overriding the lease of the entry.
+    pub fn add_with_ttl(&mut self, obj: Record, ttl: Duration) -> Result<u64, String> {
+        // a lease too long to be represented never expires
+        let deadline = Instant::now().checked_add(ttl);
+        self.add_with_deadline(obj, deadline)
+    }
+
+    fn add_with_deadline(&mut self, obj: Record, deadline: Option<Instant>) -> Result<u64, String> {
+        match self.admit(obj) {
+            Some(obj) => self.add_admitted(obj, deadline),
+            None => {
+                // the index is not reused, though the struct is not in the entry
+                self.counter += 1;
+                Ok(self.counter)
+            }
+        }
+    }
+
+    /// Return the struct to add in place of the one specified,
+    /// or None if it has been kept aside as a dead letter.
+    fn admit(&mut self, obj: Record) -> Option<Record> {
+        match self.decoder.admit(&obj) {
+            Admission::Keep => Some(obj),
+            Admission::Replace(filled) => Some(filled),
+            Admission::Reject => None,
+        }
     }
 
-    fn add_value_to_list(&mut self, arc: Arc<Value>) {
+    /// Return why the struct could not be added, if it does not fit the schema in strict mode,
+    /// or one of its fields holds another kind of value than the index of the field.
+    fn mismatch(&self, obj: &Record) -> Option<String> {
+        if let Some(ref schema) = self.schema {
+            if self.strict {
+                if let Some(mismatch) = schema.mismatch(obj) {
+                    return Some(mismatch);
+                }
+            }
+        }
+        let collated = self.collated(obj);
+        self.indexer
+            .conflict(collated.as_ref().unwrap_or(obj), self.indexed_fields.as_ref())
+    }
+
+    /// Add a struct admitted by the decoder, like `add_with_deadline`.
+    fn add_admitted(&mut self, obj: Record, deadline: Option<Instant>) -> Result<u64, String> {
+        // a duplicate is not added, and the handle of the struct equal to it is returned instead
+        if let Some(index) = self.find_equal(&obj) {
+            return Ok(index);
+        }
+        if let Some(mismatch) = self.mismatch(&obj) {
+            return Err(mismatch);
+        }
+        match self.schema {
+            Some(ref mut schema) => schema.refine(&obj),
+            None => self.schema = Some(Schema::new(&obj)),
+        }
+
         self.counter += 1;
-        self.value_map.entry(self.counter).or_insert(arc);
+        let index = self.counter;
+        let obj = Arc::new(obj);
+        self.subscriptions.retain(|subscription| subscription.notify(&obj));
+        self.insert(index, obj, deadline);
+        self.counters.writes += 1;
+        Ok(index)
+    }
+
+    /// Add a struct with the specified index to the indices, the change log and the journal,
+    /// and wake up the blocked calls waiting for it.
+    fn insert(&mut self, index: u64, obj: Arc<Record>, deadline: Option<Instant>) {
+        self.append_to_journal("write", &obj);
+        self.restore(index, obj, deadline);
+    }
+
+    /// Add a struct with the specified index like `insert`, but leave the journal alone,
+    /// e.g: for a struct checked in, which the journal never saw taken.
+    fn restore(&mut self, index: u64, obj: Arc<Record>, deadline: Option<Instant>) {
+        if let Some(deadline) = deadline {
+            self.expiries.add(index, deadline);
+        }
+        if let Some(ref mut recency) = self.recency {
+            recency.add(index, Instant::now());
+        }
+        for indexer in &mut self.intervals {
+            indexer.add(&obj, index);
+        }
+        for indexer in &mut self.spatial_indices {
+            indexer.add(&obj, index);
+        }
+        let collated = self.collated(&obj);
+        self.add_to_indices(index, collated.as_ref().unwrap_or(&obj));
+        if let Some(ref mut changes) = self.changes {
+            changes.add(index, &obj);
+        }
+        if let Some(ref mut payloads) = self.dedup {
+            payloads.add(&obj.payload(), index);
+        }
+        self.waiters.notify(collated.as_ref().unwrap_or(&obj));
+        self.memory += self.footprint(&obj);
+        let out_of_order = self.value_map.last().is_some_and(|(&last, _)| last > index);
+        self.value_map.insert(index, obj);
+        // a struct checked in goes back to its place among the structs added meanwhile
+        if out_of_order && self.policy != OrderingPolicy::Unordered {
+            self.value_map.sort_keys();
+        }
+    }
+
+    /// Remove the struct which comes first in the order of the policy,
+    /// and keep it aside until it is checked in or its lease ends. Return it with its index.
+    ///
+    /// The struct is only journaled as taken once it is committed,
+    /// so that a crash while it is checked out does not lose it.
+    pub fn check_out(&mut self, lease: Duration) -> Option<(u64, Arc<Record>)> {
+        let (index, value) = self.remove_first()?;
+        let deadline = self.expiries.deadline(index);
+        self.unindex(index, &value);
+        // a lease too long to be represented never ends
+        if let Some(end) = Instant::now().checked_add(lease) {
+            self.checkouts.add(index, end);
+        }
+        self.checked_out.insert(index, (value.clone(), deadline));
+        Some((index, value))
+    }
+
+    /// Add a struct checked out back with its index.
+    /// Return false if it is not checked out anymore, e.g: because its lease has ended.
+    pub fn check_in(&mut self, index: u64) -> bool {
+        match self.checked_out.remove(&index) {
+            Some((value, deadline)) => {
+                self.checkouts.remove(index);
+                self.restore(index, value, deadline);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop a struct checked out for good.
+    /// Return false if it is not checked out anymore, e.g: because its lease has ended.
+    pub fn commit(&mut self, index: u64) -> bool {
+        self.checkouts.remove(index);
+        match self.checked_out.remove(&index) {
+            Some((value, _)) => {
+                self.append_to_journal("take", &value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return the time the next lease of a struct checked out ends, if any.
+    pub fn next_checkout_end(&self) -> Option<Instant> {
+        self.checkouts.next_deadline()
     }
 
-    fn get_value_from_index(&self, index: &u64) -> Option<Value> {
-        self.value_map.get(index).map(|arc| {
-            let val: &Value = arc.borrow();
-            val.clone()
+    /// Return the struct added with the index, if it is still in the entry.
+    pub fn get_by_index(&self, index: u64) -> Option<Arc<Record>> {
+        self.get_value_from_index(&index)
+    }
+
+    pub fn remove_by_index(&mut self, index: u64) -> Option<Arc<Record>> {
+        let val = self.remove_value_from_index(&index);
+        val.clone().map(|val| self.remove_from_index(index, &val));
+        val
+    }
+
+    pub fn get(&self) -> Option<Arc<Record>> {
+        self.first().map(|(&index, value)| {
+            self.touch(index);
+            value.clone()
         })
     }
 
-    fn remove_value_from_index(&mut self, index: &u64) -> Option<Value> {
-        self.value_map.remove(index).map(|arc| {
-            let val: &Value = arc.borrow();
-            val.clone()
+    /// Return all structs, in the order of the policy.
+    pub fn get_all<'a>(&'a self) -> Box<Iterator<Item = Arc<Record>> + 'a> {
+        match self.policy {
+            OrderingPolicy::Lifo => Box::new(self.value_map.values().rev().cloned()),
+            _ => Box::new(self.value_map.values().cloned()),
+        }
+    }
+
+    /// Return the structs at this point in time, in the order of the policy, sharing them with the entry.
+    pub fn snapshot<T>(&self) -> Snapshot<T> {
+        Snapshot::new(self.get_all().collect(), self.collations.clone())
+    }
+
+    /// Return a copy of the entry holding the same structs under the same handles, shared rather than copied,
+    /// with the same indexed fields, lease, capacity, quota, eviction and schema, but without the structs checked out,
+    /// nor the decoder, change log, journal, subscriptions, blocked calls and metrics of the entry.
+    pub fn fork(&self) -> Entry {
+        let mut fork = Entry::new();
+        fork.counter = self.counter;
+        fork.policy = self.policy;
+        fork.hashed_fields = self.hashed_fields.clone();
+        fork.indexed_fields = self.indexed_fields.clone();
+        fork.collations = self.collations.clone();
+        fork.reset_indexer();
+        fork.intervals = self.intervals
+            .iter()
+            .map(|indexer| IntervalIndexer::new(indexer.fields().0, indexer.fields().1))
+            .collect();
+        fork.spatial_indices = self.spatial_indices
+            .iter()
+            .map(|indexer| SpatialIndexer::new(indexer.fields().0, indexer.fields().1))
+            .collect();
+        fork.compound_indices = self.compound_indices
+            .iter()
+            .map(|indexer| {
+                let fields: Vec<_> = indexer.fields().iter().map(String::as_str).collect();
+                CompoundIndexer::new(&fields)
+            })
+            .collect();
+        fork.ttl = self.ttl;
+        fork.schema = self.schema.clone();
+        fork.strict = self.strict;
+        if self.dedup.is_some() {
+            fork.dedup = Some(PayloadIndex::default());
+        }
+        fork.capacity = self.capacity
+            .as_ref()
+            .map(|capacity| Capacity::new(capacity.limit(), capacity.overflow()));
+        fork.quota = self.quota
+            .as_ref()
+            .map(|quota| Capacity::new(quota.limit(), quota.overflow()));
+        fork.set_eviction(self.eviction);
+        for (&index, value) in &self.value_map {
+            fork.insert(index, value.clone(), self.expiries.deadline(index));
+        }
+        fork
+    }
+
+    /// Return the struct which comes first in the order of the policy.
+    fn first(&self) -> Option<(&u64, &Arc<Record>)> {
+        match self.policy {
+            OrderingPolicy::Lifo => self.value_map.last(),
+            _ => self.value_map.first(),
+        }
+    }
+
+    /// Return the first struct deserialized by `f`.
+    /// The struct is only deserialized the first time it is requested.
+    pub fn get_arc<T, F>(&self, f: F) -> Option<Arc<T>>
+    where
+        T: Any + Send + Sync,
+        F: Fn(&Record) -> Option<T>,
+    {
+        let (index, value) = self.first()?;
+        self.touch(*index);
+        self.cache.get_or_insert_with(*index, || f(value))
+    }
+
+    /// Return all structs deserialized by `f`.
+    /// Each struct is only deserialized the first time it is requested.
+    pub fn get_all_arc<T, F>(&self, f: F) -> Vec<Arc<T>>
+    where
+        T: Any + Send + Sync,
+        F: Fn(&Record) -> Option<T>,
+    {
+        let mut result: Vec<_> = self.value_map
+            .iter()
+            .filter_map(|(index, value)| self.cache.get_or_insert_with(*index, || f(value)))
+            .collect();
+        if self.policy == OrderingPolicy::Lifo {
+            result.reverse();
+        }
+        result
+    }
+
+    /// Return copies of `count` structs chosen uniformly at random.
+    pub fn get_sample(&self, count: usize) -> Vec<Arc<Record>> {
+        sample_indices(self.value_map.len(), count)
+            .into_iter()
+            .filter_map(|i| {
+                self.value_map.get_index(i).map(|(&index, arc)| {
+                    self.touch(index);
+                    arc.clone()
+                })
+            })
+            .collect()
+    }
+
+    pub fn remove(&mut self) -> Option<Arc<Record>> {
+        self.remove_first().map(|(key, value)| {
+            self.remove_from_index(key, &value);
+            value
+        })
+    }
+
+    /// Remove the struct which comes first in the order of the policy from the map, but not from the indices.
+    fn remove_first(&mut self) -> Option<(u64, Arc<Record>)> {
+        match self.policy {
+            OrderingPolicy::Fifo => self.value_map.shift_remove_index(0),
+            // the last struct is the cheapest to remove
+            OrderingPolicy::Lifo | OrderingPolicy::Unordered => self.value_map.pop(),
+        }
+    }
+
+    /// Remove all structs, except those checked out, which are returned when their `Checkout` is dropped.
+    pub fn remove_all(&mut self) -> Vec<Arc<Record>> {
+        if let Some(ref mut changes) = self.changes {
+            for (index, value) in &self.value_map {
+                changes.remove(*index, value);
+            }
+        }
+        for value in self.value_map.values() {
+            self.append_to_journal("take", value);
+        }
+        self.counters.removed += self.value_map.len() as u64;
+        let result = self.get_all().collect();
+        self.clear();
+        self.notify_room();
+        result
+    }
+
+    /// Remove one struct for each of the specified payloads, and return the number of structs removed.
+    ///
+    /// Structs with the same payload could not be told apart, so any of them is removed.
+    pub fn remove_by_payloads(&mut self, payloads: &[Vec<u8>]) -> usize {
+        let mut indices: HashMap<Cow<[u8]>, Vec<u64>> = HashMap::new();
+        for (index, value) in &self.value_map {
+            indices.entry(value.payload()).or_default().push(*index);
+        }
+        let removed: Vec<u64> = payloads
+            .iter()
+            .filter_map(|payload| indices.get_mut(&payload[..]).and_then(|found| found.pop()))
+            .collect();
+
+        for i in &removed {
+            if let Some(val) = self.remove_value_from_index(i) {
+                self.remove_from_index(*i, &val);
+            }
+        }
+        removed.len()
+    }
+
+    /// Return true if at least one struct has expired, or one lease of a struct checked out has ended,
+    /// at the specified time.
+    pub fn has_expired(&self, now: Instant) -> bool {
+        let aged = match (self.age_cutoff(now), self.recency.as_ref()) {
+            (Some(cutoff), Some(recency)) => recency.written_before(cutoff),
+            _ => false,
+        };
+        self.expiries.has_expired(now) || self.checkouts.has_expired(now) || aged
+    }
+
+    /// Check in all structs whose lease has ended, and remove all structs expired at the specified time,
+    /// as well as those older than the eviction policy allows.
+    /// Return the number of structs removed.
+    pub fn remove_expired(&mut self, now: Instant) -> usize {
+        for i in self.checkouts.pop_expired(now) {
+            if let Some((value, deadline)) = self.checked_out.remove(&i) {
+                self.restore(i, value, deadline);
+            }
+        }
+        let mut count = 0;
+        for i in self.expiries.pop_expired(now) {
+            if let Some(val) = self.remove_value_from_index(&i) {
+                self.remove_from_index(i, &val);
+                self.counters.expired += 1;
+                count += 1;
+            }
+        }
+        let aged = match (self.age_cutoff(now), self.recency.as_mut()) {
+            (Some(cutoff), Some(recency)) => recency.pop_written_before(cutoff),
+            _ => Vec::new(),
+        };
+        for i in aged {
+            if let Some(val) = self.remove_value_from_index(&i) {
+                self.remove_from_index(i, &val);
+                self.counters.evicted += 1;
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Return the time at or before which structs were written too long ago to be kept,
+    /// if the eviction policy limits their age.
+    fn age_cutoff(&self, now: Instant) -> Option<Instant> {
+        match self.eviction {
+            Eviction::OlderThan(age) => now.checked_sub(age),
+            _ => None,
+        }
+    }
+
+    /// Remove all structs while keeping the settings of the entry and the structs checked out.
+    fn clear(&mut self) {
+        self.value_map.clear();
+        self.memory = 0;
+        if self.recency.is_some() {
+            self.recency = Some(Recency::new());
+        }
+        self.expiries.clear();
+        self.cache.clear();
+        if let Some(ref mut payloads) = self.dedup {
+            payloads.clear();
+        }
+        self.reset_indexer();
+        for indexer in &mut self.intervals {
+            indexer.clear();
+        }
+        for indexer in &mut self.spatial_indices {
+            indexer.clear();
+        }
+        for indexer in &mut self.compound_indices {
+            indexer.clear();
+        }
+    }
+
+    /// Release the memory held by the entry beyond what its structs need, e.g: after many structs have been taken,
+    /// and return an estimate of it in bytes.
+    pub fn compact(&mut self) -> usize {
+        let capacity = self.value_map.capacity();
+        self.value_map.shrink_to_fit();
+        // a struct takes its index, its hash, the pointer to its record, and a slot of the hash table
+        let mut reclaimed =
+            capacity.saturating_sub(self.value_map.capacity()) * mem::size_of::<(u64, u64, Arc<Record>, usize)>();
+        reclaimed += shrink_map(&mut self.checked_out);
+        reclaimed += self.indexer.compact();
+        for indexer in &mut self.compound_indices {
+            reclaimed += indexer.compact();
+        }
+        reclaimed
+    }
+
+    /// Return false if no struct could possibly have the specified value for the field.
+    fn may_contain(&self, field: &str, key: &Field) -> bool {
+        self.filters.may_contain(field, key)
+    }
+
+    fn remove_from_index(&mut self, index: u64, val: &Arc<Record>) {
+        self.append_to_journal("take", val);
+        self.unindex(index, val);
+    }
+
+    /// Remove a struct with the specified index like `remove_from_index`, but leave the journal alone.
+    fn unindex(&mut self, index: u64, val: &Arc<Record>) {
+        if let Some(ref mut changes) = self.changes {
+            changes.remove(index, val);
+        }
+        self.counters.removed += 1;
+        self.memory = self.memory.saturating_sub(self.footprint(val));
+        self.expiries.remove(index);
+        if let Some(ref mut recency) = self.recency {
+            recency.remove(index);
+        }
+        self.cache.remove(index);
+        if let Some(ref mut payloads) = self.dedup {
+            payloads.remove(&val.payload(), index);
+        }
+        for indexer in &mut self.intervals {
+            indexer.remove(val, index);
+        }
+        for indexer in &mut self.spatial_indices {
+            indexer.remove(val, index);
+        }
+        let collated = self.collated(val);
+        self.remove_from_indices(index, collated.as_ref().unwrap_or(val));
+        self.notify_room();
+    }
+
+    fn get_value_from_index(&self, index: &u64) -> Option<Arc<Record>> {
+        let value = self.value_map.get(index).cloned();
+        if value.is_some() {
+            self.touch(*index);
+        }
+        value
+    }
+
+    /// Record that a struct has been read, if the eviction policy needs it.
+    fn touch(&self, index: u64) {
+        if let Some(ref recency) = self.recency {
+            recency.touch(index);
+        }
+    }
+
+    fn remove_value_from_index(&mut self, index: &u64) -> Option<Arc<Record>> {
+        match self.policy {
+            OrderingPolicy::Unordered => self.value_map.swap_remove(index),
+            // keep the other structs in the order they were added in
+            OrderingPolicy::Fifo | OrderingPolicy::Lifo => self.value_map.shift_remove(index),
+        }
+    }
+}
+
+impl Entry {
+    pub fn get_by_query(&self, query: &Query) -> Option<Arc<Record>> {
+        let index = self.get_indices_by_query(query).into_iter().next();
+        index.and_then(|i| self.get_value_from_index(&i))
+    }
+
+    pub fn get_all_by_query(&self, query: &Query) -> Vec<Arc<Record>> {
+        self.get_indices_by_query(query)
+            .into_iter()
+            .filter_map(|i| self.get_value_from_index(&i))
+            .collect()
+    }
+
+    pub fn remove_by_query(&mut self, query: &Query) -> Option<Arc<Record>> {
+        let index = self.get_indices_by_query(query).into_iter().next();
+        index.and_then(|i| {
+            let val = self.remove_value_from_index(&i);
+            val.clone().map(|val| self.remove_from_index(i, &val));
+            val
         })
     }
+
+    pub fn remove_all_by_query(&mut self, query: &Query) -> Vec<Arc<Record>> {
+        let mut result = Vec::new();
+        for i in self.get_indices_by_query(query) {
+            if let Some(val) = self.remove_value_from_index(&i) {
+                self.remove_from_index(i, &val);
+                result.push(val);
+            }
+        }
+        result
+    }
+
+    /// Return the indices of all structs satisfying the query, oldest first and without duplicates.
+    fn get_indices_by_query(&self, query: &Query) -> BTreeSet<u64> {
+        match *query {
+            Query::Eq(ref field, ref value) => {
+                let collated = self.collate(field, value);
+                let value = collated.as_ref().unwrap_or(value);
+                if !self.may_contain(field, &value.to_field()) {
+                    return BTreeSet::new();
+                }
+                self.indexer(field).get_all_indices_by_value(field, value).collect()
+            }
+            Query::Range(ref field, ref start, ref end) => match TypedBounds::new(start, end) {
+                TypedBounds::Int(range) => self.indexer(field).get_all_indices_by_range(field, range).collect(),
+                TypedBounds::Float(range) => self.indexer(field).get_all_indices_by_range(field, range).collect(),
+                TypedBounds::Str(range) => {
+                    let range = collate_range(&self.collations, field, &range);
+                    self.indexer(field).get_all_indices_by_range(field, range).collect()
+                }
+            },
+            Query::Or(ref queries) => queries
+                .iter()
+                .flat_map(|query| self.get_indices_by_query(query))
+                .collect(),
+            Query::And(ref queries) => {
+                let (mut result, rest) = match self.get_indices_by_compound_index(queries) {
+                    Some(found) => found,
+                    None => {
+                        let mut queries = queries.iter();
+                        match queries.next() {
+                            Some(query) => (self.get_indices_by_query(query), queries.collect()),
+                            None => return self.value_map.keys().cloned().collect(),
+                        }
+                    }
+                };
+                for query in rest {
+                    if result.is_empty() {
+                        break;
+                    }
+                    let indices = self.get_indices_by_query(query);
+                    result.retain(|i| indices.contains(i));
+                }
+                result
+            }
+        }
+    }
+
+    /// Look up the conditions of a conjunction on the fields of a compound index, if they are all compared by equality.
+    /// Return the indices found, and the conditions left to check.
+    fn get_indices_by_compound_index<'q>(
+        &self,
+        queries: &'q [Query],
+    ) -> Option<(BTreeSet<u64>, Vec<&'q Query>)> {
+        for indexer in &self.compound_indices {
+            let mut used = Vec::new();
+            let mut values = Vec::new();
+            for field in indexer.fields() {
+                let found = queries.iter().position(|query| match *query {
+                    Query::Eq(ref name, _) => name == field,
+                    _ => false,
+                });
+                match found {
+                    Some(position) => {
+                        if let Query::Eq(_, ref value) = queries[position] {
+                            let collated = self.collate(field, value);
+                            values.push(collated.as_ref().unwrap_or(value).to_field());
+                        }
+                        used.push(position);
+                    }
+                    None => break,
+                }
+            }
+            if used.len() == indexer.fields().len() {
+                let rest = queries
+                    .iter()
+                    .enumerate()
+                    .filter(|&(position, _)| !used.contains(&position))
+                    .map(|(_, query)| query)
+                    .collect();
+                return Some((indexer.get_all_indices(&values).collect(), rest));
+            }
+        }
+        None
+    }
 }
 
 pub trait ValueLookupEntry<U> {
-    fn get_by_value(&self, field: &str, key: &U) -> Option<Value>;
+    fn get_by_value(&self, field: &str, key: &U) -> Option<Arc<Record>>;
+
+    fn get_all_by_value<'a>(&'a self, field: &str, key: &U) -> Box<Iterator<Item = Arc<Record>> + 'a>;
 
-    fn get_all_by_value<'a>(&'a self, field: &str, key: &U) -> Box<Iterator<Item = Value> + 'a>;
+    fn remove_by_value(&mut self, field: &str, key: &U) -> Option<Arc<Record>>;
 
-    fn remove_by_value(&mut self, field: &str, key: &U) -> Option<Value>;
+    fn remove_all_by_value(&mut self, field: &str, key: &U) -> Vec<Arc<Record>>;
 
-    fn remove_all_by_value(&mut self, field: &str, key: &U) -> Vec<Value>;
+    /// Replace a struct whose field has the specified value by the result of `f`,
+    /// which keeps the lease of the struct.
+    /// Return None if no struct matches, or why the result does not fit the schema in strict mode.
+    /// Return false and keep the struct if the result could not be added otherwise,
+    /// e.g: because it would exceed the quota of the entry, or has been kept aside as a dead letter.
+    fn update_by_value<F>(&mut self, field: &str, key: &U, f: F) -> Option<Result<bool, String>>
+    where
+        F: FnOnce(&Record) -> Record;
 }
 
 macro_rules! impl_value_lookup_entry {
     ($($ty:ty)*) => {
         $(            
             impl ValueLookupEntry<$ty> for Entry {
-                fn get_by_value(&self, field: &str, key: &$ty) -> Option<Value> {
-                    let index = self.indexer.get_index_by_value(field, key);
+                fn get_by_value(&self, field: &str, key: &$ty) -> Option<Arc<Record>> {
+                    let collated = self.collate(field, key);
+                    let key = collated.as_ref().unwrap_or(key);
+                    if !self.may_contain(field, &Field::from(key.clone())) {
+                        return None;
+                    }
+                    let index = self.indexer(field).get_index_by_value(field, key);
                     index.and_then(|i| self.get_value_from_index(&i))
                 }
 
-                fn get_all_by_value<'a>(&'a self, field: &str, key: &$ty) -> Box<Iterator<Item = Value> + 'a> {
-                    let indices = self.indexer.get_all_indices_by_value(field, key);
+                fn get_all_by_value<'a>(&'a self, field: &str, key: &$ty) -> Box<Iterator<Item = Arc<Record>> + 'a> {
+                    let collated = self.collate(field, key);
+                    let key = collated.as_ref().unwrap_or(key);
+                    if !self.may_contain(field, &Field::from(key.clone())) {
+                        return Box::new(empty());
+                    }
+                    let indices = self.indexer(field).get_all_indices_by_value(field, key);
                     Box::new(
                         indices.filter_map(move |i| self.get_value_from_index(&i))
                     )
                 }
 
-                fn remove_by_value(&mut self, field: &str, key: &$ty) -> Option<Value> {
-                    let index = self.indexer.get_index_by_value(field, key);
+                fn remove_by_value(&mut self, field: &str, key: &$ty) -> Option<Arc<Record>> {
+                    let collated = self.collate(field, key);
+                    let key = collated.as_ref().unwrap_or(key);
+                    if !self.may_contain(field, &Field::from(key.clone())) {
+                        return None;
+                    }
+                    let index = self.indexer(field).get_index_by_value(field, key);
                     index.and_then(|i| {
                         let val = self.remove_value_from_index(&i);
-                        val.clone().map(|val| self.indexer.remove(i, &val));
+                        val.clone().map(|val| self.remove_from_index(i, &val));
                         val
                     })
                 }
 
-                fn remove_all_by_value(&mut self, field: &str, key: &$ty) -> Vec<Value> {
-                    let indices: Vec<u64> = self.indexer.get_all_indices_by_value(field, key).collect();
+                fn remove_all_by_value(&mut self, field: &str, key: &$ty) -> Vec<Arc<Record>> {
+                    let collated = self.collate(field, key);
+                    let key = collated.as_ref().unwrap_or(key);
+                    if !self.may_contain(field, &Field::from(key.clone())) {
+                        return Vec::new();
+                    }
+                    let indices: Vec<u64> = self.indexer(field).get_all_indices_by_value(field, key).collect();
                     let mut result = Vec::new();
                     for i in indices {
                         if let Some(val) = self.remove_value_from_index(&i) {
-                            self.indexer.remove(i, &val.clone());
+                            self.remove_from_index(i, &val);
                             result.push(val);
                         }
                     }
                     result
                 }
+
+                fn update_by_value<F>(&mut self, field: &str, key: &$ty, f: F) -> Option<Result<bool, String>>
+                where
+                    F: FnOnce(&Record) -> Record,
+                {
+                    let collated = self.collate(field, key);
+                    let key = collated.as_ref().unwrap_or(key);
+                    if !self.may_contain(field, &Field::from(key.clone())) {
+                        return None;
+                    }
+                    let index = self.indexer(field).get_index_by_value(field, key)?;
+                    let old = self.get_value_from_index(&index)?;
+                    let new = match self.admit(f(&*old)) {
+                        Some(new) => new,
+                        None => return Some(Ok(false)),
+                    };
+                    // a result equal to the struct is deduplicated with it, which must then be kept
+                    if self.find_equal(&new) == Some(index) {
+                        return Some(Ok(true));
+                    }
+                    if let Some(mismatch) = self.mismatch(&new) {
+                        return Some(Err(mismatch));
+                    }
+                    let deadline = self.expiries.deadline(index);
+                    self.remove_value_from_index(&index);
+                    self.unindex(index, &old);
+                    // the result is bound by the capacity and quota of the entry, as a struct written is
+                    if !self.make_room(&new, true) {
+                        self.counters.removed -= 1;
+                        self.restore(index, old, deadline);
+                        return Some(Ok(false));
+                    }
+                    self.append_to_journal("take", &old);
+                    Some(self.add_admitted(new, deadline).map(|_| true))
+                }
             }
         )*
     };
 }
 
-impl_value_lookup_entry!{i64 String bool f64}
+impl_value_lookup_entry!{i64 u64 i128 String bool f64 FieldValue}
+#[cfg(feature = "uuid")]
+impl_value_lookup_entry!{Uuid}
 
 pub trait RangeLookupEntry<U> {
-    fn get_by_range<R>(&self, field: &str, range: R) -> Option<Value>
+    fn get_by_range<R>(&self, field: &str, range: R) -> Option<Arc<Record>>
+    where
+        R: RangeBounds<U>;
+
+    fn get_all_by_range<'a, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = Arc<Record>> + 'a>
     where
         R: RangeBounds<U>;
 
-    fn get_all_by_range<'a, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = Value> + 'a>
+    fn remove_by_range<R>(&mut self, field: &str, range: R) -> Option<Arc<Record>>
     where
         R: RangeBounds<U>;
 
-    fn remove_by_range<R>(&mut self, field: &str, range: R) -> Option<Value>
+    fn remove_all_by_range<'a, R>(&'a mut self, field: &str, range: R) -> Vec<Arc<Record>>
     where
         R: RangeBounds<U>;
 
-    fn remove_all_by_range<'a, R>(&'a mut self, field: &str, range: R) -> Vec<Value>
+    fn explain_range<R>(&self, field: &str, range: R) -> QueryPlan
     where
         R: RangeBounds<U>;
 }
@@ -152,49 +1519,463 @@ macro_rules! impl_range_lookup_entry {
     ($($ty:ty)*) => {
         $(            
             impl RangeLookupEntry<$ty> for Entry {
-                fn get_by_range<R>(&self, field: &str, range: R) -> Option<Value> 
+                fn get_by_range<R>(&self, field: &str, range: R) -> Option<Arc<Record>> 
                 where R: RangeBounds<$ty>
                 {
-                    let index = self.indexer.get_index_by_range(field, range);
+                    let range = collate_range(&self.collations, field, &range);
+                    let index = self.indexer(field).get_index_by_range(field, range);
                     index.and_then(|i| self.get_value_from_index(&i))
                 }
 
-                fn get_all_by_range<'a, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = Value> + 'a> 
+                fn get_all_by_range<'a, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = Arc<Record>> + 'a> 
                 where R: RangeBounds<$ty>
                 {
-                    let indices = self.indexer.get_all_indices_by_range(field, range);
+                    let range = collate_range(&self.collations, field, &range);
+                    let indices = self.indexer(field).get_all_indices_by_range(field, range);
                     Box::new(
                         indices.filter_map(move |i| self.get_value_from_index(&i))
                     )
                 }
 
-                fn remove_by_range<R>(&mut self, field: &str, range: R) -> Option<Value> 
+                fn remove_by_range<R>(&mut self, field: &str, range: R) -> Option<Arc<Record>> 
                 where R: RangeBounds<$ty>
                 {
-                    let index = self.indexer.get_index_by_range(field, range);
+                    let range = collate_range(&self.collations, field, &range);
+                    let index = self.indexer(field).get_index_by_range(field, range);
                     index.and_then(|i| {
                         let val = self.remove_value_from_index(&i);
-                        val.clone().map(|val| self.indexer.remove(i, &val));
+                        val.clone().map(|val| self.remove_from_index(i, &val));
                         val
                     })
                 }
 
-                fn remove_all_by_range<R>(&mut self, field: &str, range: R) -> Vec<Value> 
+                fn remove_all_by_range<R>(&mut self, field: &str, range: R) -> Vec<Arc<Record>> 
                 where R: RangeBounds<$ty>
                 {
-                    let indices: Vec<u64> = self.indexer.get_all_indices_by_range(field, range).collect();
+                    let range = collate_range(&self.collations, field, &range);
+                    let indices: Vec<u64> = self.indexer(field).get_all_indices_by_range(field, range).collect();
                     let mut result = Vec::new();
                     for i in indices {
                         if let Some(val) = self.remove_value_from_index(&i) {
-                            self.indexer.remove(i, &val.clone());
+                            self.remove_from_index(i, &val);
                             result.push(val);
                         }
                     }
                     result
                 }
+
+                fn explain_range<R>(&self, field: &str, range: R) -> QueryPlan
+                where R: RangeBounds<$ty>
+                {
+                    let range = collate_range(&self.collations, field, &range);
+                    let counts = if is_selected(self.indexed_fields.as_ref(), field) {
+                        self.indexer.count_by_range(field, range)
+                    } else {
+                        None
+                    };
+                    self.plan(field, counts)
+                }
             }
         )*
     };
 }
 
-impl_range_lookup_entry!{i64 String f64}
+impl_range_lookup_entry!{i64 u64 i128 String f64}
+#[cfg(feature = "chrono")]
+impl_range_lookup_entry!{DateTime<Utc>}
+
+pub trait NearestLookupEntry<U> {
+    fn get_nearest(&self, field: &str, target: &U) -> Option<Arc<Record>>;
+
+    fn remove_nearest(&mut self, field: &str, target: &U) -> Option<Arc<Record>>;
+}
+
+macro_rules! impl_nearest_lookup_entry {
+    ($($ty:ty)*) => {
+        $(
+            impl NearestLookupEntry<$ty> for Entry {
+                fn get_nearest(&self, field: &str, target: &$ty) -> Option<Arc<Record>> {
+                    let index = self.indexer(field).get_index_nearest(field, target);
+                    index.and_then(|i| self.get_value_from_index(&i))
+                }
+
+                fn remove_nearest(&mut self, field: &str, target: &$ty) -> Option<Arc<Record>> {
+                    let index = self.indexer(field).get_index_nearest(field, target);
+                    index.and_then(|i| {
+                        let val = self.remove_value_from_index(&i);
+                        val.clone().map(|val| self.remove_from_index(i, &val));
+                        val
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_nearest_lookup_entry!{i64 f64}
+
+pub trait IntervalLookupEntry<U> {
+    fn get_by_interval(&self, fields: (&str, &str), point: &U) -> Option<Arc<Record>>;
+
+    fn get_all_by_interval<'a>(
+        &'a self,
+        fields: (&str, &str),
+        point: &U,
+    ) -> Box<Iterator<Item = Arc<Record>> + 'a>;
+
+    fn remove_by_interval(&mut self, fields: (&str, &str), point: &U) -> Option<Arc<Record>>;
+
+    fn remove_all_by_interval(&mut self, fields: (&str, &str), point: &U) -> Vec<Arc<Record>>;
+}
+
+impl Entry {
+    fn get_index_covering<K>(&self, fields: (&str, &str), point: K) -> Option<u64>
+    where
+        K: IntervalKey,
+    {
+        match self.intervals.iter().find(|indexer| indexer.is_on(fields)) {
+            Some(indexer) => K::tree(indexer).and_then(|tree| tree.get_index_covering(point)),
+            None => self
+                .value_map
+                .iter()
+                .find(|&(_, value)| covers(value, fields, point))
+                .map(|(index, _)| *index),
+        }
+    }
+
+    fn get_all_indices_covering<K>(&self, fields: (&str, &str), point: K) -> Vec<u64>
+    where
+        K: IntervalKey,
+    {
+        match self.intervals.iter().find(|indexer| indexer.is_on(fields)) {
+            Some(indexer) => K::tree(indexer)
+                .map_or(Vec::new(), |tree| tree.get_all_indices_covering(point)),
+            None => self
+                .value_map
+                .iter()
+                .filter(|&(_, value)| covers(value, fields, point))
+                .map(|(index, _)| *index)
+                .collect(),
+        }
+    }
+}
+
+fn covers<K>(value: &Record, fields: (&str, &str), point: K) -> bool
+where
+    K: IntervalKey,
+{
+    read_interval::<K>(value, fields).is_some_and(|(start, end)| start <= point && point < end)
+}
+
+impl<U: ToIntervalKey> IntervalLookupEntry<U> for Entry {
+    fn get_by_interval(&self, fields: (&str, &str), point: &U) -> Option<Arc<Record>> {
+        let index = self.get_index_covering(fields, point.to_key());
+        index.and_then(|i| self.get_value_from_index(&i))
+    }
+
+    fn get_all_by_interval<'a>(
+        &'a self,
+        fields: (&str, &str),
+        point: &U,
+    ) -> Box<Iterator<Item = Arc<Record>> + 'a> {
+        let indices = self.get_all_indices_covering(fields, point.to_key());
+        Box::new(
+            indices
+                .into_iter()
+                .filter_map(move |i| self.get_value_from_index(&i)),
+        )
+    }
+
+    fn remove_by_interval(&mut self, fields: (&str, &str), point: &U) -> Option<Arc<Record>> {
+        let index = self.get_index_covering(fields, point.to_key());
+        index.and_then(|i| {
+            let val = self.remove_value_from_index(&i);
+            val.clone().map(|val| self.remove_from_index(i, &val));
+            val
+        })
+    }
+
+    fn remove_all_by_interval(&mut self, fields: (&str, &str), point: &U) -> Vec<Arc<Record>> {
+        let indices = self.get_all_indices_covering(fields, point.to_key());
+        let mut result = Vec::new();
+        for i in indices {
+            if let Some(val) = self.remove_value_from_index(&i) {
+                self.remove_from_index(i, &val);
+                result.push(val);
+            }
+        }
+        result
+    }
+}
+
+impl Entry {
+    fn get_all_indices_in_rect<R>(&self, fields: (&str, &str), bounds: &(R, R)) -> Vec<u64>
+    where
+        R: RangeBounds<f64>,
+    {
+        match self
+            .spatial_indices
+            .iter()
+            .find(|indexer| indexer.is_on(fields))
+        {
+            Some(indexer) => indexer.get_all_indices_in_rect(bounds),
+            None => self
+                .value_map
+                .iter()
+                .filter(|&(_, value)| {
+                    read_point(value, fields).is_some_and(|point| in_rect(point, bounds))
+                })
+                .map(|(index, _)| *index)
+                .collect(),
+        }
+    }
+
+    pub fn get_by_rect<R>(&self, fields: (&str, &str), bounds: &(R, R)) -> Option<Arc<Record>>
+    where
+        R: RangeBounds<f64>,
+    {
+        let indices = self.get_all_indices_in_rect(fields, bounds);
+        indices
+            .first()
+            .and_then(|i| self.get_value_from_index(i))
+    }
+
+    pub fn get_all_by_rect<'a, R>(
+        &'a self,
+        fields: (&str, &str),
+        bounds: &(R, R),
+    ) -> Box<Iterator<Item = Arc<Record>> + 'a>
+    where
+        R: RangeBounds<f64>,
+    {
+        let indices = self.get_all_indices_in_rect(fields, bounds);
+        Box::new(
+            indices
+                .into_iter()
+                .filter_map(move |i| self.get_value_from_index(&i)),
+        )
+    }
+
+    pub fn remove_by_rect<R>(&mut self, fields: (&str, &str), bounds: &(R, R)) -> Option<Arc<Record>>
+    where
+        R: RangeBounds<f64>,
+    {
+        let indices = self.get_all_indices_in_rect(fields, bounds);
+        indices.first().cloned().and_then(|i| {
+            let val = self.remove_value_from_index(&i);
+            val.clone().map(|val| self.remove_from_index(i, &val));
+            val
+        })
+    }
+
+    pub fn remove_all_by_rect<R>(&mut self, fields: (&str, &str), bounds: &(R, R)) -> Vec<Arc<Record>>
+    where
+        R: RangeBounds<f64>,
+    {
+        let indices = self.get_all_indices_in_rect(fields, bounds);
+        let mut result = Vec::new();
+        for i in indices {
+            if let Some(val) = self.remove_value_from_index(&i) {
+                self.remove_from_index(i, &val);
+                result.push(val);
+            }
+        }
+        result
+    }
+}
+
+impl Entry {
+    /// Return the struct with the first value of the field in the order.
+    pub fn get_ordered(&self, field: &str, order: Order) -> Option<Arc<Record>> {
+        let index = self.indexer(field).get_all_indices_ordered(field, order).next();
+        index.and_then(|i| self.get_value_from_index(&i))
+    }
+
+    /// Return the structs having the field, by value of the field in the order.
+    pub fn get_all_ordered<'a>(&'a self, field: &str, order: Order) -> Box<Iterator<Item = Arc<Record>> + 'a> {
+        let indices = self.indexer(field).get_all_indices_ordered(field, order);
+        Box::new(indices.filter_map(move |i| self.get_value_from_index(&i)))
+    }
+
+    pub fn remove_ordered(&mut self, field: &str, order: Order) -> Option<Arc<Record>> {
+        let index = self.indexer(field).get_all_indices_ordered(field, order).next();
+        index.and_then(|i| {
+            let val = self.remove_value_from_index(&i);
+            val.clone().map(|val| self.remove_from_index(i, &val));
+            val
+        })
+    }
+}
+
+impl Entry {
+    /// Return the indices of the structs whose field matches the pattern, in the order of the field.
+    fn get_indices_by_pattern<'a>(
+        &'a self,
+        field: &'a str,
+        pattern: &Pattern,
+    ) -> Box<Iterator<Item = u64> + 'a> {
+        let collation = self.collations.get(field).cloned().unwrap_or_default();
+        let pattern = pattern.collate(collation);
+        let indices = self.indexer(field).get_all_indices_by_range(field, pattern.range());
+        if pattern.is_prefix() {
+            return indices;
+        }
+        Box::new(indices.filter(move |i| {
+            self.value_map
+                .get(i)
+                .is_some_and(|value| matches_pattern(value, field, &pattern, collation))
+        }))
+    }
+
+    pub fn get_by_pattern(&self, field: &str, pattern: &Pattern) -> Option<Arc<Record>> {
+        let index = self.get_indices_by_pattern(field, pattern).next();
+        index.and_then(|i| self.get_value_from_index(&i))
+    }
+
+    pub fn get_all_by_pattern(&self, field: &str, pattern: &Pattern) -> Vec<Arc<Record>> {
+        self.get_indices_by_pattern(field, pattern)
+            .filter_map(|i| self.get_value_from_index(&i))
+            .collect()
+    }
+
+    pub fn remove_by_pattern(&mut self, field: &str, pattern: &Pattern) -> Option<Arc<Record>> {
+        let index = self.get_indices_by_pattern(field, pattern).next();
+        index.and_then(|i| {
+            let val = self.remove_value_from_index(&i);
+            val.clone().map(|val| self.remove_from_index(i, &val));
+            val
+        })
+    }
+
+    pub fn remove_all_by_pattern(&mut self, field: &str, pattern: &Pattern) -> Vec<Arc<Record>> {
+        let indices: Vec<u64> = self.get_indices_by_pattern(field, pattern).collect();
+        let mut result = Vec::new();
+        for i in indices {
+            if let Some(val) = self.remove_value_from_index(&i) {
+                self.remove_from_index(i, &val);
+                result.push(val);
+            }
+        }
+        result
+    }
+}
+
+/// Return true if the field of the struct, or any of its elements, is a string matching the pattern
+/// once normalized by the collation.
+pub fn matches_pattern(value: &Record, field: &str, pattern: &Pattern, collation: Collation) -> bool {
+    value.indexed_fields().any(|(name, found)| {
+        name == field && match *found {
+            Field::Str(ref s) if collation == Collation::Binary => pattern.is_match(s),
+            Field::Str(ref s) => pattern.is_match(&collation.normalize(s)),
+            _ => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+
+    use codec::Codec;
+    use entry::decoder::DecodeError;
+
+    use super::*;
+
+    fn record(value: Value) -> Record {
+        Record::from_json(&value, Codec::Json).unwrap()
+    }
+
+    fn counts(entry: &Entry) -> Vec<i64> {
+        let mut counts: Vec<i64> = entry
+            .get_all()
+            .filter_map(|value| value.deserialize::<Value>().unwrap()["count"].as_i64())
+            .collect();
+        counts.sort();
+        counts
+    }
+
+    #[test]
+    fn remove_all_checked_out() {
+        let mut entry = Entry::new();
+        for count in 0..3 {
+            entry.add(record(serde_json::json!({ "count": count }))).unwrap();
+        }
+        let (index, _) = entry.check_out(Duration::from_secs(60)).unwrap();
+
+        assert_eq!(entry.remove_all().len(), 2);
+        assert!(entry.is_empty());
+        // the struct checked out is still there to be checked in
+        assert!(entry.check_in(index));
+        assert_eq!(entry.len(), 1);
+
+        let (index, _) = entry.check_out(Duration::from_secs(60)).unwrap();
+        entry.clear();
+        assert!(entry.commit(index));
+        assert!(entry.is_empty());
+    }
+
+    #[test]
+    fn update_by_value() {
+        let mut entry = Entry::new();
+        entry.add(record(serde_json::json!({ "count": 3, "name": "Tuan" }))).unwrap();
+        entry.add(record(serde_json::json!({ "count": 5, "name": "Duane" }))).unwrap();
+
+        let updated = entry.update_by_value("count", &3i64, |_| {
+            record(serde_json::json!({ "count": 4, "name": "Tuan" }))
+        });
+        assert_eq!(updated, Some(Ok(true)));
+        assert_eq!(counts(&entry), vec![4, 5]);
+        assert!(entry.get_by_value("count", &3i64).is_none());
+        assert!(entry.get_by_value("name", &String::from("Tuan")).is_some());
+        assert_eq!(entry.update_by_value("count", &3i64, |_| unreachable!()), None);
+
+        // a field holding another kind of value fails the update, which keeps the struct
+        let updated = entry.update_by_value("count", &4i64, |_| {
+            record(serde_json::json!({ "count": 4.5, "name": "Tuan" }))
+        });
+        assert!(updated.unwrap().is_err());
+        assert_eq!(counts(&entry), vec![4, 5]);
+    }
+
+    #[test]
+    fn update_by_value_dedup() {
+        let mut entry = Entry::new();
+        entry.set_dedup(true);
+        entry.add(record(serde_json::json!({ "count": 3 }))).unwrap();
+        entry.add(record(serde_json::json!({ "count": 5 }))).unwrap();
+
+        // the result equal to the struct is the struct itself
+        let updated = entry.update_by_value("count", &3i64, |_| record(serde_json::json!({ "count": 3 })));
+        assert_eq!(updated, Some(Ok(true)));
+        assert_eq!(counts(&entry), vec![3, 5]);
+    }
+
+    #[test]
+    fn update_by_value_rejected() {
+        let mut entry = Entry::new();
+        entry.add(record(serde_json::json!({ "count": 3, "name": "Tuan" }))).unwrap();
+        entry.set_quota(entry.memory() + 64, Overflow::EvictOldest);
+
+        // a result which could not fit the quota even on its own evicts nothing
+        let long_name = "a".repeat(1000);
+        let updated = entry.update_by_value("count", &3i64, |_| {
+            record(serde_json::json!({ "count": 4, "name": long_name }))
+        });
+        assert_eq!(updated, Some(Ok(false)));
+        assert_eq!(counts(&entry), vec![3]);
+
+        // a result which the decoder keeps aside as a dead letter is not added
+        entry.decoder_mut().set_check(|record| match record.get("name") {
+            Some(&Field::Str(_)) => Ok(()),
+            _ => Err(DecodeError {
+                payload: Value::Null,
+                error: String::from("missing field `name`"),
+            }),
+        });
+        entry.decoder_mut().keep_dead_letters();
+        let updated = entry.update_by_value("count", &3i64, |_| record(serde_json::json!({ "count": 4 })));
+        assert_eq!(updated, Some(Ok(false)));
+        assert_eq!(counts(&entry), vec![3]);
+        assert_eq!(entry.decoder().dead_letters().len(), 1);
+    }
+}
@@ -0,0 +1,150 @@
+use std::char;
+use std::fmt;
+use std::ops::Bound;
+
+use entry::collation::Collation;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Token {
+    Char(char),
+    // `?`
+    AnyChar,
+    // `*`
+    AnyString,
+}
+
+/// A glob pattern on strings: `*` matches any sequence of characters, `?` matches any single character,
+/// and `\` makes the next character match itself.
+#[derive(Clone, PartialEq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '*' => Token::AnyString,
+                '?' => Token::AnyChar,
+                // a trailing backslash matches itself
+                '\\' => Token::Char(chars.next().unwrap_or('\\')),
+                c => Token::Char(c),
+            });
+        }
+        Pattern { tokens }
+    }
+
+    /// Return a pattern matching the strings starting with `prefix`.
+    pub fn prefix(prefix: &str) -> Self {
+        let mut tokens: Vec<_> = prefix.chars().map(Token::Char).collect();
+        tokens.push(Token::AnyString);
+        Pattern { tokens }
+    }
+
+    /// Return true if the pattern is a prefix, so that every string in `range` matches it.
+    pub fn is_prefix(&self) -> bool {
+        let literal = self.literal_prefix().chars().count();
+        literal + 1 == self.tokens.len() && self.tokens[literal] == Token::AnyString
+    }
+
+    /// Return the bounds of the strings starting with the literal prefix of the pattern,
+    /// which contain every string matching the pattern.
+    pub fn range(&self) -> (Bound<String>, Bound<String>) {
+        let prefix = self.literal_prefix();
+        if self.tokens.iter().all(|token| matches!(*token, Token::Char(_))) {
+            return (Bound::Included(prefix.clone()), Bound::Included(prefix));
+        }
+        let end = prefix_end(&prefix);
+        (Bound::Included(prefix), end)
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        let chars: Vec<char> = s.chars().collect();
+        let (mut t, mut c) = (0, 0);
+        // the position of the last `*` and of the character it was matched up to
+        let mut backtrack = None;
+        while c < chars.len() {
+            match self.tokens.get(t) {
+                Some(&Token::AnyString) => {
+                    backtrack = Some((t, c));
+                    t += 1;
+                    continue;
+                }
+                Some(&Token::AnyChar) => {
+                    t += 1;
+                    c += 1;
+                    continue;
+                }
+                Some(&Token::Char(expected)) if expected == chars[c] => {
+                    t += 1;
+                    c += 1;
+                    continue;
+                }
+                _ => (),
+            }
+            match backtrack {
+                // let the last `*` match one more character
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    t = star + 1;
+                    c = matched + 1;
+                }
+                None => return false,
+            }
+        }
+        self.tokens[t..].iter().all(|&token| token == Token::AnyString)
+    }
+
+    /// Return the pattern matching the strings normalized by the collation.
+    pub fn collate(&self, collation: Collation) -> Self {
+        let tokens = self.tokens
+            .iter()
+            .flat_map(|token| match *token {
+                Token::Char(c) => collation.normalize(&c.to_string()).chars().map(Token::Char).collect(),
+                token => vec![token],
+            })
+            .collect();
+        Pattern { tokens }
+    }
+
+    fn literal_prefix(&self) -> String {
+        self.tokens
+            .iter()
+            .map_while(|token| match *token {
+                Token::Char(c) => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in &self.tokens {
+            match *token {
+                Token::Char(c) if c == '*' || c == '?' || c == '\\' => write!(f, "\\{}", c)?,
+                Token::Char(c) => write!(f, "{}", c)?,
+                Token::AnyChar => write!(f, "?")?,
+                Token::AnyString => write!(f, "*")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Return the smallest string greater than every string starting with `prefix`,
+/// or `Unbounded` if there is none.
+fn prefix_end(prefix: &str) -> Bound<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        // strings are ordered by code point, skipping surrogates
+        let next = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+        if let Some(next) = next {
+            chars.push(next);
+            return Bound::Excluded(chars.into_iter().collect());
+        }
+    }
+    Bound::Unbounded
+}
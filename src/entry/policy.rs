@@ -0,0 +1,12 @@
+/// The order in which structs of a type are returned by lookups without a condition,
+/// e.g: `read`, `take` and `read_all`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OrderingPolicy {
+    /// The oldest struct comes first.
+    Fifo,
+    /// The newest struct comes first.
+    Lifo,
+    /// Structs come in any order, which makes taking a struct the fastest. This is the default.
+    #[default]
+    Unordered,
+}
@@ -0,0 +1,843 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::mem;
+
+use serde::de::Deserialize;
+use serde::ser::{self, Error as SerError, Impossible, Serialize};
+use serde_json::{Error, Value};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
+
+use codec::{Codec, CodecError};
+#[cfg(feature = "chrono")]
+use entry::collation::format_time;
+use entry::collation::Collation;
+use entry::spill::{SpillStore, Spilled};
+
+/// The value of a flattened field of a struct.
+///
+/// Only basic values are kept: sequences are recorded without their elements,
+/// which are kept apart by the `Record`, and byte strings without their bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Field {
+    Null,
+    Bool(bool),
+    Int(i64),
+    /// An integer too large to fit in an `i64`.
+    BigInt(i128),
+    Float(f64),
+    Str(String),
+    Seq,
+    /// A byte string, e.g: a `serde_bytes::ByteBuf` or a `Vec<u8>` with `#[serde(with = "serde_bytes")]`,
+    /// which is kept in the payload only and never indexed, whatever its size.
+    Bytes,
+}
+
+impl Field {
+    /// Return the memory taken by the value outside of the field itself, in bytes.
+    pub fn heap_size(&self) -> usize {
+        match *self {
+            Field::Str(ref s) => s.capacity(),
+            _ => 0,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Field::Int(i) => Some(i as f64),
+            Field::BigInt(i) => Some(i as f64),
+            Field::Float(f) => Some(f),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for Field {
+    fn from(i: i64) -> Self {
+        Field::Int(i)
+    }
+}
+
+impl From<u64> for Field {
+    fn from(u: u64) -> Self {
+        Field::from(i128::from(u))
+    }
+}
+
+impl From<i128> for Field {
+    fn from(i: i128) -> Self {
+        if i >= i128::from(i64::MIN) && i <= i128::from(i64::MAX) {
+            Field::Int(i as i64)
+        } else {
+            Field::BigInt(i)
+        }
+    }
+}
+
+impl From<f64> for Field {
+    fn from(f: f64) -> Self {
+        Field::Float(f)
+    }
+}
+
+impl From<bool> for Field {
+    fn from(b: bool) -> Self {
+        Field::Bool(b)
+    }
+}
+
+impl From<String> for Field {
+    fn from(s: String) -> Self {
+        Field::Str(s)
+    }
+}
+
+/// A time is compared to the fields collated by `Collation::Timestamp`.
+#[cfg(feature = "chrono")]
+impl From<DateTime<Utc>> for Field {
+    fn from(time: DateTime<Utc>) -> Self {
+        Field::Str(format_time(&time))
+    }
+}
+
+/// A uuid is stored as its hyphenated lowercase string, the form it is serialized to.
+#[cfg(feature = "uuid")]
+impl From<Uuid> for Field {
+    fn from(uuid: Uuid) -> Self {
+        Field::Str(uuid.to_string())
+    }
+}
+
+/// A struct as stored in the space.
+///
+/// The struct is kept twice: as a table of its flattened basic fields, sorted by name,
+/// which is what indices and lookups work on;
+/// and as its payload, serialized by the codec of the space, which is what the struct is deserialized from.
+/// Neither requires building an intermediate `serde_json::Value`.
+///
+/// The elements of sequences are kept as well, named after their sequence followed by `[]`,
+/// e.g: `"tags[]"` or `"items[].name"`, so that structs could be looked up by any of them.
+/// Byte strings are not sequences: their bytes are neither kept apart nor indexed.
+///
+/// The payload of a large struct could be spilled to disk, while its fields stay in memory.
+pub struct Record {
+    fields: Vec<(String, Field)>,
+    elements: Vec<(String, Field)>,
+    payload: Payload,
+    codec: Codec,
+}
+
+enum Payload {
+    Inline(Vec<u8>),
+    Spilled(Spilled),
+}
+
+impl Record {
+    pub fn new<T>(obj: &T, codec: Codec) -> Result<Self, CodecError>
+    where
+        T: Serialize,
+    {
+        let payload = codec.encode(obj)?;
+        Record::with_payload(obj, payload, codec)
+    }
+
+    /// Make a record of a JSON document, e.g: a struct restored from a checkpoint.
+    pub fn from_json(value: &Value, codec: Codec) -> Result<Self, CodecError> {
+        let payload = codec.encode_json(value)?;
+        Record::with_payload(value, payload, codec)
+    }
+
+    fn with_payload<T>(obj: &T, payload: Vec<u8>, codec: Codec) -> Result<Self, CodecError>
+    where
+        T: Serialize,
+    {
+        let mut fields = Vec::new();
+        let mut elements = Vec::new();
+        obj.serialize(FieldCollector {
+            prefix: String::new(),
+            fields: &mut fields,
+            elements: Some(&mut elements),
+        })?;
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Record {
+            fields,
+            elements,
+            payload: Payload::Inline(payload),
+            codec,
+        })
+    }
+
+    /// Return the value of a flattened field, e.g: `"person.name"`.
+    /// The field of a struct of a basic type is `""`.
+    pub fn get(&self, field: &str) -> Option<&Field> {
+        self.fields
+            .binary_search_by(|probe| probe.0.as_str().cmp(field))
+            .ok()
+            .map(|i| &self.fields[i].1)
+    }
+
+    pub fn fields<'a>(&'a self) -> Box<Iterator<Item = (&'a str, &'a Field)> + 'a> {
+        Box::new(self.fields.iter().map(|&(ref name, ref value)| (name.as_str(), value)))
+    }
+
+    /// Return the flattened fields followed by the elements of sequences, which are what indices are built on.
+    /// A name is repeated for every element of a sequence.
+    pub fn indexed_fields<'a>(&'a self) -> Box<Iterator<Item = (&'a str, &'a Field)> + 'a> {
+        Box::new(
+            self.fields
+                .iter()
+                .chain(self.elements.iter())
+                .map(|&(ref name, ref value)| (name.as_str(), value)),
+        )
+    }
+
+    /// Return a copy of the fields and elements of the struct, without its payload,
+    /// where the strings of the fields with a collation are normalized.
+    /// This is what the indices of an entry with collations are built on.
+    pub fn collate(&self, collations: &HashMap<String, Collation>) -> Record {
+        let collate = |&(ref name, ref value): &(String, Field)| {
+            let value = match collations.get(name) {
+                Some(collation) => collation.normalize_field(value),
+                None => value.clone(),
+            };
+            (name.clone(), value)
+        };
+        Record {
+            fields: self.fields.iter().map(collate).collect(),
+            elements: self.elements.iter().map(collate).collect(),
+            payload: Payload::Inline(Vec::new()),
+            codec: self.codec,
+        }
+    }
+
+    /// Return an estimate of the memory taken by the record, in bytes, not counting a spilled payload.
+    pub fn memory(&self) -> usize {
+        let fields: usize = self.fields
+            .iter()
+            .chain(self.elements.iter())
+            .map(|&(ref name, ref value)| mem::size_of::<(String, Field)>() + name.capacity() + value.heap_size())
+            .sum();
+        let payload = match self.payload {
+            Payload::Inline(ref payload) => payload.capacity(),
+            Payload::Spilled(_) => 0,
+        };
+        mem::size_of::<Record>() + fields + payload
+    }
+
+    /// Move the payload to the store if it is large enough, see `SpillStore::spill`.
+    pub fn spill(&mut self, store: &SpillStore) {
+        let spilled = match self.payload {
+            Payload::Inline(ref payload) => store.spill(payload),
+            Payload::Spilled(_) => None,
+        };
+        if let Some(spilled) = spilled {
+            self.payload = Payload::Spilled(spilled);
+        }
+    }
+
+    /// Return the struct in its serialized form, as encoded by its codec, read back from disk if spilled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the payload has been spilled to a file which could not be read any more.
+    pub fn payload(&self) -> Cow<[u8]> {
+        self.read_payload().expect("spilled struct could not be read")
+    }
+
+    fn read_payload(&self) -> Result<Cow<[u8]>, CodecError> {
+        match self.payload {
+            Payload::Inline(ref payload) => Ok(Cow::Borrowed(payload)),
+            Payload::Spilled(ref spilled) => spilled.read().map(Cow::Owned).map_err(CodecError::from),
+        }
+    }
+
+    pub fn deserialize<T>(&self) -> Result<T, CodecError>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.codec.decode(&self.read_payload()?)
+    }
+
+    /// Return the struct as a JSON document.
+    pub fn to_json(&self) -> Result<Value, CodecError> {
+        self.codec.decode_json(&self.read_payload()?)
+    }
+
+    /// Return the struct serialized as JSON, without copying it if its codec is JSON and it is in memory.
+    pub fn json_payload(&self) -> Result<Cow<[u8]>, CodecError> {
+        match self.read_payload()? {
+            Cow::Borrowed(payload) => self.codec.json_bytes(payload),
+            Cow::Owned(payload) => Ok(Cow::Owned(self.codec.json_bytes(&payload)?.into_owned())),
+        }
+    }
+}
+
+fn join(prefix: &str, field: &str) -> String {
+    if prefix.is_empty() {
+        field.to_owned()
+    } else {
+        format!("{}.{}", prefix, field)
+    }
+}
+
+/// Collect the basic fields of a struct, named by their path from the root separated by dots.
+///
+/// Values are laid out the way `serde_json` lays them out,
+/// e.g: a newtype variant is a field named after the variant.
+struct FieldCollector<'r> {
+    prefix: String,
+    fields: &'r mut Vec<(String, Field)>,
+    // where the elements of sequences go, or None within an element, where they go to `fields`
+    elements: Option<&'r mut Vec<(String, Field)>>,
+}
+
+impl<'r> FieldCollector<'r> {
+    fn push(self, value: Field) -> Result<(), Error> {
+        self.fields.push((self.prefix, value));
+        Ok(())
+    }
+
+    fn nested(self, variant: Option<&str>) -> FieldMap<'r> {
+        let prefix = match variant {
+            Some(variant) => join(&self.prefix, variant),
+            None => self.prefix,
+        };
+        FieldMap {
+            prefix,
+            fields: self.fields,
+            elements: self.elements,
+            key: None,
+        }
+    }
+
+    fn elements(self, field: String) -> ElementCollector<'r> {
+        self.fields.push((field.clone(), Field::Seq));
+        ElementCollector {
+            prefix: format!("{}[]", field),
+            fields: match self.elements {
+                Some(elements) => elements,
+                None => self.fields,
+            },
+        }
+    }
+}
+
+impl<'r> ser::Serializer for FieldCollector<'r> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = ElementCollector<'r>;
+    type SerializeTuple = ElementCollector<'r>;
+    type SerializeTupleStruct = ElementCollector<'r>;
+    type SerializeTupleVariant = ElementCollector<'r>;
+    type SerializeMap = FieldMap<'r>;
+    type SerializeStruct = FieldMap<'r>;
+    type SerializeStructVariant = FieldMap<'r>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.push(Field::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.push(Field::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.push(Field::from(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), Error> {
+        self.push(Field::from(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), Error> {
+        // a Null field would be matched by lookups of null fields
+        if v > i128::MAX as u128 {
+            Err(Error::custom(format!("integer {} is out of the range of i128", v)))
+        } else {
+            self.push(Field::from(v as i128))
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        // serde_json writes non-finite floats as null
+        if v.is_finite() {
+            self.push(Field::Float(v))
+        } else {
+            self.push(Field::Null)
+        }
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.push(Field::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.push(Field::Str(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<(), Error> {
+        self.push(Field::Bytes)
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.push(Field::Null)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.push(Field::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.push(Field::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let prefix = join(&self.prefix, variant);
+        value.serialize(FieldCollector {
+            prefix,
+            fields: self.fields,
+            elements: self.elements,
+        })
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<ElementCollector<'r>, Error> {
+        let field = self.prefix.clone();
+        Ok(self.elements(field))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ElementCollector<'r>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<ElementCollector<'r>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<ElementCollector<'r>, Error> {
+        let field = join(&self.prefix, variant);
+        Ok(self.elements(field))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<FieldMap<'r>, Error> {
+        Ok(self.nested(None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<FieldMap<'r>, Error> {
+        Ok(self.nested(None))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<FieldMap<'r>, Error> {
+        Ok(self.nested(Some(variant)))
+    }
+}
+
+/// Collect the elements of a sequence, all named after the sequence followed by `[]`.
+struct ElementCollector<'r> {
+    prefix: String,
+    fields: &'r mut Vec<(String, Field)>,
+}
+
+impl<'r> ElementCollector<'r> {
+    fn add<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(FieldCollector {
+            prefix: self.prefix.clone(),
+            fields: self.fields,
+            elements: None,
+        })
+    }
+}
+
+impl<'r> ser::SerializeSeq for ElementCollector<'r> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.add(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'r> ser::SerializeTuple for ElementCollector<'r> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.add(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'r> ser::SerializeTupleStruct for ElementCollector<'r> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.add(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'r> ser::SerializeTupleVariant for ElementCollector<'r> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.add(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Collect the fields of a struct or a map nested under `prefix`.
+struct FieldMap<'r> {
+    prefix: String,
+    fields: &'r mut Vec<(String, Field)>,
+    elements: Option<&'r mut Vec<(String, Field)>>,
+    key: Option<String>,
+}
+
+impl<'r> FieldMap<'r> {
+    fn add<T>(&mut self, field: &str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(FieldCollector {
+            prefix: join(&self.prefix, field),
+            fields: self.fields,
+            elements: self.elements.as_deref_mut(),
+        })
+    }
+}
+
+impl<'r> ser::SerializeMap for FieldMap<'r> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let key = self.key.take().expect("value serialized before its key");
+        self.add(&key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'r> ser::SerializeStruct for FieldMap<'r> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.add(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'r> ser::SerializeStructVariant for FieldMap<'r> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.add(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Turn a map key into a field name, accepting the same keys as `serde_json`.
+struct KeySerializer;
+
+fn key_error() -> Error {
+    Error::custom("key must be a string")
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> {
+        Ok(v.to_owned())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_none(self) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_some<T>(self, _value: &T) -> Result<String, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(key_error())
+    }
+
+    fn serialize_unit(self) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<String, Error> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<String, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(key_error())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(key_error())
+    }
+}
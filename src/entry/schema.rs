@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::map::Map;
+use serde_json::value::Value;
+
+use entry::record::{Field, Record};
+
+/// The kind of value held by a flattened field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldKind {
+    Bool,
+    Int,
+    Float,
+    String,
+    Seq,
+    /// A byte string, which is not indexed.
+    Bytes,
+    /// The field has only ever been null, e.g: an `Option` which was always `None`.
+    Unknown,
+}
+
+impl FieldKind {
+    pub(crate) fn of(field: &Field) -> Self {
+        match *field {
+            Field::Bool(_) => FieldKind::Bool,
+            Field::Int(_) | Field::BigInt(_) => FieldKind::Int,
+            Field::Float(_) => FieldKind::Float,
+            Field::Str(_) => FieldKind::String,
+            Field::Seq => FieldKind::Seq,
+            Field::Bytes => FieldKind::Bytes,
+            Field::Null => FieldKind::Unknown,
+        }
+    }
+
+    /// A null value is accepted by any kind, since it could come from an `Option`.
+    /// Byte strings and sequences accept one another, since JSON writes bytes as an array.
+    fn accepts(&self, field: &Field) -> bool {
+        let kind = FieldKind::of(field);
+        kind == FieldKind::Unknown || *self == FieldKind::Unknown || kind.base() == self.base()
+    }
+
+    fn base(self) -> Self {
+        match self {
+            FieldKind::Bytes => FieldKind::Seq,
+            kind => kind,
+        }
+    }
+
+    /// Return the name of the corresponding JSON Schema type.
+    fn json_type(&self) -> Option<&'static str> {
+        match *self {
+            FieldKind::Bool => Some("boolean"),
+            FieldKind::Int => Some("integer"),
+            FieldKind::Float => Some("number"),
+            FieldKind::String => Some("string"),
+            FieldKind::Seq | FieldKind::Bytes => Some("array"),
+            FieldKind::Unknown => None,
+        }
+    }
+}
+
+/// The shape of the structs of a type: the kind of each of their flattened fields.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Schema {
+    fields: BTreeMap<String, FieldKind>,
+    nullable: BTreeSet<String>,
+}
+
+impl Schema {
+    pub(crate) fn new(obj: &Record) -> Self {
+        let mut schema = Schema {
+            fields: obj.fields()
+                .map(|(name, field)| (name.to_owned(), FieldKind::of(field)))
+                .collect(),
+            nullable: BTreeSet::new(),
+        };
+        schema.refine(obj);
+        schema
+    }
+
+    /// Return the kind of a flattened field, e.g: `"person.name"`.
+    pub fn get(&self, field: &str) -> Option<FieldKind> {
+        self.fields.get(field).cloned()
+    }
+
+    /// Return true if the field has been null in some struct.
+    pub fn is_nullable(&self, field: &str) -> bool {
+        self.nullable.contains(field)
+    }
+
+    /// Return all flattened fields and their kinds, ordered by name.
+    pub fn fields<'a>(&'a self) -> Box<Iterator<Item = (&'a str, FieldKind)> + 'a> {
+        Box::new(self.fields.iter().map(|(name, kind)| (name.as_str(), *kind)))
+    }
+
+    /// Learn the kind of fields which have only been null so far, and which fields could be null.
+    pub(crate) fn refine(&mut self, obj: &Record) {
+        for (name, field) in obj.fields() {
+            if let Some(kind) = self.fields.get_mut(name) {
+                if *kind == FieldKind::Unknown {
+                    *kind = FieldKind::of(field);
+                }
+            }
+            if *field == Field::Null && !self.nullable.contains(name) {
+                self.nullable.insert(name.to_owned());
+            }
+        }
+    }
+
+    /// Return a JSON Schema document describing the structs, with the given title.
+    ///
+    /// Flattened fields are nested back into objects, and every field is required.
+    /// Fields which have only ever been null accept any value.
+    pub fn to_json_schema(&self, title: &str) -> Value {
+        let mut root = JsonNode::default();
+        for (name, kind) in &self.fields {
+            let path: Vec<&str> = if name.is_empty() {
+                Vec::new()
+            } else {
+                name.split('.').collect()
+            };
+            root.insert(&path, *kind, self.nullable.contains(name));
+        }
+
+        let mut result = match root.to_json() {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        };
+        result.insert(
+            "$schema".to_owned(),
+            Value::from("http://json-schema.org/draft-07/schema#"),
+        );
+        result.insert("title".to_owned(), Value::from(title));
+        Value::Object(result)
+    }
+
+    /// Return a description of the first difference between the shape of a struct and the schema.
+    pub(crate) fn mismatch(&self, obj: &Record) -> Option<String> {
+        for (name, field) in obj.fields() {
+            match self.fields.get(name) {
+                None => return Some(format!("unexpected field `{}`", name)),
+                Some(kind) if !kind.accepts(field) => {
+                    return Some(format!("field `{}` is not of kind {:?}", name, kind))
+                }
+                _ => (),
+            }
+        }
+        self.fields
+            .keys()
+            .find(|name| obj.get(name).is_none())
+            .map(|name| format!("missing field `{}`", name))
+    }
+}
+
+/// A field being nested back into objects while building a JSON Schema.
+#[derive(Default)]
+struct JsonNode {
+    leaf: Option<(FieldKind, bool)>,
+    children: BTreeMap<String, JsonNode>,
+}
+
+impl JsonNode {
+    fn insert(&mut self, path: &[&str], kind: FieldKind, nullable: bool) {
+        match path.split_first() {
+            Some((first, rest)) => self.children
+                .entry((*first).to_owned())
+                .or_default()
+                .insert(rest, kind, nullable),
+            None => self.leaf = Some((kind, nullable)),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut result = Map::new();
+        match self.leaf {
+            Some((kind, nullable)) => if let Some(json_type) = kind.json_type() {
+                let json_type = if nullable {
+                    Value::from(vec![json_type, "null"])
+                } else {
+                    Value::from(json_type)
+                };
+                result.insert("type".to_owned(), json_type);
+            },
+            None => {
+                let properties: Map<String, Value> = self.children
+                    .iter()
+                    .map(|(name, child)| (name.clone(), child.to_json()))
+                    .collect();
+                let required: Vec<Value> = self.children.keys().cloned().map(Value::from).collect();
+                result.insert("type".to_owned(), Value::from("object"));
+                result.insert("properties".to_owned(), Value::Object(properties));
+                result.insert("required".to_owned(), Value::from(required));
+            }
+        }
+        Value::Object(result)
+    }
+}
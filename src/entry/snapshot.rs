@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use entry::collation::Collation;
+use entry::record::{Field, Record};
+use entry::wait_queue::Condition;
+use query::{FieldValue, Query};
+
+/// The structs of type T in the space at a point in time, returned by `TreeObjectSpace::snapshot`.
+///
+/// A snapshot shares the structs with the space instead of copying them,
+/// and is not affected by the structs written or taken afterwards,
+/// so that it could be iterated, counted and queried many times with consistent results.
+/// Structs which could not be read as T are skipped, as `read_all` does.
+pub struct Snapshot<T> {
+    records: Vec<Arc<Record>>,
+    // the collations of the type when the snapshot was taken, which queries are normalized by
+    collations: HashMap<String, Collation>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Snapshot<T> {
+    pub(crate) fn new(records: Vec<Arc<Record>>, collations: HashMap<String, Collation>) -> Self {
+        Snapshot {
+            records,
+            collations,
+            marker: PhantomData,
+        }
+    }
+
+    /// Return the number of structs in the snapshot.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Return the number of structs in the snapshot satisfying the query.
+    pub fn count_by_query(&self, query: &Query) -> usize {
+        self.find(query).count()
+    }
+
+    fn find<'a>(&'a self, query: &Query) -> Box<Iterator<Item = &'a Arc<Record>> + 'a> {
+        let filter = Filter::new(query, &self.collations);
+        Box::new(self.records.iter().filter(move |record| {
+            if self.collations.is_empty() {
+                filter.matches(record)
+            } else {
+                filter.matches(&record.collate(&self.collations))
+            }
+        }))
+    }
+}
+
+/// A query whose conditions are normalized by the collations of the type,
+/// to be checked against structs normalized the same way.
+enum Filter {
+    Condition(Condition),
+    Or(Vec<Filter>),
+    And(Vec<Filter>),
+}
+
+impl Filter {
+    fn new(query: &Query, collations: &HashMap<String, Collation>) -> Self {
+        let condition = match *query {
+            Query::Eq(ref field, ref value) => Condition::Eq(field.clone(), value.to_field()),
+            Query::Range(ref field, ref start, ref end) => {
+                Condition::Range(field.clone(), to_field(start), to_field(end))
+            }
+            Query::Or(ref queries) => {
+                return Filter::Or(queries.iter().map(|query| Filter::new(query, collations)).collect())
+            }
+            Query::And(ref queries) => {
+                return Filter::And(queries.iter().map(|query| Filter::new(query, collations)).collect())
+            }
+        };
+        Filter::Condition(condition.collate(collations))
+    }
+
+    fn matches(&self, record: &Record) -> bool {
+        match *self {
+            Filter::Condition(ref condition) => condition.matches(record),
+            Filter::Or(ref filters) => filters.iter().any(|filter| filter.matches(record)),
+            Filter::And(ref filters) => filters.iter().all(|filter| filter.matches(record)),
+        }
+    }
+}
+
+impl<T> Snapshot<T>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    /// Return the structs of the snapshot, in the order `read_all` would.
+    pub fn iter<'a>(&'a self) -> Box<Iterator<Item = T> + 'a> {
+        Box::new(self.records.iter().filter_map(|record| record.deserialize().ok()))
+    }
+
+    /// Return a struct of the snapshot satisfying the query, if there is any.
+    pub fn try_read_by_query(&self, query: &Query) -> Option<T> {
+        self.find(query).filter_map(|record| record.deserialize().ok()).next()
+    }
+
+    /// Return all structs of the snapshot satisfying the query.
+    pub fn read_all_by_query<'a>(&'a self, query: &Query) -> Box<Iterator<Item = T> + 'a> {
+        Box::new(self.find(query).filter_map(|record| record.deserialize().ok()))
+    }
+}
+
+impl<T> Clone for Snapshot<T> {
+    fn clone(&self) -> Self {
+        Snapshot::new(self.records.clone(), self.collations.clone())
+    }
+}
+
+fn to_field(bound: &Bound<FieldValue>) -> Bound<Field> {
+    match *bound {
+        Bound::Included(ref value) => Bound::Included(value.to_field()),
+        Bound::Excluded(ref value) => Bound::Excluded(value.to_field()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::Bound;
+use std::ops::RangeBounds;
+
+use entry::record::Record;
+
+const LEAF_CAPACITY: usize = 16;
+const MAX_DEPTH: u32 = 32;
+
+/// Map a float to a key so that the order of floats is preserved.
+fn to_key(value: f64) -> u32 {
+    // -0.0 and 0.0 are equal and must map to the same key
+    let bits = if value == 0.0 { 0 } else { value.to_bits() };
+    let sortable = if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | (1 << 63)
+    };
+    (sortable >> 32) as u32
+}
+
+fn key_range<R>(range: &R) -> (u64, u64)
+where
+    R: RangeBounds<f64>,
+{
+    let low = match range.start_bound() {
+        Bound::Included(value) | Bound::Excluded(value) => u64::from(to_key(*value)),
+        Bound::Unbounded => 0,
+    };
+    let high = match range.end_bound() {
+        Bound::Included(value) | Bound::Excluded(value) => u64::from(to_key(*value)),
+        Bound::Unbounded => u64::from(u32::MAX),
+    };
+    (low, high)
+}
+
+/// Read the point stored in the two specified fields of a flattened struct.
+pub fn read_point(obj: &Record, fields: (&str, &str)) -> Option<(f64, f64)> {
+    let x = obj.get(fields.0)?.as_f64()?;
+    let y = obj.get(fields.1)?.as_f64()?;
+    Some((x, y))
+}
+
+/// Check whether a point is within a rectangle given by a range for each axis.
+pub fn in_rect<R>(point: (f64, f64), bounds: &(R, R)) -> bool
+where
+    R: RangeBounds<f64>,
+{
+    bounds.0.contains(&point.0) && bounds.1.contains(&point.1)
+}
+
+#[derive(Clone, Copy)]
+struct Point {
+    x: f64,
+    y: f64,
+    index: u64,
+}
+
+impl Point {
+    fn quadrant(&self, depth: u32) -> usize {
+        let shift = MAX_DEPTH - 1 - depth;
+        let x_bit = (to_key(self.x) >> shift) & 1;
+        let y_bit = (to_key(self.y) >> shift) & 1;
+        (x_bit * 2 + y_bit) as usize
+    }
+}
+
+enum QuadNode {
+    Leaf(Vec<Point>),
+    Inner(Box<[QuadNode; 4]>),
+}
+
+impl QuadNode {
+    fn empty_leaf() -> Self {
+        QuadNode::Leaf(Vec::new())
+    }
+
+    fn len(&self) -> Option<usize> {
+        match *self {
+            QuadNode::Leaf(ref points) => Some(points.len()),
+            QuadNode::Inner(_) => None,
+        }
+    }
+
+    fn insert(&mut self, point: Point, depth: u32) {
+        let split = match *self {
+            QuadNode::Inner(ref mut children) => {
+                let quadrant = point.quadrant(depth);
+                return children[quadrant].insert(point, depth + 1);
+            }
+            QuadNode::Leaf(ref mut points) => {
+                points.push(point);
+                points.len() > LEAF_CAPACITY && depth < MAX_DEPTH
+            }
+        };
+
+        if split {
+            let points = match ::std::mem::replace(self, QuadNode::empty_leaf()) {
+                QuadNode::Leaf(points) => points,
+                QuadNode::Inner(_) => unreachable!(),
+            };
+            *self = QuadNode::Inner(Box::new([
+                QuadNode::empty_leaf(),
+                QuadNode::empty_leaf(),
+                QuadNode::empty_leaf(),
+                QuadNode::empty_leaf(),
+            ]));
+            for point in points {
+                self.insert(point, depth);
+            }
+        }
+    }
+
+    fn remove(&mut self, point: &Point, depth: u32) {
+        let merge = match *self {
+            QuadNode::Leaf(ref mut points) => {
+                points.retain(|p| p.index != point.index);
+                return;
+            }
+            QuadNode::Inner(ref mut children) => {
+                children[point.quadrant(depth)].remove(point, depth + 1);
+                let lens: Option<Vec<usize>> = children.iter().map(QuadNode::len).collect();
+                lens.is_some_and(|lens| lens.iter().sum::<usize>() <= LEAF_CAPACITY)
+            }
+        };
+
+        // collapse children back into a leaf once they are small enough
+        if merge {
+            let children = match ::std::mem::replace(self, QuadNode::empty_leaf()) {
+                QuadNode::Inner(children) => children,
+                QuadNode::Leaf(_) => unreachable!(),
+            };
+            let mut merged = Vec::new();
+            for child in children.iter() {
+                if let QuadNode::Leaf(ref points) = *child {
+                    merged.extend(points.iter().cloned());
+                }
+            }
+            *self = QuadNode::Leaf(merged);
+        }
+    }
+
+    fn query<R>(
+        &self,
+        base: (u64, u64),
+        depth: u32,
+        keys: ((u64, u64), (u64, u64)),
+        bounds: &(R, R),
+        result: &mut Vec<u64>,
+    ) where
+        R: RangeBounds<f64>,
+    {
+        match *self {
+            QuadNode::Leaf(ref points) => result.extend(
+                points
+                    .iter()
+                    .filter(|p| in_rect((p.x, p.y), bounds))
+                    .map(|p| p.index),
+            ),
+            QuadNode::Inner(ref children) => {
+                let half = 1u64 << (MAX_DEPTH - 1 - depth);
+                for (quadrant, child) in children.iter().enumerate() {
+                    let x0 = base.0 + if quadrant >= 2 { half } else { 0 };
+                    let y0 = base.1 + if quadrant % 2 == 1 { half } else { 0 };
+                    let overlaps = x0 <= (keys.0).1
+                        && (keys.0).0 < x0 + half
+                        && y0 <= (keys.1).1
+                        && (keys.1).0 < y0 + half;
+                    if overlaps {
+                        child.query((x0, y0), depth + 1, keys, bounds, result);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A 2D index over a pair of numeric fields of a type.
+///
+/// Points are stored in a quadtree over an order-preserving integer mapping of the coordinates,
+/// so that the tree covers the whole range of `f64` without knowing the bounds of the data beforehand.
+pub struct SpatialIndexer {
+    x_field: String,
+    y_field: String,
+    root: QuadNode,
+}
+
+impl SpatialIndexer {
+    pub fn new(x_field: &str, y_field: &str) -> Self {
+        SpatialIndexer {
+            x_field: x_field.to_owned(),
+            y_field: y_field.to_owned(),
+            root: QuadNode::empty_leaf(),
+        }
+    }
+
+    pub fn is_on(&self, fields: (&str, &str)) -> bool {
+        self.x_field == fields.0 && self.y_field == fields.1
+    }
+
+    pub fn fields(&self) -> (&str, &str) {
+        (&self.x_field, &self.y_field)
+    }
+
+    pub fn clear(&mut self) {
+        self.root = QuadNode::empty_leaf();
+    }
+
+    pub fn add(&mut self, obj: &Record, index: u64) {
+        let fields = (self.x_field.as_str(), self.y_field.as_str());
+        if let Some((x, y)) = read_point(obj, fields) {
+            self.root.insert(Point { x, y, index }, 0);
+        }
+    }
+
+    pub fn remove(&mut self, obj: &Record, index: u64) {
+        let fields = (self.x_field.as_str(), self.y_field.as_str());
+        if let Some((x, y)) = read_point(obj, fields) {
+            self.root.remove(&Point { x, y, index }, 0);
+        }
+    }
+
+    /// Return the indices of all points within the rectangle in ascending order.
+    pub fn get_all_indices_in_rect<R>(&self, bounds: &(R, R)) -> Vec<u64>
+    where
+        R: RangeBounds<f64>,
+    {
+        let keys = (key_range(&bounds.0), key_range(&bounds.1));
+        let mut result = Vec::new();
+        self.root.query((0, 0), 0, keys, bounds, &mut result);
+        result.sort();
+        result
+    }
+}
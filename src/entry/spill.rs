@@ -0,0 +1,67 @@
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// names the files of every store of the process apart
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// Where the payloads of large structs are moved out of memory, a file per struct.
+#[derive(Clone)]
+pub struct SpillStore {
+    dir: PathBuf,
+    threshold: usize,
+}
+
+impl SpillStore {
+    pub fn new(dir: PathBuf, threshold: usize) -> Self {
+        SpillStore { dir, threshold }
+    }
+
+    /// Write the payload to a file of its own if it is larger than the threshold.
+    /// Return None if it is not, or if it could not be written, in which case it stays in memory.
+    pub fn spill(&self, payload: &[u8]) -> Option<Spilled> {
+        if payload.len() <= self.threshold {
+            return None;
+        }
+        let name = format!("{}-{}.payload", process::id(), NEXT_FILE.fetch_add(1, Ordering::Relaxed));
+        let path = self.dir.join(name);
+        match write_file(&path, payload) {
+            Ok(()) => Some(Spilled { path }),
+            Err(_) => {
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+}
+
+fn write_file(path: &Path, payload: &[u8]) -> io::Result<()> {
+    let mut file = match File::create(path) {
+        Err(ref e) if e.kind() == ErrorKind::NotFound => {
+            // the directory is only created once something is spilled
+            fs::create_dir_all(path.parent().expect("spilled payloads have a directory"))?;
+            File::create(path)?
+        }
+        file => file?,
+    };
+    file.write_all(payload)
+}
+
+/// The payload of a struct kept in a file, which is removed with the struct.
+pub struct Spilled {
+    path: PathBuf,
+}
+
+impl Spilled {
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        fs::read(&self.path)
+    }
+}
+
+impl Drop for Spilled {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
+
+use entry::schema::FieldKind;
+
+/// How long the write guard of a type has been held by an operation.
+///
+/// Also used for the latencies of operations in `TypeMetrics`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HoldTime {
+    /// The number of times the guard has been taken.
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl HoldTime {
+    pub fn record(&mut self, held: Duration) {
+        self.count += 1;
+        self.total += held;
+        if held > self.max {
+            self.max = held;
+        }
+    }
+
+    /// Return the average time the guard has been held for.
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            Duration::default()
+        } else {
+            self.total / self.count as u32
+        }
+    }
+}
+
+/// The operations done on the structs of a type so far, recorded by its entry.
+#[derive(Default)]
+pub struct Counters {
+    pub writes: u64,
+    // every struct removed, expired, evicted or not
+    pub removed: u64,
+    pub expired: u64,
+    pub evicted: u64,
+    // lookups only hold the read guard of the entry
+    pub reads: AtomicU64,
+    pub blocked: usize,
+    pub latencies: BTreeMap<&'static str, HoldTime>,
+    pub waits: BTreeMap<&'static str, HoldTime>,
+}
+
+/// A snapshot of the metrics of a type, returned by `TreeObjectSpace::metrics`.
+///
+/// Counters start over if the type is collected, i.e: once it holds no struct and is not used.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypeMetrics {
+    /// The full name of the type, e.g: `my_crate::Task`.
+    pub type_name: &'static str,
+    /// The number of structs written.
+    pub writes: u64,
+    /// The number of lookups leaving the structs in the space.
+    /// A blocking call looks the space up again every time it is woken up.
+    pub reads: u64,
+    /// The number of structs removed by an operation, including checked out structs.
+    pub takes: u64,
+    /// The number of structs removed because their lease ended.
+    pub expired: u64,
+    /// The number of structs removed to make room for others, as the type was full.
+    pub evicted: u64,
+    /// The number of blocking calls currently waiting for a struct.
+    pub blocked: usize,
+    /// The number of structs in the space.
+    pub size: usize,
+    /// An estimate of the memory taken by the structs and their indices, in bytes, see `TypeInfo::memory`.
+    pub memory: usize,
+    /// How long operations taking the write guard took, including waiting for the guard, by operation.
+    pub latencies: BTreeMap<&'static str, HoldTime>,
+    /// How long blocking calls took to return, including waiting for a struct, by operation.
+    pub waits: BTreeMap<&'static str, HoldTime>,
+}
+
+/// A description of a type in the space, returned by `TreeObjectSpace::list_types`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypeInfo {
+    /// The full name of the type, e.g: `my_crate::Task`.
+    pub type_name: &'static str,
+    /// The number of structs in the space.
+    pub count: usize,
+    /// The fields indexed so far and the kind of their values, e.g: `"person.name"` holding strings.
+    /// A field which has only ever been null is of kind `FieldKind::Unknown`.
+    pub indexed_fields: BTreeMap<String, FieldKind>,
+    /// The indexed fields which are hash-indexed, see `TreeObjectSpace::hash_index`.
+    pub hashed_fields: BTreeSet<String>,
+    /// An estimate of the memory taken by the structs and their indices, in bytes.
+    /// Payloads spilled to disk are not counted.
+    pub memory: usize,
+}
+
+/// How a lookup of a type by a range of a field would be answered,
+/// returned by `ExplainObjectSpace::explain_query`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryPlan {
+    pub field: String,
+    /// Whether the field is indexed, see `TreeObjectSpace::index_fields`.
+    /// A lookup by a field which is not indexed panics, or fails if checked.
+    pub indexed: bool,
+    /// Whether the field is hash-indexed, which only supports lookups by value.
+    pub hashed: bool,
+    /// The kind of the values indexed for the field, or None if no struct has had the field.
+    pub kind: Option<FieldKind>,
+    /// The number of distinct values of the field within the range, each a bucket of the index.
+    pub buckets: usize,
+    /// The number of structs in those buckets, which the lookup could look at.
+    /// A struct is counted once per element of an array within the range.
+    pub candidates: usize,
+    /// The number of structs of the type.
+    pub total: usize,
+}
@@ -0,0 +1,29 @@
+use entry::record::{Field, Record};
+
+/// A receiver of copies of the structs written to an entry, optionally only those with a field of some value.
+pub struct Subscription {
+    condition: Option<(String, Field)>,
+    // return false if the receiver has hung up
+    send: Box<Fn(&Record) -> bool + Send + Sync>,
+}
+
+impl Subscription {
+    pub fn new<F>(condition: Option<(String, Field)>, send: F) -> Self
+    where
+        F: Fn(&Record) -> bool + Send + Sync + 'static,
+    {
+        Subscription {
+            condition,
+            send: Box::new(send),
+        }
+    }
+
+    /// Send a copy of the struct if it matches the condition.
+    /// Return false if the receiver has hung up, so that the subscription could be dropped.
+    pub fn notify(&self, obj: &Record) -> bool {
+        match self.condition {
+            Some((ref field, ref value)) if obj.get(field) != Some(value) => true,
+            _ => (self.send)(obj),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+use entry::collation::Collation;
+use entry::matches_pattern;
+use entry::pattern::Pattern;
+use entry::record::{Field, Record};
+
+/// A condition on a field of the structs which could satisfy a blocked call.
+pub enum Condition {
+    /// The field equals the value.
+    Eq(String, Field),
+    /// The field is within the bounds.
+    Range(String, Bound<Field>, Bound<Field>),
+    /// The field is a string matching the pattern.
+    Pattern(String, Pattern),
+}
+
+impl Condition {
+    pub fn eq<U>(field: &str, key: &U) -> Self
+    where
+        U: Clone + Into<Field>,
+    {
+        Condition::Eq(field.to_owned(), key.clone().into())
+    }
+
+    pub fn range<U, R>(field: &str, range: &R) -> Self
+    where
+        U: Clone + Into<Field>,
+        R: RangeBounds<U>,
+    {
+        let convert = |bound: Bound<&U>| match bound {
+            Bound::Included(value) => Bound::Included(value.clone().into()),
+            Bound::Excluded(value) => Bound::Excluded(value.clone().into()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        Condition::Range(
+            field.to_owned(),
+            convert(range.start_bound()),
+            convert(range.end_bound()),
+        )
+    }
+
+    /// The struct has a value for the field, whatever it is.
+    pub fn has(field: &str) -> Self {
+        Condition::Range(field.to_owned(), Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Return the condition on the fields normalized by their collations,
+    /// to be checked against structs normalized the same way.
+    pub fn collate(self, collations: &HashMap<String, Collation>) -> Self {
+        let collation = match collations.get(self.field()) {
+            Some(&collation) => collation,
+            None => return self,
+        };
+        let bound = |bound: Bound<Field>| match bound {
+            Bound::Included(value) => Bound::Included(collation.normalize_field(&value)),
+            Bound::Excluded(value) => Bound::Excluded(collation.normalize_field(&value)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        match self {
+            Condition::Eq(field, value) => {
+                let value = collation.normalize_field(&value);
+                Condition::Eq(field, value)
+            }
+            Condition::Range(field, start, end) => Condition::Range(field, bound(start), bound(end)),
+            Condition::Pattern(field, pattern) => {
+                let pattern = pattern.collate(collation);
+                Condition::Pattern(field, pattern)
+            }
+        }
+    }
+
+    fn field(&self) -> &str {
+        match *self {
+            Condition::Eq(ref field, _) | Condition::Range(ref field, ..) | Condition::Pattern(ref field, _) => field,
+        }
+    }
+
+    /// Return true if a lookup could find the struct.
+    /// Values which could not be compared, e.g: a string and an int, never match.
+    pub fn matches(&self, obj: &Record) -> bool {
+        match *self {
+            Condition::Eq(ref field, ref value) => obj.indexed_fields()
+                .any(|(name, found)| name == field && compare(found, value) == Some(Ordering::Equal)),
+            Condition::Range(ref field, ref start, ref end) => obj.indexed_fields()
+                .any(|(name, found)| name == field && in_bounds(found, start, end)),
+            // the struct is normalized by the collations of the entry, as the pattern is
+            Condition::Pattern(ref field, ref pattern) => matches_pattern(obj, field, pattern, Collation::Binary),
+        }
+    }
+}
+
+fn compare(a: &Field, b: &Field) -> Option<Ordering> {
+    match (a, b) {
+        (&Field::Null, &Field::Null) => Some(Ordering::Equal),
+        (&Field::Bool(a), &Field::Bool(b)) => Some(a.cmp(&b)),
+        (&Field::Str(ref a), &Field::Str(ref b)) => Some(a.cmp(b)),
+        (&Field::Int(a), &Field::Int(b)) => Some(a.cmp(&b)),
+        (&Field::Int(a), &Field::BigInt(b)) => Some(i128::from(a).cmp(&b)),
+        (&Field::BigInt(a), &Field::Int(b)) => Some(a.cmp(&i128::from(b))),
+        (&Field::BigInt(a), &Field::BigInt(b)) => Some(a.cmp(&b)),
+        _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+    }
+}
+
+fn in_bounds(value: &Field, start: &Bound<Field>, end: &Bound<Field>) -> bool {
+    let after_start = match *start {
+        Bound::Included(ref start) => compare(value, start).is_some_and(|o| o != Ordering::Less),
+        Bound::Excluded(ref start) => compare(value, start) == Some(Ordering::Greater),
+        Bound::Unbounded => true,
+    };
+    let before_end = match *end {
+        Bound::Included(ref end) => compare(value, end).is_some_and(|o| o != Ordering::Greater),
+        Bound::Excluded(ref end) => compare(value, end) == Some(Ordering::Less),
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+/// A blocked call waiting for a struct satisfying its condition,
+/// with its own condition variable so that other writes leave it asleep.
+pub struct Ticket {
+    condition: Condition,
+    woken: AtomicBool,
+    cvar: Condvar,
+}
+
+impl Ticket {
+    pub fn new(condition: Condition) -> Arc<Ticket> {
+        Arc::new(Ticket {
+            condition,
+            woken: AtomicBool::new(false),
+            cvar: Condvar::new(),
+        })
+    }
+
+    /// Return true if a struct satisfying the condition has been added since the last `reset`.
+    pub fn is_woken(&self) -> bool {
+        self.woken.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Forget the structs added so far, e.g: once the call has looked them up.
+    pub fn reset(&self) {
+        self.woken.store(false, atomic::Ordering::SeqCst);
+    }
+
+    /// The condition variable to wait on, together with the lock of the type.
+    pub fn cvar(&self) -> &Condvar {
+        &self.cvar
+    }
+}
+
+/// A blocked call waiting for a struct of any of several types, registered with the entry of each,
+/// possibly along with a condition on the structs of the type.
+///
+/// The call resets the signal before looking the types up,
+/// so that a struct added during the lookups wakes it up rather than being missed.
+#[derive(Default)]
+pub struct Signal {
+    raised: Mutex<bool>,
+    cvar: Condvar,
+}
+
+impl Signal {
+    pub fn new() -> Arc<Signal> {
+        Arc::new(Signal::default())
+    }
+
+    /// Forget the structs added so far, e.g: before looking them up.
+    pub fn reset(&self) {
+        *self.raised.lock().unwrap() = false;
+    }
+
+    fn raise(&self) {
+        *self.raised.lock().unwrap() = true;
+        self.cvar.notify_one();
+    }
+
+    /// Wait until a struct is added since the last `reset`, and return true,
+    /// or return false once the deadline has passed.
+    pub fn wait(&self, deadline: Option<Instant>) -> bool {
+        let mut raised = self.raised.lock().unwrap();
+        while !*raised {
+            raised = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.cvar.wait_timeout(raised, deadline - now).unwrap().0
+                }
+                None => self.cvar.wait(raised).unwrap(),
+            };
+        }
+        true
+    }
+}
+
+/// The blocked calls of an entry which are only woken up by structs satisfying their condition,
+/// and those waiting for a struct of any of several types.
+///
+/// Calls register while holding the lock of the type, and structs are added while holding it,
+/// so that no struct is added between a failed lookup and the registration of the call.
+#[derive(Default)]
+pub struct WaitQueue {
+    tickets: Vec<Arc<Ticket>>,
+    // None if any struct raises the signal
+    signals: Vec<(Arc<Signal>, Option<Condition>)>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn register(&mut self, ticket: &Arc<Ticket>) {
+        if !self.tickets.iter().any(|registered| Arc::ptr_eq(registered, ticket)) {
+            self.tickets.push(ticket.clone());
+        }
+    }
+
+    pub fn unregister(&mut self, ticket: &Arc<Ticket>) {
+        self.tickets.retain(|registered| !Arc::ptr_eq(registered, ticket));
+    }
+
+    /// Raise the signal when a struct satisfying the condition is added, or any struct if there is none.
+    /// A signal could be registered with several conditions, e.g: one per arm of a call.
+    pub fn register_signal(&mut self, signal: &Arc<Signal>, condition: Option<Condition>) {
+        self.signals.push((signal.clone(), condition));
+    }
+
+    pub fn unregister_signal(&mut self, signal: &Arc<Signal>) {
+        self.signals.retain(|&(ref registered, _)| !Arc::ptr_eq(registered, signal));
+    }
+
+    /// Wake up every call, whatever its condition, e.g: because the space is closed.
+    pub fn wake_all(&self) {
+        for ticket in &self.tickets {
+            ticket.woken.store(true, atomic::Ordering::SeqCst);
+            ticket.cvar.notify_one();
+        }
+        for &(ref signal, _) in &self.signals {
+            signal.raise();
+        }
+    }
+
+    /// Wake up the calls whose condition the struct satisfies.
+    pub fn notify(&self, obj: &Record) {
+        for &(ref signal, ref condition) in &self.signals {
+            if condition.as_ref().is_none_or(|condition| condition.matches(obj)) {
+                signal.raise();
+            }
+        }
+        for ticket in &self.tickets {
+            if !ticket.is_woken() && ticket.condition.matches(obj) {
+                ticket.woken.store(true, atomic::Ordering::SeqCst);
+                ticket.cvar.notify_one();
+            }
+        }
+    }
+}
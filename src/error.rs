@@ -0,0 +1,78 @@
+use std::error::Error;
+use std::fmt;
+
+use entry::schema::FieldKind;
+
+/// Why a lookup could not be answered, e.g: because of a typo in the name of a field.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SpaceError {
+    /// The field is not among those declared with `index_fields`.
+    NotIndexed(String),
+    /// No struct written so far has had the field.
+    NoSuchField(String),
+    /// The field holds values of a kind which could not be compared to the key,
+    /// e.g: a string key on an int field.
+    WrongType {
+        field: String,
+        kind: FieldKind,
+        key: FieldKind,
+    },
+    /// The field is declared with `hash_index`, which only supports lookup by value.
+    HashIndexed(String),
+    /// The key is a NaN float, which could not be compared to any value.
+    NaN,
+    /// The type already holds as many structs as its capacity, see `set_capacity`.
+    Full(usize),
+    /// The structs of the type would take more memory than its quota, in bytes, see `set_memory_quota`.
+    OverQuota(usize),
+    /// The space has been closed, so that it accepts no struct and blocking calls could not wait.
+    Closed,
+    /// The struct could not be serialized, or read as its type,
+    /// e.g: because it was written before the type gained a field.
+    Serialization(String),
+    /// The struct does not match the strict schema of its type.
+    Mismatch(String),
+    /// A thread panicked while holding the lock of the type.
+    Poisoned,
+    /// The server of a `RemoteObjectSpace` could not be reached, or failed to answer the request.
+    Remote(String),
+}
+
+impl fmt::Display for SpaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SpaceError::NotIndexed(ref field) => {
+                write!(f, "Field `{}` is not indexed, see `index_fields`", field)
+            }
+            SpaceError::NoSuchField(ref field) => write!(f, "No struct has a field `{}`", field),
+            SpaceError::WrongType {
+                ref field,
+                kind,
+                key,
+            } => write!(
+                f,
+                "Field `{}` holds {:?} values, which could not be compared to a {:?} key",
+                field, kind, key
+            ),
+            SpaceError::HashIndexed(ref field) => write!(
+                f,
+                "Field `{}` is hash-indexed, which only supports lookup by value",
+                field
+            ),
+            SpaceError::NaN => write!(f, "NaN values are not accepted"),
+            SpaceError::Full(limit) => write!(f, "The type already holds its capacity of {} structs", limit),
+            SpaceError::OverQuota(bytes) => {
+                write!(f, "The structs of the type would take more than its quota of {} bytes", bytes)
+            }
+            SpaceError::Closed => write!(f, "The space is closed"),
+            SpaceError::Serialization(ref error) => write!(f, "Struct could not be serialized: {}", error),
+            SpaceError::Mismatch(ref mismatch) => {
+                write!(f, "Struct does not match the schema of its type: {}", mismatch)
+            }
+            SpaceError::Poisoned => write!(f, "The lock of the type is poisoned"),
+            SpaceError::Remote(ref error) => write!(f, "The space server failed: {}", error),
+        }
+    }
+}
+
+impl Error for SpaceError {}
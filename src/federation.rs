@@ -0,0 +1,389 @@
+use std::any::type_name;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use entry::handle::ObjectHandle;
+use helpers::{poll, sample_indices};
+use object_space::{ObjectSpace, QueryObjectSpace, TreeObjectSpace};
+use query::Query;
+use remote::RemoteObjectSpace;
+
+/// A space attached to a `FederatedObjectSpace`.
+enum Member {
+    Local(Arc<TreeObjectSpace>),
+    Remote(RemoteObjectSpace),
+}
+
+/// Evaluate `$body` with `$space` bound to the space of a member, whatever its kind.
+macro_rules! on_member {
+    ($member:expr, $space:ident => $body:expr) => {
+        match *$member {
+            Member::Local(ref $space) => $body,
+            Member::Remote(ref $space) => $body,
+        }
+    };
+}
+
+/// An `ObjectSpace` made of several spaces, e.g: a local space and the spaces of other machines.
+///
+/// Lookups search the spaces in the order they were attached, and return the first struct found.
+/// Writes go to the first space, unless routed otherwise with `route_writes`.
+/// Blocking calls look up every space in turn until a struct is found,
+/// so they notice a new struct after a short delay rather than right away.
+///
+/// # Example
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use object_space::{FederatedObjectSpace, ObjectSpace, RemoteObjectSpace, SpaceServer, TreeObjectSpace};
+/// let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+/// let remote = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+/// server.spawn();
+///
+/// let local = Arc::new(TreeObjectSpace::new());
+/// let space = FederatedObjectSpace::new()
+///     .attach_local(local.clone())
+///     .attach_remote(remote)
+///     .route_writes(|type_name| if type_name == "alloc::string::String" { 1 } else { 0 });
+///
+/// space.write::<i64>(3);
+/// space.write(String::from("Hello World"));
+/// assert_eq!(local.try_read::<i64>(), Some(3));
+/// assert_eq!(local.try_read::<String>(), None);
+/// assert_eq!(space.take::<String>(), String::from("Hello World"));
+/// ```
+pub struct FederatedObjectSpace {
+    members: Vec<Member>,
+    // the position of the member structs of a type are written to, by name of the type
+    router: Box<Fn(&str) -> usize + Send + Sync>,
+}
+
+impl Default for FederatedObjectSpace {
+    fn default() -> Self {
+        FederatedObjectSpace {
+            members: Vec::new(),
+            router: Box::new(|_| 0),
+        }
+    }
+}
+
+impl FederatedObjectSpace {
+    pub fn new() -> FederatedObjectSpace {
+        Default::default()
+    }
+
+    /// Attach a space of this process, searched after the spaces attached before.
+    pub fn attach_local(mut self, space: Arc<TreeObjectSpace>) -> FederatedObjectSpace {
+        self.members.push(Member::Local(space));
+        self
+    }
+
+    /// Attach a space served by a `SpaceServer`, searched after the spaces attached before.
+    pub fn attach_remote(mut self, space: RemoteObjectSpace) -> FederatedObjectSpace {
+        self.members.push(Member::Remote(space));
+        self
+    }
+
+    /// Write the structs of a type to the space returned by `router` for the full name of the type,
+    /// e.g: `my_crate::Task`. Spaces are numbered from 0 in the order they were attached.
+    ///
+    /// Writes panic if `router` returns the number of a space which has not been attached.
+    pub fn route_writes<F>(mut self, router: F) -> FederatedObjectSpace
+    where
+        F: Fn(&str) -> usize + Send + Sync + 'static,
+    {
+        self.router = Box::new(router);
+        self
+    }
+
+    /// Return the position of the member structs of type T are written to.
+    fn route<T>(&self) -> usize {
+        let position = (self.router)(type_name::<T>());
+        assert!(
+            position < self.members.len(),
+            "no space attached at position {}",
+            position
+        );
+        position
+    }
+
+    /// Return a handle identifying the member as well as the struct within it.
+    fn encode_handle(&self, position: usize, handle: ObjectHandle) -> ObjectHandle {
+        ObjectHandle::new(handle.index() * self.members.len() as u64 + position as u64)
+    }
+
+    /// Return the member a handle was returned by, and the handle of the struct within it.
+    fn decode_handle(&self, handle: ObjectHandle) -> Option<(&Member, ObjectHandle)> {
+        let count = self.members.len() as u64;
+        if count == 0 {
+            return None;
+        }
+        let member = &self.members[(handle.index() % count) as usize];
+        Some((member, ObjectHandle::new(handle.index() / count)))
+    }
+
+    /// Return the first struct found by `lookup` in the members,
+    /// looking them up again until one is found or the timeout has passed.
+    fn poll<T, F>(&self, timeout: Option<Duration>, mut lookup: F) -> Option<T>
+    where
+        F: FnMut(&Member) -> Option<T>,
+    {
+        poll(timeout, || self.members.iter().find_map(&mut lookup))
+    }
+}
+
+impl ObjectSpace for FederatedObjectSpace {
+    fn write<T>(&self, obj: T) -> ObjectHandle
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let position = self.route::<T>();
+        let handle = on_member!(&self.members[position], space => space.write(obj));
+        self.encode_handle(position, handle)
+    }
+
+    fn write_all<T, I>(&self, objs: I)
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        I: IntoIterator<Item = T>,
+    {
+        let position = self.route::<T>();
+        on_member!(&self.members[position], space => space.write_all(objs))
+    }
+
+    fn try_read<T>(&self) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.members
+            .iter()
+            .find_map(|member| on_member!(member, space => space.try_read::<T>()))
+    }
+
+    fn read_all<'a, T>(&'a self) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        Box::new(
+            self.members
+                .iter()
+                .flat_map(|member| on_member!(member, space => space.read_all::<T>())),
+        )
+    }
+
+    fn read_sample<'a, T>(&'a self, count: usize) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        // each member contributes its own sample, which is then sampled again
+        let mut samples: Vec<Option<T>> = self.members
+            .iter()
+            .flat_map(|member| on_member!(member, space => space.read_sample::<T>(count)))
+            .map(Some)
+            .collect();
+        let chosen: Vec<T> = sample_indices(samples.len(), count)
+            .into_iter()
+            .filter_map(|i| samples[i].take())
+            .collect();
+        Box::new(chosen.into_iter())
+    }
+
+    fn read<T>(&self) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.poll(None, |member| on_member!(member, space => space.try_read::<T>()))
+            .unwrap()
+    }
+
+    fn read_timeout<T>(&self, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.poll(Some(timeout), |member| on_member!(member, space => space.try_read::<T>()))
+    }
+
+    fn try_take<T>(&self) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.members
+            .iter()
+            .find_map(|member| on_member!(member, space => space.try_take::<T>()))
+    }
+
+    fn take_all<'a, T>(&'a self) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        // every member is emptied right away, rather than when the iterator gets to it
+        let taken: Vec<T> = self.members
+            .iter()
+            .flat_map(|member| on_member!(member, space => space.take_all::<T>()))
+            .collect();
+        Box::new(taken.into_iter())
+    }
+
+    fn take<T>(&self) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.poll(None, |member| on_member!(member, space => space.try_take::<T>()))
+            .unwrap()
+    }
+
+    fn take_timeout<T>(&self, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.poll(Some(timeout), |member| on_member!(member, space => space.try_take::<T>()))
+    }
+
+    fn try_read_by_handle<T>(&self, handle: ObjectHandle) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let (member, handle) = self.decode_handle(handle)?;
+        on_member!(member, space => space.try_read_by_handle::<T>(handle))
+    }
+
+    fn try_take_by_handle<T>(&self, handle: ObjectHandle) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let (member, handle) = self.decode_handle(handle)?;
+        on_member!(member, space => space.try_take_by_handle::<T>(handle))
+    }
+}
+
+impl QueryObjectSpace for FederatedObjectSpace {
+    fn try_read_by_query<T>(&self, query: &Query) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.members
+            .iter()
+            .find_map(|member| on_member!(member, space => space.try_read_by_query::<T>(query)))
+    }
+
+    fn read_all_by_query<'a, T>(&'a self, query: &Query) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        let found: Vec<T> = self.members
+            .iter()
+            .flat_map(|member| on_member!(member, space => space.read_all_by_query::<T>(query)))
+            .collect();
+        Box::new(found.into_iter())
+    }
+
+    fn read_by_query<T>(&self, query: &Query) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.poll(None, |member| on_member!(member, space => space.try_read_by_query::<T>(query)))
+            .unwrap()
+    }
+
+    fn try_take_by_query<T>(&self, query: &Query) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.members
+            .iter()
+            .find_map(|member| on_member!(member, space => space.try_take_by_query::<T>(query)))
+    }
+
+    fn take_all_by_query<'a, T>(&'a self, query: &Query) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        let taken: Vec<T> = self.members
+            .iter()
+            .flat_map(|member| on_member!(member, space => space.take_all_by_query::<T>(query)))
+            .collect();
+        Box::new(taken.into_iter())
+    }
+
+    fn take_by_query<T>(&self, query: &Query) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.poll(None, |member| on_member!(member, space => space.try_take_by_query::<T>(query)))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use query::field;
+    use remote::SpaceServer;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct TestStruct {
+        count: i32,
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct CompoundStruct {
+        person: TestStruct,
+        gpa: f64,
+    }
+
+    #[test]
+    fn federated_space() {
+        let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        server.spawn();
+        let local = Arc::new(TreeObjectSpace::new());
+        let remote = RemoteObjectSpace::connect(addr).unwrap();
+        let space = Arc::new(
+            FederatedObjectSpace::new()
+                .attach_local(local.clone())
+                .attach_remote(RemoteObjectSpace::connect(addr).unwrap())
+                .route_writes(|type_name| type_name.ends_with("TestStruct") as usize),
+        );
+
+        // a consumer finds a struct written to any member
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || consumer_space.take::<CompoundStruct>().person.count);
+        remote.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.0,
+        });
+        assert_eq!(consumer.join().unwrap(), 3);
+
+        // members are searched in the order they were attached
+        local.write::<i64>(3);
+        remote.write::<i64>(5);
+        assert_eq!(space.try_read::<i64>(), Some(3));
+        assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![3, 5]);
+        assert_eq!(space.read_sample::<i64>(1).count(), 1);
+        assert_eq!(space.try_read_by_query::<i64>(&field("").gt(4)), Some(5));
+        assert_eq!(space.take_all::<i64>().collect::<Vec<_>>(), vec![3, 5]);
+        assert_eq!(space.take_timeout::<i64>(Duration::from_millis(10)), None);
+
+        // writes are routed by type, and handles refer to the member written to
+        let remote_handle = space.write(TestStruct {
+            count: 1,
+            name: String::from("Duane"),
+        });
+        let local_handle = space.write::<i64>(7);
+        assert_eq!(remote.try_read::<TestStruct>().map(|s| s.count), Some(1));
+        assert_eq!(local.try_read::<i64>(), Some(7));
+        assert_eq!(space.try_read_by_handle::<TestStruct>(local_handle), None);
+        assert_eq!(space.try_take_by_handle::<TestStruct>(remote_handle).map(|s| s.count), Some(1));
+        assert_eq!(space.try_take_by_handle::<i64>(local_handle), Some(7));
+        assert_eq!(remote.try_read::<TestStruct>(), None);
+    }
+}
@@ -1,63 +1,76 @@
-use std::iter::Peekable;
+use std::cmp::min;
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hasher};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use serde_json::map::Map;
-use serde_json::value::Value;
+// blocking calls spanning several spaces look them up again after at most this long
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
-pub fn flatten(v: Value) -> Value {
-    match v {
-        Value::Object(_) => {
-            let mut result = Map::new();
-            flatten_helper("", v, &mut result);
-            Value::Object(result)
+/// Return `count` distinct indices chosen uniformly from `0..len`, in ascending order.
+/// Return all indices if `count` is not less than `len`.
+pub fn sample_indices(len: usize, count: usize) -> Vec<usize> {
+    if count >= len {
+        return (0..len).collect();
+    }
+
+    // Floyd's algorithm: O(count) regardless of len
+    let mut rng = XorShift::new();
+    let mut chosen = HashSet::with_capacity(count);
+    for j in (len - count)..len {
+        let candidate = rng.next_below(j + 1);
+        if !chosen.insert(candidate) {
+            chosen.insert(j);
         }
-        _ => v,
     }
+    let mut result: Vec<usize> = chosen.into_iter().collect();
+    result.sort();
+    result
 }
 
-pub fn deflatten(v: Value) -> Value {
-    match v {
-        Value::Object(map) => Value::Object(deflatten_helper(map)),
-        _ => v,
+/// A small xorshift generator seeded from the randomly keyed std hasher,
+/// good enough for sampling but not for anything security related.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new() -> Self {
+        let seed = RandomState::new().build_hasher().finish();
+        XorShift(seed | 1)
     }
-}
 
-fn flatten_helper(path: &str, value: Value, result: &mut Map<String, Value>) {
-    match value {
-        Value::Object(map) => for (k, v) in map {
-            let new_path = if path.is_empty() {
-                k
-            } else {
-                format!("{}.{}", path, k)
-            };
-            flatten_helper(&new_path, v, result)
-        },
-        _ => {
-            result.insert(path.to_owned(), value);
-        }
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
     }
-}
 
-fn deflatten_helper(map: Map<String, Value>) -> Map<String, Value> {
-    let mut result = Map::new();
-    for (key, value) in map {
-        insert_to_map(&mut result, &mut key.split('.').peekable(), value);
+    fn next_below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
     }
-    result
 }
 
-fn insert_to_map<'a, I>(map: &mut Map<String, Value>, iter: &mut Peekable<I>, value: Value)
+/// Call `lookup` until it returns a value or the timeout has passed,
+/// backing off between calls, for blocking calls which could not wait on a single space.
+pub fn poll<T, F>(timeout: Option<Duration>, mut lookup: F) -> Option<T>
 where
-    I: Iterator<Item = &'a str>,
+    F: FnMut() -> Option<T>,
 {
-    if let Some(field_val) = iter.next() {
-        match iter.peek() {
-            Some(_) => {
-                let child = map.entry(field_val).or_insert(Value::Object(Map::new()));
-                insert_to_map(child.as_object_mut().expect("invalid JSON"), iter, value);
-            }
-            None => {
-                map.insert(field_val.to_owned(), value);
-            }
+    // a timeout too large to be represented is the same as none
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    let mut interval = Duration::from_millis(1);
+    loop {
+        if let Some(found) = lookup() {
+            return Some(found);
         }
+        let now = Instant::now();
+        let sleep = match deadline {
+            Some(deadline) if now >= deadline => return None,
+            Some(deadline) => min(interval, deadline - now),
+            None => interval,
+        };
+        thread::sleep(sleep);
+        interval = min(interval * 2, MAX_POLL_INTERVAL);
     }
 }
@@ -17,7 +17,7 @@ An ObjectSpace could perform the following tasks:
 
 Notice that an ObjectSpace could hold data from any types, which means that an i64, a String, and a complex struct could all live under one space (which leads to the somewhat wordy API for retrieving items).
 
-Additionally, by implementing `ValueLookupObjectSpace` and `RangeLookupObjectSpace`, an ObjectSpace could retrieve item based on the value of a field. Notice that the field must be a "basic" field: the type of the field must be either an int, a string, or a bool.
+Additionally, by implementing `ValueLookupObjectSpace` and `RangeLookupObjectSpace`, an ObjectSpace could retrieve item based on the value of a field. Notice that the field must be a "basic" field: the type of the field must be either an int, a string, or a bool. Integer fields could be looked up by `i64`, `u64` or `i128` keys, so integer literals used as keys need a suffix, e.g: `&3i64`.
 
 E.g: Given a TestStruct:
 
@@ -34,11 +34,21 @@ struct TestStruct {
 
 `space.try_take_by_value::<TestStruct>("property.touched", true)` will return a `TestStruct` with the value `true` for `property.touched`. `space.try_take_by_range::<TestStruct>("index", 2..10)` will return a `TestStruct` with the value of `index` between in the range `2..10`
 
-For further information, please read the documentation of `ObjectSpace`, `RangeLookupObjectSpace`, and `ValueLookupObjectSpace`
+Other lookups are provided by [`QueryObjectSpace`], [`IntervalLookupObjectSpace`], [`SpatialLookupObjectSpace`], [`PatternLookupObjectSpace`], [`NearestLookupObjectSpace`], [`OrderedLookupObjectSpace`] and [`ExplainObjectSpace`], while [`UpdateObjectSpace`] replaces structs atomically and [`AsyncObjectSpace`] awaits them without blocking a thread. Queries could also be written with the [`template!`] and [`fields!`] macros.
+
+For further information, please read the documentation of [`ObjectSpace`], [`RangeLookupObjectSpace`], and [`ValueLookupObjectSpace`]
 
 # TreeObjectSpace
 
-`TreeSpaceObject` is a referenced implementation of `ObjectSpace` trait. It is, in essence, a concurrent HashMap of `TypeId` and corresponding `Entry` for each type. Each `Entry` stores objects by serializing & flattening their structure, then put the values of basic fields in a `BTreeMap` for efficient lookup. `TreeSpaceObject` is thread-safe, which allows it to be used in concurrent and distributed settings.
+`TreeSpaceObject` is a referenced implementation of `ObjectSpace` trait. It is, in essence, a concurrent HashMap of `TypeId` and corresponding `Entry` for each type. Each `Entry` stores objects as a table of their flattened basic fields together with their serialized form, then put the values of basic fields in a `BTreeMap` for efficient lookup. `TreeSpaceObject` is thread-safe, which allows it to be used in concurrent and distributed settings. Each type has its own lock and its own condition variable, so that operations on different types never contend.
+
+Each type of a [`TreeObjectSpace`] could be configured on its own, e.g: with an [`OrderingPolicy`], a capacity or memory quota and its [`Overflow`], an [`Eviction`] policy, a [`Collation`] of its string fields, a lease, deduplication or a strict schema. Settings shared by every type, such as the [`Codec`] of the structs, a default lease or a [`WaitStrategy`], are given to the [`TreeObjectSpaceBuilder`].
+
+The space could also be made durable with a journal or checkpoints, inspected with metrics, [`Snapshot`]s and the descriptions of its types, and shut down with `close`, after which the checked calls return a [`SpaceError`]. See the methods of [`TreeObjectSpace`].
+
+# Distribution
+
+A [`SpaceServer`] shares a space over TCP with [`RemoteObjectSpace`] clients, which could find it on the local network. The module [`server`] serves it to clients written in other languages as well, over gRPC or WebSocket, and its metrics in the Prometheus text format. [`DynObjectSpace`] lets a program choose at runtime whether its structs are kept locally or on a server, while a [`FederatedObjectSpace`] combines several spaces and a [`ShardedObjectSpace`] partitions a type across several.
 
 # Example
 
@@ -133,14 +143,48 @@ struct Task {
 
 #![feature(collections_range)]
 extern crate chashmap;
+extern crate erased_serde;
 extern crate indexmap;
 extern crate ordered_float;
+extern crate parking_lot;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate socket2;
+#[cfg(feature = "grpc")]
+extern crate prost;
+#[cfg(feature = "grpc")]
+extern crate tokio;
+#[cfg(feature = "grpc")]
+extern crate tonic;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "bincode")]
+extern crate bincode;
+#[cfg(feature = "msgpack")]
+extern crate rmp_serde;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "uuid")]
+extern crate uuid;
 
 pub use self::object_space::*;
+mod codec;
+mod discovery;
 mod entry;
+mod error;
+mod federation;
 mod helpers;
+// before the modules using `template!` and `fields!`
+#[macro_use]
+mod query;
+#[macro_use]
+mod typed;
 mod object_space;
+mod registry;
+mod remote;
+pub mod server;
+mod sharding;
+mod trace;
+mod watchdog;
@@ -1,13 +1,60 @@
-use std::any::TypeId;
-use std::ops::RangeBounds;
-use std::sync::{Arc, Condvar, Mutex};
+use std::any::{type_name, TypeId};
+use std::cmp::{max, min, Reverse};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use chashmap::{CHashMap, ReadGuard, WriteGuard};
+use chashmap::CHashMap;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, RawRwLock, RwLock};
 use serde::{Deserialize, Serialize};
-use serde_json::value::{from_value, to_value};
+use serde_json;
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
 
-use entry::{Entry, RangeLookupEntry, ValueLookupEntry};
-use helpers::{deflatten, flatten};
+use entry::decoder::{fill_missing_fields, Decoder};
+use entry::capacity::Breach;
+use entry::journal::Journal;
+use entry::pattern::Pattern;
+use entry::record::{Field, Record};
+use entry::spill::SpillStore;
+use entry::subscription::Subscription;
+use entry::wait_queue::{Condition, Signal, Ticket};
+pub use codec::{Codec, CodecError, Format};
+pub use entry::capacity::Overflow;
+pub use entry::collation::Collation;
+pub use entry::decoder::DecodeError;
+pub use entry::eviction::Eviction;
+pub use entry::handle::ObjectHandle;
+pub use entry::indexer::Order;
+pub use entry::policy::OrderingPolicy;
+pub use error::SpaceError;
+pub use entry::schema::{FieldKind, Schema};
+pub use entry::snapshot::Snapshot;
+pub use entry::stats::{HoldTime, QueryPlan, TypeInfo, TypeMetrics};
+pub use query::{field, FieldCondition, FieldValue, Query};
+pub use discovery::{SpaceAddr, DISCOVERY_GROUP};
+pub use federation::FederatedObjectSpace;
+pub use remote::{RemoteObjectSpace, SpaceServer};
+pub use sharding::ShardedObjectSpace;
+pub use typed::{FieldLookup, TypedField};
+use registry::TypeRegistry;
+use trace;
+use watchdog::{describe_range, WaiterRegistry, Watchdog};
+pub use watchdog::BlockedCall;
+use entry::{Entry, IntervalLookupEntry, NearestLookupEntry, RangeLookupEntry, ValueLookupEntry};
 
 /// Basic interface of an ObjectSpace.
 ///
@@ -26,7 +73,8 @@ use helpers::{deflatten, flatten};
 /// );
 /// ```
 pub trait ObjectSpace {
-    /// Add a struct to the object space.
+    /// Add a struct to the object space and return its handle,
+    /// which could later be used to read or take this very struct.
     ///
     /// # Example
     ///
@@ -35,10 +83,29 @@ pub trait ObjectSpace {
     /// let space = TreeObjectSpace::new();
     /// space.write(String::from("Hello World"));
     /// ```
-    fn write<T>(&self, obj: T)
+    fn write<T>(&self, obj: T) -> ObjectHandle
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static;
 
+    /// Add all structs of an iterator to the object space.
+    ///
+    /// Threads waiting for structs of type T are woken up once for the whole batch,
+    /// instead of once per struct.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write_all::<i64, _>(vec![3, 5, 7]);
+    ///
+    /// assert_eq!(space.read_all::<i64>().count(), 3);
+    /// ```
+    fn write_all<T, I>(&self, objs: I)
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        I: IntoIterator<Item = T>;
+
     /// Return a copy of a struct of type T.
     /// The operation is non-blocking
     /// and will returns None if no struct satisfies condition.
@@ -79,6 +146,26 @@ pub trait ObjectSpace {
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static;
 
+    /// Return copies of `count` structs of type T chosen uniformly at random,
+    /// or copies of all structs of type T if there are fewer than `count` of them.
+    /// The operation is non-blocking and only copies the chosen structs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// for i in 0..100 {
+    ///     space.write::<i64>(i);
+    /// }
+    ///
+    /// assert_eq!(space.read_sample::<i64>(10).count(), 10);
+    /// assert_eq!(space.read_sample::<i64>(1000).count(), 100);
+    /// ```
+    fn read_sample<'a, T>(&'a self, count: usize) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
     /// Return a copy of a struct of type T.
     /// The operation blocks until such a struct is found.
     ///
@@ -97,6 +184,28 @@ pub trait ObjectSpace {
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static;
 
+    /// Return a copy of a struct of type T.
+    /// The operation blocks until such a struct is found,
+    /// and returns None if none is found before the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// assert_eq!(space.read_timeout::<String>(Duration::from_millis(10)), None);
+    ///
+    /// space.write(String::from("Hello World"));
+    /// assert_eq!(
+    ///     space.read_timeout::<String>(Duration::from_millis(10)),
+    ///     Some(String::from("Hello World"))
+    /// );
+    /// ```
+    fn read_timeout<T>(&self, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
     /// Remove and return a struct of type T.
     /// The operation is non-blocking and will returns None if no struct satisfies condition.
     ///
@@ -155,6 +264,67 @@ pub trait ObjectSpace {
     fn take<T>(&self) -> T
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Remove and return a struct of type T.
+    /// The operation blocks until such a struct is found,
+    /// and returns None if none is found before the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Hello World"));
+    /// assert_eq!(
+    ///     space.take_timeout::<String>(Duration::from_millis(10)),
+    ///     Some(String::from("Hello World"))
+    /// );
+    /// assert_eq!(space.take_timeout::<String>(Duration::from_millis(10)), None);
+    /// ```
+    fn take_timeout<T>(&self, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Return a copy of the struct of type T whose handle was returned by `write`.
+    /// The operation is non-blocking and will return None if the struct has been taken.
+    ///
+    /// A handle is only meaningful for the type of the struct it was returned for.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// let hello = space.write(String::from("Hello"));
+    /// space.write(String::from("World"));
+    ///
+    /// assert_eq!(space.try_read_by_handle::<String>(hello), Some(String::from("Hello")));
+    /// ```
+    fn try_read_by_handle<T>(&self, handle: ObjectHandle) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Remove and return the struct of type T whose handle was returned by `write`.
+    /// The operation is non-blocking and will return None if the struct has been taken.
+    ///
+    /// A handle is only meaningful for the type of the struct it was returned for.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// let first = space.write::<i64>(3);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_take_by_handle::<i64>(first), Some(3));
+    /// assert_eq!(space.try_take_by_handle::<i64>(first), None);
+    /// assert_eq!(space.read_all::<i64>().count(), 1);
+    /// ```
+    fn try_take_by_handle<T>(&self, handle: ObjectHandle) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
 }
 
 /// An extension of `ObjectSpace` supporting retrieving structs by range of a field.
@@ -172,8 +342,8 @@ pub trait ObjectSpace {
 /// space.write::<i64>(3);
 /// space.write::<i64>(5);
 ///
-/// assert_eq!(space.try_read_by_range::<i64, _>("", 2..4), Some(3));
-/// assert_eq!(space.try_read_by_range::<i64, _>("", ..2), None);
+/// assert_eq!(space.try_read_by_range::<i64, _>("", 2i64..4i64), Some(3));
+/// assert_eq!(space.try_read_by_range::<i64, _>("", ..2i64), None);
 /// ```
 pub trait RangeLookupObjectSpace<U>: ObjectSpace {
     /// Given a path to an element of the struct and a range of possible values,
@@ -188,8 +358,8 @@ pub trait RangeLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.try_read_by_range::<i64, _>("", 2..4), Some(3));
-    /// assert_eq!(space.try_read_by_range::<i64, _>("", ..2), None);
+    /// assert_eq!(space.try_read_by_range::<i64, _>("", 2i64..4i64), Some(3));
+    /// assert_eq!(space.try_read_by_range::<i64, _>("", ..2i64), None);
     /// ```
     fn try_read_by_range<T, R>(&self, field: &str, range: R) -> Option<T>
     where
@@ -207,8 +377,8 @@ pub trait RangeLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.read_all_by_range::<i64, _>("", 2..4).count(), 1);
-    /// assert_eq!(space.read_all_by_range::<i64, _>("", 2..).count(), 2);
+    /// assert_eq!(space.read_all_by_range::<i64, _>("", 2i64..4i64).count(), 1);
+    /// assert_eq!(space.read_all_by_range::<i64, _>("", 2i64..).count(), 2);
     /// ```
     fn read_all_by_range<'a, T, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = T> + 'a>
     where
@@ -227,13 +397,36 @@ pub trait RangeLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.read_by_range::<i64, _>("", 2..4), 3);
+    /// assert_eq!(space.read_by_range::<i64, _>("", 2i64..4i64), 3);
     /// ```
     fn read_by_range<T, R>(&self, field: &str, range: R) -> T
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static,
         R: RangeBounds<U> + Clone;
 
+    /// Given a path to an element of the struct and a range of possible values,
+    /// return a copy of a struct whose specified element is within the range.
+    /// The operation blocks until a struct satisfies the condition is found,
+    /// and returns None if none is found before the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, RangeLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// let timeout = Duration::from_millis(10);
+    /// assert_eq!(space.read_by_range_timeout::<i64, _>("", 2i64..4i64, timeout), Some(3));
+    /// assert_eq!(space.read_by_range_timeout::<i64, _>("", ..2i64, timeout), None);
+    /// ```
+    fn read_by_range_timeout<T, R>(&self, field: &str, range: R, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<U> + Clone;
+
     /// Given a path to an element of the struct and a range of possible values,
     /// remove and return a struct whose specified element is within the range.
     /// The operation is non-blocking and will returns None if no struct satisfies condition.
@@ -246,9 +439,9 @@ pub trait RangeLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.try_take_by_range::<i64, _>("", 2..4), Some(3));
-    /// assert_eq!(space.try_take_by_range::<i64, _>("", 2..4), None);
-    /// assert_eq!(space.try_take_by_range::<i64, _>("", 2..), Some(5));
+    /// assert_eq!(space.try_take_by_range::<i64, _>("", 2i64..4i64), Some(3));
+    /// assert_eq!(space.try_take_by_range::<i64, _>("", 2i64..4i64), None);
+    /// assert_eq!(space.try_take_by_range::<i64, _>("", 2i64..), Some(5));
     /// ```
     fn try_take_by_range<T, R>(&self, field: &str, range: R) -> Option<T>
     where
@@ -266,8 +459,8 @@ pub trait RangeLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.take_all_by_range::<i64, _>("", 2..4).count(), 1);
-    /// assert_eq!(space.take_all_by_range::<i64, _>("", 2..).count(), 1);
+    /// assert_eq!(space.take_all_by_range::<i64, _>("", 2i64..4i64).count(), 1);
+    /// assert_eq!(space.take_all_by_range::<i64, _>("", 2i64..).count(), 1);
     /// ```
     fn take_all_by_range<'a, T, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = T> + 'a>
     where
@@ -286,13 +479,124 @@ pub trait RangeLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.take_by_range::<i64, _>("", 2..4), 3);
-    /// assert_eq!(space.take_by_range::<i64, _>("", 2..), 5);
+    /// assert_eq!(space.take_by_range::<i64, _>("", 2i64..4i64), 3);
+    /// assert_eq!(space.take_by_range::<i64, _>("", 2i64..), 5);
     /// ```
     fn take_by_range<T, R>(&self, field: &str, range: R) -> T
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static,
         R: RangeBounds<U> + Clone;
+
+    /// Given a path to an element of the struct and a range of possible values,
+    /// remove and return a struct whose specified element is within the range.
+    /// The operation blocks until a struct satisfies the condition is found,
+    /// and returns None if none is found before the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, RangeLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// let timeout = Duration::from_millis(10);
+    /// assert_eq!(space.take_by_range_timeout::<i64, _>("", 2i64..4i64, timeout), Some(3));
+    /// assert_eq!(space.take_by_range_timeout::<i64, _>("", ..2i64, timeout), None);
+    /// ```
+    fn take_by_range_timeout<T, R>(&self, field: &str, range: R, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<U> + Clone;
+
+    /// Like `try_read_by_range`, but return an error instead of panicking
+    /// if the field could not be looked up by the range, e.g: because it holds strings.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, RangeLookupObjectSpace, SpaceError};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_read_by_range_checked::<i64, _>("", 2i64..4i64), Ok(Some(3)));
+    /// assert_eq!(
+    ///     space.try_read_by_range_checked::<i64, _>("count", 2i64..4i64),
+    ///     Err(SpaceError::NoSuchField(String::from("count")))
+    /// );
+    /// ```
+    fn try_read_by_range_checked<T, R>(&self, field: &str, range: R) -> Result<Option<T>, SpaceError>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<U> + Clone;
+
+    /// Like `read_all_by_range`, but return an error instead of panicking
+    /// if the field could not be looked up by the range.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, RangeLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// assert_eq!(space.read_all_by_range_checked::<i64, _>("", 2i64..).map(|found| found.count()), Ok(2));
+    /// assert!(space.read_all_by_range_checked::<i64, _>("", String::from("a")..).is_err());
+    /// ```
+    fn read_all_by_range_checked<'a, T, R>(
+        &'a self,
+        field: &str,
+        range: R,
+    ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+        R: RangeBounds<U> + Clone;
+
+    /// Like `try_take_by_range`, but return an error instead of panicking
+    /// if the field could not be looked up by the range.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, RangeLookupObjectSpace, SpaceError};
+    /// let space = TreeObjectSpace::new();
+    /// space.hash_index::<i64>(&[""]);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(
+    ///     space.try_take_by_range_checked::<i64, _>("", 2i64..4i64),
+    ///     Err(SpaceError::HashIndexed(String::new()))
+    /// );
+    /// ```
+    fn try_take_by_range_checked<T, R>(&self, field: &str, range: R) -> Result<Option<T>, SpaceError>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<U> + Clone;
+
+    /// Like `take_all_by_range`, but return an error instead of panicking
+    /// if the field could not be looked up by the range.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, RangeLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// assert_eq!(space.take_all_by_range_checked::<i64, _>("", 2i64..4i64).map(|found| found.count()), Ok(1));
+    /// assert_eq!(space.read_all::<i64>().count(), 1);
+    /// ```
+    fn take_all_by_range_checked<'a, T, R>(
+        &'a self,
+        field: &str,
+        range: R,
+    ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+        R: RangeBounds<U> + Clone;
 }
 
 /// An extension of `ObjectSpace` supporting retrieving structs by value of a field.
@@ -310,8 +614,8 @@ pub trait RangeLookupObjectSpace<U>: ObjectSpace {
 /// space.write::<i64>(3);
 /// space.write::<i64>(5);
 ///
-/// assert_eq!(space.try_read_by_value::<i64>("", &3), Some(3));
-/// assert_eq!(space.try_read_by_value::<i64>("", &2), None);
+/// assert_eq!(space.try_read_by_value::<i64>("", &3i64), Some(3));
+/// assert_eq!(space.try_read_by_value::<i64>("", &2i64), None);
 /// ```
 pub trait ValueLookupObjectSpace<U>: ObjectSpace {
     /// Given a path to an element of the struct and a possible value,
@@ -326,8 +630,8 @@ pub trait ValueLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.try_read_by_value::<i64>("", &3), Some(3));
-    /// assert_eq!(space.try_read_by_value::<i64>("", &2), None);
+    /// assert_eq!(space.try_read_by_value::<i64>("", &3i64), Some(3));
+    /// assert_eq!(space.try_read_by_value::<i64>("", &2i64), None);
     /// ```
     fn try_read_by_value<T>(&self, field: &str, key: &U) -> Option<T>
     where
@@ -344,8 +648,8 @@ pub trait ValueLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.read_all_by_value::<i64>("", &3).count(), 1);
-    /// assert_eq!(space.read_all_by_value::<i64>("", &2).count(), 0);
+    /// assert_eq!(space.read_all_by_value::<i64>("", &3i64).count(), 1);
+    /// assert_eq!(space.read_all_by_value::<i64>("", &2i64).count(), 0);
     /// ```
     fn read_all_by_value<'a, T>(&'a self, field: &str, key: &U) -> Box<Iterator<Item = T> + 'a>
     where
@@ -363,12 +667,34 @@ pub trait ValueLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.read_by_value::<i64>("", &3), 3);
+    /// assert_eq!(space.read_by_value::<i64>("", &3i64), 3);
     /// ```
     fn read_by_value<T>(&self, field: &str, key: &U) -> T
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static;
 
+    /// Given a path to an element of the struct and a possible value,
+    /// return a copy of a struct whose specified element is equal to the value.
+    /// The operation blocks until a struct satisfies the condition is found,
+    /// and returns None if none is found before the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// let timeout = Duration::from_millis(10);
+    /// assert_eq!(space.read_by_value_timeout::<i64>("", &3i64, timeout), Some(3));
+    /// assert_eq!(space.read_by_value_timeout::<i64>("", &4i64, timeout), None);
+    /// ```
+    fn read_by_value_timeout<T>(&self, field: &str, key: &U, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
     /// Given a path to an element of the struct and a possible value,
     /// remove and return a struct whose specified element of the specified value.
     /// The operation is non-blocking and will returns None if no struct satisfies condition.
@@ -381,9 +707,9 @@ pub trait ValueLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.try_take_by_value::<i64>("", &3), Some(3));
-    /// assert_eq!(space.try_take_by_value::<i64>("", &3), None);
-    /// assert_eq!(space.try_take_by_value::<i64>("", &4), None);
+    /// assert_eq!(space.try_take_by_value::<i64>("", &3i64), Some(3));
+    /// assert_eq!(space.try_take_by_value::<i64>("", &3i64), None);
+    /// assert_eq!(space.try_take_by_value::<i64>("", &4i64), None);
     /// ```
     fn try_take_by_value<T>(&self, field: &str, key: &U) -> Option<T>
     where
@@ -400,8 +726,8 @@ pub trait ValueLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.take_all_by_value::<i64>("", &3).count(), 1);
-    /// assert_eq!(space.take_all_by_value::<i64>("", &4).count(), 0);
+    /// assert_eq!(space.take_all_by_value::<i64>("", &3i64).count(), 1);
+    /// assert_eq!(space.take_all_by_value::<i64>("", &4i64).count(), 0);
     /// ```
     fn take_all_by_value<'a, T>(&'a self, field: &str, key: &U) -> Box<Iterator<Item = T> + 'a>
     where
@@ -419,609 +745,9679 @@ pub trait ValueLookupObjectSpace<U>: ObjectSpace {
     /// space.write::<i64>(3);
     /// space.write::<i64>(5);
     ///
-    /// assert_eq!(space.take_by_value::<i64>("", &3), 3);
+    /// assert_eq!(space.take_by_value::<i64>("", &3i64), 3);
     /// ```
     fn take_by_value<T>(&self, field: &str, key: &U) -> T
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static;
-}
-
-type Lock = Arc<(Mutex<bool>, Condvar)>;
-
-/// A thread-safe reference `ObjectSpace` implementation
-///
-/// # Implementation
-///
-/// A `TreeObjectSpace` is a `HashMap` between a `TypeId`
-/// and the actual `Entry` structure holding the structs.
-/// Before structs are stored in `Entry`,
-/// they are serialized into a JSON-like structure and then flattened.
-///
-/// An `Entry` is a `HashMap` whose key is a flattened field and
-/// value is a `BTreeMap` between possible values of the field
-/// and the `Vec` of structs containing the corresponding value of such field.
-///
-/// `Mutex` is used sparingly to ensure blocking `read` and `take` calls do not hijack CPU cycles
-#[derive(Default)]
-pub struct TreeObjectSpace {
-    typeid_entries_dict: CHashMap<TypeId, Entry>,
-    lock_dict: CHashMap<TypeId, Lock>,
-}
-
-impl TreeObjectSpace {
-    pub fn new() -> TreeObjectSpace {
-        Default::default()
-    }
 
-    fn get_object_entry_ref<T>(&self) -> Option<ReadGuard<TypeId, Entry>>
+    /// Given a path to an element of the struct and a possible value,
+    /// remove and return a struct whose specified element is equal to the value.
+    /// The operation blocks until a struct satisfies the condition is found,
+    /// and returns None if none is found before the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// let timeout = Duration::from_millis(10);
+    /// assert_eq!(space.take_by_value_timeout::<i64>("", &3i64, timeout), Some(3));
+    /// assert_eq!(space.take_by_value_timeout::<i64>("", &4i64, timeout), None);
+    /// ```
+    fn take_by_value_timeout<T>(&self, field: &str, key: &U, timeout: Duration) -> Option<T>
     where
-        T: 'static,
-    {
-        let type_id = TypeId::of::<T>();
-        self.typeid_entries_dict.get(&type_id)
-    }
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
 
-    fn get_object_entry_mut<T>(&self) -> Option<WriteGuard<TypeId, Entry>>
+    /// Like `try_read_by_value`, but return an error instead of panicking
+    /// if the field could not be looked up by the value, e.g: because it holds strings.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace, SpaceError};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_read_by_value_checked::<i64>("", &3i64), Ok(Some(3)));
+    /// assert_eq!(
+    ///     space.try_read_by_value_checked::<i64>("count", &3i64),
+    ///     Err(SpaceError::NoSuchField(String::from("count")))
+    /// );
+    /// ```
+    fn try_read_by_value_checked<T>(&self, field: &str, key: &U) -> Result<Option<T>, SpaceError>
     where
-        T: 'static,
-    {
-        let type_id = TypeId::of::<T>();
-        self.typeid_entries_dict.get_mut(&type_id)
-    }
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
 
-    fn get_lock<T>(&self) -> Option<ReadGuard<TypeId, Lock>>
+    /// Like `read_all_by_value`, but return an error instead of panicking
+    /// if the field could not be looked up by the value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.read_all_by_value_checked::<i64>("", &3i64).map(|found| found.count()), Ok(2));
+    /// assert!(space.read_all_by_value_checked::<i64>("", &String::from("3")).is_err());
+    /// ```
+    fn read_all_by_value_checked<'a, T>(
+        &'a self,
+        field: &str,
+        key: &U,
+    ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
     where
-        T: 'static,
-    {
-        let type_id = TypeId::of::<T>();
-        self.lock_dict.get(&type_id)
-    }
-
-    fn add_entry(&self, id: TypeId) {
-        let default_value = Entry::new();
-
-        self.typeid_entries_dict
-            .upsert(id, || default_value, |_| ());
-        self.lock_dict
-            .upsert(id, || Arc::new((Mutex::new(false), Condvar::new())), |_| ());
-    }
-}
+        for<'de> T: Deserialize<'de> + 'static;
 
-impl ObjectSpace for TreeObjectSpace {
-    fn write<T>(&self, obj: T)
-    where
+    /// Like `try_take_by_value`, but return an error instead of panicking
+    /// if the field could not be looked up by the value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace, SpaceError};
+    /// let space = TreeObjectSpace::new();
+    /// space.index_fields::<i64>(&[""]);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_take_by_value_checked::<i64>("", &3i64), Ok(Some(3)));
+    /// assert_eq!(
+    ///     space.try_take_by_value_checked::<i64>("count", &3i64),
+    ///     Err(SpaceError::NotIndexed(String::from("count")))
+    /// );
+    /// ```
+    fn try_take_by_value_checked<T>(&self, field: &str, key: &U) -> Result<Option<T>, SpaceError>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Like `take_all_by_value`, but return an error instead of panicking
+    /// if the field could not be looked up by the value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// assert_eq!(space.take_all_by_value_checked::<i64>("", &3i64).map(|found| found.count()), Ok(1));
+    /// assert_eq!(space.read_all::<i64>().count(), 1);
+    /// ```
+    fn take_all_by_value_checked<'a, T>(
+        &'a self,
+        field: &str,
+        key: &U,
+    ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+    where
+        for<'de> T: Deserialize<'de> + 'static;
+}
+
+/// An extension of `ValueLookupObjectSpace` supporting updating a struct atomically.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace, UpdateObjectSpace};
+/// let space = TreeObjectSpace::new();
+/// space.write::<i64>(3);
+///
+/// assert!(space.update_by_value::<i64, _>("", &3i64, |i| i * 2));
+/// assert_eq!(space.try_read_by_value::<i64>("", &6i64), Some(6));
+/// ```
+pub trait UpdateObjectSpace<U>: ValueLookupObjectSpace<U> {
+    /// Given a path to an element of the struct and a possible value,
+    /// replace a struct whose specified element is equal to the value by the result of `f`.
+    /// The result keeps the lease of the struct, if any.
+    /// Return false and leave the space untouched if no struct satisfies the condition,
+    /// or if the result could not be written, e.g: because it would exceed the memory quota of its type,
+    /// which is not waited for whatever the overflow, or is kept aside as a dead letter.
+    ///
+    /// No other operation on type T could happen between taking the struct and writing the result,
+    /// so `f` must not use the space itself.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, UpdateObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Hello"));
+    ///
+    /// assert!(space.update_by_value::<String, _>("", &String::from("Hello"), |s| s + " World"));
+    /// assert!(!space.update_by_value::<String, _>("", &String::from("Hello"), |s| s));
+    /// assert_eq!(space.try_read::<String>(), Some(String::from("Hello World")));
+    /// ```
+    fn update_by_value<T, F>(&self, field: &str, key: &U, f: F) -> bool
+    where
         for<'de> T: Serialize + Deserialize<'de> + 'static,
-    {
-        let type_id = TypeId::of::<T>();
-        self.add_entry(type_id);
-        let &(ref lock, ref cvar) = &*self.get_lock::<T>().unwrap().clone();
-        let value = flatten(to_value(obj).expect("struct cannot be serialized"));
-        let mut status = lock.lock().unwrap();
-        *status = !*status;
-        self.typeid_entries_dict
-            .get_mut(&type_id)
-            .unwrap()
-            .add(value);
-        cvar.notify_all();
-    }
+        F: FnOnce(T) -> T;
+}
 
-    fn try_read<T>(&self) -> Option<T>
+/// An extension of `ObjectSpace` supporting retrieving structs by a `Query`
+/// combining conditions on several fields.
+///
+/// A query is built with `field`, e.g: `field("count").eq(3).or(field("name").eq("Tuan"))`.
+/// A struct satisfying several parts of a query is only returned once.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::{field, TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+/// let space = TreeObjectSpace::new();
+/// space.write::<i64>(3);
+/// space.write::<i64>(5);
+///
+/// let query = field("").eq(3).or(field("").gt(4));
+/// assert_eq!(space.read_all_by_query::<i64>(&query).count(), 2);
+/// ```
+pub trait QueryObjectSpace: ObjectSpace {
+    /// Return a copy of a struct satisfying the query.
+    /// The operation is non-blocking and will returns None if no struct satisfies the query.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{field, TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// assert_eq!(space.try_read_by_query::<i64>(&field("").eq(5).or(field("").eq(7))), Some(5));
+    /// assert_eq!(space.try_read_by_query::<i64>(&field("").lt(2)), None);
+    /// ```
+    fn try_read_by_query<T>(&self, query: &Query) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Return copies of all structs satisfying the query.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{field, TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// assert_eq!(space.read_all_by_query::<i64>(&field("").eq(3).or(field("").le(3))).count(), 1);
+    /// ```
+    fn read_all_by_query<'a, T>(&'a self, query: &Query) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static;
+
+    /// Return a copy of a struct satisfying the query.
+    /// The operation blocks until such a struct is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{field, TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.read_by_query::<i64>(&field("").eq(3).or(field("").eq(5))), 3);
+    /// ```
+    fn read_by_query<T>(&self, query: &Query) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Remove and return a struct satisfying the query.
+    /// The operation is non-blocking and will returns None if no struct satisfies the query.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{field, TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    ///
+    /// let query = field("").eq(3).or(field("").eq(5));
+    /// assert_eq!(space.try_take_by_query::<i64>(&query), Some(3));
+    /// assert_eq!(space.try_take_by_query::<i64>(&query), None);
+    /// ```
+    fn try_take_by_query<T>(&self, query: &Query) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Remove and return all structs satisfying the query.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{field, TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// assert_eq!(space.take_all_by_query::<i64>(&field("").eq(3).or(field("").eq(5))).count(), 2);
+    /// assert_eq!(space.try_read::<i64>(), None);
+    /// ```
+    fn take_all_by_query<'a, T>(&'a self, query: &Query) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static;
+
+    /// Remove and return a struct satisfying the query.
+    /// The operation blocks until such a struct is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{field, TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(5);
+    ///
+    /// assert_eq!(space.take_by_query::<i64>(&field("").eq(3).or(field("").eq(5))), 5);
+    /// ```
+    fn take_by_query<T>(&self, query: &Query) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Return a copy of a struct whose fields all equal the specified values.
+    /// The operation is non-blocking and will returns None if no struct matches.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{FieldValue, TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Task {
+    ///     status: String,
+    ///     priority: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Task { status: String::from("pending"), priority: 5 });
+    /// space.write(Task { status: String::from("pending"), priority: 3 });
+    ///
+    /// let fields = [("status", FieldValue::from("pending")), ("priority", FieldValue::from(3))];
+    /// let task = space.try_read_by_match::<Task>(&fields).unwrap();
+    /// assert_eq!(task.priority, 3);
+    /// # }
+    /// ```
+    fn try_read_by_match<T>(&self, fields: &[(&str, FieldValue)]) -> Option<T>
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static,
     {
-        let value = match self.get_object_entry_ref::<T>() {
-            Some(entry) => entry.get(),
-            _ => None,
-        };
-        match value {
-            Some(val) => from_value(deflatten(val)).ok(),
-            _ => None,
-        }
+        self.try_read_by_query(&Query::matching(fields))
     }
 
-    fn read_all<'a, T>(&'a self) -> Box<Iterator<Item = T> + 'a>
+    /// Return copies of all structs whose fields all equal the specified values.
+    fn read_all_by_match<'a, T>(&'a self, fields: &[(&str, FieldValue)]) -> Box<Iterator<Item = T> + 'a>
     where
-        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        for<'de> T: Deserialize<'de> + 'static,
     {
-        let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
-            Some(ent) => ent.get_all().collect(),
-            None => Vec::new(),
-        };
-
-        Box::new(
-            val_iter
-                .into_iter()
-                .filter_map(|item| from_value(deflatten(item)).ok()),
-        )
+        self.read_all_by_query(&Query::matching(fields))
     }
 
-    fn read<T>(&self) -> T
+    /// Return a copy of a struct whose fields all equal the specified values.
+    /// The operation blocks until such a struct is found.
+    fn read_by_match<T>(&self, fields: &[(&str, FieldValue)]) -> T
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static,
     {
-        self.add_entry(TypeId::of::<T>());
-        let &(ref lock, ref cvar) = &*self.get_lock::<T>().unwrap().clone();
-        let value;
-        {
-            let mut fetched = lock.lock().unwrap();
-            loop {
-                let result = match self.get_object_entry_ref::<T>() {
-                    Some(entry) => entry.get(),
-                    _ => None,
-                };
-                if let Some(item) = result {
-                    value = item;
-                    break;
-                }
-                fetched = cvar.wait(fetched).unwrap();
-            }
-        }
-        from_value(deflatten(value)).unwrap()
+        self.read_by_query(&Query::matching(fields))
     }
 
-    fn try_take<T>(&self) -> Option<T>
+    /// Atomically remove and return a struct whose fields all equal the specified values.
+    /// The operation is non-blocking and will returns None if no struct matches.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{FieldValue, TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Task {
+    ///     status: String,
+    ///     priority: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Task { status: String::from("pending"), priority: 5 });
+    /// space.write(Task { status: String::from("done"), priority: 3 });
+    ///
+    /// let fields = [("status", FieldValue::from("pending")), ("priority", FieldValue::from(3))];
+    /// assert!(space.try_take_by_match::<Task>(&fields).is_none());
+    /// assert_eq!(space.read_all::<Task>().count(), 2);
+    /// # }
+    /// ```
+    fn try_take_by_match<T>(&self, fields: &[(&str, FieldValue)]) -> Option<T>
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static,
     {
-        let value = match self.get_object_entry_mut::<T>() {
-            Some(mut entry) => entry.remove(),
-            _ => None,
-        };
-        match value {
-            Some(val) => from_value(deflatten(val)).ok(),
-            _ => None,
-        }
+        self.try_take_by_query(&Query::matching(fields))
     }
 
-    fn take_all<'a, T>(&'a self) -> Box<Iterator<Item = T> + 'a>
+    /// Remove and return all structs whose fields all equal the specified values.
+    fn take_all_by_match<'a, T>(&'a self, fields: &[(&str, FieldValue)]) -> Box<Iterator<Item = T> + 'a>
     where
-        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        for<'de> T: Deserialize<'de> + 'static,
     {
-        let val_iter = match self.get_object_entry_mut::<T>() {
-            Some(mut ent) => ent.remove_all(),
-            None => Vec::new(),
-        };
-
-        Box::new(
-            val_iter
-                .into_iter()
-                .filter_map(|item| from_value(deflatten(item)).ok()),
-        )
+        self.take_all_by_query(&Query::matching(fields))
     }
 
-    fn take<T>(&self) -> T
+    /// Atomically remove and return a struct whose fields all equal the specified values.
+    /// The operation blocks until such a struct is found.
+    fn take_by_match<T>(&self, fields: &[(&str, FieldValue)]) -> T
     where
         for<'de> T: Serialize + Deserialize<'de> + 'static,
     {
-        self.add_entry(TypeId::of::<T>());
-        let &(ref lock, ref cvar) = &*self.get_lock::<T>().unwrap().clone();
-        let value;
-        {
-            let mut fetched = lock.lock().unwrap();
-            loop {
-                let result = match self.get_object_entry_mut::<T>() {
-                    Some(mut entry) => entry.remove(),
-                    _ => None,
-                };
-                if let Some(item) = result {
-                    value = item;
-                    break;
-                }
-                fetched = cvar.wait(fetched).unwrap();
-            }
-        }
-        from_value(deflatten(value)).unwrap()
+        self.take_by_query(&Query::matching(fields))
     }
 }
 
-macro_rules! object_range{
-    ($($ty:ident)*) => {
-        $(
-            impl RangeLookupObjectSpace<$ty> for TreeObjectSpace {
-                fn try_read_by_range<T, R>(&self, field: &str, range: R) -> Option<T>
-                where
-                    for<'de> T: Serialize + Deserialize<'de> + 'static,
-                    R: RangeBounds<$ty> + Clone,
-                {
-                    let value = match self.get_object_entry_ref::<T>() {
-                        Some(entry) => entry.get_by_range::<_>(field, range),
-                        _ => None,
-                    };
-                    match value {
-                        Some(val) => from_value(deflatten(val)).ok(),
-                        _ => None,
-                    }
-                }
+/// An extension of `ObjectSpace` supporting retrieving structs by an interval stored in two fields.
+///
+/// Given a type `T` with two fields (might be nested) of type `U` holding the start and the end of a range,
+/// paths to the two fields and a value of type `U`,
+/// an `IntervalLookupObjectSpace<U>` could retrieve structs of type `T`
+/// whose range `start..end` contains the specified value.
+///
+/// Such queries are answered by an interval tree if one is declared with `TreeObjectSpace::interval_index`,
+/// and by scanning all structs of type `T` otherwise.
+///
+/// # Example
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate object_space;
+/// # use object_space::{TreeObjectSpace, ObjectSpace, IntervalLookupObjectSpace};
+/// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// struct Task {
+///     start: i64,
+///     end: i64,
+/// }
+///
+/// # fn main() {
+/// let space = TreeObjectSpace::new();
+/// space.write(Task { start: 0, end: 10 });
+/// space.write(Task { start: 5, end: 20 });
+///
+/// space.interval_index::<Task>(("start", "end"));
+/// assert_eq!(space.read_all_covering::<Task>(("start", "end"), &7).count(), 2);
+/// assert_eq!(space.read_all_covering::<Task>(("start", "end"), &10).count(), 1);
+/// # }
+/// ```
+pub trait IntervalLookupObjectSpace<U>: ObjectSpace {
+    /// Given paths to the start and end of a range and a value,
+    /// return a copy of a struct whose range contains the value.
+    /// The operation is non-blocking and will returns None if no struct satisfies condition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, IntervalLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Task {
+    ///     start: i64,
+    ///     end: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Task { start: 0, end: 10 });
+    /// space.write(Task { start: 5, end: 20 });
+    ///
+    /// assert_eq!(
+    ///     space.try_read_covering::<Task>(("start", "end"), &12),
+    ///     Some(Task { start: 5, end: 20 })
+    /// );
+    /// assert_eq!(space.try_read_covering::<Task>(("start", "end"), &20), None);
+    /// # }
+    /// ```
+    fn try_read_covering<T>(&self, fields: (&str, &str), point: &U) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
 
-                fn read_all_by_range<'a, T, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = T> + 'a>
-                where
+    /// Given paths to the start and end of a range and a value,
+    /// return copies of all structs whose range contains the value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, IntervalLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Task {
+    ///     start: i64,
+    ///     end: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Task { start: 0, end: 10 });
+    /// space.write(Task { start: 5, end: 20 });
+    ///
+    /// assert_eq!(space.read_all_covering::<Task>(("start", "end"), &7).count(), 2);
+    /// assert_eq!(space.read_all_covering::<Task>(("start", "end"), &20).count(), 0);
+    /// # }
+    /// ```
+    fn read_all_covering<'a, T>(
+        &'a self,
+        fields: (&str, &str),
+        point: &U,
+    ) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static;
+
+    /// Given paths to the start and end of a range and a value,
+    /// return a copy of a struct whose range contains the value.
+    /// The operation blocks until a struct satisfies the condition is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, IntervalLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Task {
+    ///     start: i64,
+    ///     end: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Task { start: 0, end: 10 });
+    /// space.write(Task { start: 5, end: 20 });
+    ///
+    /// assert_eq!(
+    ///     space.read_covering::<Task>(("start", "end"), &3),
+    ///     Task { start: 0, end: 10 }
+    /// );
+    /// # }
+    /// ```
+    fn read_covering<T>(&self, fields: (&str, &str), point: &U) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given paths to the start and end of a range and a value,
+    /// remove and return a struct whose range contains the value.
+    /// The operation is non-blocking and will returns None if no struct satisfies condition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, IntervalLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Task {
+    ///     start: i64,
+    ///     end: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Task { start: 0, end: 10 });
+    /// space.write(Task { start: 5, end: 20 });
+    ///
+    /// assert_eq!(
+    ///     space.try_take_covering::<Task>(("start", "end"), &3),
+    ///     Some(Task { start: 0, end: 10 })
+    /// );
+    /// assert_eq!(space.try_take_covering::<Task>(("start", "end"), &3), None);
+    /// # }
+    /// ```
+    fn try_take_covering<T>(&self, fields: (&str, &str), point: &U) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given paths to the start and end of a range and a value,
+    /// remove and return all structs whose range contains the value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, IntervalLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Task {
+    ///     start: i64,
+    ///     end: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Task { start: 0, end: 10 });
+    /// space.write(Task { start: 5, end: 20 });
+    ///
+    /// assert_eq!(space.take_all_covering::<Task>(("start", "end"), &7).count(), 2);
+    /// assert_eq!(space.take_all_covering::<Task>(("start", "end"), &7).count(), 0);
+    /// # }
+    /// ```
+    fn take_all_covering<'a, T>(
+        &'a self,
+        fields: (&str, &str),
+        point: &U,
+    ) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static;
+
+    /// Given paths to the start and end of a range and a value,
+    /// remove and return a struct whose range contains the value.
+    /// The operation blocks until a struct satisfies the condition is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, IntervalLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Task {
+    ///     start: i64,
+    ///     end: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Task { start: 0, end: 10 });
+    /// space.write(Task { start: 5, end: 20 });
+    ///
+    /// assert_eq!(
+    ///     space.take_covering::<Task>(("start", "end"), &12),
+    ///     Task { start: 5, end: 20 }
+    /// );
+    /// # }
+    /// ```
+    fn take_covering<T>(&self, fields: (&str, &str), point: &U) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+}
+
+/// An extension of `ObjectSpace` supporting retrieving structs by a point stored in two numeric fields.
+///
+/// Given a type `T` with two numeric fields (might be nested),
+/// paths to the two fields and a range for each of them,
+/// a `SpatialLookupObjectSpace` could retrieve structs of type `T`
+/// whose point lies within the rectangle bounded by the ranges.
+///
+/// Such queries are answered by a quadtree if one is declared with `TreeObjectSpace::spatial_index`,
+/// and by scanning all structs of type `T` otherwise.
+///
+/// # Example
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate object_space;
+/// # use object_space::{TreeObjectSpace, ObjectSpace, SpatialLookupObjectSpace};
+/// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// struct Place {
+///     lat: f64,
+///     lon: f64,
+/// }
+///
+/// # fn main() {
+/// let space = TreeObjectSpace::new();
+/// space.write(Place { lat: 42.7, lon: -73.2 });
+/// space.write(Place { lat: 40.7, lon: -74.0 });
+///
+/// space.spatial_index::<Place>(("lat", "lon"));
+/// assert_eq!(space.read_all_in_rect::<Place, _>(("lat", "lon"), (40.0..43.0, -75.0..-73.0)).count(), 2);
+/// assert_eq!(space.read_all_in_rect::<Place, _>(("lat", "lon"), (42.0..43.0, -75.0..-73.0)).count(), 1);
+/// # }
+/// ```
+pub trait SpatialLookupObjectSpace: ObjectSpace {
+    /// Given paths to two numeric fields and a range for each of them,
+    /// return a copy of a struct whose point lies within the ranges.
+    /// The operation is non-blocking and will returns None if no struct satisfies condition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, SpatialLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Place {
+    ///     lat: f64,
+    ///     lon: f64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Place { lat: 42.7, lon: -73.2 });
+    /// space.write(Place { lat: 40.7, lon: -74.0 });
+    ///
+    /// assert_eq!(
+    ///     space.try_read_in_rect::<Place, _>(("lat", "lon"), (40.0..41.0, -75.0..-73.0)),
+    ///     Some(Place { lat: 40.7, lon: -74.0 })
+    /// );
+    /// assert_eq!(space.try_read_in_rect::<Place, _>(("lat", "lon"), (0.0..1.0, 0.0..1.0)), None);
+    /// # }
+    /// ```
+    fn try_read_in_rect<T, R>(&self, fields: (&str, &str), bounds: (R, R)) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone;
+
+    /// Given paths to two numeric fields and a range for each of them,
+    /// return copies of all structs whose point lies within the ranges.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, SpatialLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Place {
+    ///     lat: f64,
+    ///     lon: f64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Place { lat: 42.7, lon: -73.2 });
+    /// space.write(Place { lat: 40.7, lon: -74.0 });
+    ///
+    /// assert_eq!(space.read_all_in_rect::<Place, _>(("lat", "lon"), (40.0..50.0, -80.0..-73.0)).count(), 2);
+    /// assert_eq!(space.read_all_in_rect::<Place, _>(("lat", "lon"), (41.0..50.0, -80.0..-74.0)).count(), 0);
+    /// # }
+    /// ```
+    fn read_all_in_rect<'a, T, R>(
+        &'a self,
+        fields: (&str, &str),
+        bounds: (R, R),
+    ) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone;
+
+    /// Given paths to two numeric fields and a range for each of them,
+    /// return a copy of a struct whose point lies within the ranges.
+    /// The operation blocks until a struct satisfies the condition is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, SpatialLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Place {
+    ///     lat: f64,
+    ///     lon: f64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Place { lat: 42.7, lon: -73.2 });
+    /// space.write(Place { lat: 40.7, lon: -74.0 });
+    ///
+    /// assert_eq!(
+    ///     space.read_in_rect::<Place, _>(("lat", "lon"), (42.0..43.0, -74.0..-73.0)),
+    ///     Place { lat: 42.7, lon: -73.2 }
+    /// );
+    /// # }
+    /// ```
+    fn read_in_rect<T, R>(&self, fields: (&str, &str), bounds: (R, R)) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone;
+
+    /// Given paths to two numeric fields and a range for each of them,
+    /// remove and return a struct whose point lies within the ranges.
+    /// The operation is non-blocking and will returns None if no struct satisfies condition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, SpatialLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Place {
+    ///     lat: f64,
+    ///     lon: f64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Place { lat: 42.7, lon: -73.2 });
+    /// space.write(Place { lat: 40.7, lon: -74.0 });
+    ///
+    /// assert_eq!(
+    ///     space.try_take_in_rect::<Place, _>(("lat", "lon"), (40.0..41.0, -75.0..-73.0)),
+    ///     Some(Place { lat: 40.7, lon: -74.0 })
+    /// );
+    /// assert_eq!(space.try_take_in_rect::<Place, _>(("lat", "lon"), (40.0..41.0, -75.0..-73.0)), None);
+    /// # }
+    /// ```
+    fn try_take_in_rect<T, R>(&self, fields: (&str, &str), bounds: (R, R)) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone;
+
+    /// Given paths to two numeric fields and a range for each of them,
+    /// remove and return all structs whose point lies within the ranges.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, SpatialLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Place {
+    ///     lat: f64,
+    ///     lon: f64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Place { lat: 42.7, lon: -73.2 });
+    /// space.write(Place { lat: 40.7, lon: -74.0 });
+    ///
+    /// assert_eq!(space.take_all_in_rect::<Place, _>(("lat", "lon"), (40.0..50.0, -80.0..-73.0)).count(), 2);
+    /// assert_eq!(space.take_all_in_rect::<Place, _>(("lat", "lon"), (40.0..50.0, -80.0..-73.0)).count(), 0);
+    /// # }
+    /// ```
+    fn take_all_in_rect<'a, T, R>(
+        &'a self,
+        fields: (&str, &str),
+        bounds: (R, R),
+    ) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone;
+
+    /// Given paths to two numeric fields and a range for each of them,
+    /// remove and return a struct whose point lies within the ranges.
+    /// The operation blocks until a struct satisfies the condition is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, SpatialLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Place {
+    ///     lat: f64,
+    ///     lon: f64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Place { lat: 42.7, lon: -73.2 });
+    /// space.write(Place { lat: 40.7, lon: -74.0 });
+    ///
+    /// assert_eq!(
+    ///     space.take_in_rect::<Place, _>(("lat", "lon"), (42.0..43.0, -74.0..-73.0)),
+    ///     Place { lat: 42.7, lon: -73.2 }
+    /// );
+    /// # }
+    /// ```
+    fn take_in_rect<T, R>(&self, fields: (&str, &str), bounds: (R, R)) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone;
+}
+
+/// An extension of `ObjectSpace` supporting retrieving structs by a string field
+/// starting with a prefix, or matching a glob pattern.
+///
+/// In a pattern, `*` matches any sequence of characters, `?` matches any single character,
+/// and `\` makes the next character match itself.
+/// Only the structs whose field starts with the characters before the first wildcard are looked at,
+/// so that a pattern starting with a few characters does not scan every struct of the type.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+/// let space = TreeObjectSpace::new();
+/// space.write(String::from("Tuan"));
+/// space.write(String::from("Tran"));
+///
+/// assert_eq!(space.try_read_by_prefix::<String>("", "Tu"), Some(String::from("Tuan")));
+/// assert_eq!(space.read_all_by_pattern::<String>("", "T*n").count(), 2);
+/// ```
+pub trait PatternLookupObjectSpace: ObjectSpace {
+    /// Given a path to a string field and a prefix,
+    /// return a copy of a struct whose field starts with the prefix.
+    /// The operation is non-blocking and will returns None if no struct satisfies condition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.try_read_by_prefix::<String>("", "Tu"), Some(String::from("Tuan")));
+    /// assert_eq!(space.try_read_by_prefix::<String>("", "Ng"), None);
+    /// ```
+    fn try_read_by_prefix<T>(&self, field: &str, prefix: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a prefix,
+    /// return copies of all structs whose field starts with the prefix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.read_all_by_prefix::<String>("", "T").count(), 2);
+    /// assert_eq!(space.read_all_by_prefix::<String>("", "Tu").count(), 1);
+    /// ```
+    fn read_all_by_prefix<'a, T>(&'a self, field: &str, prefix: &str) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a prefix,
+    /// return a copy of a struct whose field starts with the prefix.
+    /// The operation blocks until a struct satisfies the condition is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.read_by_prefix::<String>("", "Tr"), String::from("Tran"));
+    /// ```
+    fn read_by_prefix<T>(&self, field: &str, prefix: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a prefix,
+    /// remove and return a struct whose field starts with the prefix.
+    /// The operation is non-blocking and will returns None if no struct satisfies condition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.try_take_by_prefix::<String>("", "Tu"), Some(String::from("Tuan")));
+    /// assert_eq!(space.try_take_by_prefix::<String>("", "Tu"), None);
+    /// ```
+    fn try_take_by_prefix<T>(&self, field: &str, prefix: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a prefix,
+    /// remove and return all structs whose field starts with the prefix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.take_all_by_prefix::<String>("", "T").count(), 2);
+    /// assert_eq!(space.take_all_by_prefix::<String>("", "T").count(), 0);
+    /// ```
+    fn take_all_by_prefix<'a, T>(&'a self, field: &str, prefix: &str) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a prefix,
+    /// remove and return a struct whose field starts with the prefix.
+    /// The operation blocks until a struct satisfies the condition is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.take_by_prefix::<String>("", "Tr"), String::from("Tran"));
+    /// assert_eq!(space.try_read::<String>(), Some(String::from("Tuan")));
+    /// ```
+    fn take_by_prefix<T>(&self, field: &str, prefix: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a pattern,
+    /// return a copy of a struct whose field matches the pattern.
+    /// The operation is non-blocking and will returns None if no struct satisfies condition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.try_read_by_pattern::<String>("", "?uan"), Some(String::from("Tuan")));
+    /// assert_eq!(space.try_read_by_pattern::<String>("", "Ng*"), None);
+    /// ```
+    fn try_read_by_pattern<T>(&self, field: &str, pattern: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a pattern,
+    /// return copies of all structs whose field matches the pattern.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.read_all_by_pattern::<String>("", "T*n").count(), 2);
+    /// assert_eq!(space.read_all_by_pattern::<String>("", "?uan").count(), 1);
+    /// ```
+    fn read_all_by_pattern<'a, T>(&'a self, field: &str, pattern: &str) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a pattern,
+    /// return a copy of a struct whose field matches the pattern.
+    /// The operation blocks until a struct satisfies the condition is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.read_by_pattern::<String>("", "*ran"), String::from("Tran"));
+    /// ```
+    fn read_by_pattern<T>(&self, field: &str, pattern: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a pattern,
+    /// remove and return a struct whose field matches the pattern.
+    /// The operation is non-blocking and will returns None if no struct satisfies condition.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.try_take_by_pattern::<String>("", "?uan"), Some(String::from("Tuan")));
+    /// assert_eq!(space.try_take_by_pattern::<String>("", "?uan"), None);
+    /// ```
+    fn try_take_by_pattern<T>(&self, field: &str, pattern: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a pattern,
+    /// remove and return all structs whose field matches the pattern.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.take_all_by_pattern::<String>("", "T*n").count(), 2);
+    /// assert_eq!(space.take_all_by_pattern::<String>("", "T*n").count(), 0);
+    /// ```
+    fn take_all_by_pattern<'a, T>(&'a self, field: &str, pattern: &str) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static;
+
+    /// Given a path to a string field and a pattern,
+    /// remove and return a struct whose field matches the pattern.
+    /// The operation blocks until a struct satisfies the condition is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, PatternLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Tuan"));
+    /// space.write(String::from("Tran"));
+    ///
+    /// assert_eq!(space.take_by_pattern::<String>("", "*ran"), String::from("Tran"));
+    /// assert_eq!(space.try_read::<String>(), Some(String::from("Tuan")));
+    /// ```
+    fn take_by_pattern<T>(&self, field: &str, pattern: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+}
+
+/// An extension of `ObjectSpace` supporting retrieving structs by the distance of a numeric field to a value.
+///
+/// Given a type `T` with a numeric field (might be nested) of type `U`,
+/// a path to a field of type `U` and a target value of type `U`,
+/// a `NearestLookupObjectSpace<U>` could retrieve the struct of type `T`
+/// whose value of the specified field is closest to the target.
+/// If two values are equally close, the smaller one is chosen.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::{TreeObjectSpace, ObjectSpace, NearestLookupObjectSpace};
+/// let space = TreeObjectSpace::new();
+/// space.write::<i64>(3);
+/// space.write::<i64>(10);
+///
+/// assert_eq!(space.try_read_nearest::<i64>("", &5), Some(3));
+/// assert_eq!(space.try_read_nearest::<i64>("", &7), Some(10));
+/// ```
+pub trait NearestLookupObjectSpace<U>: ObjectSpace {
+    /// Given a path to an element of the struct and a target value,
+    /// return a copy of a struct whose specified element is closest to the target.
+    /// The operation is non-blocking and will returns None if there is no struct.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, NearestLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// assert_eq!(space.try_read_nearest::<i64>("", &5), None);
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(10);
+    ///
+    /// assert_eq!(space.try_read_nearest::<i64>("", &5), Some(3));
+    /// assert_eq!(space.try_read_nearest::<i64>("", &100), Some(10));
+    /// ```
+    fn try_read_nearest<T>(&self, field: &str, target: &U) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to an element of the struct and a target value,
+    /// remove and return a struct whose specified element is closest to the target.
+    /// The operation is non-blocking and will returns None if there is no struct.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, NearestLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(10);
+    ///
+    /// assert_eq!(space.try_take_nearest::<i64>("", &5), Some(3));
+    /// assert_eq!(space.try_take_nearest::<i64>("", &5), Some(10));
+    /// assert_eq!(space.try_take_nearest::<i64>("", &5), None);
+    /// ```
+    fn try_take_nearest<T>(&self, field: &str, target: &U) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+}
+
+/// An extension of `ObjectSpace` supporting retrieving the struct with the smallest or largest value of a field.
+///
+/// Among structs with the same value, the oldest is chosen.
+/// Fields declared with `hash_index` are not ordered, so lookups on them panic.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::{TreeObjectSpace, ObjectSpace, OrderedLookupObjectSpace};
+/// let space = TreeObjectSpace::new();
+/// space.write::<i64>(5);
+/// space.write::<i64>(3);
+/// space.write::<i64>(10);
+///
+/// assert_eq!(space.try_take_min_by::<i64>(""), Some(3));
+/// assert_eq!(space.try_take_max_by::<i64>(""), Some(10));
+/// ```
+pub trait OrderedLookupObjectSpace: ObjectSpace {
+    /// Given a path to an element of the struct,
+    /// return a copy of a struct whose specified element has the smallest value.
+    /// The operation is non-blocking and will returns None if no struct has the element.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, OrderedLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// assert_eq!(space.try_read_min_by::<i64>(""), None);
+    /// space.write::<i64>(5);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_read_min_by::<i64>(""), Some(3));
+    /// assert_eq!(space.try_read_min_by::<i64>(""), Some(3));
+    /// ```
+    fn try_read_min_by<T>(&self, field: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to an element of the struct,
+    /// return a copy of a struct whose specified element has the largest value.
+    /// The operation is non-blocking and will returns None if no struct has the element.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, OrderedLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(5);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_read_max_by::<i64>(""), Some(5));
+    /// ```
+    fn try_read_max_by<T>(&self, field: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to an element of the struct,
+    /// remove and return a struct whose specified element has the smallest value.
+    /// The operation is non-blocking and will returns None if no struct has the element.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, OrderedLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(5);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_take_min_by::<i64>(""), Some(3));
+    /// assert_eq!(space.try_take_min_by::<i64>(""), Some(5));
+    /// assert_eq!(space.try_take_min_by::<i64>(""), None);
+    /// ```
+    fn try_take_min_by<T>(&self, field: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to an element of the struct,
+    /// remove and return a struct whose specified element has the largest value.
+    /// The operation is non-blocking and will returns None if no struct has the element.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, OrderedLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(5);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_take_max_by::<i64>(""), Some(5));
+    /// assert_eq!(space.try_take_max_by::<i64>(""), Some(3));
+    /// assert_eq!(space.try_take_max_by::<i64>(""), None);
+    /// ```
+    fn try_take_max_by<T>(&self, field: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to an element of the struct,
+    /// remove and return a struct whose specified element has the smallest value.
+    /// The operation blocks until a struct has the element.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, OrderedLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(5);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.take_min_by::<i64>(""), 3);
+    /// ```
+    fn take_min_by<T>(&self, field: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to an element of the struct,
+    /// remove and return a struct whose specified element has the largest value.
+    /// The operation blocks until a struct has the element.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, OrderedLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(5);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.take_max_by::<i64>(""), 5);
+    /// ```
+    fn take_max_by<T>(&self, field: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to an element of the struct,
+    /// return copies of all structs having the specified element, ordered by its value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, OrderedLookupObjectSpace, Order};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(5);
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(10);
+    ///
+    /// assert_eq!(
+    ///     space.read_all_ordered::<i64>("", Order::Descending).collect::<Vec<_>>(),
+    ///     vec![10, 5, 3]
+    /// );
+    /// ```
+    fn read_all_ordered<'a, T>(&'a self, field: &str, order: Order) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static;
+
+    /// Given a path to an element of the struct,
+    /// remove and return the struct whose specified element comes first in the order.
+    /// The operation blocks until a struct has the element,
+    /// so that consumers take the structs by priority rather than in the order they were written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, OrderedLookupObjectSpace, Order};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(5);
+    /// space.write::<i64>(10);
+    ///
+    /// assert_eq!(space.take_ordered::<i64>("", Order::Descending), 10);
+    /// assert_eq!(space.take_ordered::<i64>("", Order::Descending), 5);
+    /// ```
+    fn take_ordered<T>(&self, field: &str, order: Order) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Given a path to an element of the struct,
+    /// remove and return the struct whose specified element comes first in the order.
+    /// The operation blocks until a struct has the element,
+    /// and returns None if none is found before the timeout.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, OrderedLookupObjectSpace, Order};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(5);
+    ///
+    /// let timeout = Duration::from_millis(10);
+    /// assert_eq!(space.take_ordered_timeout::<i64>("", Order::Ascending, timeout), Some(5));
+    /// assert_eq!(space.take_ordered_timeout::<i64>("", Order::Ascending, timeout), None);
+    /// ```
+    fn take_ordered_timeout<T>(&self, field: &str, order: Order, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+}
+
+/// The lock of a type and the condition variable notified when a struct of the type is written.
+///
+/// The lock holds the number of writes of the type,
+/// so that waiters could tell whether anything has been written since they last looked.
+type Lock = Arc<(Mutex<u64>, Condvar)>;
+
+/// The state kept for a type: the entry holding its structs,
+/// and the lock which blocking calls on the type wait on.
+///
+/// Each type has its own slot, so that operations on different types never contend,
+/// and operations reading a type do not block each other.
+struct Slot {
+    entry: Arc<RwLock<Entry>>,
+    lock: Lock,
+    type_name: &'static str,
+}
+
+impl Slot {
+    /// Return true if nobody holds a guard of the entry or a clone of the lock.
+    fn is_unused(&self) -> bool {
+        Arc::strong_count(&self.entry) == 1 && Arc::strong_count(&self.lock) == 1
+    }
+}
+
+/// What adding a struct does when its type already holds as many structs as its capacity.
+#[derive(Clone, Copy, PartialEq)]
+enum WhenFull {
+    // wait, fail or evict as the overflow of the type says
+    Overflow,
+    // wait for room, whatever the overflow of the type
+    Wait,
+    // fail rather than wait
+    Fail,
+}
+
+/// Why a struct could not be added to its entry.
+enum Rejection {
+    // the struct does not match the strict schema of its type
+    Mismatch(String),
+    // the type holds as many structs as its capacity
+    Full(usize),
+    // the structs of the type would take more memory than its quota
+    OverQuota(usize),
+    // the space is closed
+    Closed,
+    // a thread panicked while holding the lock of the type
+    Poisoned,
+}
+
+impl From<Breach> for Rejection {
+    fn from(breach: Breach) -> Self {
+        match breach {
+            Breach::Capacity(limit) => Rejection::Full(limit),
+            Breach::Quota(bytes) => Rejection::OverQuota(bytes),
+        }
+    }
+}
+
+impl Rejection {
+    /// Panic, which must only be done once the lock of the type is released, so that it is not poisoned.
+    fn raise<R>(self) -> R {
+        panic!("{}", self.into_error())
+    }
+
+    fn into_error(self) -> SpaceError {
+        match self {
+            Rejection::Mismatch(mismatch) => SpaceError::Mismatch(mismatch),
+            Rejection::Full(limit) => SpaceError::Full(limit),
+            Rejection::OverQuota(bytes) => SpaceError::OverQuota(bytes),
+            Rejection::Closed => SpaceError::Closed,
+            Rejection::Poisoned => SpaceError::Poisoned,
+        }
+    }
+}
+
+fn deserialize<T>(record: &Record) -> Option<T>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    record.deserialize().ok()
+}
+
+/// Write a file by writing a temporary file first and then renaming it,
+/// so that an existing file is never left half-written.
+fn write_atomically<F>(path: &Path, f: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        f(&mut writer)?;
+        writer.flush()?;
+    }
+    fs::rename(&tmp_path, path)
+}
+
+/// Write the payloads of structs as JSON Lines, each preceded by `prefix` and followed by `suffix`.
+/// Return the number of structs written.
+fn write_lines<'a, I>(writer: &mut Write, records: I, prefix: &[u8], suffix: &[u8]) -> io::Result<usize>
+where
+    I: Iterator<Item = &'a Record>,
+{
+    let mut count = 0;
+    for record in records {
+        let payload = record
+            .json_payload()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writer.write_all(prefix)?;
+        writer.write_all(&payload)?;
+        writer.write_all(suffix)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Read every line of JSON Lines, skipping blank ones.
+/// An error is returned, naming the line, if any of them could not be read as T.
+fn read_lines<T>(reader: &mut Read) -> io::Result<Vec<T>>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    let mut values = Vec::new();
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value = serde_json::from_str(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e)))?;
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// Write the payloads of structs as a JSON array.
+fn write_payloads<'a, W, I>(writer: &mut W, records: I) -> io::Result<()>
+where
+    W: Write,
+    I: Iterator<Item = &'a Record>,
+{
+    writer.write_all(b"[")?;
+    for (i, record) in records.enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        let payload = record
+            .json_payload()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writer.write_all(&payload)?;
+    }
+    writer.write_all(b"]")
+}
+
+/// An extension of `ObjectSpace` which tells how a lookup by a range of a field would be answered,
+/// e.g: to find out why a lookup is slow.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::{TreeObjectSpace, ObjectSpace, ExplainObjectSpace, FieldKind};
+/// let space = TreeObjectSpace::new();
+/// for i in 0..10 {
+///     space.write::<i64>(i % 5);
+/// }
+///
+/// let plan = space.explain_query::<i64, _>("", 2i64..4);
+/// assert!(plan.indexed);
+/// assert_eq!(plan.kind, Some(FieldKind::Int));
+/// assert_eq!((plan.buckets, plan.candidates, plan.total), (2, 4, 10));
+/// ```
+pub trait ExplainObjectSpace<U>: ObjectSpace {
+    /// Given a path to an element of the struct and a range of possible values,
+    /// describe how a lookup of the structs of type T whose element is within the range would be answered:
+    /// whether the field is indexed, how many values of the index are within the range,
+    /// and how many structs have them. Neither the structs nor the metrics of the type are affected.
+    ///
+    /// A value is looked up by a range holding only it, e.g: `3i64..=3`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ExplainObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.index_fields::<i64>(&[]);
+    /// space.write::<i64>(3);
+    /// space.write(String::from("three"));
+    ///
+    /// assert!(!space.explain_query::<i64, _>("", 3i64..=3).indexed);
+    /// let plan = space.explain_query::<String, _>("", String::from("t")..);
+    /// assert_eq!((plan.buckets, plan.candidates), (1, 1));
+    /// ```
+    fn explain_query<T, R>(&self, field: &str, range: R) -> QueryPlan
+    where
+        T: 'static,
+        R: RangeBounds<U>;
+}
+
+/// The operations of an object space on structs given as JSON documents, and types given by their stable name,
+/// e.g: `my_crate::Task` or the name registered with `register_type`.
+///
+/// Unlike `ObjectSpace`, whose methods are generic, this trait could be used as a trait object,
+/// so that a program could hold a `Box<DynObjectSpace>` and choose a local or remote space at runtime.
+///
+/// # Example
+///
+/// ```
+/// # #[macro_use] extern crate serde_json;
+/// # extern crate object_space;
+/// # use object_space::{field, DynObjectSpace, ObjectSpace, RemoteObjectSpace, SpaceServer, TreeObjectSpace};
+/// # fn main() {
+/// let remote = false;
+/// let space: Box<DynObjectSpace> = if remote {
+///     let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+///     let space = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+///     server.spawn();
+///     space.register_type::<i64>("counter");
+///     space.write::<i64>(3);
+///     Box::new(space)
+/// } else {
+///     let space = TreeObjectSpace::new();
+///     space.register_type::<i64>("counter");
+///     space.write::<i64>(3);
+///     Box::new(space)
+/// };
+///
+/// space.write_json("counter", json!(5));
+/// assert_eq!(space.read_all_json("counter").len(), 2);
+/// assert_eq!(space.try_take_json_by_query("counter", &field("").gt(4i64)), Some(json!(5)));
+/// assert_eq!(space.read_all_json("counter"), vec![json!(3)]);
+/// # }
+/// ```
+pub trait DynObjectSpace {
+    /// Add a struct of the type with the specified name to the object space and return its handle.
+    ///
+    /// Return None if the space does not know the type,
+    /// e.g: a `TreeObjectSpace` to which no struct of the type has been written.
+    fn write_json(&self, type_name: &str, obj: serde_json::Value) -> Option<ObjectHandle>;
+
+    /// Add a struct of the type with the specified name to the object space and return its handle,
+    /// whether or not the space knows a type with that name, e.g: to forward documents from external systems.
+    fn write_value(&self, type_name: &str, value: serde_json::Value) -> ObjectHandle;
+
+    /// Return a struct of the type with the specified name, as a JSON document, if there is any.
+    fn try_read_json(&self, type_name: &str) -> Option<serde_json::Value>;
+
+    /// Return all structs of the type with the specified name, as JSON documents.
+    fn read_all_json(&self, type_name: &str) -> Vec<serde_json::Value>;
+
+    /// Remove a struct of the type with the specified name from the space and return it, if there is any.
+    fn try_take_json(&self, type_name: &str) -> Option<serde_json::Value>;
+
+    /// Remove all structs of the type with the specified name from the space and return them.
+    fn take_all_json(&self, type_name: &str) -> Vec<serde_json::Value>;
+
+    /// Return a struct of the type with the specified name satisfying the query, if there is any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_json;
+    /// # extern crate object_space;
+    /// # use object_space::{field, DynObjectSpace, ObjectSpace, TreeObjectSpace};
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// let space: &DynObjectSpace = &space;
+    /// assert_eq!(space.try_read_json_by_query("i64", &field("").gt(4i64)), Some(json!(5)));
+    /// assert_eq!(space.take_all_json_by_query("i64", &field("").lt(4i64)), vec![json!(3)]);
+    /// assert_eq!(space.read_all_json("i64"), vec![json!(5)]);
+    /// # }
+    /// ```
+    fn try_read_json_by_query(&self, type_name: &str, query: &Query) -> Option<serde_json::Value>;
+
+    /// Return all structs of the type with the specified name satisfying the query.
+    fn read_all_json_by_query(&self, type_name: &str, query: &Query) -> Vec<serde_json::Value>;
+
+    /// Remove a struct of the type with the specified name satisfying the query from the space and return it,
+    /// if there is any.
+    fn try_take_json_by_query(&self, type_name: &str, query: &Query) -> Option<serde_json::Value>;
+
+    /// Remove all structs of the type with the specified name satisfying the query from the space and return them.
+    fn take_all_json_by_query(&self, type_name: &str, query: &Query) -> Vec<serde_json::Value>;
+}
+
+/// An extension of `ObjectSpace` whose blocking operations return futures,
+/// so that awaiting a struct does not park a thread.
+///
+/// A future is woken up whenever a struct of its type is written,
+/// and looks the space up again when polled.
+///
+/// # Example
+///
+/// ```
+/// # extern crate futures;
+/// # extern crate object_space;
+/// # use object_space::{TreeObjectSpace, ObjectSpace, AsyncObjectSpace};
+/// # fn main() {
+/// let space = TreeObjectSpace::new();
+/// space.write(String::from("Hello World"));
+///
+/// let hello = futures::executor::block_on(space.take_async::<String>());
+/// assert_eq!(hello, String::from("Hello World"));
+/// # }
+/// ```
+pub trait AsyncObjectSpace: ObjectSpace {
+    /// Return a future resolving to a copy of a struct of type T,
+    /// once such a struct is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, AsyncObjectSpace};
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(futures::executor::block_on(space.read_async::<i64>()), 3);
+    /// assert_eq!(space.try_read::<i64>(), Some(3));
+    /// # }
+    /// ```
+    fn read_async<'a, T>(&'a self) -> Box<Future<Output = T> + Send + Unpin + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+
+    /// Return a future removing and resolving to a struct of type T,
+    /// once such a struct is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate futures;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, AsyncObjectSpace};
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(futures::executor::block_on(space.take_async::<i64>()), 3);
+    /// assert_eq!(space.try_read::<i64>(), None);
+    /// # }
+    /// ```
+    fn take_async<'a, T>(&'a self) -> Box<Future<Output = T> + Send + Unpin + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static;
+}
+
+/// The changes saved by `TreeObjectSpace::checkpoint_delta`.
+#[derive(Deserialize)]
+struct Delta<T> {
+    removed: Vec<T>,
+    added: Vec<T>,
+}
+
+/// A line written by `TreeObjectSpace::export_all`.
+#[derive(Deserialize)]
+struct ExportedStruct {
+    #[serde(rename = "type")]
+    type_name: String,
+    value: serde_json::Value,
+}
+
+const MIN_GC_THRESHOLD: usize = 64;
+
+// blocking calls back off after being woken up this many times without finding a struct
+const FREE_WAKEUPS: u32 = 3;
+const MIN_BACKOFF: Duration = Duration::from_micros(10);
+const MAX_BACKOFF: Duration = Duration::from_millis(1);
+
+/// A thread-safe reference `ObjectSpace` implementation
+///
+/// # Implementation
+///
+/// A `TreeObjectSpace` is a `HashMap` between a `TypeId`
+/// and the actual `Entry` structure holding the structs.
+/// Each `Entry` is guarded by its own `RwLock`,
+/// so that writers of a type only block the operations on the same type,
+/// while readers of a type proceed concurrently.
+/// The map itself is only locked for writing when a type is first used or collected.
+/// Before structs are stored in `Entry`,
+/// they are serialized into a table of their basic fields, flattened by name (e.g: `person.name`),
+/// alongside a serialized payload which structs are deserialized from when read.
+///
+/// An `Entry` is a `HashMap` whose key is a flattened field and
+/// value is a `BTreeMap` between possible values of the field
+/// and the `Vec` of structs containing the corresponding value of such field.
+/// Each field also has a counting Bloom filter,
+/// so that lookups for values not in the space return without touching the `BTreeMap`.
+///
+/// Structs written with a lease are removed lazily:
+/// expired structs of a type are dropped the next time the type is accessed.
+///
+/// `Mutex` is used sparingly to ensure blocking `read` and `take` calls do not hijack CPU cycles.
+/// Every type has its own `Mutex` and `Condvar`: a write only wakes up the calls waiting on its type.
+/// Both the `RwLock` and the `Mutex` are fair in practice:
+/// a thread waiting for a guard is not starved by threads repeatedly taking it.
+#[derive(Default)]
+pub struct TreeObjectSpace {
+    slots: RwLock<HashMap<TypeId, Slot>>,
+    default_ttl: Option<Duration>,
+    default_capacity: Option<(usize, Overflow)>,
+    gc_threshold: AtomicUsize,
+    watchdog: Option<Watchdog>,
+    // the blocking calls waiting, if the space is built in diagnostics mode
+    waiters: Option<WaiterRegistry>,
+    wait_strategy: WaitStrategy,
+    codec: Codec,
+    // where the payloads of large structs are moved out of memory, if anywhere
+    spill: Option<SpillStore>,
+    // the futures of `AsyncObjectSpace` operations waiting on each type
+    wakers: CHashMap<TypeId, Vec<Waker>>,
+    journal: Option<Arc<Journal>>,
+    registry: RwLock<TypeRegistry>,
+    // the structs written with `write_value` under a name which is not that of a type
+    untyped: RwLock<HashMap<String, Arc<RwLock<Entry>>>>,
+    closed: AtomicBool,
+}
+
+/// A builder for `TreeObjectSpace` with non-default settings.
+///
+/// # Example
+///
+/// ```
+/// # use std::time::Duration;
+/// # use object_space::{TreeObjectSpace, ObjectSpace};
+/// let space = TreeObjectSpace::builder()
+///     .default_ttl(Duration::from_secs(60))
+///     .build();
+/// space.write::<i64>(3);
+///
+/// assert_eq!(space.try_read::<i64>(), Some(3));
+/// ```
+#[derive(Default)]
+pub struct TreeObjectSpaceBuilder {
+    default_ttl: Option<Duration>,
+    default_capacity: Option<(usize, Overflow)>,
+    watchdog: Option<Watchdog>,
+    diagnostics: bool,
+    wait_strategy: WaitStrategy,
+    codec: Codec,
+    spill: Option<(PathBuf, usize)>,
+}
+
+impl TreeObjectSpaceBuilder {
+    pub fn new() -> TreeObjectSpaceBuilder {
+        Default::default()
+    }
+
+    /// Give every struct written to the space a lease of the specified duration,
+    /// after which it is removed from the space.
+    ///
+    /// The lease of a type could be overridden with `TreeObjectSpace::ttl`.
+    pub fn default_ttl(mut self, ttl: Duration) -> TreeObjectSpaceBuilder {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    /// Limit the number of structs of every type in the space, unless overridden with
+    /// `TreeObjectSpace::set_capacity`, so that an unbounded producer could not exhaust memory.
+    pub fn default_capacity(mut self, limit: usize, overflow: Overflow) -> TreeObjectSpaceBuilder {
+        self.default_capacity = Some((limit, overflow));
+        self
+    }
+
+    /// Call `handler` with a description of every blocking call
+    /// which has been waiting longer than `threshold`, e.g: to log it.
+    /// Each call is reported at most once.
+    ///
+    /// This helps finding dead pipelines, where nobody produces the awaited type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::TreeObjectSpace;
+    /// let space = TreeObjectSpace::builder()
+    ///     .starvation_watchdog(Duration::from_secs(60), |call| eprintln!("{}", call))
+    ///     .build();
+    /// ```
+    pub fn starvation_watchdog<F>(mut self, threshold: Duration, handler: F) -> TreeObjectSpaceBuilder
+    where
+        F: Fn(&BlockedCall) + Send + Sync + 'static,
+    {
+        self.watchdog = Some(Watchdog {
+            threshold,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Track every blocking call while it waits, so that `TreeObjectSpace::dump_waiters`
+    /// could tell which calls wait, on what type and predicate, and for how long.
+    ///
+    /// Every blocking call is described and registered, which costs a lock of the space per call.
+    pub fn diagnostics(mut self) -> TreeObjectSpaceBuilder {
+        self.diagnostics = true;
+        self
+    }
+
+    /// Set how blocking operations wait for structs to be written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, WaitStrategy};
+    /// let space = TreeObjectSpace::builder()
+    ///     .wait_strategy(WaitStrategy::SpinThenBlock(Duration::from_micros(50)))
+    ///     .build();
+    /// ```
+    pub fn wait_strategy(mut self, strategy: WaitStrategy) -> TreeObjectSpaceBuilder {
+        self.wait_strategy = strategy;
+        self
+    }
+
+    /// Set how structs are serialized in the space, JSON by default.
+    ///
+    /// A binary codec cuts the cost of writing and taking large structs,
+    /// which lookups are not affected by, see `Codec`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, Codec};
+    /// let space = TreeObjectSpace::builder().codec(Codec::Json).build();
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_read::<i64>(), Some(3));
+    /// ```
+    pub fn codec(mut self, codec: Codec) -> TreeObjectSpaceBuilder {
+        self.codec = codec;
+        self
+    }
+
+    /// Keep the structs whose payload, as serialized by the codec, is larger than `threshold` bytes
+    /// in files of their own in `dir`, created when first needed, instead of in memory.
+    /// Their indexed fields stay in memory, so lookups are not slowed down,
+    /// but reading or taking such a struct reads its file back.
+    ///
+    /// A file is removed with its struct. A struct whose file could not be written stays in memory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::env;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::builder()
+    ///     .spill_to_disk(env::temp_dir().join("space-spill"), 64 * 1024)
+    ///     .build();
+    /// space.write(vec![0u32; 100_000]);
+    ///
+    /// assert_eq!(space.try_take::<Vec<u32>>().map(|pixels| pixels.len()), Some(100_000));
+    /// ```
+    pub fn spill_to_disk<P>(mut self, dir: P, threshold: usize) -> TreeObjectSpaceBuilder
+    where
+        P: Into<PathBuf>,
+    {
+        self.spill = Some((dir.into(), threshold));
+        self
+    }
+
+    pub fn build(self) -> TreeObjectSpace {
+        TreeObjectSpace {
+            default_ttl: self.default_ttl,
+            default_capacity: self.default_capacity,
+            watchdog: self.watchdog,
+            waiters: if self.diagnostics {
+                Some(WaiterRegistry::default())
+            } else {
+                None
+            },
+            wait_strategy: self.wait_strategy,
+            codec: self.codec,
+            spill: self.spill.map(|(dir, threshold)| SpillStore::new(dir, threshold)),
+            ..Default::default()
+        }
+    }
+}
+
+/// A write guard of an entry, recording how long it has been held when dropped.
+struct EntryGuard {
+    entry: ArcRwLockWriteGuard<RawRwLock, Entry>,
+    operation: &'static str,
+    // when the guard was requested, and when it was taken
+    requested: Instant,
+    since: Instant,
+    // exited once the guard is released
+    _span: trace::Span,
+}
+
+impl Deref for EntryGuard {
+    type Target = Entry;
+
+    fn deref(&self) -> &Entry {
+        &self.entry
+    }
+}
+
+impl DerefMut for EntryGuard {
+    fn deref_mut(&mut self) -> &mut Entry {
+        &mut self.entry
+    }
+}
+
+impl Drop for EntryGuard {
+    fn drop(&mut self) {
+        let held = self.since.elapsed();
+        self.entry.record_hold_time(self.operation, held);
+        let latency = self.requested.elapsed();
+        self.entry.record_latency(self.operation, latency);
+    }
+}
+
+/// A blocking call waiting on the condition variable of its type,
+/// or on its own if it only waits for structs satisfying a condition.
+struct Waiter<'a> {
+    space: &'a TreeObjectSpace,
+    type_id: TypeId,
+    operation: &'static str,
+    // the call to report to the watchdog, or None if there is no watchdog or it has been reported
+    call: Option<BlockedCall>,
+    since: Instant,
+    // the end of the spinning phase, or None if the call does not spin or has finished spinning
+    spin_deadline: Option<Instant>,
+    // the number of times the call has been woken up without finding a struct
+    wakeups: u32,
+    // the time after which the call gives up, or None if it waits forever
+    deadline: Option<Instant>,
+    // the ticket registered with the entry, or None if any struct of the type wakes the call up
+    ticket: Option<Arc<Ticket>>,
+    // true once the call has waited for a struct
+    blocked: bool,
+    // true if the caller returns an error once the space is closed, instead of panicking
+    checked: bool,
+    // the id of the call in the registry of the space, if the space is in diagnostics mode
+    registration: Option<usize>,
+    // exited once the call returns
+    _span: trace::Span,
+}
+
+impl<'a> Waiter<'a> {
+    /// Wait for a struct of the type to be written.
+    ///
+    /// The call is reported to the watchdog if it waits longer than the threshold,
+    /// and backs off once it has been woken up repeatedly without finding a struct.
+    /// It returns without a new struct once its deadline has passed, or the space is closed.
+    /// A call without a deadline panics instead if the space is closed, unless it is checked.
+    fn wait<'g>(
+        &mut self,
+        lock: &'g Mutex<u64>,
+        cvar: &Condvar,
+        mut guard: MutexGuard<'g, u64>,
+    ) -> MutexGuard<'g, u64> {
+        if self.space.is_closed() {
+            if self.deadline.is_none() && !self.checked {
+                // the call could never return, and must release the lock so that it is not poisoned
+                drop(guard);
+                panic!("{}", SpaceError::Closed);
+            }
+            return guard;
+        }
+        if !self.blocked {
+            self.blocked = true;
+            trace::waiting();
+        }
+        let generation = *guard;
+        // the structs added so far have been looked up
+        let ticket = self.ticket.clone();
+        if let Some(ref ticket) = ticket {
+            ticket.reset();
+        }
+        let cvar = ticket.as_ref().map_or(cvar, |ticket| ticket.cvar());
+        if let Some(spin_deadline) = self.spin_deadline {
+            let spin_deadline = self.deadline.map_or(spin_deadline, |d| min(d, spin_deadline));
+            guard = self.spin(lock, guard, spin_deadline);
+            if self.is_woken(&guard, generation) || self.expired() {
+                return guard;
+            }
+            self.spin_deadline = None;
+        }
+
+        // wakeups without any matching write since the last lookup are ignored
+        let space = self.space;
+        // structs checked out are returned to the space once their lease ends, without any write
+        let returned_at = space
+            .entry(self.type_id)
+            .and_then(|entry| entry.read().next_checkout_end());
+        while !self.is_woken(&guard, generation) {
+            let report_at = match space.watchdog {
+                Some(ref watchdog) if self.call.is_some() => Some(self.since + watchdog.threshold),
+                _ => None,
+            };
+            let wake_at = [report_at, self.deadline, returned_at].iter().flatten().min().cloned();
+            guard = match wake_at {
+                Some(wake_at) => {
+                    let timeout = wake_at.saturating_duration_since(Instant::now());
+                    cvar.wait_timeout(guard, timeout).unwrap().0
+                }
+                None => cvar.wait(guard).unwrap(),
+            };
+            if let (Some(watchdog), Some(report_at)) = (space.watchdog.as_ref(), report_at) {
+                if Instant::now() >= report_at {
+                    guard = self.report(watchdog, lock, guard);
+                }
+            }
+            if self.expired() || returned_at.is_some_and(|at| Instant::now() >= at) {
+                return guard;
+            }
+        }
+        self.back_off(lock, guard)
+    }
+
+    /// Return true if a struct the call could find has been written since the generation of the lock.
+    fn is_woken(&self, guard: &MutexGuard<u64>, generation: u64) -> bool {
+        match self.ticket {
+            Some(ref ticket) => ticket.is_woken(),
+            None => **guard != generation,
+        }
+    }
+
+    /// Return true if the call has a deadline, which has passed or will never be reached as the space is closed.
+    fn expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| self.space.is_closed() || Instant::now() >= deadline)
+    }
+
+    fn report<'g>(
+        &mut self,
+        watchdog: &Watchdog,
+        lock: &'g Mutex<u64>,
+        guard: MutexGuard<'g, u64>,
+    ) -> MutexGuard<'g, u64> {
+        let mut call = self.call.take().unwrap();
+        call.waited = self.since.elapsed();
+        call.count = self.space
+            .entry(self.type_id)
+            .map_or(0, |entry| entry.read().len());
+        // call the handler without holding the lock, so that it could use the space
+        drop(guard);
+        (watchdog.handler)(&call);
+        lock.lock().unwrap()
+    }
+
+    /// Yield the CPU until a struct of the type is written or the spinning phase is over.
+    fn spin<'g>(
+        &mut self,
+        lock: &'g Mutex<u64>,
+        guard: MutexGuard<'g, u64>,
+        spin_deadline: Instant,
+    ) -> MutexGuard<'g, u64> {
+        let generation = *guard;
+        drop(guard);
+        loop {
+            thread::yield_now();
+            let guard = lock.lock().unwrap();
+            if self.is_woken(&guard, generation) || Instant::now() >= spin_deadline {
+                return guard;
+            }
+        }
+    }
+
+    /// Delay the next lookup of a call woken up many times without finding a struct,
+    /// so that it handles the writes made in the meantime at once.
+    ///
+    /// This keeps frequent writes of structs the call does not match
+    /// from turning it into a busy loop.
+    fn back_off<'g>(
+        &mut self,
+        lock: &'g Mutex<u64>,
+        guard: MutexGuard<'g, u64>,
+    ) -> MutexGuard<'g, u64> {
+        self.wakeups += 1;
+        if self.wakeups <= FREE_WAKEUPS {
+            return guard;
+        }
+        let exponent = min(self.wakeups - FREE_WAKEUPS - 1, 16);
+        let mut backoff = min(MIN_BACKOFF * (1 << exponent), MAX_BACKOFF);
+        if let Some(deadline) = self.deadline {
+            backoff = min(backoff, deadline.saturating_duration_since(Instant::now()));
+        }
+        drop(guard);
+        thread::sleep(backoff);
+        lock.lock().unwrap()
+    }
+}
+
+impl<'a> Drop for Waiter<'a> {
+    fn drop(&mut self) {
+        if let Some(entry) = self.space.entry(self.type_id) {
+            let mut entry = entry.write();
+            if let Some(ticket) = self.ticket.take() {
+                entry.unregister_waiter(&ticket);
+            }
+            entry.wait_ended(self.operation, self.since.elapsed());
+        }
+        if let (Some(waiters), Some(id)) = (self.space.waiters.as_ref(), self.registration) {
+            waiters.unregister(id);
+        }
+        trace::returned(self.blocked, self.since.elapsed());
+    }
+}
+
+/// The future of an `AsyncObjectSpace` operation, resolving once `lookup` finds a struct of type T.
+struct Awaited<'a, T, F> {
+    space: &'a TreeObjectSpace,
+    // a clone of the lock keeps the type from being collected while it is awaited
+    lock: Lock,
+    lookup: F,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T, F> Future for Awaited<'a, T, F>
+where
+    T: 'static,
+    F: FnMut() -> Option<T> + Unpin,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let this = &mut *self;
+        // writers wake the futures of the type while holding the lock, so no write is missed
+        let generation = this.lock.0.lock().unwrap();
+        match (this.lookup)() {
+            Some(value) => Poll::Ready(value),
+            None if this.space.is_closed() => {
+                drop(generation);
+                panic!("{}", SpaceError::Closed);
+            }
+            None => {
+                this.space.register_waker::<T>(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// How blocking operations wait for structs to be written.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum WaitStrategy {
+    /// Sleep until a struct of the type is written. This is the default.
+    #[default]
+    Block,
+    /// Keep polling the space, yielding the CPU in between, for the specified duration
+    /// before sleeping.
+    /// This trades CPU time for a lower latency when structs are written shortly after the call.
+    SpinThenBlock(Duration),
+}
+
+/// A running transformer registered with `TreeObjectSpace::pipe`.
+///
+/// Dropping a `Pipe` leaves its workers running in the background.
+pub struct Pipe {
+    stop: Arc<AtomicBool>,
+    lock: Lock,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Pipe {
+    /// Stop taking new structs, and wait for the workers to write the structs they are transforming.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        {
+            let &(ref lock, ref cvar) = &*self.lock;
+            // waiters ignore wakeups without a new generation
+            let mut generation = lock.lock().unwrap();
+            *generation = generation.wrapping_add(1);
+            cvar.notify_all();
+        }
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A background thread removing expired and evicted structs, started with `TreeObjectSpace::sweep_every`.
+///
+/// Dropping a `Sweeper` leaves its thread running in the background, until the space is dropped.
+pub struct Sweeper {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: JoinHandle<()>,
+}
+
+impl Sweeper {
+    /// Stop sweeping, and wait for the sweep in progress, if any, to finish.
+    pub fn stop(self) {
+        {
+            let &(ref stopped, ref cvar) = &*self.stop;
+            *stopped.lock().unwrap() = true;
+            cvar.notify_all();
+        }
+        let _ = self.thread.join();
+    }
+}
+
+/// A struct checked out with `TreeObjectSpace::checkout`,
+/// which other calls could not find until it is checked in.
+///
+/// Dropping a `Checkout` without committing it, e.g: because the worker processing it panicked,
+/// returns the struct to the space. So does the end of its lease,
+/// in case the worker is stuck or the `Checkout` is leaked.
+pub struct Checkout<'a, T: 'static> {
+    space: &'a TreeObjectSpace,
+    lock: Lock,
+    index: u64,
+    value: T,
+    done: bool,
+}
+
+impl<'a, T: 'static> Checkout<'a, T> {
+    /// Return the handle of the struct, which it keeps when it is returned to the space.
+    pub fn handle(&self) -> ObjectHandle {
+        ObjectHandle::new(self.index)
+    }
+
+    /// Remove the struct from the space for good.
+    /// Return false if the lease has ended, so that the struct has been returned to the space meanwhile.
+    pub fn commit(mut self) -> bool {
+        self.done = true;
+        match self.space.get_object_entry_mut::<T>("commit") {
+            Some(mut entry) => entry.commit(self.index),
+            None => false,
+        }
+    }
+
+    /// Return the struct to the space, e.g: to let another worker process it.
+    pub fn release(self) {}
+}
+
+impl<'a, T: 'static> Deref for Checkout<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<'a, T: 'static> Drop for Checkout<'a, T> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let &(ref lock, ref cvar) = &*self.lock;
+        let mut generation = lock.lock().unwrap();
+        let returned = match self.space.get_object_entry_mut::<T>("release") {
+            Some(mut entry) => entry.check_in(self.index),
+            None => false,
+        };
+        if returned {
+            *generation = generation.wrapping_add(1);
+            cvar.notify_all();
+            self.space.wake_futures::<T>();
+        }
+    }
+}
+
+/// A blocking call finding a struct of any of several types, built with `TreeObjectSpace::select`.
+///
+/// Each arm looks up a type, possibly by the value of a field, and maps the struct it finds
+/// to the result of the call, which tells which arm found it, e.g: a variant of an enum per arm.
+/// Arms are tried in the order they were added, so earlier arms take priority.
+pub struct Select<'a, R> {
+    space: &'a TreeObjectSpace,
+    arms: Vec<Arm<'a, R>>,
+    // the types looked up, whose entries are kept by holding their lock
+    types: Vec<(TypeId, Lock)>,
+}
+
+struct Arm<'a, R> {
+    lookup: Box<FnMut() -> Option<R> + 'a>,
+    type_id: TypeId,
+    // the structs which wake the call up, or None if any struct of the type does,
+    // until the call registers it with the entry of the type
+    condition: Option<Condition>,
+}
+
+impl<'a, R> Select<'a, R> {
+    /// Add an arm looking up structs of type T with `lookup`, e.g: `try_take_matching`.
+    /// The call is woken up by any struct of type T written, and then tries every arm again.
+    pub fn arm<T, F>(self, lookup: F) -> Self
+    where
+        T: 'static,
+        F: FnMut(&TreeObjectSpace) -> Option<R> + 'a,
+    {
+        self.add_arm::<T, F>(None, lookup)
+    }
+
+    fn add_arm<T, F>(mut self, condition: Option<Condition>, mut lookup: F) -> Self
+    where
+        T: 'static,
+        F: FnMut(&TreeObjectSpace) -> Option<R> + 'a,
+    {
+        let type_id = TypeId::of::<T>();
+        if !self.types.iter().any(|&(id, _)| id == type_id) {
+            self.types.push((type_id, self.space.get_or_add_lock::<T>()));
+        }
+        let space = self.space;
+        self.arms.push(Arm {
+            lookup: Box::new(move || lookup(space)),
+            type_id,
+            condition,
+        });
+        self
+    }
+
+    /// Add an arm taking a struct of type T, mapped by `f`.
+    pub fn take<T, F>(self, f: F) -> Self
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        F: Fn(T) -> R + 'a,
+    {
+        self.arm::<T, _>(move |space| space.try_take::<T>().map(&f))
+    }
+
+    /// Add an arm reading a struct of type T, mapped by `f`.
+    pub fn read<T, F>(self, f: F) -> Self
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        F: Fn(T) -> R + 'a,
+    {
+        self.arm::<T, _>(move |space| space.try_read::<T>().map(&f))
+    }
+
+    /// Add an arm taking a struct of type T whose field equals the key, mapped by `f`.
+    /// Only such structs wake the call up.
+    pub fn take_by_value<T, U, F>(self, field: &str, key: U, f: F) -> Self
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        TreeObjectSpace: ValueLookupObjectSpace<U>,
+        U: Clone + Into<Field> + 'a,
+        F: Fn(T) -> R + 'a,
+    {
+        let condition = Condition::eq(field, &key);
+        let field = field.to_owned();
+        self.add_arm::<T, _>(Some(condition), move |space| {
+            space.try_take_by_value::<T>(&field, &key).map(&f)
+        })
+    }
+
+    /// Add an arm reading a struct of type T whose field equals the key, mapped by `f`.
+    /// Only such structs wake the call up.
+    pub fn read_by_value<T, U, F>(self, field: &str, key: U, f: F) -> Self
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        TreeObjectSpace: ValueLookupObjectSpace<U>,
+        U: Clone + Into<Field> + 'a,
+        F: Fn(T) -> R + 'a,
+    {
+        let condition = Condition::eq(field, &key);
+        let field = field.to_owned();
+        self.add_arm::<T, _>(Some(condition), move |space| {
+            space.try_read_by_value::<T>(&field, &key).map(&f)
+        })
+    }
+
+    /// Add an arm taking a struct of type T whose field is within the range, mapped by `f`.
+    /// Only such structs wake the call up.
+    pub fn take_by_range<T, U, B, F>(self, field: &str, range: B, f: F) -> Self
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        TreeObjectSpace: RangeLookupObjectSpace<U>,
+        U: Clone + Into<Field>,
+        B: RangeBounds<U> + Clone + 'a,
+        F: Fn(T) -> R + 'a,
+    {
+        let condition = Condition::range(field, &range);
+        let field = field.to_owned();
+        self.add_arm::<T, _>(Some(condition), move |space| {
+            space.try_take_by_range::<T, _>(&field, range.clone()).map(&f)
+        })
+    }
+
+    /// Add an arm reading a struct of type T whose field is within the range, mapped by `f`.
+    /// Only such structs wake the call up.
+    pub fn read_by_range<T, U, B, F>(self, field: &str, range: B, f: F) -> Self
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        TreeObjectSpace: RangeLookupObjectSpace<U>,
+        U: Clone + Into<Field>,
+        B: RangeBounds<U> + Clone + 'a,
+        F: Fn(T) -> R + 'a,
+    {
+        let condition = Condition::range(field, &range);
+        let field = field.to_owned();
+        self.add_arm::<T, _>(Some(condition), move |space| {
+            space.try_read_by_range::<T, _>(&field, range.clone()).map(&f)
+        })
+    }
+
+    /// Return the result of the first arm finding a struct, without waiting.
+    pub fn try_now(&mut self) -> Option<R> {
+        self.arms.iter_mut().filter_map(|arm| (arm.lookup)()).next()
+    }
+
+    /// Return the result of the first arm finding a struct, waiting until one does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the space is closed while no arm finds a struct.
+    pub fn wait(mut self) -> R {
+        match self.wait_until(None) {
+            Some(found) => found,
+            None => panic!("{}", SpaceError::Closed),
+        }
+    }
+
+    /// Return the result of the first arm finding a struct,
+    /// or None if none does within the timeout or the space is closed.
+    pub fn wait_timeout(mut self, timeout: Duration) -> Option<R> {
+        // a timeout too long to be represented never ends
+        let deadline = Instant::now().checked_add(timeout);
+        self.wait_until(deadline)
+    }
+
+    fn wait_until(&mut self, deadline: Option<Instant>) -> Option<R> {
+        let signal = Signal::new();
+        for arm in &mut self.arms {
+            if let Some(entry) = self.space.entry(arm.type_id) {
+                entry.write().register_signal(&signal, arm.condition.take());
+            }
+        }
+        let found = loop {
+            // the structs written from now on wake the call up, even during the lookups
+            signal.reset();
+            if let Some(found) = self.try_now() {
+                break Some(found);
+            }
+            if self.space.is_closed() || !signal.wait(deadline) {
+                break None;
+            }
+        };
+        for &(type_id, _) in &self.types {
+            if let Some(entry) = self.space.entry(type_id) {
+                entry.write().unregister_signal(&signal);
+            }
+        }
+        found
+    }
+}
+
+impl TreeObjectSpace {
+    pub fn new() -> TreeObjectSpace {
+        Default::default()
+    }
+
+    pub fn builder() -> TreeObjectSpaceBuilder {
+        TreeObjectSpaceBuilder::new()
+    }
+
+    /// Return a space appending every struct written and taken to the journal at the specified path,
+    /// which is created if it does not exist.
+    ///
+    /// The structs left in an existing journal, e.g: by a previous run of the program,
+    /// are added back to the space when their type is first used.
+    /// Each line of the journal is handed to the operating system before the operation returns.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::env;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace};
+    /// let path = env::temp_dir().join("journal_example.jsonl");
+    /// # let _ = std::fs::remove_file(&path);
+    /// {
+    ///     let space = TreeObjectSpace::with_journal(&path).unwrap();
+    ///     space.write::<i64>(3);
+    ///     space.write::<i64>(5);
+    ///     space.take_by_value::<i64>("", &3i64);
+    /// }
+    ///
+    /// let space = TreeObjectSpace::with_journal(&path).unwrap();
+    /// assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![5]);
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn with_journal<P: AsRef<Path>>(path: P) -> io::Result<TreeObjectSpace> {
+        Ok(TreeObjectSpace {
+            journal: Some(Arc::new(Journal::open(path.as_ref())?)),
+            ..Default::default()
+        })
+    }
+
+    /// Write a struct of type T once its type holds fewer structs than its capacity,
+    /// waiting for a consumer to take one if needed, whatever the overflow set with `set_capacity`.
+    ///
+    /// With a capacity, the space is then a bounded queue of structs of type T,
+    /// shared by any number of producers and consumers. Without one, the struct is written at once.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use std::thread;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, Overflow};
+    /// let space = Arc::new(TreeObjectSpace::new());
+    /// space.set_capacity::<i64>(4, Overflow::Reject);
+    /// let producer = {
+    ///     let space = space.clone();
+    ///     thread::spawn(move || {
+    ///         for i in 0..100 {
+    ///             space.write_blocking::<i64>(i);
+    ///         }
+    ///     })
+    /// };
+    ///
+    /// let sum: i64 = (0..100).map(|_| space.take::<i64>()).sum();
+    /// producer.join().unwrap();
+    /// assert_eq!(sum, 4950);
+    /// ```
+    pub fn write_blocking<T>(&self, obj: T) -> ObjectHandle
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = Record::new(&obj, self.codec).expect("struct cannot be serialized");
+        self.add_record::<T>(value, None, "write_blocking", WhenFull::Wait)
+            .unwrap_or_else(Rejection::raise)
+    }
+
+    /// Write a struct of type T, unless its type already holds as many structs as its capacity
+    /// and does not evict, see `set_capacity`. Never waits for room, unlike `write`,
+    /// and returns an error instead of panicking, like `write_checked`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, Overflow, SpaceError};
+    /// let space = TreeObjectSpace::new();
+    /// space.set_capacity::<i64>(1, Overflow::Block);
+    /// assert!(space.try_write::<i64>(3).is_ok());
+    /// assert_eq!(space.try_write::<i64>(5), Err(SpaceError::Full(1)));
+    ///
+    /// space.take::<i64>();
+    /// assert!(space.try_write::<i64>(5).is_ok());
+    /// ```
+    pub fn try_write<T>(&self, obj: T) -> Result<ObjectHandle, SpaceError>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = Record::new(&obj, self.codec).map_err(|e| SpaceError::Serialization(e.to_string()))?;
+        self.add_record::<T>(value, None, "try_write", WhenFull::Fail)
+            .map_err(Rejection::into_error)
+    }
+
+    /// Write a struct with a lease, after which it is removed from the space.
+    /// The lease overrides the lease of the type and the default lease of the space.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write_with_ttl::<i64>(3, Duration::from_millis(1));
+    /// space.write::<i64>(5);
+    /// std::thread::sleep(Duration::from_millis(10));
+    ///
+    /// assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![5]);
+    /// ```
+    pub fn write_with_ttl<T>(&self, obj: T, ttl: Duration) -> ObjectHandle
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = Record::new(&obj, self.codec).expect("struct cannot be serialized");
+        self.add_record::<T>(value, Some(ttl), "write_with_ttl", WhenFull::Overflow)
+            .unwrap_or_else(Rejection::raise)
+    }
+
+    /// Return a receiver of copies of the structs of type T written from now on.
+    ///
+    /// The subscription is dropped after the receiver is, once another struct of type T is written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// let receiver = space.subscribe::<i64>();
+    /// space.write::<i64>(5);
+    ///
+    /// assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![5]);
+    /// assert_eq!(space.read_all::<i64>().count(), 2);
+    /// ```
+    pub fn subscribe<T>(&self) -> Receiver<T>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        self.add_subscription::<T>(None)
+    }
+
+    /// Return a receiver of copies of the structs of type T written from now on
+    /// whose specified field is equal to the value.
+    ///
+    /// The subscription is dropped after the receiver is, once another matching struct is written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    /// struct Task {
+    ///     id: i64,
+    ///     status: String,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// let receiver = space.subscribe_by_value::<Task, _>("status", &"done");
+    /// space.write(Task { id: 1, status: String::from("todo") });
+    /// space.write(Task { id: 2, status: String::from("done") });
+    ///
+    /// assert_eq!(receiver.try_iter().map(|task| task.id).collect::<Vec<_>>(), vec![2]);
+    /// # }
+    /// ```
+    pub fn subscribe_by_value<T, U>(&self, field: &str, key: &U) -> Receiver<T>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+        U: Clone + Into<FieldValue>,
+    {
+        let value = key.clone().into().to_field();
+        self.add_subscription::<T>(Some((field.to_owned(), value)))
+    }
+
+    fn add_subscription<T>(&self, condition: Option<(String, Field)>) -> Receiver<T>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        let subscription = Subscription::new(condition, move |obj: &Record| {
+            match obj.deserialize() {
+                Ok(obj) => sender.send(obj).is_ok(),
+                // a struct which could not be deserialized is not sent, like in `read_all`
+                Err(_) => true,
+            }
+        });
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("subscribe")
+            .unwrap()
+            .subscribe(subscription);
+        receiver
+    }
+}
+
+impl TreeObjectSpace {
+    /// Set the lease given to structs of type T written afterward,
+    /// overriding the default lease of the space.
+    /// `None` means structs of type T never expire.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::builder()
+    ///     .default_ttl(Duration::from_millis(1))
+    ///     .build();
+    /// space.ttl::<String>(None);
+    /// space.write(String::from("Hello World"));
+    /// space.write::<i64>(3);
+    /// std::thread::sleep(Duration::from_millis(10));
+    ///
+    /// assert_eq!(space.try_read::<String>(), Some(String::from("Hello World")));
+    /// assert_eq!(space.try_read::<i64>(), None);
+    /// ```
+    pub fn ttl<T>(&self, ttl: Option<Duration>)
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("ttl").unwrap().set_ttl(ttl);
+    }
+
+    /// Set the order in which `read`, `take`, `read_all`, `take_all` and their variants
+    /// return structs of type T.
+    ///
+    /// With `OrderingPolicy::Fifo`, the oldest struct comes first, and with `OrderingPolicy::Lifo`, the newest.
+    /// By default, structs come in any order, which makes `take` the fastest.
+    /// Lookups by a condition, e.g: `take_by_value`, are not affected.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, OrderingPolicy};
+    /// let space = TreeObjectSpace::new();
+    /// space.set_policy::<i64>(OrderingPolicy::Fifo);
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// assert_eq!(space.take::<i64>(), 3);
+    /// assert_eq!(space.take::<i64>(), 5);
+    /// ```
+    pub fn set_policy<T>(&self, policy: OrderingPolicy)
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("set_policy")
+            .unwrap()
+            .set_policy(policy);
+    }
+
+    /// Make writing a struct of type T equal to one already in the space a no-op, or not.
+    ///
+    /// Structs are equal if they serialize to the same JSON. Writing a duplicate returns the handle
+    /// of the struct equal to it, and wakes up no blocked call. Structs already equal to each other
+    /// when deduplication is turned on are left as is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.set_dedup::<i64>(true);
+    /// let handle = space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.write::<i64>(3), handle);
+    /// space.write::<i64>(5);
+    /// assert_eq!(space.read_all::<i64>().count(), 2);
+    ///
+    /// // once taken, the struct could be written again
+    /// space.try_take_by_handle::<i64>(handle);
+    /// space.write::<i64>(3);
+    /// assert_eq!(space.read_all::<i64>().count(), 2);
+    /// ```
+    pub fn set_dedup<T>(&self, dedup: bool)
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("set_dedup")
+            .unwrap()
+            .set_dedup(dedup);
+    }
+
+    /// Limit the number of structs of type T in the space, and set what writing one more does.
+    ///
+    /// With `Overflow::Block`, `write` waits until a struct is removed, e.g: taken by a consumer,
+    /// so that producers slow down to the pace of consumers. With `Overflow::Reject`, `write` panics,
+    /// and with `Overflow::EvictOldest`, the oldest struct is removed to make room.
+    /// `try_write` returns an error instead of waiting or panicking.
+    /// Structs beyond the limit when it is set are left as is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, Overflow, SpaceError};
+    /// let space = TreeObjectSpace::new();
+    /// space.set_capacity::<i64>(2, Overflow::Reject);
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    /// assert_eq!(space.try_write::<i64>(7), Err(SpaceError::Full(2)));
+    ///
+    /// space.set_capacity::<i64>(2, Overflow::EvictOldest);
+    /// space.write::<i64>(7);
+    /// let mut values: Vec<i64> = space.read_all::<i64>().collect();
+    /// values.sort();
+    /// assert_eq!(values, vec![5, 7]);
+    /// ```
+    pub fn set_capacity<T>(&self, limit: usize, overflow: Overflow)
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("set_capacity")
+            .unwrap()
+            .set_capacity(limit, overflow);
+    }
+
+    /// Limit the memory taken by the structs of type T in the space, in bytes, and set what writing one more does,
+    /// as `set_capacity` does for their number.
+    ///
+    /// The memory of a struct is estimated from its serialized size, its fields and their entries in the indices,
+    /// and reported by `memory_usage`. This suits types whose structs vary widely in size,
+    /// e.g: rows of pixels, which a capacity could not bound.
+    /// A struct larger than the quota on its own is rejected, even with `Overflow::Block`.
+    /// Structs beyond the limit when it is set are left as is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, Overflow, SpaceError};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(vec![0u8; 100]);
+    /// let quota = 3 * space.memory_usage::<Vec<u8>>();
+    /// space.set_memory_quota::<Vec<u8>>(quota, Overflow::Reject);
+    /// space.write(vec![1u8; 100]);
+    /// space.write(vec![2u8; 100]);
+    ///
+    /// assert_eq!(space.try_write(vec![3u8; 100]), Err(SpaceError::OverQuota(quota)));
+    /// assert!(space.memory_usage::<Vec<u8>>() <= quota);
+    /// ```
+    pub fn set_memory_quota<T>(&self, bytes: usize, overflow: Overflow)
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("set_memory_quota")
+            .unwrap()
+            .set_quota(bytes, overflow);
+    }
+
+    /// Return an estimate of the memory taken by the structs of type T in the space and their indices, in bytes.
+    /// Payloads spilled to disk are not counted.
+    pub fn memory_usage<T>(&self) -> usize
+    where
+        T: 'static,
+    {
+        self.get_object_entry_ref::<T>().map_or(0, |entry| entry.memory())
+    }
+
+    /// Choose which structs of type T are evicted, e.g: to use the space as a cache of type T.
+    ///
+    /// With `Eviction::LeastRecentlyRead`, `Overflow::EvictOldest` removes the struct of type T which has gone unread
+    /// the longest, rather than the one written the longest ago. Lookups of a single struct, by condition or not,
+    /// count as reads, while `read_all`, `read_all_arc` and snapshots do not.
+    /// With `Eviction::OlderThan(age)`, structs of type T written longer than `age` ago are removed
+    /// whenever the type is accessed, or by `sweep`, whatever the capacity of the type.
+    /// Evicted structs are reported in `TypeMetrics::evicted`.
+    ///
+    /// The structs already in the space are considered written, and read, when a policy is first set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{Eviction, TreeObjectSpace, ObjectSpace, Overflow, ValueLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.set_capacity::<i64>(2, Overflow::EvictOldest);
+    /// space.set_eviction::<i64>(Eviction::LeastRecentlyRead);
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    /// space.read_by_value::<i64>("", &3i64);
+    /// space.write::<i64>(7);
+    ///
+    /// let mut cached: Vec<i64> = space.read_all::<i64>().collect();
+    /// cached.sort();
+    /// assert_eq!(cached, vec![3, 7]);
+    /// ```
+    pub fn set_eviction<T>(&self, eviction: Eviction)
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("set_eviction")
+            .unwrap()
+            .set_eviction(eviction);
+    }
+
+    /// Declare fields of structs of type T which are only ever queried by equality.
+    ///
+    /// Such fields are indexed by a `HashMap` instead of a `BTreeMap`,
+    /// which makes value lookup faster.
+    /// Range lookup on these fields will panic.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.hash_index::<i64>(&[""]);
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_read_by_value::<i64>("", &3i64), Some(3));
+    /// ```
+    pub fn hash_index<T>(&self, fields: &[&str])
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        let mut entry = self.get_object_entry_mut::<T>("hash_index").unwrap();
+        for field in fields {
+            entry.set_hash_index(field);
+        }
+    }
+
+    /// Declare the only fields of structs of type T which are looked up by value or range.
+    ///
+    /// By default, every field of a struct is indexed when it is written.
+    /// Once some fields are declared, only they are indexed, which makes writing large structs faster.
+    /// Fields declared by successive calls are all indexed, and lookups on other fields panic.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Pixel {
+    ///     row: i64,
+    ///     col: i64,
+    ///     color: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.index_fields::<Pixel>(&["row"]);
+    /// space.write(Pixel { row: 3, col: 5, color: 255 });
+    ///
+    /// assert_eq!(space.try_read_by_value::<Pixel>("row", &3i64).map(|pixel| pixel.col), Some(5));
+    /// # }
+    /// ```
+    pub fn index_fields<T>(&self, fields: &[&str])
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("index_fields")
+            .unwrap()
+            .set_indexed_fields(fields);
+    }
+
+    /// Compare the strings of a field of structs of type T according to a collation.
+    ///
+    /// With `Collation::CaseInsensitive`, value, range, query and pattern lookups on the field
+    /// ignore case, as do the calls blocked on them. Structs are returned as they were written.
+    /// With `Collation::Timestamp`, which requires the `chrono` feature, times are compared as instants,
+    /// and the field could be looked up by ranges of `DateTime<Utc>`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace, Collation};
+    /// let space = TreeObjectSpace::new();
+    /// space.set_collation::<String>("", Collation::CaseInsensitive);
+    /// space.write(String::from("Hello"));
+    ///
+    /// assert_eq!(
+    ///     space.try_read_by_value::<String>("", &String::from("HELLO")),
+    ///     Some(String::from("Hello"))
+    /// );
+    /// ```
+    pub fn set_collation<T>(&self, field: &str, collation: Collation)
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("set_collation")
+            .unwrap()
+            .set_collation(field, collation);
+    }
+
+    /// Declare an interval index on a pair of fields `(start, end)` of structs of type T.
+    ///
+    /// The index is an interval tree answering queries of `IntervalLookupObjectSpace`
+    /// without scanning all structs of type T.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, IntervalLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Task {
+    ///     start: i64,
+    ///     end: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.interval_index::<Task>(("start", "end"));
+    /// space.write(Task { start: 0, end: 10 });
+    ///
+    /// assert_eq!(space.read_all_covering::<Task>(("start", "end"), &7).count(), 1);
+    /// # }
+    /// ```
+    pub fn interval_index<T>(&self, fields: (&str, &str))
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("interval_index")
+            .unwrap()
+            .set_interval_index(fields);
+    }
+
+    /// Declare a 2D index on a pair of numeric fields `(x, y)` of structs of type T.
+    ///
+    /// The index is a quadtree answering queries of `SpatialLookupObjectSpace`
+    /// without scanning all structs of type T.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, SpatialLookupObjectSpace};
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Place {
+    ///     lat: f64,
+    ///     lon: f64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.spatial_index::<Place>(("lat", "lon"));
+    /// space.write(Place { lat: 42.7, lon: -73.2 });
+    ///
+    /// assert_eq!(space.read_all_in_rect::<Place, _>(("lat", "lon"), (42.0..43.0, -74.0..-73.0)).count(), 1);
+    /// # }
+    /// ```
+    pub fn spatial_index<T>(&self, fields: (&str, &str))
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("spatial_index")
+            .unwrap()
+            .set_spatial_index(fields);
+    }
+
+    /// Declare a compound index on several fields of structs of type T.
+    ///
+    /// Queries of `QueryObjectSpace` requiring all these fields to equal some values,
+    /// e.g: `try_take_by_match`, find the matching structs in a single lookup
+    /// instead of intersecting the structs matching each field.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate object_space;
+    /// # use object_space::{FieldValue, TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Task {
+    ///     finished: bool,
+    ///     priority: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.create_index::<Task>(&["finished", "priority"]);
+    /// space.write(Task { finished: false, priority: 5 });
+    ///
+    /// let fields = [("finished", FieldValue::from(false)), ("priority", FieldValue::from(5))];
+    /// assert!(space.try_take_by_match::<Task>(&fields).is_some());
+    /// # }
+    /// ```
+    pub fn create_index<T>(&self, fields: &[&str])
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("create_index")
+            .unwrap()
+            .set_compound_index(fields);
+    }
+
+    /// Return the schema of type T: the kind of each flattened field of its structs,
+    /// captured when the first struct of type T is written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate object_space;
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, FieldKind};
+    /// # fn main() {
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Person {
+    ///     name: String,
+    ///     age: Option<i64>,
+    /// }
+    ///
+    /// let space = TreeObjectSpace::new();
+    /// assert_eq!(space.schema::<Person>(), None);
+    ///
+    /// space.write(Person { name: String::from("Tuan"), age: None });
+    /// let schema = space.schema::<Person>().unwrap();
+    /// assert_eq!(schema.get("name"), Some(FieldKind::String));
+    /// assert_eq!(schema.get("age"), Some(FieldKind::Unknown));
+    ///
+    /// space.write(Person { name: String::from("Duane"), age: Some(21) });
+    /// assert_eq!(space.schema::<Person>().unwrap().get("age"), Some(FieldKind::Int));
+    /// # }
+    /// ```
+    pub fn schema<T>(&self) -> Option<Schema>
+    where
+        T: 'static,
+    {
+        self.get_object_entry_ref::<T>()
+            .and_then(|entry| entry.schema().cloned())
+    }
+
+    /// Return a JSON Schema document describing structs of type T, built from the schema of type T,
+    /// so that clients written in other languages know what to send.
+    /// The document is titled with the name of type T.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate object_space;
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # #[macro_use]
+    /// # extern crate serde_json;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// # fn main() {
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Reminder {
+    ///     time: i64,
+    ///     note: Option<String>,
+    /// }
+    ///
+    /// let space = TreeObjectSpace::new();
+    /// space.write(Reminder { time: 0, note: None });
+    /// space.write(Reminder { time: 1, note: Some(String::from("Hello World")) });
+    ///
+    /// let schema = space.json_schema::<Reminder>().unwrap();
+    /// assert_eq!(schema["properties"]["time"], json!({ "type": "integer" }));
+    /// assert_eq!(schema["properties"]["note"], json!({ "type": ["string", "null"] }));
+    /// assert_eq!(schema["required"], json!(["note", "time"]));
+    /// # }
+    /// ```
+    pub fn json_schema<T>(&self) -> Option<serde_json::Value>
+    where
+        T: 'static,
+    {
+        self.schema::<T>()
+            .map(|schema| schema.to_json_schema(self.stable_name::<T>()))
+    }
+}
+
+impl TreeObjectSpace {
+    /// Name type T, so that its structs are addressed by this name instead of its full Rust name,
+    /// which depends on the module it is defined in.
+    ///
+    /// The name of a type is what identifies it outside of the process: in the journal of the space,
+    /// in its metrics, and in `write_json` and `read_all_json`.
+    /// Processes built from different binaries or versions should name the types they share alike.
+    /// A type should be named before it is used, as the structs already journaled keep their former name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is already registered for another type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.register_type::<i64>("counter");
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.stable_name::<i64>(), "counter");
+    /// assert_eq!(space.type_names(), vec!["counter"]);
+    /// ```
+    pub fn register_type<T>(&self, name: &'static str)
+    where
+        T: 'static,
+    {
+        self.registry.write().register::<T>(name);
+        if let Some(slot) = self.slots.write().get_mut(&TypeId::of::<T>()) {
+            slot.type_name = name;
+        }
+    }
+
+    /// Return the name of type T: the one registered with `register_type`, or its full Rust name.
+    pub fn stable_name<T>(&self) -> &'static str
+    where
+        T: 'static,
+    {
+        self.registry.read().name::<T>()
+    }
+
+    /// Return the names of the types in the space, sorted.
+    pub fn type_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.slots.read().values().map(|slot| slot.type_name).collect();
+        names.sort();
+        names
+    }
+
+    /// Write a struct given as a JSON document to the space, as a struct of the type with the specified name,
+    /// e.g: to replicate structs received from a process built from another binary.
+    ///
+    /// Return None if the space holds no entry for the type,
+    /// e.g: because no struct of the type has been written to it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_json;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.register_type::<i64>("counter");
+    /// space.write::<i64>(3);
+    ///
+    /// assert!(space.write_json("counter", json!(5)).is_some());
+    /// assert!(space.write_json("unknown", json!(5)).is_none());
+    /// assert_eq!(space.read_all_json("counter"), vec![json!(3), json!(5)]);
+    /// # }
+    /// ```
+    pub fn write_json(&self, name: &str, obj: serde_json::Value) -> Option<ObjectHandle> {
+        let type_id = self.registry.read().type_id(name)?;
+        let value = Record::from_json(&obj, self.codec).expect("struct cannot be serialized");
+        match self.add_values(type_id, vec![value], None, "write_json", WhenFull::Overflow) {
+            (_, Some(rejection)) => rejection.raise(),
+            (indices, None) => indices.first().map(|&index| ObjectHandle::new(index)),
+        }
+    }
+
+    /// Return all structs of the type with the specified name, as JSON documents.
+    pub fn read_all_json(&self, name: &str) -> Vec<serde_json::Value> {
+        self.read_json(name, |entry| entry.get_all().collect())
+    }
+
+    /// Write a JSON document to the space as a struct of the type with the specified name,
+    /// whether or not a Rust type has that name, e.g: to forward documents received from external systems.
+    ///
+    /// The document is written as a struct of the Rust type with that name if the space holds an entry for it,
+    /// as `write_json` does. Otherwise it is kept with the other documents written under that name,
+    /// which are looked up, by field too, with `DynObjectSpace`.
+    /// Such documents are not seen by typed lookups, even once a Rust type is given their name,
+    /// nor by blocking calls, journals and checkpoints.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the space is closed, if the name already holds as many documents as the default capacity
+    /// of the space and does not evict the oldest ones, or if a field of the document holds another kind of value
+    /// than in the documents written before, e.g: a float where ints have been written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_json;
+    /// # extern crate object_space;
+    /// # use object_space::{field, DynObjectSpace, TreeObjectSpace};
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.write_value("order", json!({ "id": 1, "item": "book" }));
+    /// space.write_value("order", json!({ "id": 2, "item": "pen" }));
+    ///
+    /// assert_eq!(space.read_all_json("order").len(), 2);
+    /// assert_eq!(
+    ///     space.try_take_json_by_query("order", &field("item").eq("pen")),
+    ///     Some(json!({ "id": 2, "item": "pen" }))
+    /// );
+    /// assert!(space.try_read_json("invoice").is_none());
+    /// # }
+    /// ```
+    pub fn write_value(&self, type_name: &str, value: serde_json::Value) -> ObjectHandle {
+        self.add_value(type_name, value, "write_value", WhenFull::Overflow)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `write_value`, but return an error instead of panicking,
+    /// and never wait for room in a Rust type whose capacity blocks writers, like `try_write`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_json;
+    /// # extern crate object_space;
+    /// # use object_space::{SpaceError, TreeObjectSpace};
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.close();
+    /// assert_eq!(space.try_write_value("order", json!({ "id": 1 })), Err(SpaceError::Closed));
+    /// # }
+    /// ```
+    pub fn try_write_value(&self, type_name: &str, value: serde_json::Value) -> Result<ObjectHandle, SpaceError> {
+        self.add_value(type_name, value, "try_write_value", WhenFull::Fail)
+    }
+
+    fn add_value(
+        &self,
+        type_name: &str,
+        value: serde_json::Value,
+        operation: &'static str,
+        when_full: WhenFull,
+    ) -> Result<ObjectHandle, SpaceError> {
+        let to_record = || Record::from_json(&value, self.codec).map_err(|e| SpaceError::Serialization(e.to_string()));
+        // released before the entries are looked up, as adding an entry locks the registry
+        let type_id = self.registry.read().type_id(type_name);
+        if let Some(type_id) = type_id {
+            match self.add_values(type_id, vec![to_record()?], None, operation, when_full) {
+                (_, Some(rejection)) => return Err(rejection.into_error()),
+                (indices, None) => {
+                    if let Some(&index) = indices.first() {
+                        return Ok(ObjectHandle::new(index));
+                    }
+                }
+            }
+        }
+        // the space holds no entry for a Rust type with that name
+        let mut record = to_record()?;
+        if let Some(ref store) = self.spill {
+            record.spill(store);
+        }
+        let entry = self.untyped_entry(type_name);
+        let added = {
+            let mut entry = entry.write();
+            entry.remove_expired(Instant::now());
+            if self.is_closed() {
+                Err(Rejection::Closed)
+            } else if !entry.make_room(&record, true) {
+                let (breach, _) = entry.breach(&record).expect("only an entry with a limit is full");
+                Err(Rejection::from(breach))
+            } else {
+                entry.add(record).map_err(Rejection::Mismatch)
+            }
+        };
+        added.map(ObjectHandle::new).map_err(Rejection::into_error)
+    }
+
+    /// Return the entry of the documents written with `write_value` under the specified name,
+    /// adding it if there is none.
+    fn untyped_entry(&self, name: &str) -> Arc<RwLock<Entry>> {
+        if let Some(entry) = self.untyped.read().get(name) {
+            return entry.clone();
+        }
+        self.untyped
+            .write()
+            .entry(name.to_owned())
+            .or_insert_with(|| Arc::new(RwLock::new(self.new_entry())))
+            .clone()
+    }
+
+    /// Return the entry holding the structs of the type with the specified name, if any:
+    /// the entry of the Rust type with that name, or else that of the documents written with `write_value`.
+    /// Expired structs are removed from the entry first.
+    fn named_entry(&self, name: &str) -> Option<Arc<RwLock<Entry>>> {
+        let type_id = self.registry.read().type_id(name);
+        if let Some(type_id) = type_id {
+            self.remove_expired(type_id);
+            if let Some(entry) = self.entry(type_id) {
+                return Some(entry);
+            }
+        }
+        let entry = self.untyped.read().get(name).cloned()?;
+        let now = Instant::now();
+        if entry.read().has_expired(now) {
+            entry.write().remove_expired(now);
+        }
+        Some(entry)
+    }
+
+    /// Look up the entry of the type with the specified name, if any.
+    pub(crate) fn read_named<F, R>(&self, name: &str, lookup: F) -> Option<R>
+    where
+        F: FnOnce(&Entry) -> R,
+    {
+        self.named_entry(name).map(|entry| {
+            let entry = entry.read();
+            entry.record_read();
+            lookup(&entry)
+        })
+    }
+
+    /// Remove structs from the entry of the type with the specified name, if any.
+    pub(crate) fn take_named<F, R>(&self, name: &str, operation: &'static str, remove: F) -> Option<R>
+    where
+        F: FnOnce(&mut Entry) -> R,
+    {
+        let type_id = self.registry.read().type_id(name);
+        let slot = type_id.and_then(|type_id| self.slots.read().get(&type_id).map(|slot| (type_id, slot.type_name)));
+        match slot {
+            Some((type_id, type_name)) => self.entry_mut(type_id, type_name, operation)
+                .map(|mut entry| remove(&mut entry)),
+            None => self.untyped.read().get(name).map(|entry| {
+                let mut entry = entry.write();
+                entry.remove_expired(Instant::now());
+                remove(&mut entry)
+            }),
+        }
+    }
+
+    /// Notify a subscription of the structs of the type with the specified name written from now on,
+    /// whether they are structs of the Rust type with that name or documents written with `write_value`.
+    pub(crate) fn subscribe_named(&self, name: &str, subscription: Subscription) {
+        let entry = self.named_entry(name).unwrap_or_else(|| self.untyped_entry(name));
+        entry.write().subscribe(subscription);
+    }
+
+    /// Look up the entry of the type with the specified name, and return the structs found as JSON documents.
+    fn read_json<F>(&self, name: &str, lookup: F) -> Vec<serde_json::Value>
+    where
+        F: FnOnce(&Entry) -> Vec<Arc<Record>>,
+    {
+        self.read_named(name, lookup)
+            .unwrap_or_default()
+            .iter()
+            .map(|record| record.to_json().expect("struct cannot be read as JSON"))
+            .collect()
+    }
+
+    /// Remove structs from the entry of the type with the specified name, and return them as JSON documents.
+    fn take_json<F>(&self, name: &str, operation: &'static str, remove: F) -> Vec<serde_json::Value>
+    where
+        F: FnOnce(&mut Entry) -> Vec<Arc<Record>>,
+    {
+        self.take_named(name, operation, remove)
+            .unwrap_or_default()
+            .iter()
+            .map(|record| record.to_json().expect("struct cannot be read as JSON"))
+            .collect()
+    }
+
+    /// Reject structs of type T whose shape differs from the schema of type T:
+    /// writing such a struct panics and leaves the space untouched.
+    ///
+    /// A null value matches a field of any kind, so that `Option` fields could be `None`.
+    /// Other differences, e.g: enum variants with different fields, are rejected.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate object_space;
+    /// # extern crate serde_json;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// # use std::panic::{catch_unwind, AssertUnwindSafe};
+    /// # use serde_json::value::Value;
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.strict_schema::<Value>();
+    /// space.write(serde_json::json!({ "name": "Tuan" }));
+    ///
+    /// let result = catch_unwind(AssertUnwindSafe(|| space.write(serde_json::json!({ "name": 3 }))));
+    /// assert!(result.is_err());
+    /// assert_eq!(space.read_all::<Value>().count(), 1);
+    /// # }
+    /// ```
+    pub fn strict_schema<T>(&self)
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        self.get_object_entry_mut::<T>("strict_schema")
+            .unwrap()
+            .set_strict_schema(true);
+    }
+
+    /// Complete the structs of type T which could not be read as T, e.g: because they were saved
+    /// before T gained a field, with the fields they miss from `T::default()`.
+    ///
+    /// This applies to the structs already in the space, and to those added from now on,
+    /// e.g: by `restore` or `write_json`. Structs which could still not be read are kept aside
+    /// by `dead_letter`, or otherwise left in the space, where lookups skip them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_derive;
+    /// # #[macro_use] extern crate serde_json;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// #[derive(Serialize, Deserialize, Default)]
+    /// struct Task {
+    ///     name: String,
+    ///     retries: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.fill_missing_fields::<Task>();
+    /// space.register_type::<Task>("Task");
+    /// // saved before `Task` gained `retries`
+    /// space.write_json("Task", json!({ "name": "backup" }));
+    ///
+    /// assert_eq!(space.try_take::<Task>().unwrap().retries, 0);
+    /// # }
+    /// ```
+    pub fn fill_missing_fields<T>(&self)
+    where
+        for<'de> T: Default + Serialize + Deserialize<'de> + 'static,
+    {
+        let default = serde_json::to_value(T::default()).expect("struct cannot be serialized");
+        let codec = self.codec;
+        self.set_decoder::<T, _>("fill_missing_fields", move |decoder| {
+            decoder.set_fill(move |record| {
+                let mut value = record.to_json().ok()?;
+                fill_missing_fields(&mut value, &default);
+                let filled = Record::from_json(&value, codec).ok()?;
+                filled.deserialize::<T>().ok().map(|_| filled)
+            })
+        });
+    }
+
+    /// Keep the structs of type T which could not be read as T aside, as dead letters,
+    /// instead of leaving them in the space, where lookups skip them.
+    ///
+    /// This applies to the structs already in the space, and to those added from now on,
+    /// e.g: by `restore` or `write_json`. Dead letters could be inspected with `dead_letters`,
+    /// and migrated by hand with `requeue_dead_letter`, or after `take_dead_letters`.
+    ///
+    /// Structs taken which could not be read as T are kept as dead letters whether or not this is called.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_json;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.dead_letter::<i64>();
+    /// space.write_json("i64", json!("three"));
+    /// space.write_json("i64", json!(5));
+    ///
+    /// assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![5]);
+    /// let dead_letters = space.take_dead_letters::<i64>();
+    /// assert_eq!(dead_letters[0].payload, json!("three"));
+    /// assert!(space.dead_letters::<i64>().is_empty());
+    /// # }
+    /// ```
+    pub fn dead_letter<T>(&self)
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        self.set_decoder::<T, _>("dead_letter", Decoder::keep_dead_letters);
+    }
+
+    /// Return the structs of type T kept aside because they could not be read as T.
+    pub fn dead_letters<T>(&self) -> Vec<DecodeError>
+    where
+        T: 'static,
+    {
+        self.entry(TypeId::of::<T>())
+            .map(|entry| entry.read().decoder().dead_letters().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Remove and return the structs of type T kept aside because they could not be read as T,
+    /// e.g: to write them back once migrated.
+    pub fn take_dead_letters<T>(&self) -> Vec<DecodeError>
+    where
+        T: 'static,
+    {
+        match self.get_object_entry_mut::<T>("take_dead_letters") {
+            Some(mut entry) => entry.decoder_mut().take_dead_letters(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Write back the dead letter of type T at the specified index, migrated to `payload`,
+    /// and return the handle of the struct written.
+    ///
+    /// If `payload` could not be read as T either, the dead letter is left as is
+    /// and the error is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no dead letter of type T at the specified index.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_derive;
+    /// # #[macro_use] extern crate serde_json;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    /// struct Task {
+    ///     name: String,
+    ///     retries: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.register_type::<Task>("Task");
+    /// space.write(Task { name: String::from("backup"), retries: 3 });
+    /// space.write_json("Task", json!({ "name": "cleanup" }));
+    ///
+    /// // the struct taken which could not be read is kept aside instead of being lost
+    /// assert_eq!(space.take_all::<Task>().count(), 1);
+    /// let mut payload = space.dead_letters::<Task>()[0].payload.clone();
+    /// payload["retries"] = json!(0);
+    /// space.requeue_dead_letter::<Task>(0, payload).unwrap();
+    ///
+    /// assert!(space.dead_letters::<Task>().is_empty());
+    /// assert_eq!(space.try_take::<Task>(), Some(Task { name: String::from("cleanup"), retries: 0 }));
+    /// # }
+    /// ```
+    pub fn requeue_dead_letter<T>(&self, index: usize, payload: serde_json::Value) -> Result<ObjectHandle, DecodeError>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let obj: T = match serde_json::from_value(payload.clone()) {
+            Ok(obj) => obj,
+            Err(e) => {
+                return Err(DecodeError {
+                    payload,
+                    error: e.to_string(),
+                })
+            }
+        };
+        let removed = self
+            .get_object_entry_mut::<T>("requeue_dead_letter")
+            .and_then(|mut entry| entry.decoder_mut().remove_dead_letter(index));
+        if removed.is_none() {
+            panic!("No dead letter of type {} at index {}", self.stable_name::<T>(), index);
+        }
+        Ok(self.write(obj))
+    }
+
+    /// Return all structs of type T, or why each could not be read as T,
+    /// instead of skipping the structs which could not be read like `read_all`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_derive;
+    /// # #[macro_use] extern crate serde_json;
+    /// # extern crate object_space;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    /// struct Task {
+    ///     name: String,
+    ///     retries: i64,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.register_type::<Task>("Task");
+    /// space.write(Task { name: String::from("backup"), retries: 3 });
+    /// space.write_json("Task", json!({ "name": "cleanup" }));
+    ///
+    /// let decoded = space.read_all_decoded::<Task>();
+    /// assert_eq!(decoded[0], Ok(Task { name: String::from("backup"), retries: 3 }));
+    /// assert_eq!(decoded[1].as_ref().unwrap_err().payload, json!({ "name": "cleanup" }));
+    /// # }
+    /// ```
+    pub fn read_all_decoded<T>(&self) -> Vec<Result<T, DecodeError>>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        let records: Vec<_> = match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get_all().collect(),
+            None => Vec::new(),
+        };
+        records
+            .iter()
+            .map(|record| record.deserialize().map_err(|e| DecodeError::new(record, &e)))
+            .collect()
+    }
+}
+
+impl TreeObjectSpace {
+    /// Save all structs of type T to a file at the specified path.
+    ///
+    /// Structs are stored as a JSON array, so that the file could be inspected by hand.
+    /// The file is written to a temporary path first and then renamed,
+    /// so an existing checkpoint is never left half-written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// # let path = std::env::temp_dir().join("object_space_doc_checkpoint.json");
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.checkpoint::<i64>(&path).unwrap();
+    ///
+    /// let other = TreeObjectSpace::new();
+    /// assert_eq!(other.restore::<i64>(&path).unwrap(), 1);
+    /// assert_eq!(other.try_read::<i64>(), Some(3));
+    /// ```
+    pub fn checkpoint<T>(&self, path: &AsRef<Path>) -> io::Result<()>
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        let records: Vec<_> = {
+            let mut entry = self.get_object_entry_mut::<T>("checkpoint").unwrap();
+            entry.track_changes();
+            entry.get_all().collect()
+        };
+
+        let saved = write_atomically(path.as_ref(), |writer| {
+            write_payloads(writer, records.iter().map(|record| &**record))
+        });
+        if saved.is_err() {
+            self.forget_changes::<T>();
+        }
+        saved
+    }
+
+    /// Save the structs of type T added and removed since the previous checkpoint of the type,
+    /// full or incremental, to a file at the specified path.
+    ///
+    /// This is much cheaper than `checkpoint` for large types which change slowly.
+    /// An error is returned if type T has never been checkpointed,
+    /// or if the previous checkpoint could not be saved.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace};
+    /// # let full_path = std::env::temp_dir().join("object_space_doc_full.json");
+    /// # let delta_path = std::env::temp_dir().join("object_space_doc_delta.json");
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    /// space.checkpoint::<i64>(&full_path).unwrap();
+    /// space.write::<i64>(7);
+    /// space.take_by_value::<i64>("", &3i64);
+    /// space.checkpoint_delta::<i64>(&delta_path).unwrap();
+    ///
+    /// let other = TreeObjectSpace::new();
+    /// other.restore::<i64>(&full_path).unwrap();
+    /// other.restore_delta::<i64>(&delta_path).unwrap();
+    /// let mut restored: Vec<i64> = other.read_all::<i64>().collect();
+    /// restored.sort();
+    /// assert_eq!(restored, vec![5, 7]);
+    /// ```
+    pub fn checkpoint_delta<T>(&self, path: &AsRef<Path>) -> io::Result<()>
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        let changes = self.get_object_entry_mut::<T>("checkpoint_delta")
+            .unwrap()
+            .take_changes();
+        let changes = match changes {
+            Some(changes) => changes,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the type has no previous checkpoint to record changes from",
+                ))
+            }
+        };
+
+        let saved = write_atomically(path.as_ref(), |writer| {
+            writer.write_all(b"{\"removed\":")?;
+            write_payloads(writer, changes.removed())?;
+            writer.write_all(b",\"added\":")?;
+            write_payloads(writer, changes.added())?;
+            writer.write_all(b"}")
+        });
+        if saved.is_err() {
+            self.forget_changes::<T>();
+        }
+        saved
+    }
+
+    /// Add all structs of type T saved by `checkpoint` at the specified path to the space.
+    /// Return the number of structs restored.
+    ///
+    /// Structs already in the space are kept.
+    /// The file is rejected as a whole if any of its structs is not of type T,
+    /// unless such structs are completed by `fill_missing_fields` or kept aside by `dead_letter`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// # let path = std::env::temp_dir().join("object_space_doc_restore.json");
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Hello World"));
+    /// space.checkpoint::<String>(&path).unwrap();
+    ///
+    /// assert_eq!(space.restore::<String>(&path).unwrap(), 1);
+    /// assert_eq!(space.read_all::<String>().count(), 2);
+    /// ```
+    pub fn restore<T>(&self, path: &AsRef<Path>) -> io::Result<usize>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let reader = BufReader::new(File::open(path)?);
+        let values: Vec<serde_json::Value> = serde_json::from_reader(reader)?;
+        let count = values.len();
+        if self.entry(TypeId::of::<T>()).is_some_and(|entry| entry.read().decoder().is_set()) {
+            // structs which could not be read as T are completed or kept aside by the decoder
+            let records = values
+                .iter()
+                .map(|value| Record::from_json(value, self.codec).expect("struct cannot be serialized"))
+                .collect();
+            self.add_records::<T>(records, "restore");
+        } else {
+            let structs = values
+                .into_iter()
+                .map(serde_json::from_value)
+                .collect::<Result<Vec<T>, _>>()?;
+            self.write_all(structs);
+        }
+        Ok(count)
+    }
+
+    /// Apply the changes saved by `checkpoint_delta` at the specified path to the space,
+    /// and return the number of structs added.
+    ///
+    /// For each struct removed since the previous checkpoint,
+    /// one struct equal to it is removed from the space, if there is any.
+    /// Deltas should be applied in the order they were saved,
+    /// on top of the checkpoint they started from.
+    pub fn restore_delta<T>(&self, path: &AsRef<Path>) -> io::Result<usize>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let reader = BufReader::new(File::open(path)?);
+        let delta: Delta<T> = serde_json::from_reader(reader)?;
+        let payloads = delta.removed
+            .iter()
+            .map(|obj| self.codec.encode(obj))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(mut entry) = self.get_object_entry_mut::<T>("restore_delta") {
+            entry.remove_by_payloads(&payloads);
+        }
+
+        let count = delta.added.len();
+        self.write_all(delta.added);
+        Ok(count)
+    }
+
+    /// Write all structs of type T to `writer` as JSON Lines, a JSON document per line,
+    /// e.g: to seed another space from them with `import`. Return the number of structs written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// let mut lines = Vec::new();
+    /// assert_eq!(space.export::<i64>(&mut lines).unwrap(), 2);
+    /// assert_eq!(lines, b"3\n5\n");
+    ///
+    /// let other = TreeObjectSpace::new();
+    /// assert_eq!(other.import::<i64>(&mut &lines[..]).unwrap(), 2);
+    /// assert_eq!(other.read_all::<i64>().collect::<Vec<_>>(), vec![3, 5]);
+    /// ```
+    pub fn export<T>(&self, writer: &mut Write) -> io::Result<usize>
+    where
+        T: 'static,
+    {
+        // the type is only locked while the structs are collected, not while they are written
+        let records: Vec<_> = match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get_all().collect(),
+            None => Vec::new(),
+        };
+        write_lines(writer, records.iter().map(|record| &**record), b"", b"")
+    }
+
+    /// Add the structs of type T read from `reader` as JSON Lines, such as written by `export`, to the space.
+    /// Return the number of structs imported.
+    ///
+    /// Blank lines are skipped. As with `restore`, the input is rejected as a whole
+    /// if any of its lines is not a struct of type T,
+    /// unless such structs are completed by `fill_missing_fields` or kept aside by `dead_letter`.
+    pub fn import<T>(&self, reader: &mut Read) -> io::Result<usize>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        if self.entry(TypeId::of::<T>()).is_some_and(|entry| entry.read().decoder().is_set()) {
+            let values: Vec<serde_json::Value> = read_lines(reader)?;
+            let records: Vec<_> = values
+                .iter()
+                .map(|value| Record::from_json(value, self.codec).expect("struct cannot be serialized"))
+                .collect();
+            let count = records.len();
+            self.add_records::<T>(records, "import");
+            Ok(count)
+        } else {
+            let structs: Vec<T> = read_lines(reader)?;
+            let count = structs.len();
+            self.write_all(structs);
+            Ok(count)
+        }
+    }
+
+    /// Write the structs of every type in the space to `writer` as JSON Lines,
+    /// each line holding the name of the type of a struct and the struct, e.g: `{"type":"counter","value":3}`,
+    /// including the documents written with `write_value`. Return the number of structs written.
+    ///
+    /// Types are named as by `stable_name`, so that `import_all` finds them in a space built from another binary
+    /// once they are registered under the same names. Types are written one after the other, sorted by name,
+    /// each at a point in time, so the whole space is not locked at once.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[macro_use] extern crate serde_json;
+    /// # extern crate object_space;
+    /// # use object_space::{DynObjectSpace, TreeObjectSpace, ObjectSpace};
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.register_type::<i64>("counter");
+    /// space.write::<i64>(3);
+    /// space.write_value("order", json!({ "id": 1 }));
+    ///
+    /// let mut lines = Vec::new();
+    /// assert_eq!(space.export_all(&mut lines).unwrap(), 2);
+    /// assert_eq!(
+    ///     String::from_utf8(lines.clone()).unwrap(),
+    ///     "{\"type\":\"counter\",\"value\":3}\n{\"type\":\"order\",\"value\":{\"id\":1}}\n"
+    /// );
+    ///
+    /// let other = TreeObjectSpace::new();
+    /// assert_eq!(other.import_all(&mut &lines[..]).unwrap(), 2);
+    /// assert_eq!(other.read_all_json("order"), vec![json!({ "id": 1 })]);
+    /// # }
+    /// ```
+    pub fn export_all(&self, writer: &mut Write) -> io::Result<usize> {
+        let mut entries: Vec<(String, Arc<RwLock<Entry>>)> = self.slots
+            .read()
+            .values()
+            .map(|slot| (slot.type_name.to_owned(), slot.entry.clone()))
+            .collect();
+        entries.extend(
+            self.untyped
+                .read()
+                .iter()
+                .map(|(name, entry)| (name.clone(), entry.clone())),
+        );
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut count = 0;
+        for (name, entry) in entries {
+            let now = Instant::now();
+            let records: Vec<_> = {
+                if entry.read().has_expired(now) {
+                    entry.write().remove_expired(now);
+                }
+                entry.read().get_all().collect()
+            };
+            let name = serde_json::to_vec(&name).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let prefix = [&b"{\"type\":"[..], &name, b",\"value\":"].concat();
+            count += write_lines(writer, records.iter().map(|record| &**record), &prefix, b"}")?;
+        }
+        Ok(count)
+    }
+
+    /// Add the structs read from `reader` as JSON Lines, such as written by `export_all`, to the space.
+    /// Return the number of structs imported.
+    ///
+    /// Each struct is written as by `write_value`: as a struct of the Rust type with its name
+    /// if the space holds an entry for it, or else as a document looked up with `DynObjectSpace`.
+    /// The input is rejected as a whole if any of its lines could not be read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `write_value` would.
+    pub fn import_all(&self, reader: &mut Read) -> io::Result<usize> {
+        let structs: Vec<ExportedStruct> = read_lines(reader)?;
+        let count = structs.len();
+        for obj in structs {
+            self.write_value(&obj.type_name, obj.value);
+        }
+        Ok(count)
+    }
+}
+
+impl TreeObjectSpace {
+    /// Call `f` with a struct of type T, if there is any, and return the result.
+    ///
+    /// The struct is deserialized straight from the space,
+    /// skipping the copy of the stored value made by `try_read`.
+    /// The space is not locked while `f` runs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// assert_eq!(space.with_read::<String, _, _>(|s| s.len()), None);
+    ///
+    /// space.write(String::from("Hello World"));
+    /// assert_eq!(space.with_read::<String, _, _>(|s| s.len()), Some(11));
+    /// ```
+    pub fn with_read<T, F, R>(&self, f: F) -> Option<R>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+        F: FnOnce(&T) -> R,
+    {
+        let record = self.get_object_entry_ref::<T>()?.get()?;
+        let obj: T = record.deserialize().ok()?;
+        Some(f(&obj))
+    }
+
+    /// Return a shared copy of a struct of type T, if there is any.
+    ///
+    /// Unlike `try_read`, the struct is deserialized only once
+    /// and kept in the space for later calls, so reading it again is cheap.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// assert_eq!(space.try_read_arc::<String>(), None);
+    ///
+    /// space.write(String::from("Hello World"));
+    /// let first = space.try_read_arc::<String>().unwrap();
+    /// let second = space.try_read_arc::<String>().unwrap();
+    /// assert_eq!(*first, "Hello World");
+    /// assert!(std::sync::Arc::ptr_eq(&first, &second));
+    /// ```
+    pub fn try_read_arc<T>(&self) -> Option<Arc<T>>
+    where
+        for<'de> T: Deserialize<'de> + Send + Sync + 'static,
+    {
+        self.get_object_entry_ref::<T>()
+            .and_then(|entry| entry.get_arc(deserialize))
+    }
+
+    /// Return shared copies of all structs of type T.
+    ///
+    /// Each struct is deserialized only once and kept in the space for later calls.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// let primes: Vec<i64> = space.read_all_arc::<i64>().iter().map(|i| **i).collect();
+    /// assert_eq!(primes, vec![3, 5]);
+    /// ```
+    pub fn read_all_arc<T>(&self) -> Vec<Arc<T>>
+    where
+        for<'de> T: Deserialize<'de> + Send + Sync + 'static,
+    {
+        match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get_all_arc(deserialize),
+            None => Vec::new(),
+        }
+    }
+
+    /// Return the structs of type T in the space at this point in time, which could be iterated, counted
+    /// and queried many times with consistent results, whatever is written or taken meanwhile.
+    ///
+    /// The structs are shared with the space rather than copied, and the type is only locked while they are
+    /// collected, so that writers are not held up while the snapshot is used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{field, TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// let snapshot = space.snapshot::<i64>();
+    /// space.write::<i64>(7);
+    /// space.take::<i64>();
+    ///
+    /// assert_eq!(snapshot.len(), 2);
+    /// assert_eq!(snapshot.iter().sum::<i64>(), 8);
+    /// assert_eq!(snapshot.count_by_query(&field("").gt(4i64)), 1);
+    /// assert_eq!(snapshot.try_read_by_query(&field("").lt(4i64)), Some(3));
+    /// ```
+    pub fn snapshot<T>(&self) -> Snapshot<T>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.snapshot(),
+            None => Snapshot::new(Vec::new(), HashMap::new()),
+        }
+    }
+
+    /// Return an independent copy of the space, e.g: to try a plan against the copy before applying it,
+    /// or to seed a test fixture from a live space.
+    ///
+    /// The copy shares the structs of the space rather than copying them, and rebuilds their indices.
+    /// Structs written to or taken from either space afterwards are not seen by the other,
+    /// and their handles are valid in both. The settings of the space and of its types are copied,
+    /// except for the journal, the watchdog and the decoders, and the structs checked out are left out.
+    /// Each type is copied at a point in time, while writers of the other types carry on.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// let handle = space.write::<i64>(5);
+    ///
+    /// let fork = space.fork();
+    /// fork.write::<i64>(7);
+    /// assert_eq!(fork.try_take_by_handle::<i64>(handle), Some(5));
+    ///
+    /// assert_eq!(fork.read_all::<i64>().count(), 2);
+    /// assert_eq!(space.try_read_by_value::<i64>("", &7i64), None);
+    /// assert_eq!(space.try_read_by_handle::<i64>(handle), Some(5));
+    /// ```
+    pub fn fork(&self) -> TreeObjectSpace {
+        // the entries are only locked one at a time, once the slots are released
+        let slots: Vec<_> = self.slots
+            .read()
+            .iter()
+            .map(|(&type_id, slot)| (type_id, slot.entry.clone(), slot.type_name))
+            .collect();
+        let untyped: Vec<_> = self.untyped
+            .read()
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.clone()))
+            .collect();
+        let now = Instant::now();
+        let fork = |entry: &Arc<RwLock<Entry>>| {
+            let mut entry = entry.write();
+            entry.remove_expired(now);
+            Arc::new(RwLock::new(entry.fork()))
+        };
+        TreeObjectSpace {
+            slots: RwLock::new(slots
+                .iter()
+                .map(|&(type_id, ref entry, type_name)| {
+                    let slot = Slot {
+                        entry: fork(entry),
+                        lock: Arc::new((Mutex::new(0), Condvar::new())),
+                        type_name,
+                    };
+                    (type_id, slot)
+                })
+                .collect()),
+            default_ttl: self.default_ttl,
+            default_capacity: self.default_capacity,
+            gc_threshold: AtomicUsize::new(self.gc_threshold.load(Ordering::Relaxed)),
+            waiters: self.waiters.as_ref().map(|_| WaiterRegistry::default()),
+            wait_strategy: self.wait_strategy,
+            codec: self.codec,
+            spill: self.spill.clone(),
+            registry: RwLock::new(self.registry.read().clone()),
+            untyped: RwLock::new(untyped.iter().map(|&(ref name, ref entry)| (name.clone(), fork(entry))).collect()),
+            closed: AtomicBool::new(self.is_closed()),
+            ..Default::default()
+        }
+    }
+
+    /// Return a shared copy of a struct of type T.
+    /// The operation blocks until such a struct is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("Hello World"));
+    ///
+    /// assert_eq!(*space.read_arc::<String>(), "Hello World");
+    /// ```
+    pub fn read_arc<T>(&self) -> Arc<T>
+    where
+        for<'de> T: Deserialize<'de> + Send + Sync + 'static,
+    {
+        let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+        let mut waiter = self.waiter::<T, _>("read_arc", || (None, None));
+        let mut fetched = lock.lock().unwrap();
+        loop {
+            if let Some(item) = self.try_read_arc::<T>() {
+                return item;
+            }
+            fetched = waiter.wait(lock, cvar, fetched);
+        }
+    }
+
+    /// Return how long the write guard of type T has been held, by each operation which took it.
+    ///
+    /// Writers and takers of a type wait for each other,
+    /// so long hold times, e.g: of `take_all` on many structs or of writing huge structs,
+    /// stall every other writer of the type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    /// space.take_all::<i64>().count();
+    ///
+    /// let hold_times = space.lock_hold_times::<i64>();
+    /// assert_eq!(hold_times["write"].count, 2);
+    /// assert_eq!(hold_times["take_all"].count, 1);
+    /// ```
+    pub fn lock_hold_times<T>(&self) -> BTreeMap<&'static str, HoldTime>
+    where
+        T: 'static,
+    {
+        self.entry(TypeId::of::<T>())
+            .map(|entry| entry.read().hold_times().clone())
+            .unwrap_or_default()
+    }
+
+    /// Return every blocking call currently waiting, the longest waiting first,
+    /// with its type, operation, field and predicate, how long it has been waiting,
+    /// and the number of structs of its type in the space.
+    ///
+    /// Calls are only tracked in a space built in diagnostics mode,
+    /// with `TreeObjectSpaceBuilder::diagnostics`. Other spaces return no call.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, ValueLookupObjectSpace};
+    /// let space = Arc::new(TreeObjectSpace::builder().diagnostics().build());
+    /// let worker_space = space.clone();
+    /// let worker = thread::spawn(move || worker_space.take_by_value::<i64>("", &3i64));
+    /// while space.dump_waiters().is_empty() {
+    ///     thread::sleep(Duration::from_millis(1));
+    /// }
+    ///
+    /// let waiters = space.dump_waiters();
+    /// assert_eq!(waiters[0].operation, "take_by_value");
+    /// assert_eq!(waiters[0].predicate, Some(String::from("== 3")));
+    ///
+    /// space.write::<i64>(3);
+    /// worker.join().unwrap();
+    /// assert!(space.dump_waiters().is_empty());
+    /// ```
+    pub fn dump_waiters(&self) -> Vec<BlockedCall> {
+        let waiters = match self.waiters {
+            Some(ref waiters) => waiters.waiting(),
+            None => return Vec::new(),
+        };
+        let mut calls: Vec<_> = waiters
+            .into_iter()
+            .map(|(mut call, type_id, since)| {
+                call.waited = since.elapsed();
+                call.count = self.entry(type_id).map_or(0, |entry| entry.read().len());
+                call
+            })
+            .collect();
+        calls.sort_by_key(|call| Reverse(call.waited));
+        calls
+    }
+
+    /// Return the metrics of every type in the space, sorted by type name,
+    /// e.g: to tell whether workers are starved or the space grows unbounded.
+    ///
+    /// The metrics could also be exported to Prometheus with `server::metrics::MetricsServer`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    /// space.try_read::<i64>();
+    /// space.try_take::<i64>();
+    ///
+    /// let metrics = &space.metrics()[0];
+    /// assert_eq!(metrics.type_name, "i64");
+    /// assert_eq!((metrics.writes, metrics.reads, metrics.takes), (2, 1, 1));
+    /// assert_eq!((metrics.size, metrics.blocked), (1, 0));
+    /// assert_eq!(metrics.latencies["try_take"].count, 1);
+    /// ```
+    pub fn metrics(&self) -> Vec<TypeMetrics> {
+        let entries: Vec<_> = self.slots
+            .read()
+            .iter()
+            .map(|(&type_id, slot)| (type_id, slot.type_name, slot.entry.clone()))
+            .collect();
+        let mut metrics: Vec<_> = entries
+            .into_iter()
+            .map(|(type_id, type_name, entry)| {
+                self.remove_expired(type_id);
+                let metrics = entry.read().metrics(type_name);
+                metrics
+            })
+            .collect();
+        metrics.sort_by_key(|metrics| metrics.type_name);
+        metrics
+    }
+
+    /// Describe every type in the space, sorted by type name:
+    /// the number of its structs, its indexed fields and the kind of their values,
+    /// and an estimate of the memory its structs take, e.g: for admin tooling.
+    ///
+    /// Every struct is looked at to estimate the memory, so this is as slow as reading every struct.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate object_space;
+    /// # #[macro_use] extern crate serde_derive;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, FieldKind};
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Task {
+    ///     id: i64,
+    ///     owner: String,
+    ///     done: bool,
+    /// }
+    ///
+    /// # fn main() {
+    /// let space = TreeObjectSpace::new();
+    /// space.hash_index::<Task>(&["owner"]);
+    /// space.write(Task { id: 1, owner: String::from("tuan"), done: false });
+    /// space.write::<i64>(3);
+    ///
+    /// let types = space.list_types();
+    /// assert_eq!(types.len(), 2);
+    /// let task = types.iter().find(|info| info.type_name.ends_with("Task")).unwrap();
+    /// assert_eq!(task.count, 1);
+    /// assert_eq!(task.indexed_fields["id"], FieldKind::Int);
+    /// assert_eq!(task.indexed_fields["done"], FieldKind::Bool);
+    /// assert!(task.hashed_fields.contains("owner"));
+    /// assert!(task.memory > 0);
+    /// # }
+    /// ```
+    pub fn list_types(&self) -> Vec<TypeInfo> {
+        let entries: Vec<_> = self.slots
+            .read()
+            .iter()
+            .map(|(&type_id, slot)| (type_id, slot.type_name, slot.entry.clone()))
+            .collect();
+        let mut types: Vec<_> = entries
+            .into_iter()
+            .map(|(type_id, type_name, entry)| {
+                self.remove_expired(type_id);
+                entry.read().info(type_name)
+            })
+            .collect();
+        types.sort_by_key(|info| info.type_name);
+        types
+    }
+}
+
+impl TreeObjectSpace {
+    /// Look up the structs of type T by a field declared with `fields!`,
+    /// by keys and ranges of the type of the field only.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, TypedField};
+    /// const VALUE: TypedField<i64, i64> = TypedField::new("");
+    ///
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    ///
+    /// assert_eq!(space.lookup(VALUE).read_all_by_range(4..).collect::<Vec<_>>(), vec![5]);
+    /// assert_eq!(space.lookup(VALUE).try_take_by_value(&3), Some(3));
+    /// ```
+    pub fn lookup<T, K>(&self, field: TypedField<T, K>) -> FieldLookup<'_, T, K> {
+        FieldLookup::new(self, field)
+    }
+
+    /// Run a computation on a worker thread, and write its result to the space when done.
+    ///
+    /// This is the `eval` operation of Linda:
+    /// other threads could block on the result with `read` or `take` meanwhile.
+    /// The returned thread could be joined to wait for the result to be written,
+    /// and to get the handle of the result.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = Arc::new(TreeObjectSpace::new());
+    /// space.eval(|| (1..=10).product::<i64>());
+    ///
+    /// assert_eq!(space.take::<i64>(), 3628800);
+    /// ```
+    pub fn eval<T, F>(self: &Arc<Self>, f: F) -> JoinHandle<ObjectHandle>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let space = self.clone();
+        thread::spawn(move || space.write(f()))
+    }
+
+    /// Start a call finding a struct of any of several types, e.g: so that a single worker
+    /// serves several kinds of messages. Each type is added as an arm of the returned `Select`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// #[derive(Debug, PartialEq)]
+    /// enum Message {
+    ///     Count(i64),
+    ///     Name(String),
+    /// }
+    ///
+    /// let space = TreeObjectSpace::new();
+    /// space.write(String::from("backup"));
+    ///
+    /// let message = space
+    ///     .select()
+    ///     .take::<i64, _>(Message::Count)
+    ///     .take::<String, _>(Message::Name)
+    ///     .wait();
+    /// assert_eq!(message, Message::Name(String::from("backup")));
+    ///
+    /// let message = space
+    ///     .select()
+    ///     .take::<i64, _>(Message::Count)
+    ///     .take::<String, _>(Message::Name)
+    ///     .wait_timeout(Duration::from_millis(10));
+    /// assert_eq!(message, None);
+    /// ```
+    pub fn select<R>(&self) -> Select<R> {
+        Select {
+            space: self,
+            arms: Vec::new(),
+            types: Vec::new(),
+        }
+    }
+
+    /// Remove a struct of type T until the returned `Checkout` is committed.
+    /// The struct is returned to the space if the `Checkout` is dropped without being committed,
+    /// or once `lease` has passed.
+    /// The operation blocks until such a struct is found.
+    ///
+    /// This lets workers process structs without losing them if a worker panics or gets stuck.
+    /// While checked out, a struct is not part of the checkpoints of the space,
+    /// but stays in its journal until it is committed, so that a crash does not lose it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    ///
+    /// {
+    ///     let task = space.checkout::<i64>(Duration::from_secs(60));
+    ///     assert_eq!(*task, 3);
+    ///     assert_eq!(space.try_read::<i64>(), None);
+    /// }
+    /// // the checkout was dropped without being committed
+    /// assert_eq!(space.try_read::<i64>(), Some(3));
+    ///
+    /// let task = space.checkout::<i64>(Duration::from_secs(60));
+    /// assert!(task.commit());
+    /// assert_eq!(space.try_read::<i64>(), None);
+    /// ```
+    pub fn checkout<T>(&self, lease: Duration) -> Checkout<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let operation = "checkout";
+        let lock = self.get_or_add_lock::<T>();
+        let mut index = None;
+        let value = self.wait_for::<T, _, _, _>(operation, || (None, None), None, None, || {
+            let (i, value) = self.get_object_entry_mut::<T>(operation)?.check_out(lease)?;
+            index = Some(i);
+            Some(value)
+        });
+        Checkout {
+            space: self,
+            lock,
+            index: index.unwrap(),
+            value: value.unwrap().deserialize().unwrap(),
+            done: false,
+        }
+    }
+
+    /// Remove a struct of type T until the returned `Checkout` is committed, like `checkout`.
+    /// The operation is non-blocking and will return None if there is no struct of type T.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// assert!(space.try_checkout::<i64>(Duration::from_secs(60)).is_none());
+    ///
+    /// space.write::<i64>(3);
+    /// let task = space.try_checkout::<i64>(Duration::from_secs(60)).unwrap();
+    /// task.release();
+    /// assert_eq!(space.try_read::<i64>(), Some(3));
+    /// ```
+    pub fn try_checkout<T>(&self, lease: Duration) -> Option<Checkout<T>>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let lock = self.get_or_add_lock::<T>();
+        let (index, value) = self.get_object_entry_mut::<T>("try_checkout")?.check_out(lease)?;
+        Some(Checkout {
+            space: self,
+            lock,
+            index,
+            value: value.deserialize().unwrap(),
+            done: false,
+        })
+    }
+
+    /// Register a transformer which continuously takes structs of type A,
+    /// applies `f` to them, and writes the results to the space.
+    ///
+    /// The transformer runs on the specified number of worker threads until it is stopped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = Arc::new(TreeObjectSpace::new());
+    /// let pipe = space.pipe::<i64, String, _>(2, |i| i.to_string());
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.take::<String>(), "3");
+    /// pipe.stop();
+    /// ```
+    pub fn pipe<A, B, F>(self: &Arc<Self>, workers: usize, f: F) -> Pipe
+    where
+        for<'de> A: Serialize + Deserialize<'de> + 'static,
+        for<'de> B: Serialize + Deserialize<'de> + 'static,
+        F: Fn(A) -> B + Send + Sync + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let f = Arc::new(f);
+        let workers = (0..max(workers, 1))
+            .map(|_| {
+                let space = self.clone();
+                let stop = stop.clone();
+                let f = f.clone();
+                thread::spawn(move || {
+                    while let Some(obj) = space.take_unless_stopped::<A>(&stop) {
+                        space.write(f(obj));
+                    }
+                })
+            })
+            .collect();
+        Pipe {
+            stop,
+            lock: self.get_or_add_lock::<A>(),
+            workers,
+        }
+    }
+
+    /// Close the space, e.g: so that the threads using it could exit once the application shuts down.
+    ///
+    /// Writing to a closed space panics, and `try_write` returns `SpaceError::Closed`.
+    /// Blocking calls, whether pending or future, first look the space up as usual,
+    /// so that consumers could still drain the structs left, but panic instead of waiting,
+    /// while `take_checked` and `read_checked` return `SpaceError::Closed`
+    /// and calls with a timeout return None. Pipes stop taking structs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use std::thread;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, SpaceError};
+    /// let space = Arc::new(TreeObjectSpace::new());
+    /// let consumer = {
+    ///     let space = space.clone();
+    ///     thread::spawn(move || {
+    ///         let mut sum = 0;
+    ///         while let Ok(i) = space.take_checked::<i64>() {
+    ///             sum += i;
+    ///         }
+    ///         sum
+    ///     })
+    /// };
+    /// space.write::<i64>(3);
+    /// space.write::<i64>(5);
+    /// space.close();
+    ///
+    /// assert_eq!(consumer.join().unwrap(), 8);
+    /// assert_eq!(space.try_write::<i64>(7), Err(SpaceError::Closed));
+    /// ```
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let slots: Vec<_> = self.slots
+            .read()
+            .iter()
+            .map(|(&type_id, slot)| (type_id, slot.lock.clone(), slot.entry.clone()))
+            .collect();
+        for (type_id, lock, entry) in slots {
+            let &(ref lock, ref cvar) = &*lock;
+            // the calls check whether the space is closed while holding the lock, so none is missed
+            let mut generation = lock.lock().unwrap();
+            entry.read().wake_all();
+            *generation = generation.wrapping_add(1);
+            cvar.notify_all();
+            self.wake_futures_of(type_id);
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Like `write`, but return an error instead of panicking,
+    /// e.g: `SpaceError::Serialization` if the struct could not be serialized,
+    /// or `SpaceError::Closed` once the space is closed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, SpaceError};
+    /// let space = TreeObjectSpace::new();
+    /// assert!(space.write_checked::<i64>(3).is_ok());
+    ///
+    /// // JSON objects only have string keys
+    /// let mut map = HashMap::new();
+    /// map.insert((1, 2), 3);
+    /// match space.write_checked::<HashMap<(i64, i64), i64>>(map) {
+    ///     Err(SpaceError::Serialization(_)) => (),
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// ```
+    pub fn write_checked<T>(&self, obj: T) -> Result<ObjectHandle, SpaceError>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = Record::new(&obj, self.codec).map_err(|e| SpaceError::Serialization(e.to_string()))?;
+        self.add_record::<T>(value, None, "write_checked", WhenFull::Overflow)
+            .map_err(Rejection::into_error)
+    }
+
+    /// Like `try_read`, but return `SpaceError::Serialization`
+    /// if the struct found could not be read as T, instead of skipping it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace, SpaceError};
+    /// let space = TreeObjectSpace::new();
+    /// assert_eq!(space.try_read_checked::<i64>(), Ok(None));
+    ///
+    /// space.write::<i64>(3);
+    /// assert_eq!(space.try_read_checked::<i64>(), Ok(Some(3)));
+    /// ```
+    pub fn try_read_checked<T>(&self) -> Result<Option<T>, SpaceError>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get(),
+            _ => None,
+        };
+        match value {
+            Some(val) => val
+                .deserialize()
+                .map(Some)
+                .map_err(|e| SpaceError::Serialization(e.to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Like `try_take`, but return `SpaceError::Serialization`
+    /// if the struct taken could not be read as T. The struct is kept as a dead letter.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    ///
+    /// assert_eq!(space.try_take_checked::<i64>(), Ok(Some(3)));
+    /// assert_eq!(space.try_take_checked::<i64>(), Ok(None));
+    /// ```
+    pub fn try_take_checked<T>(&self) -> Result<Option<T>, SpaceError>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_mut::<T>("try_take_checked") {
+            Some(mut entry) => entry.remove(),
+            _ => None,
+        };
+        value.map(|val| self.decode_taken_checked::<T>(&val)).transpose()
+    }
+
+    /// Like `read`, but return an error instead of panicking,
+    /// e.g: `SpaceError::Closed` once the space is closed without a struct of type T,
+    /// or `SpaceError::Poisoned` if a thread panicked while holding the lock of the type.
+    pub fn read_checked<T>(&self) -> Result<T, SpaceError>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait_unless_closed::<T, _>("read_checked", || self.try_read_checked::<T>())
+    }
+
+    /// Like `take`, but return an error instead of panicking,
+    /// e.g: `SpaceError::Closed` once the space is closed without a struct of type T,
+    /// or `SpaceError::Poisoned` if a thread panicked while holding the lock of the type.
+    pub fn take_checked<T>(&self) -> Result<T, SpaceError>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait_unless_closed::<T, _>("take_checked", || self.try_take_checked::<T>())
+    }
+}
+
+impl TreeObjectSpace {
+    /// Remove the entries of all idle types, and return the number of entries removed.
+    ///
+    /// A type is idle if there is no struct of the type in the space,
+    /// no index or lease has been declared for it, it has never been checkpointed,
+    /// and no thread is waiting on it.
+    /// This is done automatically from time to time when structs of new types are written,
+    /// so that long-lived spaces do not keep the state of every type ever written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    /// space.write(String::from("Hello World"));
+    /// space.take::<i64>();
+    ///
+    /// assert_eq!(space.collect_garbage(), 1);
+    /// assert_eq!(space.try_read::<String>(), Some(String::from("Hello World")));
+    /// ```
+    pub fn collect_garbage(&self) -> usize {
+        let mut slots = self.slots.write();
+        let count = slots.len();
+        slots.retain(|_, slot| !self.is_idle(slot));
+        let mut untyped = self.untyped.write();
+        let untyped_count = untyped.len();
+        // an entry held by a writer is kept, so that the document it adds is not lost
+        untyped.retain(|_, entry| Arc::strong_count(entry) > 1 || !entry.read().is_empty());
+        count - slots.len() + untyped_count - untyped.len()
+    }
+
+    /// Release the memory the entries of all types hold beyond what their structs need,
+    /// and return an estimate of it in bytes.
+    ///
+    /// The indices of a type keep a bucket for every value of a field, which is removed with its last struct,
+    /// but maps and buckets keep the room they have grown to, e.g: after a burst of structs has been taken.
+    /// Each type is compacted in turn, while operations on the other types carry on.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// for i in 0..1000 {
+    ///     space.write::<i64>(i % 10);
+    /// }
+    /// space.take_all::<i64>().count();
+    /// space.write::<i64>(3);
+    ///
+    /// assert!(space.compact() > 0);
+    /// assert_eq!(space.compact(), 0);
+    /// assert_eq!(space.try_read::<i64>(), Some(3));
+    /// ```
+    pub fn compact(&self) -> usize {
+        // the entries are only locked one at a time, once the slots are released
+        let mut entries: Vec<_> = self.slots.read().values().map(|slot| slot.entry.clone()).collect();
+        entries.extend(self.untyped.read().values().cloned());
+        let now = Instant::now();
+        entries
+            .iter()
+            .map(|entry| {
+                let mut entry = entry.write();
+                entry.remove_expired(now);
+                entry.compact()
+            })
+            .sum()
+    }
+
+    /// Remove the structs of every type whose lease has ended, or which are older than the eviction policy
+    /// of their type allows, and return the number of structs removed.
+    ///
+    /// Such structs are removed anyway when their type is accessed. Sweeping removes those of the types
+    /// which are not, e.g: so that a cache releases memory while idle.
+    /// Each type is swept in turn, while operations on the other types carry on.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// # use object_space::{Eviction, TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.set_eviction::<i64>(Eviction::OlderThan(Duration::from_millis(10)));
+    /// space.write::<i64>(3);
+    /// space.write(String::from("Hello World"));
+    /// thread::sleep(Duration::from_millis(20));
+    ///
+    /// assert_eq!(space.sweep(), 1);
+    /// assert_eq!(space.metrics().iter().map(|m| m.size).sum::<usize>(), 1);
+    /// ```
+    pub fn sweep(&self) -> usize {
+        // the entries are only locked one at a time, once the slots are released
+        let mut entries: Vec<_> = self.slots.read().values().map(|slot| slot.entry.clone()).collect();
+        entries.extend(self.untyped.read().values().cloned());
+        let now = Instant::now();
+        entries
+            .iter()
+            .filter(|entry| entry.read().has_expired(now))
+            .map(|entry| entry.write().remove_expired(now))
+            .sum()
+    }
+
+    /// Call `sweep` on a background thread every `interval`,
+    /// until the returned `Sweeper` is stopped or the space is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// # use object_space::{Eviction, TreeObjectSpace, ObjectSpace};
+    /// let space = Arc::new(TreeObjectSpace::new());
+    /// space.set_eviction::<i64>(Eviction::OlderThan(Duration::from_millis(10)));
+    /// let sweeper = space.sweep_every(Duration::from_millis(5));
+    /// space.write::<i64>(3);
+    /// thread::sleep(Duration::from_millis(50));
+    /// sweeper.stop();
+    ///
+    /// assert_eq!(space.metrics()[0].evicted, 1);
+    /// ```
+    pub fn sweep_every(self: &Arc<Self>, interval: Duration) -> Sweeper {
+        // the thread does not keep the space alive
+        let space = Arc::downgrade(self);
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || loop {
+                {
+                    let &(ref stopped, ref cvar) = &*stop;
+                    let (stopped, _) = cvar
+                        .wait_timeout_while(stopped.lock().unwrap(), interval, |stopped| !*stopped)
+                        .unwrap();
+                    if *stopped {
+                        return;
+                    }
+                }
+                match space.upgrade() {
+                    Some(space) => space.sweep(),
+                    None => return,
+                };
+            })
+        };
+        Sweeper { stop, thread }
+    }
+
+    /// Remove all structs of type T together with the settings of the type,
+    /// e.g: its indices and lease.
+    /// Return false and leave the space untouched if a thread is waiting on type T.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{TreeObjectSpace, ObjectSpace};
+    /// let space = TreeObjectSpace::new();
+    /// space.write::<i64>(3);
+    ///
+    /// assert!(space.drop_type::<i64>());
+    /// assert_eq!(space.try_read::<i64>(), None);
+    /// ```
+    pub fn drop_type<T>(&self) -> bool
+    where
+        T: 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        let slot = {
+            let mut slots = self.slots.write();
+            match slots.get(&type_id) {
+                Some(slot) if Arc::strong_count(&slot.lock) == 1 => (),
+                _ => return false,
+            }
+            slots.remove(&type_id).unwrap()
+        };
+        // the structs are taken, as far as the journal is concerned
+        slot.entry.write().remove_all();
+        true
+    }
+}
+
+impl TreeObjectSpace {
+    fn forget_changes<T>(&self)
+    where
+        T: 'static,
+    {
+        if let Some(mut entry) = self.get_object_entry_mut::<T>("checkpoint") {
+            entry.forget_changes();
+        }
+    }
+
+    fn take_unless_stopped<T>(&self, stop: &AtomicBool) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+        let mut waiter = self.waiter::<T, _>("pipe", || (None, None));
+        let mut fetched = lock.lock().unwrap();
+        loop {
+            if stop.load(Ordering::SeqCst) || self.is_closed() {
+                return None;
+            }
+            if let Some(item) = self.try_take::<T>() {
+                return Some(item);
+            }
+            fetched = waiter.wait(lock, cvar, fetched);
+        }
+    }
+
+    /// Block until `lookup` finds a struct of type T,
+    /// or return an error once the space is closed or `lookup` fails.
+    fn wait_unless_closed<T, F>(&self, operation: &'static str, mut lookup: F) -> Result<T, SpaceError>
+    where
+        T: 'static,
+        F: FnMut() -> Result<Option<T>, SpaceError>,
+    {
+        let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+        let mut waiter = self.waiter::<T, _>(operation, || (None, None));
+        waiter.checked = true;
+        let mut fetched = lock.lock().map_err(|_| SpaceError::Poisoned)?;
+        loop {
+            if let Some(item) = lookup()? {
+                return Ok(item);
+            }
+            if self.is_closed() {
+                return Err(SpaceError::Closed);
+            }
+            fetched = waiter.wait(lock, cvar, fetched);
+        }
+    }
+
+    /// Start tracking a blocking call on type T for the watchdog.
+    ///
+    /// `describe` returns the field and predicate of the call,
+    /// and is only called if the space has a watchdog.
+    fn waiter<T, F>(&self, operation: &'static str, describe: F) -> Waiter
+    where
+        T: 'static,
+        F: FnOnce() -> (Option<String>, Option<String>),
+    {
+        // the call is only described if it is reported
+        let described = self.watchdog.is_some() || self.waiters.is_some();
+        let (field, predicate) = if described || cfg!(feature = "tracing") {
+            describe()
+        } else {
+            (None, None)
+        };
+        let span = trace::blocking(
+            type_name::<T>(),
+            TypeId::of::<T>(),
+            operation,
+            field.as_deref(),
+            predicate.as_deref(),
+        );
+        let call = if described {
+            Some(BlockedCall {
+                type_name: type_name::<T>(),
+                operation,
+                field,
+                predicate,
+                waited: Duration::default(),
+                count: 0,
+            })
+        } else {
+            None
+        };
+        let since = Instant::now();
+        let registration = match (self.waiters.as_ref(), call.as_ref()) {
+            (Some(waiters), Some(call)) => Some(waiters.register(call.clone(), TypeId::of::<T>(), since)),
+            _ => None,
+        };
+        // the call is reported to the watchdog at most once
+        let call = call.filter(|_| self.watchdog.is_some());
+        let spin_deadline = match self.wait_strategy {
+            WaitStrategy::Block => None,
+            WaitStrategy::SpinThenBlock(duration) => Some(since + duration),
+        };
+        if let Some(entry) = self.entry(TypeId::of::<T>()) {
+            entry.write().wait_started();
+        }
+        Waiter {
+            space: self,
+            type_id: TypeId::of::<T>(),
+            operation,
+            call,
+            since,
+            spin_deadline,
+            wakeups: 0,
+            deadline: None,
+            ticket: None,
+            blocked: false,
+            checked: false,
+            registration,
+            _span: span,
+        }
+    }
+
+    /// Return a future resolving once `lookup` finds a struct of type T.
+    fn awaited<'a, T, F>(&'a self, lookup: F) -> Awaited<'a, T, F>
+    where
+        T: 'static,
+    {
+        Awaited {
+            space: self,
+            lock: self.get_or_add_lock::<T>(),
+            lookup,
+            marker: PhantomData,
+        }
+    }
+
+    /// Wake `waker` up the next time a struct of type T is written.
+    /// Must be called while holding the guard of the lock of the type.
+    fn register_waker<T>(&self, waker: &Waker)
+    where
+        T: 'static,
+    {
+        self.wakers.upsert(
+            TypeId::of::<T>(),
+            || vec![waker.clone()],
+            |wakers| {
+                if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+                    wakers.push(waker.clone());
+                }
+            },
+        );
+    }
+
+    /// Wake up the futures waiting on type T.
+    /// Must be called while holding the guard of the lock of the type.
+    fn wake_futures<T>(&self)
+    where
+        T: 'static,
+    {
+        self.wake_futures_of(TypeId::of::<T>());
+    }
+
+    fn wake_futures_of(&self, type_id: TypeId) {
+        if let Some(wakers) = self.wakers.remove(&type_id) {
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Block until `lookup` finds a struct of type T, and read it as T.
+    /// A struct which could not be read is left in the space, and the call keeps waiting.
+    fn wait_to_read<T, D, F>(
+        &self,
+        operation: &'static str,
+        describe: D,
+        timeout: Option<Duration>,
+        condition: Option<Condition>,
+        mut lookup: F,
+    ) -> Option<T>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+        D: FnOnce() -> (Option<String>, Option<String>),
+        F: FnMut() -> Option<Arc<Record>>,
+    {
+        self.wait_for::<T, _, _, _>(operation, describe, timeout, condition, || {
+            lookup().and_then(|value| value.deserialize().ok())
+        })
+    }
+
+    /// Block until `lookup` removes a struct of type T, and read it as T.
+    /// A struct which could not be read is kept as a dead letter, and the call keeps looking.
+    fn wait_to_take<T, D, F>(
+        &self,
+        operation: &'static str,
+        describe: D,
+        timeout: Option<Duration>,
+        condition: Option<Condition>,
+        mut lookup: F,
+    ) -> Option<T>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+        D: FnOnce() -> (Option<String>, Option<String>),
+        F: FnMut() -> Option<Arc<Record>>,
+    {
+        self.wait_for::<T, _, _, _>(operation, describe, timeout, condition, || loop {
+            let value = lookup()?;
+            if let Some(obj) = self.decode_taken::<T>(&value) {
+                return Some(obj);
+            }
+        })
+    }
+
+    /// Block until `lookup` finds a struct of type T,
+    /// or return None if `timeout` is given and passes first.
+    ///
+    /// `lookup` is called with the lock of the type held, every time a struct of the type is written.
+    /// If a `condition` is given, only the structs satisfying it wake the call up.
+    fn wait_for<T, D, F, R>(
+        &self,
+        operation: &'static str,
+        describe: D,
+        timeout: Option<Duration>,
+        mut condition: Option<Condition>,
+        mut lookup: F,
+    ) -> Option<R>
+    where
+        T: 'static,
+        D: FnOnce() -> (Option<String>, Option<String>),
+        F: FnMut() -> Option<R>,
+    {
+        let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+        let mut waiter = self.waiter::<T, _>(operation, describe);
+        // a timeout too large to be represented is the same as none
+        waiter.deadline = timeout.and_then(|timeout| waiter.since.checked_add(timeout));
+        let mut fetched = lock.lock().unwrap();
+        loop {
+            if let Some(item) = lookup() {
+                return Some(item);
+            }
+            if waiter.expired() {
+                return None;
+            }
+            // registered while holding the lock, so that no struct is added before the call waits
+            if let Some(condition) = condition.take() {
+                if let Some(entry) = self.entry(TypeId::of::<T>()) {
+                    waiter.ticket = Some(entry.write().register_waiter(condition));
+                }
+            }
+            fetched = waiter.wait(lock, cvar, fetched);
+        }
+    }
+
+    fn try_read_matching<T>(&self, field: &str, pattern: &Pattern) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get_by_pattern(field, pattern),
+            _ => None,
+        };
+        match value {
+            Some(val) => val.deserialize().ok(),
+            _ => None,
+        }
+    }
+
+    fn read_all_matching<'a, T>(&'a self, field: &str, pattern: &Pattern) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        let val_iter = match self.get_object_entry_ref::<T>() {
+            Some(ent) => ent.get_all_by_pattern(field, pattern),
+            None => Vec::new(),
+        };
+
+        Box::new(val_iter.into_iter().filter_map(|item| item.deserialize().ok()))
+    }
+
+    fn read_matching<T>(&self, operation: &'static str, field: &str, pattern: Pattern) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait_to_read::<T, _, _>(
+            operation,
+            || (Some(field.to_owned()), Some(format!("matches {:?}", pattern.to_string()))),
+            None,
+            Some(Condition::Pattern(field.to_owned(), pattern.clone())),
+            || match self.get_object_entry_ref::<T>() {
+                Some(entry) => entry.get_by_pattern(field, &pattern),
+                _ => None,
+            },
+        ).unwrap()
+    }
+
+    fn try_take_matching<T>(&self, operation: &'static str, field: &str, pattern: &Pattern) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_mut::<T>(operation) {
+            Some(mut entry) => entry.remove_by_pattern(field, pattern),
+            _ => None,
+        };
+        match value {
+            Some(val) => self.decode_taken::<T>(&val),
+            _ => None,
+        }
+    }
+
+    fn take_all_matching<'a, T>(
+        &'a self,
+        operation: &'static str,
+        field: &str,
+        pattern: &Pattern,
+    ) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        let val_iter = match self.get_object_entry_mut::<T>(operation) {
+            Some(mut ent) => ent.remove_all_by_pattern(field, pattern),
+            None => Vec::new(),
+        };
+
+        Box::new(val_iter.into_iter().filter_map(move |item| self.decode_taken::<T>(&item)))
+    }
+
+    fn take_matching<T>(&self, operation: &'static str, field: &str, pattern: Pattern) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait_to_take::<T, _, _>(
+            operation,
+            || (Some(field.to_owned()), Some(format!("matches {:?}", pattern.to_string()))),
+            None,
+            Some(Condition::Pattern(field.to_owned(), pattern.clone())),
+            || match self.get_object_entry_mut::<T>(operation) {
+                Some(mut entry) => entry.remove_by_pattern(field, &pattern),
+                _ => None,
+            },
+        ).unwrap()
+    }
+
+    fn try_read_first_by<T>(&self, field: &str, order: Order) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get_ordered(field, order),
+            _ => None,
+        };
+        match value {
+            Some(val) => val.deserialize().ok(),
+            _ => None,
+        }
+    }
+
+    fn try_take_first_by<T>(&self, operation: &'static str, field: &str, order: Order) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_mut::<T>(operation) {
+            Some(mut entry) => entry.remove_ordered(field, order),
+            _ => None,
+        };
+        match value {
+            Some(val) => self.decode_taken::<T>(&val),
+            _ => None,
+        }
+    }
+
+    fn take_first_by<T>(
+        &self,
+        operation: &'static str,
+        field: &str,
+        order: Order,
+        timeout: Option<Duration>,
+    ) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait_to_take::<T, _, _>(
+            operation,
+            || (Some(field.to_owned()), Some(format!("{:?}", order))),
+            timeout,
+            Some(Condition::has(field)),
+            || match self.get_object_entry_mut::<T>(operation) {
+                Some(mut entry) => entry.remove_ordered(field, order),
+                _ => None,
+            },
+        )
+    }
+
+    fn get_object_entry_ref<T>(&self) -> Option<ArcRwLockReadGuard<RawRwLock, Entry>>
+    where
+        T: 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        self.attach_journal::<T>();
+        self.remove_expired(type_id);
+        self.entry(type_id).map(|entry| {
+            let entry = entry.read_arc();
+            entry.record_read();
+            trace::lookup(type_name::<T>(), type_id);
+            entry
+        })
+    }
+
+    /// Return a struct taken as T, or keep it as a dead letter of type T if it could not be read as T.
+    fn decode_taken<T>(&self, record: &Record) -> Option<T>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        self.decode_taken_checked(record).ok()
+    }
+
+    fn decode_taken_checked<T>(&self, record: &Record) -> Result<T, SpaceError>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        record.deserialize().map_err(|e| {
+            if let Some(mut entry) = self.get_object_entry_mut::<T>("dead_letter") {
+                entry.decoder_mut().add_dead_letter(DecodeError::new(record, &e));
+            }
+            SpaceError::Serialization(e.to_string())
+        })
+    }
+
+    /// Make the decoder of type T check that structs could be read as T, let `f` set it up,
+    /// and apply it to the structs already in the space.
+    fn set_decoder<T, F>(&self, operation: &'static str, f: F)
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+        F: FnOnce(&mut Decoder),
+    {
+        let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+        let mut generation = lock.lock().unwrap();
+        let mut entry = self.get_object_entry_mut::<T>(operation).unwrap();
+        {
+            let decoder = entry.decoder_mut();
+            decoder.set_check(|record| {
+                record
+                    .deserialize::<T>()
+                    .map(|_| ())
+                    .map_err(|e| DecodeError::new(record, &e))
+            });
+            f(decoder);
+        }
+        entry.readmit_all();
+        drop(entry);
+        // structs completed could be found by the calls waiting for them
+        *generation = generation.wrapping_add(1);
+        cvar.notify_all();
+        self.wake_futures::<T>();
+    }
+
+    /// Add structs of type T, and wake up the calls waiting for them.
+    /// A struct which does not match the strict schema of type T, or for which there is no room, panics,
+    /// after the structs before it are added.
+    fn add_records<T>(&self, values: Vec<Record>, operation: &'static str)
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        if let (_, Some(rejection)) = self.add_values(TypeId::of::<T>(), values, None, operation, WhenFull::Overflow) {
+            rejection.raise::<()>();
+        }
+    }
+
+    /// Add a struct of type T, and wake up the calls waiting for it.
+    fn add_record<T>(
+        &self,
+        value: Record,
+        ttl: Option<Duration>,
+        operation: &'static str,
+        when_full: WhenFull,
+    ) -> Result<ObjectHandle, Rejection>
+    where
+        T: 'static,
+    {
+        let _lock = self.get_or_add_lock::<T>();
+        match self.add_values(TypeId::of::<T>(), vec![value], ttl, operation, when_full) {
+            (_, Some(rejection)) => Err(rejection),
+            (indices, None) => Ok(ObjectHandle::new(indices[0])),
+        }
+    }
+
+    /// Add structs of a type, and wake up the calls waiting for them.
+    /// If the type is full, wait for room, evict or fail as `when_full` says.
+    ///
+    /// Return the indices of the structs added, and why the next struct could not be added, if any.
+    /// The lock of the type has been released by then, so that panicking does not poison it.
+    fn add_values(
+        &self,
+        type_id: TypeId,
+        mut values: Vec<Record>,
+        ttl: Option<Duration>,
+        operation: &'static str,
+        when_full: WhenFull,
+    ) -> (Vec<u64>, Option<Rejection>) {
+        let (lock, type_name) = match self.slots.read().get(&type_id) {
+            Some(slot) => (slot.lock.clone(), slot.type_name),
+            None => return (Vec::new(), None),
+        };
+        let &(ref lock, ref cvar) = &*lock;
+        if let Some(ref store) = self.spill {
+            // before taking the lock, so that writing files does not hold up other calls
+            for value in &mut values {
+                value.spill(store);
+            }
+        }
+        let mut values = values.into_iter().peekable();
+        let mut indices = Vec::new();
+        let mut rejection = None;
+        let mut generation = match lock.lock() {
+            Ok(generation) => generation,
+            Err(_) => return (Vec::new(), Some(Rejection::Poisoned)),
+        };
+        loop {
+            // checked while holding the lock, so that `close` wakes up a writer which has found no room
+            if self.is_closed() {
+                rejection = Some(Rejection::Closed);
+                break;
+            }
+            let mut entry = match self.entry_mut(type_id, type_name, operation) {
+                Some(entry) => entry,
+                None => break,
+            };
+            let woken = indices.len();
+            let mut blocked = None;
+            while let Some(value) = values.peek() {
+                if !entry.make_room(value, when_full != WhenFull::Wait) {
+                    let (breach, overflow) = entry.breach(value).expect("only an entry with a limit is full");
+                    let wait = match when_full {
+                        WhenFull::Overflow => overflow == Overflow::Block,
+                        WhenFull::Wait => true,
+                        WhenFull::Fail => false,
+                    };
+                    // a struct larger than the quota would not fit however many structs are taken
+                    if wait && !entry.exceeds_quota(value) {
+                        blocked = entry.block_writer(breach);
+                    } else {
+                        rejection = Some(Rejection::from(breach));
+                    }
+                    break;
+                }
+                let value = values.next().unwrap();
+                let added = match ttl {
+                    Some(ttl) => entry.add_with_ttl(value, ttl),
+                    None => entry.add(value),
+                };
+                match added {
+                    Ok(index) => indices.push(index),
+                    Err(mismatch) => {
+                        rejection = Some(Rejection::Mismatch(mismatch));
+                        break;
+                    }
+                }
+            }
+            drop(entry);
+            // structs added before a rejection are kept, so waiters are woken up for them
+            if indices.len() > woken {
+                *generation = generation.wrapping_add(1);
+                cvar.notify_all();
+                self.wake_futures_of(type_id);
+            }
+            match blocked {
+                Some((room, seen)) => {
+                    // wait without holding the lock, so that blocked takes could consume the structs
+                    drop(generation);
+                    room.wait(seen);
+                    generation = match lock.lock() {
+                        Ok(generation) => generation,
+                        Err(_) => {
+                            rejection = Some(Rejection::Poisoned);
+                            break;
+                        }
+                    };
+                }
+                None => break,
+            }
+        }
+        (indices, rejection)
+    }
+
+    /// Return a write guard of the entry of type T,
+    /// which records how long it is held by the operation.
+    fn get_object_entry_mut<T>(&self, operation: &'static str) -> Option<EntryGuard>
+    where
+        T: 'static,
+    {
+        self.attach_journal::<T>();
+        self.entry_mut(TypeId::of::<T>(), type_name::<T>(), operation)
+    }
+
+    fn entry_mut(&self, type_id: TypeId, type_name: &'static str, operation: &'static str) -> Option<EntryGuard> {
+        self.entry(type_id).map(|entry| {
+            let span = trace::operation(type_name, type_id, operation);
+            let requested = Instant::now();
+            let mut entry = entry.write_arc();
+            let now = Instant::now();
+            entry.remove_expired(now);
+            EntryGuard {
+                entry,
+                operation,
+                requested,
+                since: now,
+                _span: span,
+            }
+        })
+    }
+
+    /// Add the structs of type T left in the journal of the space, if any,
+    /// and append the structs of type T added and removed from now on to the journal.
+    fn attach_journal<T>(&self)
+    where
+        T: 'static,
+    {
+        let journal = match self.journal {
+            Some(ref journal) => journal,
+            None => return,
+        };
+        let type_id = TypeId::of::<T>();
+        let attached = self.entry(type_id)
+            .is_some_and(|entry| entry.read().is_journaled());
+        if attached {
+            return;
+        }
+        loop {
+            self.add_entry::<T>();
+            // the entry might have been collected right after being added
+            if let Some(entry) = self.entry(type_id) {
+                let mut entry = entry.write();
+                if !entry.is_journaled() {
+                    let name = self.stable_name::<T>();
+                    for obj in journal.take_pending(name) {
+                        let record = Record::from_json(&obj, self.codec).expect("struct cannot be serialized");
+                        // the entry is new, so its schema is not strict yet
+                        let _ = entry.add(record);
+                    }
+                    entry.set_journal(journal.clone(), name);
+                }
+                return;
+            }
+        }
+    }
+
+    /// Return the lock of type T, adding an entry for T if there is none.
+    ///
+    /// An entry is never garbage collected while a clone of its lock is alive.
+    fn get_or_add_lock<T>(&self) -> Lock
+    where
+        T: 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        self.attach_journal::<T>();
+        loop {
+            if self.add_entry::<T>() {
+                self.maybe_collect_garbage();
+            }
+            // the entry might have been collected right after being added
+            if let Some(slot) = self.slots.read().get(&type_id) {
+                return slot.lock.clone();
+            }
+        }
+    }
+
+    /// Drop expired structs of a type, only taking a write guard if there is any.
+    fn remove_expired(&self, id: TypeId) {
+        let now = Instant::now();
+        if let Some(entry) = self.entry(id) {
+            if entry.read().has_expired(now) {
+                entry.write().remove_expired(now);
+            }
+        }
+    }
+
+    /// Return the entry of a type, without locking it.
+    fn entry(&self, id: TypeId) -> Option<Arc<RwLock<Entry>>> {
+        self.slots.read().get(&id).map(|slot| slot.entry.clone())
+    }
+
+    /// Return true if the entry did not exist before.
+    fn add_entry<T>(&self) -> bool
+    where
+        T: 'static,
+    {
+        let id = TypeId::of::<T>();
+        // most calls find the entry, so the map is only locked for writing when needed
+        if self.slots.read().contains_key(&id) {
+            return false;
+        }
+        let mut added = false;
+        self.slots.write().entry(id).or_insert_with(|| {
+            added = true;
+            Slot {
+                entry: Arc::new(RwLock::new(self.new_entry())),
+                lock: Arc::new((Mutex::new(0), Condvar::new())),
+                type_name: self.registry.write().add::<T>(),
+            }
+        });
+        added
+    }
+
+    /// Return an entry with the default lease and capacity of the space.
+    fn new_entry(&self) -> Entry {
+        let mut entry = Entry::new();
+        entry.set_ttl(self.default_ttl);
+        if let Some((limit, overflow)) = self.default_capacity {
+            entry.set_capacity(limit, overflow);
+        }
+        entry
+    }
+
+    /// Return true if the slot of a type could be removed: its entry holds no struct,
+    /// has no index, quota or eviction policy declared, uses the default lease and capacity of the space, and nobody is using or waiting on it.
+    /// Must be called while holding the write guard of the slots, so that nobody starts using it meanwhile.
+    fn is_idle(&self, slot: &Slot) -> bool {
+        if !slot.is_unused() {
+            return false;
+        }
+        let entry = slot.entry.read();
+        entry.is_empty() && !entry.has_settings() && entry.ttl() == self.default_ttl
+            && entry.capacity() == self.default_capacity
+    }
+
+    /// Collect garbage once the number of entries doubles since the last collection,
+    /// so that the cost of collecting is amortized over the writes of new types.
+    fn maybe_collect_garbage(&self) {
+        if self.slots.read().len() > self.gc_threshold.load(Ordering::Relaxed) {
+            self.collect_garbage();
+            let threshold = max(2 * self.slots.read().len(), MIN_GC_THRESHOLD);
+            self.gc_threshold.store(threshold, Ordering::Relaxed);
+        }
+    }
+}
+
+impl ObjectSpace for TreeObjectSpace {
+    fn write<T>(&self, obj: T) -> ObjectHandle
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = Record::new(&obj, self.codec).expect("struct cannot be serialized");
+        self.add_record::<T>(value, None, "write", WhenFull::Overflow)
+            .unwrap_or_else(Rejection::raise)
+    }
+
+    fn write_all<T, I>(&self, objs: I)
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        I: IntoIterator<Item = T>,
+    {
+        let values: Vec<_> = objs.into_iter()
+            .map(|obj| Record::new(&obj, self.codec).expect("struct cannot be serialized"))
+            .collect();
+        self.add_records::<T>(values, "write_all");
+    }
+
+    fn try_read<T>(&self) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get(),
+            _ => None,
+        };
+        match value {
+            Some(val) => val.deserialize().ok(),
+            _ => None,
+        }
+    }
+
+    fn read_all<'a, T>(&'a self) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
+            Some(ent) => ent.get_all().collect(),
+            None => Vec::new(),
+        };
+
+        Box::new(
+            val_iter
+                .into_iter()
+                .filter_map(|item| item.deserialize().ok()),
+        )
+    }
+
+    fn read_sample<'a, T>(&'a self, count: usize) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let val_iter = match self.get_object_entry_ref::<T>() {
+            Some(ent) => ent.get_sample(count),
+            None => Vec::new(),
+        };
+
+        Box::new(
+            val_iter
+                .into_iter()
+                .filter_map(|item| item.deserialize().ok()),
+        )
+    }
+
+    fn read<T>(&self) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait_to_read::<T, _, _>("read", || (None, None), None, None, || {
+            match self.get_object_entry_ref::<T>() {
+                Some(entry) => entry.get(),
+                _ => None,
+            }
+        }).unwrap()
+    }
+
+    fn read_timeout<T>(&self, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait_to_read::<T, _, _>("read_timeout", || (None, None), Some(timeout), None, || {
+            match self.get_object_entry_ref::<T>() {
+                Some(entry) => entry.get(),
+                _ => None,
+            }
+        })
+    }
+
+    fn try_take<T>(&self) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_mut::<T>("try_take") {
+            Some(mut entry) => entry.remove(),
+            _ => None,
+        };
+        match value {
+            Some(val) => self.decode_taken::<T>(&val),
+            _ => None,
+        }
+    }
+
+    fn take_all<'a, T>(&'a self) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let val_iter = match self.get_object_entry_mut::<T>("take_all") {
+            Some(mut ent) => ent.remove_all(),
+            None => Vec::new(),
+        };
+
+        Box::new(
+            val_iter
+                .into_iter()
+                .filter_map(move |item| self.decode_taken::<T>(&item)),
+        )
+    }
+
+    fn take<T>(&self) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let operation = "take";
+        self.wait_to_take::<T, _, _>(operation, || (None, None), None, None, || {
+            match self.get_object_entry_mut::<T>(operation) {
+                Some(mut entry) => entry.remove(),
+                _ => None,
+            }
+        }).unwrap()
+    }
+
+    fn take_timeout<T>(&self, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let operation = "take_timeout";
+        self.wait_to_take::<T, _, _>(operation, || (None, None), Some(timeout), None, || {
+            match self.get_object_entry_mut::<T>(operation) {
+                Some(mut entry) => entry.remove(),
+                _ => None,
+            }
+        })
+    }
+
+    fn try_read_by_handle<T>(&self, handle: ObjectHandle) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get_by_index(handle.index()),
+            _ => None,
+        };
+        match value {
+            Some(val) => val.deserialize().ok(),
+            _ => None,
+        }
+    }
+
+    fn try_take_by_handle<T>(&self, handle: ObjectHandle) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_mut::<T>("try_take_by_handle") {
+            Some(mut entry) => entry.remove_by_index(handle.index()),
+            _ => None,
+        };
+        match value {
+            Some(val) => self.decode_taken::<T>(&val),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! object_range{
+    ($($ty:ty)*) => {
+        $(
+            impl ExplainObjectSpace<$ty> for TreeObjectSpace {
+                fn explain_query<T, R>(&self, field: &str, range: R) -> QueryPlan
+                where
+                    T: 'static,
+                    R: RangeBounds<$ty>,
+                {
+                    let type_id = TypeId::of::<T>();
+                    self.remove_expired(type_id);
+                    match self.entry(type_id) {
+                        Some(entry) => entry.read().explain_range(field, range),
+                        // every field of a type not in the space is indexed
+                        None => QueryPlan {
+                            field: field.to_owned(),
+                            indexed: true,
+                            ..Default::default()
+                        },
+                    }
+                }
+            }
+
+            impl RangeLookupObjectSpace<$ty> for TreeObjectSpace {
+                fn try_read_by_range<T, R>(&self, field: &str, range: R) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let value = match self.get_object_entry_ref::<T>() {
+                        Some(entry) => entry.get_by_range::<_>(field, range),
+                        _ => None,
+                    };
+                    match value {
+                        Some(val) => val.deserialize().ok(),
+                        _ => None,
+                    }
+                }
+
+                fn read_all_by_range<'a, T, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
+                        Some(ent) => ent.get_all_by_range::<_>(field, range).collect(),
+                        None => Vec::new(),
+                    };
+
+                    Box::new(val_iter.into_iter().filter_map(|item| item.deserialize().ok()))
+                }
+
+                fn read_by_range<T, R>(&self, field: &str, range: R) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.wait_to_read::<T, _, _>(
+                        "read_by_range",
+                        || {
+                            (Some(field.to_owned()), Some(format!("in {}", describe_range(&range))))
+                        },
+                        None,
+                        Some(Condition::range(field, &range)),
+                        || {
+                            match self.get_object_entry_ref::<T>() {
+                                Some(entry) => entry.get_by_range::<_>(field, range.clone()),
+                                _ => None,
+                            }
+                        },
+                    ).unwrap()
+                }
+
+                fn read_by_range_timeout<T, R>(&self, field: &str, range: R, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.wait_to_read::<T, _, _>(
+                        "read_by_range_timeout",
+                        || {
+                            (Some(field.to_owned()), Some(format!("in {}", describe_range(&range))))
+                        },
+                        Some(timeout),
+                        Some(Condition::range(field, &range)),
+                        || {
+                            match self.get_object_entry_ref::<T>() {
+                                Some(entry) => entry.get_by_range::<_>(field, range.clone()),
+                                _ => None,
+                            }
+                        },
+                    )
+                }
+
+                fn try_take_by_range<T, R>(&self, field: &str, range: R) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let value = match self.get_object_entry_mut::<T>("try_take_by_range") {
+                        Some(mut entry) => entry.remove_by_range::<_>(field, range),
+                        _ => None,
+                    };
+                    match value {
+                        Some(val) => self.decode_taken::<T>(&val),
+                        _ => None,
+                    }
+                }
+
+                fn take_all_by_range<'a, T, R>(
+                    &'a self,
+                    field: &str,
+                    range: R,
+                ) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let val_iter = match self.get_object_entry_mut::<T>("take_all_by_range") {
+                        Some(mut ent) => ent.remove_all_by_range::<_>(field, range),
+                        None => Vec::new(),
+                    };
+
+                    Box::new(
+                        val_iter
+                            .into_iter()
+                            .filter_map(move |item| self.decode_taken::<T>(&item))
+                    )
+                }
+
+                fn take_by_range<T, R>(&self, field: &str, range: R) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let operation = "take_by_range";
+                    self.wait_to_take::<T, _, _>(
+                        operation,
+                        || {
+                            (Some(field.to_owned()), Some(format!("in {}", describe_range(&range))))
+                        },
+                        None,
+                        Some(Condition::range(field, &range)),
+                        || {
+                            match self.get_object_entry_mut::<T>(operation) {
+                                Some(mut entry) => entry.remove_by_range::<_>(field, range.clone()),
+                                _ => None,
+                            }
+                        },
+                    ).unwrap()
+                }
+
+                fn take_by_range_timeout<T, R>(&self, field: &str, range: R, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let operation = "take_by_range_timeout";
+                    self.wait_to_take::<T, _, _>(
+                        operation,
+                        || {
+                            (Some(field.to_owned()), Some(format!("in {}", describe_range(&range))))
+                        },
+                        Some(timeout),
+                        Some(Condition::range(field, &range)),
+                        || {
+                            match self.get_object_entry_mut::<T>(operation) {
+                                Some(mut entry) => entry.remove_by_range::<_>(field, range.clone()),
+                                _ => None,
+                            }
+                        },
+                    )
+                }
+
+                fn try_read_by_range_checked<T, R>(&self, field: &str, range: R) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let value = match self.get_object_entry_ref::<T>() {
+                        Some(entry) => {
+                            entry.check_range_lookup(field, &range)?;
+                            entry.get_by_range::<_>(field, range)
+                        }
+                        _ => None,
+                    };
+                    Ok(value.and_then(|val| val.deserialize().ok()))
+                }
+
+                fn read_all_by_range_checked<'a, T, R>(
+                    &'a self,
+                    field: &str,
+                    range: R,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
+                        Some(ent) => {
+                            ent.check_range_lookup(field, &range)?;
+                            ent.get_all_by_range::<_>(field, range).collect()
+                        }
+                        None => Vec::new(),
+                    };
+
+                    Ok(Box::new(val_iter.into_iter().filter_map(|item| item.deserialize().ok())))
+                }
+
+                fn try_take_by_range_checked<T, R>(&self, field: &str, range: R) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let value = match self.get_object_entry_mut::<T>("try_take_by_range_checked") {
+                        Some(mut entry) => {
+                            entry.check_range_lookup(field, &range)?;
+                            entry.remove_by_range::<_>(field, range)
+                        }
+                        _ => None,
+                    };
+                    Ok(value.and_then(|val| self.decode_taken::<T>(&val)))
+                }
+
+                fn take_all_by_range_checked<'a, T, R>(
+                    &'a self,
+                    field: &str,
+                    range: R,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let val_iter = match self.get_object_entry_mut::<T>("take_all_by_range_checked") {
+                        Some(mut ent) => {
+                            ent.check_range_lookup(field, &range)?;
+                            ent.remove_all_by_range::<_>(field, range)
+                        }
+                        None => Vec::new(),
+                    };
+
+                    Ok(Box::new(
+                        val_iter
+                            .into_iter()
+                            .filter_map(move |item| self.decode_taken::<T>(&item))
+                    ))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! object_key{
+    ($($ty:ty)*) => {
+        $(
+            impl ValueLookupObjectSpace<$ty> for TreeObjectSpace {
+                fn try_read_by_value<T>(&self, field: &str, key: &$ty) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let value = match self.get_object_entry_ref::<T>() {
+                        Some(entry) => entry.get_by_value(field, key),
+                        _ => None,
+                    };
+                    match value {
+                        Some(val) => val.deserialize().ok(),
+                        _ => None,
+                    }
+                }
+
+                fn read_all_by_value<'a, T>(&'a self, field: &str, key: &$ty) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
+                        Some(ent) => ent.get_all_by_value(field, key).collect(),
+                        None => Vec::new(),
+                    };
+
+                    Box::new(val_iter.into_iter().filter_map(|item| item.deserialize().ok()))
+                }
+
+                fn read_by_value<T>(&self, field: &str, key: &$ty) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    self.wait_to_read::<T, _, _>(
+                        "read_by_value",
+                        || {
+                            (Some(field.to_owned()), Some(format!("== {:?}", key)))
+                        },
+                        None,
+                        Some(Condition::eq(field, key)),
+                        || {
+                            match self.get_object_entry_ref::<T>() {
+                                Some(entry) => entry.get_by_value(field, key),
+                                _ => None,
+                            }
+                        },
+                    ).unwrap()
+                }
+
+                fn read_by_value_timeout<T>(&self, field: &str, key: &$ty, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    self.wait_to_read::<T, _, _>(
+                        "read_by_value_timeout",
+                        || {
+                            (Some(field.to_owned()), Some(format!("== {:?}", key)))
+                        },
+                        Some(timeout),
+                        Some(Condition::eq(field, key)),
+                        || {
+                            match self.get_object_entry_ref::<T>() {
+                                Some(entry) => entry.get_by_value(field, key),
+                                _ => None,
+                            }
+                        },
+                    )
+                }
+
+                fn try_take_by_value<T>(&self, field: &str, key: &$ty) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let value = match self.get_object_entry_mut::<T>("try_take_by_value") {
+                        Some(mut entry) => entry.remove_by_value(field, key),
+                        _ => None,
+                    };
+                    match value {
+                        Some(val) => self.decode_taken::<T>(&val),
+                        _ => None,
+                    }
+                }
+
+                fn take_all_by_value<'a, T>(
+                    &'a self,
+                    field: &str,
+                    key: &$ty,
+                ) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let val_iter = match self.get_object_entry_mut::<T>("take_all_by_value") {
+                        Some(mut ent) => ent.remove_all_by_value(field, key),
+                        None => Vec::new(),
+                    };
+
+                    Box::new(
+                        val_iter
+                            .into_iter()
+                            .filter_map(move |item| self.decode_taken::<T>(&item))
+                    )
+                }
+
+                fn take_by_value<T>(&self, field: &str, key: &$ty) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let operation = "take_by_value";
+                    self.wait_to_take::<T, _, _>(
+                        operation,
+                        || {
+                            (Some(field.to_owned()), Some(format!("== {:?}", key)))
+                        },
+                        None,
+                        Some(Condition::eq(field, key)),
+                        || {
+                            match self.get_object_entry_mut::<T>(operation) {
+                                Some(mut entry) => entry.remove_by_value(field, key),
+                                _ => None,
+                            }
+                        },
+                    ).unwrap()
+                }
+
+                fn take_by_value_timeout<T>(&self, field: &str, key: &$ty, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let operation = "take_by_value_timeout";
+                    self.wait_to_take::<T, _, _>(
+                        operation,
+                        || {
+                            (Some(field.to_owned()), Some(format!("== {:?}", key)))
+                        },
+                        Some(timeout),
+                        Some(Condition::eq(field, key)),
+                        || {
+                            match self.get_object_entry_mut::<T>(operation) {
+                                Some(mut entry) => entry.remove_by_value(field, key),
+                                _ => None,
+                            }
+                        },
+                    )
+                }
+
+                fn try_read_by_value_checked<T>(&self, field: &str, key: &$ty) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let value = match self.get_object_entry_ref::<T>() {
+                        Some(entry) => {
+                            entry.check_lookup(field, &[Field::from(key.clone())], false)?;
+                            entry.get_by_value(field, key)
+                        }
+                        _ => None,
+                    };
+                    Ok(value.and_then(|val| val.deserialize().ok()))
+                }
+
+                fn read_all_by_value_checked<'a, T>(
+                    &'a self,
+                    field: &str,
+                    key: &$ty,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
+                        Some(ent) => {
+                            ent.check_lookup(field, &[Field::from(key.clone())], false)?;
+                            ent.get_all_by_value(field, key).collect()
+                        }
+                        None => Vec::new(),
+                    };
+
+                    Ok(Box::new(val_iter.into_iter().filter_map(|item| item.deserialize().ok())))
+                }
+
+                fn try_take_by_value_checked<T>(&self, field: &str, key: &$ty) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let value = match self.get_object_entry_mut::<T>("try_take_by_value_checked") {
+                        Some(mut entry) => {
+                            entry.check_lookup(field, &[Field::from(key.clone())], false)?;
+                            entry.remove_by_value(field, key)
+                        }
+                        _ => None,
+                    };
+                    Ok(value.and_then(|val| self.decode_taken::<T>(&val)))
+                }
+
+                fn take_all_by_value_checked<'a, T>(
+                    &'a self,
+                    field: &str,
+                    key: &$ty,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let val_iter = match self.get_object_entry_mut::<T>("take_all_by_value_checked") {
+                        Some(mut ent) => {
+                            ent.check_lookup(field, &[Field::from(key.clone())], false)?;
+                            ent.remove_all_by_value(field, key)
+                        }
+                        None => Vec::new(),
+                    };
+
+                    Ok(Box::new(
+                        val_iter
+                            .into_iter()
+                            .filter_map(move |item| self.decode_taken::<T>(&item))
+                    ))
+                }
+            }
+
+            impl UpdateObjectSpace<$ty> for TreeObjectSpace {
+                fn update_by_value<T, F>(&self, field: &str, key: &$ty, f: F) -> bool
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    F: FnOnce(T) -> T,
+                {
+                    let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+                    let added = {
+                        let mut generation = lock.lock().unwrap();
+                        let updated = match self.get_object_entry_mut::<T>("update_by_value") {
+                            Some(mut entry) => entry.update_by_value(field, key, |old| {
+                                let new = f(old.deserialize().unwrap());
+                                let mut record = Record::new(&new, self.codec).expect("struct cannot be serialized");
+                                if let Some(ref store) = self.spill {
+                                    record.spill(store);
+                                }
+                                record
+                            }),
+                            None => None,
+                        };
+                        let added = match updated {
+                            Some(added) => added,
+                            None => return false,
+                        };
+                        if added == Ok(true) {
+                            *generation = generation.wrapping_add(1);
+                            cvar.notify_all();
+                            self.wake_futures::<T>();
+                        }
+                        added
+                    };
+                    // panic only after releasing the lock, so that the lock is not poisoned
+                    match added {
+                        Ok(updated) => updated,
+                        Err(mismatch) => panic!("Struct does not match the schema of its type: {}", mismatch),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! object_interval{
+    ($($ty:ty)*) => {
+        $(
+            impl IntervalLookupObjectSpace<$ty> for TreeObjectSpace {
+                fn try_read_covering<T>(&self, fields: (&str, &str), point: &$ty) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let value = match self.get_object_entry_ref::<T>() {
+                        Some(entry) => entry.get_by_interval(fields, point),
+                        _ => None,
+                    };
+                    match value {
+                        Some(val) => val.deserialize().ok(),
+                        _ => None,
+                    }
+                }
+
+                fn read_all_covering<'a, T>(
+                    &'a self,
+                    fields: (&str, &str),
+                    point: &$ty,
+                ) -> Box<Iterator<Item = T> + 'a>
+                where
                     for<'de> T: Deserialize<'de> + 'static,
-                    R: RangeBounds<$ty> + Clone,
                 {
-                    let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
-                        Some(ent) => ent.get_all_by_range::<_>(field, range).collect(),
-                        None => Vec::new(),
+                    let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
+                        Some(ent) => ent.get_all_by_interval(fields, point).collect(),
+                        None => Vec::new(),
+                    };
+
+                    Box::new(val_iter.into_iter().filter_map(|item| item.deserialize().ok()))
+                }
+
+                fn read_covering<T>(&self, fields: (&str, &str), point: &$ty) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+                    let mut waiter = self.waiter::<T, _>("read_covering", || {
+                        let fields = format!("({}, {})", fields.0, fields.1);
+                        (Some(fields), Some(format!("covering {:?}", point)))
+                    });
+                    let value;
+                    {
+                        let mut fetched = lock.lock().unwrap();
+                        loop {
+                            let result = match self.get_object_entry_ref::<T>() {
+                                Some(entry) => entry.get_by_interval(fields, point),
+                                _ => None,
+                            };
+                            if let Some(item) = result.and_then(|item| item.deserialize().ok()) {
+                                value = item;
+                                break;
+                            }
+                            fetched = waiter.wait(lock, cvar, fetched);
+                        }
+                    }
+                    value
+                }
+
+                fn try_take_covering<T>(&self, fields: (&str, &str), point: &$ty) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let value = match self.get_object_entry_mut::<T>("try_take_covering") {
+                        Some(mut entry) => entry.remove_by_interval(fields, point),
+                        _ => None,
+                    };
+                    match value {
+                        Some(val) => self.decode_taken::<T>(&val),
+                        _ => None,
+                    }
+                }
+
+                fn take_all_covering<'a, T>(
+                    &'a self,
+                    fields: (&str, &str),
+                    point: &$ty,
+                ) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let val_iter = match self.get_object_entry_mut::<T>("take_all_covering") {
+                        Some(mut ent) => ent.remove_all_by_interval(fields, point),
+                        None => Vec::new(),
+                    };
+
+                    Box::new(
+                        val_iter
+                            .into_iter()
+                            .filter_map(move |item| self.decode_taken::<T>(&item))
+                    )
+                }
+
+                fn take_covering<T>(&self, fields: (&str, &str), point: &$ty) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+                    let mut waiter = self.waiter::<T, _>("take_covering", || {
+                        let fields = format!("({}, {})", fields.0, fields.1);
+                        (Some(fields), Some(format!("covering {:?}", point)))
+                    });
+                    let value;
+                    {
+                        let mut fetched = lock.lock().unwrap();
+                        loop {
+                            let result = match self.get_object_entry_mut::<T>("take_covering") {
+                                Some(mut entry) => entry.remove_by_interval(fields, point),
+                                _ => None,
+                            };
+                            if let Some(item) = result {
+                                if let Some(obj) = self.decode_taken::<T>(&item) {
+                                    value = obj;
+                                    break;
+                                }
+                                // the struct which could not be read is kept as a dead letter, and another one is looked for
+                                continue;
+                            }
+                            fetched = waiter.wait(lock, cvar, fetched);
+                        }
+                    }
+                    value
+                }
+            }
+        )*
+    };
+}
+
+impl SpatialLookupObjectSpace for TreeObjectSpace {
+    fn try_read_in_rect<T, R>(&self, fields: (&str, &str), bounds: (R, R)) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone,
+    {
+        let value = match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get_by_rect(fields, &bounds),
+            _ => None,
+        };
+        match value {
+            Some(val) => val.deserialize().ok(),
+            _ => None,
+        }
+    }
+
+    fn read_all_in_rect<'a, T, R>(
+        &'a self,
+        fields: (&str, &str),
+        bounds: (R, R),
+    ) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone,
+    {
+        let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
+            Some(ent) => ent.get_all_by_rect(fields, &bounds).collect(),
+            None => Vec::new(),
+        };
+
+        Box::new(
+            val_iter
+                .into_iter()
+                .filter_map(|item| item.deserialize().ok()),
+        )
+    }
+
+    fn read_in_rect<T, R>(&self, fields: (&str, &str), bounds: (R, R)) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone,
+    {
+        let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+        let mut waiter = self.waiter::<T, _>("read_in_rect", || {
+            let fields = format!("({}, {})", fields.0, fields.1);
+            let (ref x, ref y) = bounds;
+            let rect = format!("in ({}, {})", describe_range(x), describe_range(y));
+            (Some(fields), Some(rect))
+        });
+        let value;
+        {
+            let mut fetched = lock.lock().unwrap();
+            loop {
+                let result = match self.get_object_entry_ref::<T>() {
+                    Some(entry) => entry.get_by_rect(fields, &bounds),
+                    _ => None,
+                };
+                if let Some(item) = result.and_then(|item| item.deserialize().ok()) {
+                    value = item;
+                    break;
+                }
+                fetched = waiter.wait(lock, cvar, fetched);
+            }
+        }
+        value
+    }
+
+    fn try_take_in_rect<T, R>(&self, fields: (&str, &str), bounds: (R, R)) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone,
+    {
+        let value = match self.get_object_entry_mut::<T>("try_take_in_rect") {
+            Some(mut entry) => entry.remove_by_rect(fields, &bounds),
+            _ => None,
+        };
+        match value {
+            Some(val) => self.decode_taken::<T>(&val),
+            _ => None,
+        }
+    }
+
+    fn take_all_in_rect<'a, T, R>(
+        &'a self,
+        fields: (&str, &str),
+        bounds: (R, R),
+    ) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone,
+    {
+        let val_iter = match self.get_object_entry_mut::<T>("take_all_in_rect") {
+            Some(mut ent) => ent.remove_all_by_rect(fields, &bounds),
+            None => Vec::new(),
+        };
+
+        Box::new(
+            val_iter
+                .into_iter()
+                .filter_map(move |item| self.decode_taken::<T>(&item)),
+        )
+    }
+
+    fn take_in_rect<T, R>(&self, fields: (&str, &str), bounds: (R, R)) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        R: RangeBounds<f64> + Clone,
+    {
+        let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+        let mut waiter = self.waiter::<T, _>("take_in_rect", || {
+            let fields = format!("({}, {})", fields.0, fields.1);
+            let (ref x, ref y) = bounds;
+            let rect = format!("in ({}, {})", describe_range(x), describe_range(y));
+            (Some(fields), Some(rect))
+        });
+        let value;
+        {
+            let mut fetched = lock.lock().unwrap();
+            loop {
+                let result = match self.get_object_entry_mut::<T>("take_in_rect") {
+                    Some(mut entry) => entry.remove_by_rect(fields, &bounds),
+                    _ => None,
+                };
+                if let Some(item) = result {
+                    if let Some(obj) = self.decode_taken::<T>(&item) {
+                        value = obj;
+                        break;
+                    }
+                    // the struct which could not be read is kept as a dead letter, and another one is looked for
+                    continue;
+                }
+                fetched = waiter.wait(lock, cvar, fetched);
+            }
+        }
+        value
+    }
+}
+
+impl PatternLookupObjectSpace for TreeObjectSpace {
+    fn try_read_by_prefix<T>(&self, field: &str, prefix: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.try_read_matching(field, &Pattern::prefix(prefix))
+    }
+
+    fn read_all_by_prefix<'a, T>(&'a self, field: &str, prefix: &str) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        self.read_all_matching(field, &Pattern::prefix(prefix))
+    }
+
+    fn read_by_prefix<T>(&self, field: &str, prefix: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.read_matching("read_by_prefix", field, Pattern::prefix(prefix))
+    }
+
+    fn try_take_by_prefix<T>(&self, field: &str, prefix: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.try_take_matching("try_take_by_prefix", field, &Pattern::prefix(prefix))
+    }
+
+    fn take_all_by_prefix<'a, T>(&'a self, field: &str, prefix: &str) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        self.take_all_matching("take_all_by_prefix", field, &Pattern::prefix(prefix))
+    }
+
+    fn take_by_prefix<T>(&self, field: &str, prefix: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.take_matching("take_by_prefix", field, Pattern::prefix(prefix))
+    }
+
+    fn try_read_by_pattern<T>(&self, field: &str, pattern: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.try_read_matching(field, &Pattern::new(pattern))
+    }
+
+    fn read_all_by_pattern<'a, T>(&'a self, field: &str, pattern: &str) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        self.read_all_matching(field, &Pattern::new(pattern))
+    }
+
+    fn read_by_pattern<T>(&self, field: &str, pattern: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.read_matching("read_by_pattern", field, Pattern::new(pattern))
+    }
+
+    fn try_take_by_pattern<T>(&self, field: &str, pattern: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.try_take_matching("try_take_by_pattern", field, &Pattern::new(pattern))
+    }
+
+    fn take_all_by_pattern<'a, T>(&'a self, field: &str, pattern: &str) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        self.take_all_matching("take_all_by_pattern", field, &Pattern::new(pattern))
+    }
+
+    fn take_by_pattern<T>(&self, field: &str, pattern: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.take_matching("take_by_pattern", field, Pattern::new(pattern))
+    }
+}
+
+impl OrderedLookupObjectSpace for TreeObjectSpace {
+    fn try_read_min_by<T>(&self, field: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.try_read_first_by(field, Order::Ascending)
+    }
+
+    fn try_read_max_by<T>(&self, field: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.try_read_first_by(field, Order::Descending)
+    }
+
+    fn try_take_min_by<T>(&self, field: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.try_take_first_by("try_take_min_by", field, Order::Ascending)
+    }
+
+    fn try_take_max_by<T>(&self, field: &str) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.try_take_first_by("try_take_max_by", field, Order::Descending)
+    }
+
+    fn take_min_by<T>(&self, field: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.take_first_by("take_min_by", field, Order::Ascending, None).unwrap()
+    }
+
+    fn take_max_by<T>(&self, field: &str) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.take_first_by("take_max_by", field, Order::Descending, None).unwrap()
+    }
+
+    fn read_all_ordered<'a, T>(&'a self, field: &str, order: Order) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
+            Some(ent) => ent.get_all_ordered(field, order).collect(),
+            None => Vec::new(),
+        };
+
+        Box::new(val_iter.into_iter().filter_map(|item| item.deserialize().ok()))
+    }
+
+    fn take_ordered<T>(&self, field: &str, order: Order) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.take_first_by("take_ordered", field, order, None).unwrap()
+    }
+
+    fn take_ordered_timeout<T>(&self, field: &str, order: Order, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.take_first_by("take_ordered_timeout", field, order, Some(timeout))
+    }
+}
+
+impl DynObjectSpace for TreeObjectSpace {
+    fn write_json(&self, type_name: &str, obj: serde_json::Value) -> Option<ObjectHandle> {
+        TreeObjectSpace::write_json(self, type_name, obj)
+    }
+
+    fn write_value(&self, type_name: &str, value: serde_json::Value) -> ObjectHandle {
+        TreeObjectSpace::write_value(self, type_name, value)
+    }
+
+    fn try_read_json(&self, type_name: &str) -> Option<serde_json::Value> {
+        self.read_json(type_name, |entry| entry.get().into_iter().collect()).pop()
+    }
+
+    fn read_all_json(&self, type_name: &str) -> Vec<serde_json::Value> {
+        TreeObjectSpace::read_all_json(self, type_name)
+    }
+
+    fn try_take_json(&self, type_name: &str) -> Option<serde_json::Value> {
+        self.take_json(type_name, "try_take_json", |entry| entry.remove().into_iter().collect())
+            .pop()
+    }
+
+    fn take_all_json(&self, type_name: &str) -> Vec<serde_json::Value> {
+        self.take_json(type_name, "take_all_json", |entry| entry.remove_all())
+    }
+
+    fn try_read_json_by_query(&self, type_name: &str, query: &Query) -> Option<serde_json::Value> {
+        self.read_json(type_name, |entry| entry.get_by_query(query).into_iter().collect())
+            .pop()
+    }
+
+    fn read_all_json_by_query(&self, type_name: &str, query: &Query) -> Vec<serde_json::Value> {
+        self.read_json(type_name, |entry| entry.get_all_by_query(query))
+    }
+
+    fn try_take_json_by_query(&self, type_name: &str, query: &Query) -> Option<serde_json::Value> {
+        self.take_json(type_name, "try_take_json_by_query", |entry| {
+            entry.remove_by_query(query).into_iter().collect()
+        }).pop()
+    }
+
+    fn take_all_json_by_query(&self, type_name: &str, query: &Query) -> Vec<serde_json::Value> {
+        self.take_json(type_name, "take_all_json_by_query", |entry| {
+            entry.remove_all_by_query(query)
+        })
+    }
+}
+
+impl QueryObjectSpace for TreeObjectSpace {
+    fn try_read_by_query<T>(&self, query: &Query) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get_by_query(query),
+            _ => None,
+        };
+        match value {
+            Some(val) => val.deserialize().ok(),
+            _ => None,
+        }
+    }
+
+    fn read_all_by_query<'a, T>(&'a self, query: &Query) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        let val_iter = match self.get_object_entry_ref::<T>() {
+            Some(ent) => ent.get_all_by_query(query),
+            None => Vec::new(),
+        };
+
+        Box::new(val_iter.into_iter().filter_map(|item| item.deserialize().ok()))
+    }
+
+    fn read_by_query<T>(&self, query: &Query) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+        let mut waiter =
+            self.waiter::<T, _>("read_by_query", || (None, Some(query.to_string())));
+        let value;
+        {
+            let mut fetched = lock.lock().unwrap();
+            loop {
+                let result = match self.get_object_entry_ref::<T>() {
+                    Some(entry) => entry.get_by_query(query),
+                    _ => None,
+                };
+                if let Some(item) = result.and_then(|item| item.deserialize().ok()) {
+                    value = item;
+                    break;
+                }
+                fetched = waiter.wait(lock, cvar, fetched);
+            }
+        }
+        value
+    }
+
+    fn try_take_by_query<T>(&self, query: &Query) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let value = match self.get_object_entry_mut::<T>("try_take_by_query") {
+            Some(mut entry) => entry.remove_by_query(query),
+            _ => None,
+        };
+        match value {
+            Some(val) => self.decode_taken::<T>(&val),
+            _ => None,
+        }
+    }
+
+    fn take_all_by_query<'a, T>(&'a self, query: &Query) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        let val_iter = match self.get_object_entry_mut::<T>("take_all_by_query") {
+            Some(mut ent) => ent.remove_all_by_query(query),
+            None => Vec::new(),
+        };
+
+        Box::new(val_iter.into_iter().filter_map(move |item| self.decode_taken::<T>(&item)))
+    }
+
+    fn take_by_query<T>(&self, query: &Query) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let &(ref lock, ref cvar) = &*self.get_or_add_lock::<T>();
+        let mut waiter =
+            self.waiter::<T, _>("take_by_query", || (None, Some(query.to_string())));
+        let value;
+        {
+            let mut fetched = lock.lock().unwrap();
+            loop {
+                let result = match self.get_object_entry_mut::<T>("take_by_query") {
+                    Some(mut entry) => entry.remove_by_query(query),
+                    _ => None,
+                };
+                if let Some(item) = result {
+                    if let Some(obj) = self.decode_taken::<T>(&item) {
+                        value = obj;
+                        break;
+                    }
+                    // the struct which could not be read is kept as a dead letter, and another one is looked for
+                    continue;
+                }
+                fetched = waiter.wait(lock, cvar, fetched);
+            }
+        }
+        value
+    }
+}
+
+impl AsyncObjectSpace for TreeObjectSpace {
+    fn read_async<'a, T>(&'a self) -> Box<Future<Output = T> + Send + Unpin + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        // a struct which could not be read is left in the space, and the future keeps waiting
+        Box::new(self.awaited::<T, _>(move || match self.get_object_entry_ref::<T>() {
+            Some(entry) => entry.get().and_then(|value| value.deserialize().ok()),
+            _ => None,
+        }))
+    }
+
+    fn take_async<'a, T>(&'a self) -> Box<Future<Output = T> + Send + Unpin + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        // a struct which could not be read is kept as a dead letter, and another one is looked for
+        Box::new(self.awaited::<T, _>(move || loop {
+            let value = match self.get_object_entry_mut::<T>("take_async") {
+                Some(mut entry) => entry.remove(),
+                _ => None,
+            }?;
+            if let Some(obj) = self.decode_taken::<T>(&value) {
+                return Some(obj);
+            }
+        }))
+    }
+}
+
+macro_rules! object_nearest{
+    ($($ty:ty)*) => {
+        $(
+            impl NearestLookupObjectSpace<$ty> for TreeObjectSpace {
+                fn try_read_nearest<T>(&self, field: &str, target: &$ty) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let value = match self.get_object_entry_ref::<T>() {
+                        Some(entry) => entry.get_nearest(field, target),
+                        _ => None,
+                    };
+                    match value {
+                        Some(val) => val.deserialize().ok(),
+                        _ => None,
+                    }
+                }
+
+                fn try_take_nearest<T>(&self, field: &str, target: &$ty) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let value = match self.get_object_entry_mut::<T>("try_take_nearest") {
+                        Some(mut entry) => entry.remove_nearest(field, target),
+                        _ => None,
                     };
+                    match value {
+                        Some(val) => self.decode_taken::<T>(&val),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+object_range!{i64 u64 i128 String f64}
+#[cfg(feature = "chrono")]
+object_range!{DateTime<Utc>}
+object_key!{i64 u64 i128 String bool f64 FieldValue}
+#[cfg(feature = "uuid")]
+object_key!{Uuid}
+object_interval!{i64 f64}
+object_nearest!{i64 f64}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct TestStruct {
+        count: i32,
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct CompoundStruct {
+        person: TestStruct,
+        gpa: f64,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum TestEnum {
+        String(String),
+        Int(i32),
+        Struct { count: i32, name: String },
+    }
+
+    #[test]
+    fn try_read() {
+        let space = TreeObjectSpace::new();
+        assert_eq!(space.try_read::<String>(), None);
+        space.write(String::from("Hello World"));
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.0,
+        });
+
+        assert_eq!(
+            space.try_read::<String>(),
+            Some(String::from("Hello World"))
+        );
+        assert_ne!(space.try_read::<String>(), None);
+
+        assert_eq!(
+            space.try_read::<TestStruct>(),
+            Some(TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            })
+        );
+
+        assert_eq!(
+            space.try_read::<CompoundStruct>(),
+            Some(CompoundStruct {
+                person: TestStruct {
+                    count: 3,
+                    name: String::from("Tuan"),
+                },
+                gpa: 3.0
+            })
+        );
+        assert!(space.try_read::<CompoundStruct>().is_some());
+    }
+
+    #[test]
+    fn try_take() {
+        let space = TreeObjectSpace::new();
+        assert_eq!(space.try_take::<String>(), None);
+        space.write(String::from("Hello World"));
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.5,
+        });
+
+        assert_eq!(
+            space.try_take::<String>(),
+            Some(String::from("Hello World"))
+        );
+        assert_eq!(space.try_take::<String>(), None);
+
+        assert_eq!(
+            space.try_take::<TestStruct>(),
+            Some(TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            })
+        );
+        assert_eq!(space.try_take::<TestStruct>(), None);
+
+        assert_eq!(
+            space.try_take::<CompoundStruct>(),
+            Some(CompoundStruct {
+                person: TestStruct {
+                    count: 3,
+                    name: String::from("Tuan"),
+                },
+                gpa: 3.5
+            })
+        );
+        assert!(space.try_take::<CompoundStruct>().is_none());
+    }
+
+    #[test]
+    fn read_all() {
+        let space = TreeObjectSpace::new();
+        assert_eq!(space.read_all::<String>().count(), 0);
+        space.write("Hello".to_string());
+        space.write("World".to_string());
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+        space.write(TestStruct {
+            count: 5,
+            name: String::from("Duane"),
+        });
+
+        assert_eq!(
+            space.read_all::<String>().collect::<Vec<String>>(),
+            vec!["Hello", "World"]
+        );
+        assert_ne!(space.read_all::<String>().count(), 0);
+
+        assert_eq!(space.read_all::<TestStruct>().count(), 2);
+        assert_eq!(space.read_all::<TestStruct>().count(), 2);
+    }
+
+    #[test]
+    fn take_all() {
+        let space = TreeObjectSpace::new();
+        assert_eq!(space.take_all::<String>().count(), 0);
+        space.write("Hello".to_string());
+        space.write("World".to_string());
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+        space.write(TestStruct {
+            count: 5,
+            name: String::from("Duane"),
+        });
+
+        assert_eq!(space.take_all::<String>().count(), 2);
+        assert_eq!(space.take_all::<String>().count(), 0);
+
+        assert_eq!(space.take_all::<TestStruct>().count(), 2);
+        assert_eq!(space.take_all::<TestStruct>().count(), 0);
+    }
+
+    #[test]
+    fn read_sample() {
+        let space = TreeObjectSpace::new();
+        assert_eq!(space.read_sample::<i64>(5).count(), 0);
+        for i in 0..1000 {
+            space.write::<i64>(i);
+        }
+
+        let sample: Vec<i64> = space.read_sample::<i64>(50).collect();
+        assert_eq!(sample.len(), 50);
+        assert!(sample.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(space.read_all::<i64>().count(), 1000);
+
+        assert_eq!(space.take_all::<i64>().count(), 1000);
+        space.write::<i64>(7);
+        assert_eq!(space.read_sample::<i64>(5).collect::<Vec<i64>>(), vec![7]);
+    }
+
+    #[test]
+    fn checkpoint() {
+        let path = ::std::env::temp_dir().join("object_space_test_checkpoint.json");
+        let space = TreeObjectSpace::new();
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.5,
+        });
+        space.write(String::from("transient"));
+        space.checkpoint::<CompoundStruct>(&path).unwrap();
+
+        let other = TreeObjectSpace::new();
+        assert_eq!(other.restore::<CompoundStruct>(&path).unwrap(), 1);
+        assert_eq!(other.try_read::<String>(), None);
+        assert_eq!(
+            other.try_read_by_value::<CompoundStruct>("person.name", &String::from("Tuan")),
+            Some(CompoundStruct {
+                person: TestStruct {
+                    count: 3,
+                    name: String::from("Tuan"),
+                },
+                gpa: 3.5
+            })
+        );
+
+        assert!(other.restore::<TestStruct>(&path).is_err());
+        assert_eq!(other.try_read::<TestStruct>(), None);
+        fs::remove_file(&path).unwrap();
+        assert!(other.restore::<CompoundStruct>(&path).is_err());
+    }
+
+    #[test]
+    fn export_import() {
+        let space = TreeObjectSpace::new();
+        space.register_type::<TestStruct>("person");
+        for i in 0..3 {
+            space.write(TestStruct {
+                count: i,
+                name: format!("person {}", i),
+            });
+        }
+        space.write(String::from("transient"));
+        space.write_value("order", serde_json::json!({ "id": 1 }));
+
+        let mut lines = Vec::new();
+        assert_eq!(space.export::<TestStruct>(&mut lines).unwrap(), 3);
+        let other = TreeObjectSpace::new();
+        assert_eq!(other.import::<TestStruct>(&mut &lines[..]).unwrap(), 3);
+        assert_eq!(other.try_read::<String>(), None);
+        assert_eq!(
+            other.try_read_by_value::<TestStruct>("name", &String::from("person 1")),
+            Some(TestStruct {
+                count: 1,
+                name: String::from("person 1"),
+            })
+        );
+
+        // a malformed line rejects the whole input
+        let input = b"{\"count\":5,\"name\":\"person 5\"}\n\n{\"count\":6}\n";
+        let error = other.import::<TestStruct>(&mut &input[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 3"));
+        assert_eq!(other.read_all::<TestStruct>().count(), 3);
+
+        let mut lines = Vec::new();
+        assert_eq!(space.export_all(&mut lines).unwrap(), 5);
+        let other = TreeObjectSpace::new();
+        other.register_type::<TestStruct>("person");
+        other.write(TestStruct {
+            count: 9,
+            name: String::from("person 9"),
+        });
+        assert_eq!(other.import_all(&mut &lines[..]).unwrap(), 5);
+        assert_eq!(other.read_all::<TestStruct>().count(), 4);
+        assert_eq!(other.read_all_json("order"), vec![serde_json::json!({ "id": 1 })]);
+        assert_eq!(other.read_all_json(type_name::<String>()), vec![serde_json::json!("transient")]);
+        assert!(other.import_all(&mut &b"{\"value\":3}"[..]).is_err());
+    }
+
+    #[test]
+    fn schema_evolution() {
+        #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+        struct Versioned {
+            count: i64,
+            name: String,
+            tags: Vec<String>,
+        }
+
+        let path = ::std::env::temp_dir().join("object_space_test_schema_evolution.json");
+        fs::write(
+            &path,
+            r#"[{"count": 1, "name": "a"}, {"count": 2, "name": "b", "tags": ["x"]}, {"count": "three", "name": "c"}]"#,
+        ).unwrap();
+        let space = TreeObjectSpace::new();
+        assert!(space.restore::<Versioned>(&path).is_err());
+        assert_eq!(space.read_all::<Versioned>().count(), 0);
+
+        space.fill_missing_fields::<Versioned>();
+        space.dead_letter::<Versioned>();
+        assert_eq!(space.restore::<Versioned>(&path).unwrap(), 3);
+        fs::remove_file(&path).unwrap();
+        let restored: Vec<_> = space.read_all::<Versioned>().collect();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored[0].tags, Vec::<String>::new());
+        assert_eq!(restored[1].tags, vec![String::from("x")]);
+        assert_eq!(space.try_read_by_value::<Versioned>("count", &1i64).unwrap().name, "a");
+        let dead_letters = space.dead_letters::<Versioned>();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].payload["count"], serde_json::Value::from("three"));
+        assert!(!dead_letters[0].error.is_empty());
+
+        // structs already in the space are completed once the policy is set
+        let other = TreeObjectSpace::new();
+        other.register_type::<Versioned>("Versioned");
+        other.write(Versioned::default());
+        let mut old = serde_json::Map::new();
+        old.insert(String::from("count"), serde_json::Value::from(4));
+        old.insert(String::from("name"), serde_json::Value::from("d"));
+        other.write_json("Versioned", serde_json::Value::Object(old));
+        assert_eq!(other.read_all::<Versioned>().count(), 1);
+        assert!(other.read_all_decoded::<Versioned>()[1].is_err());
+        other.fill_missing_fields::<Versioned>();
+        assert_eq!(other.read_all::<Versioned>().count(), 2);
+        assert!(other.read_all_decoded::<Versioned>().iter().all(Result::is_ok));
+        assert_eq!(other.take_by_value::<Versioned>("count", &4i64).name, "d");
+        assert!(other.take_dead_letters::<Versioned>().is_empty());
+    }
+
+    #[test]
+    fn dead_letter_queue() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Job {
+            id: i64,
+            owner: String,
+        }
+
+        let space = TreeObjectSpace::new();
+        space.register_type::<Job>("Job");
+        space.write(Job { id: 1, owner: String::from("a") });
+        let mut legacy = serde_json::Map::new();
+        legacy.insert(String::from("id"), serde_json::Value::from(2));
+        space.write_json("Job", serde_json::Value::Object(legacy.clone()));
+        legacy.insert(String::from("id"), serde_json::Value::from(3));
+        space.write_json("Job", serde_json::Value::Object(legacy));
+
+        // structs taken but not read are kept aside instead of vanishing
+        assert_eq!(space.take_all::<Job>().count(), 1);
+        assert_eq!(space.read_all_decoded::<Job>().len(), 0);
+        let dead_letters = space.dead_letters::<Job>();
+        assert_eq!(dead_letters.len(), 2);
+        assert_eq!(dead_letters[1].payload["id"], serde_json::Value::from(3));
+
+        // a payload still unreadable leaves the dead letter in place
+        let error = space.requeue_dead_letter::<Job>(0, dead_letters[0].payload.clone());
+        assert!(error.is_err());
+        assert_eq!(space.dead_letters::<Job>().len(), 2);
+
+        let mut migrated = dead_letters[1].payload.clone();
+        migrated["owner"] = serde_json::Value::from("b");
+        space.requeue_dead_letter::<Job>(1, migrated).unwrap();
+        assert_eq!(space.dead_letters::<Job>().len(), 1);
+        assert_eq!(space.dead_letters::<Job>()[0].payload["id"], serde_json::Value::from(2));
+        assert_eq!(space.try_take_by_value::<Job>("id", &3i64).unwrap().owner, "b");
+
+        // dead letters keep the entry of their type alive
+        for _ in 0..8 {
+            space.write::<i64>(0);
+        }
+        assert_eq!(space.take_dead_letters::<Job>().len(), 1);
+    }
+
+    #[test]
+    fn blocking_unreadable() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Job {
+            id: i64,
+            owner: String,
+        }
+
+        let space = TreeObjectSpace::new();
+        space.register_type::<Job>("Job");
+        space.write(Job { id: 2, owner: String::from("a") });
+        let mut legacy = serde_json::Map::new();
+        legacy.insert(String::from("id"), serde_json::Value::from(1));
+        space.write_json("Job", serde_json::Value::Object(legacy));
+
+        // blocking calls skip the struct which could not be read instead of panicking
+        assert_eq!(space.read_by_value::<Job>("id", &2i64).id, 2);
+        assert_eq!(space.take::<Job>().id, 2);
+        assert!(space.take_timeout::<Job>(Duration::from_millis(10)).is_none());
+        assert_eq!(space.dead_letters::<Job>().len(), 1);
+        assert_eq!(space.dead_letters::<Job>()[0].payload["id"], serde_json::Value::from(1));
+
+        let mut legacy = serde_json::Map::new();
+        legacy.insert(String::from("id"), serde_json::Value::from(3));
+        space.write(Job { id: 4, owner: String::from("b") });
+        space.write_json("Job", serde_json::Value::Object(legacy));
+        assert_eq!(futures::executor::block_on(space.take_async::<Job>()).id, 4);
+        assert!(space.take_timeout::<Job>(Duration::from_millis(10)).is_none());
+        assert_eq!(space.dead_letters::<Job>().len(), 2);
+    }
+
+    #[test]
+    fn dedup() {
+        let space = TreeObjectSpace::new();
+        space.write::<i64>(2);
+        space.write::<i64>(2);
+        space.set_dedup::<i64>(true);
+        // duplicates written before deduplication are kept
+        assert_eq!(space.read_all::<i64>().count(), 2);
+        space.write::<i64>(2);
+        assert_eq!(space.read_all::<i64>().count(), 2);
+
+        let handle = space.write::<i64>(3);
+        assert_eq!(space.write::<i64>(3), handle);
+        assert_eq!(space.take_all_by_value::<i64>("", &2i64).count(), 2);
+        space.write::<i64>(2);
+        let mut values: Vec<i64> = space.read_all::<i64>().collect();
+        values.sort();
+        assert_eq!(values, vec![2, 3]);
+        assert_eq!(space.take_all::<i64>().count(), 2);
+        space.write::<i64>(3);
+        assert_eq!(space.read_all::<i64>().count(), 1);
+
+        space.set_dedup::<i64>(false);
+        space.write::<i64>(3);
+        assert_eq!(space.read_all::<i64>().count(), 2);
+    }
+
+    #[test]
+    fn capacity() {
+        let space = Arc::new(TreeObjectSpace::new());
+        space.set_capacity::<i64>(2, Overflow::Block);
+        let writer = {
+            let space = space.clone();
+            thread::spawn(move || {
+                space.write_all::<i64, _>(0..3);
+                space.write::<i64>(3);
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(space.read_all::<i64>().count(), 2);
+        assert_eq!(space.try_write::<i64>(10), Err(SpaceError::Full(2)));
+        let mut taken: Vec<i64> = (0..4).map(|_| space.take::<i64>()).collect();
+        writer.join().unwrap();
+        taken.sort();
+        assert_eq!(taken, vec![0, 1, 2, 3]);
+
+        // raising the limit wakes up the blocked writers
+        space.write_all::<i64, _>(0..2);
+        let writer = {
+            let space = space.clone();
+            thread::spawn(move || space.write::<i64>(2))
+        };
+        thread::sleep(Duration::from_millis(50));
+        space.set_capacity::<i64>(3, Overflow::Block);
+        writer.join().unwrap();
+        assert_eq!(space.take_all::<i64>().count(), 3);
+
+        space.set_policy::<String>(OrderingPolicy::Lifo);
+        space.set_capacity::<String>(2, Overflow::EvictOldest);
+        for name in &["a", "b", "c"] {
+            space.write(String::from(*name));
+        }
+        assert_eq!(space.read_all::<String>().collect::<Vec<_>>(), vec!["c", "b"]);
+        let metrics = space.metrics();
+        let strings = metrics.iter().find(|m| m.type_name.contains("String")).unwrap();
+        assert_eq!((strings.evicted, strings.takes, strings.size), (1, 0, 2));
+
+        // unordered structs are evicted in the order they were written in too
+        space.write_all::<u8, _>(vec![0, 1, 2]);
+        space.set_capacity::<u8>(3, Overflow::EvictOldest);
+        space.take_by_value::<u8>("", &0i64);
+        space.write_all::<u8, _>(vec![3, 4]);
+        let mut bytes: Vec<_> = space.read_all::<u8>().collect();
+        bytes.sort();
+        assert_eq!(bytes, vec![2, 3, 4]);
+
+        let bounded = TreeObjectSpace::builder()
+            .default_capacity(1, Overflow::Reject)
+            .build();
+        bounded.write::<i64>(3);
+        assert!(bounded.try_write::<i64>(5).is_err());
+        assert!(bounded.try_write::<bool>(true).is_ok());
+    }
+
+    #[test]
+    fn memory_quota() {
+        let space = Arc::new(TreeObjectSpace::new());
+        assert_eq!(space.memory_usage::<Vec<u8>>(), 0);
+        space.write(vec![0u8; 100]);
+        let row = space.memory_usage::<Vec<u8>>();
+        assert!(row > 100);
+        space.write(vec![0u8; 1000]);
+        assert!(space.memory_usage::<Vec<u8>>() > row + 900);
+        space.take_by_range::<Vec<u8>, _>("[]", 0i64..1);
+        space.take_by_range::<Vec<u8>, _>("[]", 0i64..1);
+        assert_eq!(space.memory_usage::<Vec<u8>>(), 0);
+
+        space.set_policy::<Vec<u8>>(OrderingPolicy::Fifo);
+        space.set_memory_quota::<Vec<u8>>(2 * row, Overflow::EvictOldest);
+        for i in 0..3u8 {
+            space.write(vec![i; 100]);
+        }
+        assert_eq!(space.read_all::<Vec<u8>>().map(|v| v[0]).collect::<Vec<_>>(), vec![1, 2]);
+        // the oldest structs are evicted until a larger one fits, and one larger than the quota never does
+        space.write(vec![3u8; 150]);
+        assert_eq!(space.read_all::<Vec<u8>>().map(|v| v[0]).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(space.try_write(vec![4u8; 1000]), Err(SpaceError::OverQuota(2 * row)));
+        assert_eq!(space.read_all::<Vec<u8>>().map(|v| v[0]).collect::<Vec<_>>(), vec![3]);
+
+        let metrics = space.metrics();
+        let rows = metrics.iter().find(|m| m.type_name.contains("Vec")).unwrap();
+        assert_eq!(rows.evicted, 3);
+        assert_eq!(rows.memory, space.memory_usage::<Vec<u8>>());
+        space.take::<Vec<u8>>();
+
+        space.set_memory_quota::<Vec<u8>>(2 * row, Overflow::Block);
+        space.write_all(vec![vec![0u8; 100], vec![1u8; 100]]);
+        let writer = {
+            let space = space.clone();
+            thread::spawn(move || space.write(vec![2u8; 100]))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(space.read_all::<Vec<u8>>().count(), 2);
+        assert_eq!(space.take::<Vec<u8>>(), vec![0u8; 100]);
+        writer.join().unwrap();
+        assert_eq!(space.memory_usage::<Vec<u8>>(), 2 * row);
+        assert_eq!(space.list_types()[0].memory, 2 * row);
+
+        // an empty type keeps its quota through garbage collection
+        let space = TreeObjectSpace::new();
+        space.set_memory_quota::<Vec<u8>>(10, Overflow::Reject);
+        assert_eq!(space.collect_garbage(), 0);
+        assert_eq!(space.try_write(vec![1u8; 1000]), Err(SpaceError::OverQuota(10)));
+    }
+
+    #[test]
+    fn eviction() {
+        let space = Arc::new(TreeObjectSpace::new());
+        space.set_policy::<TestStruct>(OrderingPolicy::Fifo);
+        space.set_capacity::<TestStruct>(3, Overflow::EvictOldest);
+        space.set_eviction::<TestStruct>(Eviction::LeastRecentlyRead);
+        for i in 0..3 {
+            space.write(TestStruct {
+                count: i,
+                name: format!("person {}", i),
+            });
+        }
+        // reading the oldest struct keeps it, and the one read the longest ago is evicted instead
+        assert_eq!(space.try_read::<TestStruct>().map(|s| s.count), Some(0));
+        space.try_read_by_value::<TestStruct>("count", &1i64);
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("person 3"),
+        });
+        assert_eq!(space.read_all::<TestStruct>().map(|s| s.count).collect::<Vec<_>>(), vec![0, 1, 3]);
+
+        // a fork evicts as the space does, and a struct taken is not evicted
+        let fork = space.fork();
+        space.take_by_value::<TestStruct>("count", &0i64);
+        space.write(TestStruct {
+            count: 4,
+            name: String::from("person 4"),
+        });
+        space.write(TestStruct {
+            count: 5,
+            name: String::from("person 5"),
+        });
+        assert_eq!(space.read_all::<TestStruct>().map(|s| s.count).collect::<Vec<_>>(), vec![3, 4, 5]);
+        fork.write(TestStruct {
+            count: 4,
+            name: String::from("person 4"),
+        });
+        assert_eq!(fork.read_all::<TestStruct>().map(|s| s.count).collect::<Vec<_>>(), vec![1, 3, 4]);
+
+        // old structs are removed when the type is accessed, without a capacity
+        space.set_eviction::<i64>(Eviction::OlderThan(Duration::from_millis(30)));
+        space.write::<i64>(3);
+        thread::sleep(Duration::from_millis(40));
+        space.write::<i64>(5);
+        assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![5]);
+        let metrics = space.metrics();
+        let ints = metrics.iter().find(|m| m.type_name == "i64").unwrap();
+        assert_eq!((ints.evicted, ints.expired), (1, 0));
+
+        // and by the sweeper, which ends with the space
+        let sweeper = space.sweep_every(Duration::from_millis(5));
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(space.metrics().iter().find(|m| m.type_name == "i64").unwrap().size, 0);
+        space.set_eviction::<i64>(Eviction::LeastRecentlyWritten);
+        space.write::<i64>(7);
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(space.try_read::<i64>(), Some(7));
+        drop(space);
+        sweeper.thread.join().unwrap();
+
+        // an empty type keeps its eviction policy through garbage collection
+        let space = TreeObjectSpace::new();
+        space.set_eviction::<i64>(Eviction::OlderThan(Duration::from_millis(30)));
+        assert_eq!(space.collect_garbage(), 0);
+        space.write::<i64>(3);
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(space.try_read::<i64>(), None);
+    }
+
+    #[test]
+    fn write_blocking() {
+        let space = Arc::new(TreeObjectSpace::new());
+        space.set_capacity::<i64>(1, Overflow::EvictOldest);
+        space.write::<i64>(1);
+        let writer = {
+            let space = space.clone();
+            thread::spawn(move || space.write_blocking::<i64>(2))
+        };
+        thread::sleep(Duration::from_millis(50));
+        // the writer waits instead of evicting
+        assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(space.take::<i64>(), 1);
+        writer.join().unwrap();
+        assert_eq!(space.take::<i64>(), 2);
+
+        // without a capacity, the struct is written at once
+        space.write_blocking::<bool>(true);
+        assert_eq!(space.try_take::<bool>(), Some(true));
+    }
+
+    #[test]
+    fn select() {
+        let space = Arc::new(TreeObjectSpace::new());
+        let worker = {
+            let space = space.clone();
+            thread::spawn(move || {
+                (0..3)
+                    .map(|_| {
+                        space
+                            .select()
+                            .arm::<i64, _>(|space| space.try_take_by_value::<i64>("", &2i64).map(|n| n.to_string()))
+                            .take::<String, _>(|name| name)
+                            .wait()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+        thread::sleep(Duration::from_millis(20));
+        space.write::<i64>(1);
+        space.write(String::from("a"));
+        thread::sleep(Duration::from_millis(20));
+        space.write::<i64>(2);
+        space.write(String::from("b"));
+        assert_eq!(worker.join().unwrap(), vec!["a", "2", "b"]);
+        assert_eq!(space.try_take::<i64>(), Some(1));
+
+        // earlier arms take priority
+        space.write::<i64>(3);
+        space.write::<bool>(true);
+        let mut select = space.select().read::<bool, _>(|_| 0).read::<i64, _>(|n| n);
+        assert_eq!(select.try_now(), Some(0));
+        let started = Instant::now();
+        let none = space.select().take::<String, _>(|name| name).wait_timeout(Duration::from_millis(20));
+        assert_eq!(none, None);
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn select_by_predicates() {
+        #[derive(Debug, PartialEq)]
+        enum Event {
+            Ready(i64),
+            Late(i64),
+            Stop,
+        }
+
+        let space = Arc::new(TreeObjectSpace::new());
+        let machine = {
+            let space = space.clone();
+            thread::spawn(move || {
+                let mut events = Vec::new();
+                loop {
+                    let event = space
+                        .select()
+                        .take_by_value::<String, _, _>("", String::from("stop"), |_| Event::Stop)
+                        .take_by_range::<i64, _, _, _>("", 0i64..10, Event::Ready)
+                        .take_by_range::<i64, _, _, _>("", 100i64.., Event::Late)
+                        .wait();
+                    if event == Event::Stop {
+                        return events;
+                    }
+                    events.push(event);
+                }
+            })
+        };
+        for n in &[50i64, 3, 200] {
+            space.write::<i64>(*n);
+            thread::sleep(Duration::from_millis(10));
+        }
+        space.write(String::from("pause"));
+        space.write(String::from("stop"));
+        assert_eq!(machine.join().unwrap(), vec![Event::Ready(3), Event::Late(200)]);
+        assert_eq!(space.try_take::<i64>(), Some(50));
+        assert_eq!(space.try_take::<String>(), Some(String::from("pause")));
+
+        space.write::<i64>(7);
+        let found = space
+            .select()
+            .read_by_value::<i64, _, _>("", 7i64, |n| n)
+            .read_by_range::<i64, _, _, _>("", ..0i64, |n| -n)
+            .wait_timeout(Duration::from_millis(10));
+        assert_eq!(found, Some(7));
+    }
+
+    #[test]
+    fn close() {
+        let space = Arc::new(TreeObjectSpace::new());
+        space.write::<i64>(3);
+        let consumer = {
+            let space = space.clone();
+            thread::spawn(move || {
+                let taken = space.take_checked::<i64>();
+                (taken, space.take_checked::<i64>())
+            })
+        };
+        let waiting = {
+            let space = space.clone();
+            thread::spawn(move || space.take_timeout::<bool>(Duration::from_secs(60)))
+        };
+        thread::sleep(Duration::from_millis(50));
+        space.close();
+        // the structs left are still found
+        assert_eq!(consumer.join().unwrap(), (Ok(3), Err(SpaceError::Closed)));
+        assert_eq!(waiting.join().unwrap(), None);
+        assert!(space.is_closed());
+
+        assert_eq!(space.try_write::<i64>(5), Err(SpaceError::Closed));
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| space.write::<i64>(5))).is_err());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| space.take::<i64>())).is_err());
+        assert_eq!(space.read_checked::<String>(), Err(SpaceError::Closed));
+        assert_eq!(space.select().take::<i64, _>(|n| n).wait_timeout(Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn checked_tier() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Job {
+            id: i64,
+            owner: String,
+        }
+
+        let space = TreeObjectSpace::new();
+        space.register_type::<Job>("Job");
+        let job = Job { id: 1, owner: String::from("a") };
+        assert!(space.write_checked(job).is_ok());
+        assert_eq!(space.take_checked::<Job>().map(|job| job.id), Ok(1));
+        let mut legacy = serde_json::Map::new();
+        legacy.insert(String::from("id"), serde_json::Value::from(2));
+        space.write_json("Job", serde_json::Value::Object(legacy));
+
+        // the struct found could not be read, instead of the space holding no struct
+        assert!(space.try_read::<Job>().is_none());
+        match space.read_checked::<Job>() {
+            Err(SpaceError::Serialization(ref error)) => assert!(error.contains("owner")),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(space.take_checked::<Job>().is_err());
+        assert_eq!(space.dead_letters::<Job>().len(), 1);
+        assert_eq!(space.try_take_checked::<Job>(), Ok(None));
+
+        space.close();
+        assert_eq!(space.write_checked::<i64>(3), Err(SpaceError::Closed));
+    }
+
+    #[test]
+    fn codecs() {
+        #[allow(unused_mut)]
+        let mut codecs = vec![Codec::Json];
+        #[cfg(feature = "bincode")]
+        codecs.push(Codec::Bincode);
+        #[cfg(feature = "msgpack")]
+        codecs.push(Codec::MessagePack);
+
+        for codec in codecs {
+            let space = TreeObjectSpace::builder().codec(codec).build();
+            space.set_dedup::<TestStruct>(true);
+            for (count, name) in [(3, "a"), (5, "b"), (3, "a")].iter().cloned() {
+                space.write(TestStruct {
+                    count,
+                    name: String::from(name),
+                });
+            }
+            assert_eq!(space.read_all::<TestStruct>().count(), 2);
+            assert_eq!(
+                space.try_read_by_value::<TestStruct>("name", &String::from("b")).map(|s| s.count),
+                Some(5)
+            );
+            assert!(space.update_by_value::<TestStruct, _>("count", &5i64, |mut s| {
+                s.count = 7;
+                s
+            }));
+            assert_eq!(space.try_take_by_range::<TestStruct, _>("count", 6i64..).map(|s| s.count), Some(7));
+            assert_eq!(space.try_take::<TestStruct>().map(|s| s.name), Some(String::from("a")));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "msgpack")]
+    fn msgpack_json() {
+        let path = ::std::env::temp_dir().join("object_space_test_msgpack.json");
+        let space = TreeObjectSpace::builder().codec(Codec::MessagePack).build();
+        space.register_type::<TestStruct>("TestStruct");
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("a"),
+        });
+        space.write_json("TestStruct", serde_json::json!({ "count": 5, "name": "b" }));
+        assert_eq!(space.read_all_json("TestStruct")[0]["count"], serde_json::Value::from(3));
+
+        // checkpoints are JSON whatever the codec
+        space.checkpoint::<TestStruct>(&path).unwrap();
+        let other = TreeObjectSpace::new();
+        assert_eq!(other.restore::<TestStruct>(&path).unwrap(), 2);
+        assert_eq!(other.try_read_by_value::<TestStruct>("count", &5i64).unwrap().name, "b");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "bincode")]
+    fn bincode_json() {
+        let path = ::std::env::temp_dir().join("object_space_test_bincode.json");
+        let space = TreeObjectSpace::builder().codec(Codec::Bincode).build();
+        space.write::<i64>(3);
+        // bincode payloads do not describe themselves, so they could not be converted to JSON
+        let error = space.checkpoint::<i64>(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity of 1 structs")]
+    fn capacity_reject() {
+        let space = TreeObjectSpace::new();
+        space.set_capacity::<i64>(1, Overflow::Reject);
+        space.write::<i64>(3);
+        space.write::<i64>(5);
+    }
+
+    #[test]
+    #[should_panic(expected = "No dead letter")]
+    fn requeue_missing_dead_letter() {
+        let space = TreeObjectSpace::new();
+        let _ = space.requeue_dead_letter::<i64>(0, serde_json::Value::from(1));
+    }
+
+    #[test]
+    fn checkpoint_delta() {
+        let dir = ::std::env::temp_dir();
+        let full_path = dir.join("object_space_test_full.json");
+        let delta_paths: Vec<_> = (0..2)
+            .map(|i| dir.join(format!("object_space_test_delta_{}.json", i)))
+            .collect();
+
+        let space = TreeObjectSpace::new();
+        assert!(space.checkpoint_delta::<TestStruct>(&delta_paths[0]).is_err());
+        for i in 0..3 {
+            space.write(TestStruct {
+                count: i,
+                name: String::from("Tuan"),
+            });
+        }
+        space.checkpoint::<TestStruct>(&full_path).unwrap();
+
+        space.try_take_by_value::<TestStruct>("count", &0i64);
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Duane"),
+        });
+        space.checkpoint_delta::<TestStruct>(&delta_paths[0]).unwrap();
+
+        // structs added and removed between two checkpoints are not saved
+        space.write(TestStruct {
+            count: 4,
+            name: String::from("Tom"),
+        });
+        space.try_take_by_value::<TestStruct>("count", &4i64);
+        space.try_take_by_value::<TestStruct>("count", &1i64);
+        space.checkpoint_delta::<TestStruct>(&delta_paths[1]).unwrap();
+
+        let other = TreeObjectSpace::new();
+        assert_eq!(other.restore::<TestStruct>(&full_path).unwrap(), 3);
+        assert_eq!(other.restore_delta::<TestStruct>(&delta_paths[0]).unwrap(), 1);
+        assert_eq!(other.restore_delta::<TestStruct>(&delta_paths[1]).unwrap(), 0);
+        let mut counts: Vec<i32> = other.read_all::<TestStruct>().map(|s| s.count).collect();
+        counts.sort();
+        assert_eq!(counts, vec![2, 3]);
+
+        fs::remove_file(full_path).unwrap();
+        for path in delta_paths {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn journal() {
+        let path = ::std::env::temp_dir().join("object_space_test_journal.jsonl");
+        let _ = fs::remove_file(&path);
+        {
+            let space = TreeObjectSpace::with_journal(&path).unwrap();
+            for i in 0..3 {
+                space.write(TestStruct {
+                    count: i,
+                    name: String::from("Tuan"),
+                });
+            }
+            space.write(CompoundStruct {
+                person: TestStruct {
+                    count: 4,
+                    name: String::from("Duane"),
+                },
+                gpa: 3.5,
+            });
+            space.take_by_value::<TestStruct>("count", &1i64);
+            assert!(space.drop_type::<CompoundStruct>());
+        }
+        // a line half-written by a crash is discarded
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"type\":")
+            .unwrap();
+
+        let space = TreeObjectSpace::with_journal(&path).unwrap();
+        assert_eq!(space.try_read::<CompoundStruct>(), None);
+        let mut counts: Vec<i32> = space.read_all::<TestStruct>().map(|s| s.count).collect();
+        counts.sort();
+        assert_eq!(counts, vec![0, 2]);
+        space.take_all::<TestStruct>().count();
+
+        let space = TreeObjectSpace::with_journal(&path).unwrap();
+        assert_eq!(space.try_read::<TestStruct>(), None);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn journal_checkout() {
+        let path = ::std::env::temp_dir().join("object_space_test_journal_checkout.jsonl");
+        let _ = fs::remove_file(&path);
+        {
+            let space = TreeObjectSpace::with_journal(&path).unwrap();
+            for i in 0..3 {
+                space.write::<i64>(i);
+            }
+            // the process crashes while the struct is checked out
+            ::std::mem::forget(space.checkout::<i64>(Duration::from_secs(60)));
+            assert!(space.checkout::<i64>(Duration::from_secs(60)).commit());
+            space.checkout::<i64>(Duration::from_secs(60)).release();
+        }
+
+        let kept = {
+            let space = TreeObjectSpace::with_journal(&path).unwrap();
+            let mut values: Vec<i64> = space.read_all::<i64>().collect();
+            values.sort();
+            assert_eq!(values, vec![0, 2]);
+            // taking every struct leaves the one checked out in the journal
+            let checkout = space.checkout::<i64>(Duration::from_secs(60));
+            let kept = *checkout;
+            ::std::mem::forget(checkout);
+            assert_eq!(space.take_all::<i64>().count(), 1);
+            kept
+        };
+
+        let space = TreeObjectSpace::with_journal(&path).unwrap();
+        assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![kept]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn default_ttl() {
+        let space = TreeObjectSpace::builder()
+            .default_ttl(Duration::from_millis(20))
+            .build();
+        space.ttl::<String>(None);
+        space.write::<i64>(3);
+        space.write(String::from("Hello World"));
+        assert_eq!(space.try_read::<i64>(), Some(3));
+
+        ::std::thread::sleep(Duration::from_millis(40));
+        space.write::<i64>(5);
+        assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![5]);
+        assert_eq!(space.try_read::<String>(), Some(String::from("Hello World")));
+
+        ::std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(space.try_take::<i64>(), None);
+        assert_eq!(space.try_read_by_value::<i64>("", &5i64), None);
+    }
+
+    #[test]
+    fn subscribe() {
+        let space = Arc::new(TreeObjectSpace::new());
+        let all = space.subscribe::<TestStruct>();
+        let keyed = space.subscribe_by_value::<TestStruct, _>("count", &3);
+
+        let producer_space = space.clone();
+        let producer = thread::spawn(move || {
+            for i in 0..5 {
+                producer_space.write(TestStruct {
+                    count: i,
+                    name: String::from("Tuan"),
+                });
+            }
+        });
+        assert_eq!(keyed.recv().unwrap().count, 3);
+        producer.join().unwrap();
+        assert_eq!(all.try_iter().map(|s| s.count).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+        assert!(keyed.try_recv().is_err());
+
+        // subscriptions whose receiver is dropped are dropped on the next matching write
+        drop(all);
+        drop(keyed);
+        space.take_all::<TestStruct>().count();
+        assert_eq!(space.collect_garbage(), 0);
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Duane"),
+        });
+        space.take_all::<TestStruct>().count();
+        assert_eq!(space.collect_garbage(), 1);
+    }
+
+    #[test]
+    fn write_with_ttl() {
+        let space = TreeObjectSpace::builder()
+            .default_ttl(Duration::from_millis(20))
+            .build();
+        space.write_with_ttl::<i64>(3, Duration::from_secs(60));
+        space.write_with_ttl::<i64>(5, Duration::from_millis(1));
+        space.write::<i64>(7);
+        space.write_with_ttl(String::from("Hello World"), Duration::from_millis(1));
+
+        ::std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(space.try_read_by_range::<i64, _>("", 4i64..), None);
+        assert_eq!(space.try_read::<String>(), None);
+    }
+
+    #[test]
+    fn update_by_value() {
+        let space = TreeObjectSpace::new();
+        space.write_with_ttl(
+            TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            Duration::from_millis(20),
+        );
+        space.write(TestStruct {
+            count: 5,
+            name: String::from("Duane"),
+        });
+
+        assert!(space.update_by_value::<TestStruct, _>("count", &3i64, |obj| TestStruct {
+            count: obj.count + 1,
+            ..obj
+        }));
+        assert!(!space.update_by_value::<TestStruct, _>("count", &3i64, |obj| obj));
+        assert_eq!(
+            space.try_read_by_value::<TestStruct>("count", &4i64),
+            Some(TestStruct {
+                count: 4,
+                name: String::from("Tuan"),
+            })
+        );
+
+        // the updated struct keeps its lease
+        ::std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(space.try_read_by_value::<TestStruct>("count", &4i64), None);
+        assert_eq!(space.read_all::<TestStruct>().count(), 1);
+
+        // a result equal to the struct is kept, though it is deduplicated with the struct
+        space.set_dedup::<i64>(true);
+        space.write::<i64>(1);
+        assert!(space.update_by_value::<i64, _>("", &1i64, |i| i));
+        assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![1]);
+
+        // a result exceeding the quota of its type leaves the struct in place
+        space.write(String::from("a"));
+        space.set_memory_quota::<String>(space.memory_usage::<String>() + 100, Overflow::Reject);
+        assert!(!space.update_by_value::<String, _>("", &String::from("a"), |_| "b".repeat(1000)));
+        assert_eq!(space.read_all::<String>().collect::<Vec<_>>(), vec![String::from("a")]);
+        assert!(space.update_by_value::<String, _>("", &String::from("a"), |_| String::from("b")));
+        assert_eq!(space.read_all::<String>().collect::<Vec<_>>(), vec![String::from("b")]);
+    }
+
+    #[test]
+    fn wide_integer_keys() {
+        let space = TreeObjectSpace::new();
+        space.write::<u64>(3);
+        space.write::<u64>(u64::MAX);
+        space.write::<i128>(-(1 << 100));
+        space.write::<i128>(1 << 100);
+
+        assert_eq!(
+            space.try_read_by_value::<u64>("", &u64::MAX),
+            Some(u64::MAX)
+        );
+        assert_eq!(space.try_read_by_value::<u64>("", &3i64), Some(3));
+        assert_eq!(
+            space.read_all_by_range::<u64, _>("", 1u64 << 63..).collect::<Vec<_>>(),
+            vec![u64::MAX]
+        );
+        assert_eq!(
+            space.try_take_by_range::<i128, _>("", ..0i128),
+            Some(-(1 << 100))
+        );
+        assert_eq!(
+            space.try_take_by_value::<i128>("", &(1i128 << 100)),
+            Some(1 << 100)
+        );
+        assert_eq!(space.try_read::<i128>(), None);
+    }
+
+    #[test]
+    fn array_elements() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Tagged {
+            tags: Vec<String>,
+            scores: Vec<i64>,
+        }
+
+        let space = TreeObjectSpace::new();
+        space.write(Tagged {
+            tags: vec![String::from("urgent"), String::from("home")],
+            scores: vec![3, 5, 7],
+        });
+        space.write(Tagged {
+            tags: vec![String::from("work")],
+            scores: vec![],
+        });
+
+        assert_eq!(
+            space
+                .try_read_by_value::<Tagged>("tags[]", &String::from("home"))
+                .map(|obj| obj.scores),
+            Some(vec![3, 5, 7])
+        );
+        assert_eq!(
+            space.read_all_by_range::<Tagged, _>("scores[]", 4i64..).count(),
+            1
+        );
+        assert!(
+            space
+                .try_take_by_value::<Tagged>("tags[]", &String::from("urgent"))
+                .is_some()
+        );
+        assert_eq!(
+            space.try_read_by_value::<Tagged>("tags[]", &String::from("home")),
+            None
+        );
+
+        space.write(vec![
+            TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            TestStruct {
+                count: 5,
+                name: String::from("Duane"),
+            },
+        ]);
+        assert_eq!(
+            space
+                .try_read_by_value::<Vec<TestStruct>>("[].name", &String::from("Duane"))
+                .map(|people| people.len()),
+            Some(2)
+        );
+        assert_eq!(
+            space.try_take_by_range::<Vec<TestStruct>, _>("[].count", 6i64..),
+            None
+        );
+    }
+
+    #[test]
+    fn null_fields() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Node {
+            id: i64,
+            parent_id: Option<i64>,
+        }
+
+        let space = TreeObjectSpace::new();
+        space.write(Node {
+            id: 1,
+            parent_id: None,
+        });
+        space.write(Node {
+            id: 2,
+            parent_id: Some(1),
+        });
+        space.write(Node {
+            id: 3,
+            parent_id: None,
+        });
+
+        assert_eq!(
+            space.read_all_by_value::<Node>("parent_id", &FieldValue::Null).count(),
+            2
+        );
+        assert_eq!(
+            space.try_read_by_value::<Node>("parent_id", &1i64).map(|node| node.id),
+            Some(2)
+        );
+        assert_eq!(
+            space
+                .read_all_by_query::<Node>(&field("parent_id").eq(FieldValue::Null).and(field("id").gt(1)))
+                .map(|node| node.id)
+                .collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert_eq!(
+            space
+                .try_take_by_value::<Node>("parent_id", &FieldValue::Null)
+                .map(|node| node.id),
+            Some(1)
+        );
+        assert_eq!(
+            space.take_all_by_value::<Node>("parent_id", &FieldValue::Null).count(),
+            1
+        );
+        assert_eq!(space.try_read_by_value::<Node>("parent_id", &FieldValue::Null), None);
+        assert_eq!(
+            space.try_read_by_value::<Node>("parent_id", &FieldValue::Int(1)),
+            Some(Node {
+                id: 2,
+                parent_id: Some(1),
+            })
+        );
+    }
+
+    #[test]
+    fn compound_index() {
+        let space = TreeObjectSpace::new();
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+        space.create_index::<TestStruct>(&["count", "name"]);
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Duane"),
+        });
+        space.write(TestStruct {
+            count: 5,
+            name: String::from("Tuan"),
+        });
+
+        let tuan = [("name", FieldValue::from("Tuan")), ("count", FieldValue::from(3))];
+        assert_eq!(
+            space.try_take_by_match::<TestStruct>(&tuan),
+            Some(TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            })
+        );
+        assert_eq!(space.try_take_by_match::<TestStruct>(&tuan), None);
+
+        // conditions outside of the index are still checked
+        let query = field("count")
+            .eq(3)
+            .and(field("name").eq("Duane"))
+            .and(field("name").eq("Tuan"));
+        assert_eq!(space.try_read_by_query::<TestStruct>(&query), None);
+        assert_eq!(
+            space
+                .read_all_by_query::<TestStruct>(&field("name").eq("Tuan").and(field("count").ge(4)))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn index_fields() {
+        let space = TreeObjectSpace::new();
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.0,
+        });
+        space.index_fields::<CompoundStruct>(&["person.count"]);
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 5,
+                name: String::from("Duane"),
+            },
+            gpa: 4.0,
+        });
+
+        assert_eq!(
+            space.read_all_by_range::<CompoundStruct, _>("person.count", 2i64..).count(),
+            2
+        );
+        assert!(
+            space
+                .try_take_by_value::<CompoundStruct>("person.count", &3i64)
+                .is_some()
+        );
+        assert_eq!(space.read_all::<CompoundStruct>().count(), 1);
+
+        let space = Arc::new(space);
+        let space_clone = space.clone();
+        let lookup = ::std::thread::spawn(move || {
+            space_clone.try_read_by_value::<CompoundStruct>("person.name", &String::from("Duane"))
+        });
+        assert!(lookup.join().is_err());
+
+        space.index_fields::<CompoundStruct>(&["person.name"]);
+        assert!(
+            space
+                .try_read_by_value::<CompoundStruct>("person.name", &String::from("Duane"))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn collect_garbage() {
+        let space = Arc::new(TreeObjectSpace::new());
+        space.write::<i64>(3);
+        space.hash_index::<String>(&[""]);
+        space.take::<i64>();
+        assert_eq!(space.collect_garbage(), 1);
+        assert_eq!(space.slots.read().len(), 1);
+
+        let space_clone = space.clone();
+        let handle = ::std::thread::spawn(move || space_clone.take::<bool>());
+        ::std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(space.collect_garbage(), 0);
+        assert!(!space.drop_type::<bool>());
+        space.write(true);
+        assert!(handle.join().unwrap());
+
+        assert!(space.drop_type::<String>());
+        assert_eq!(space.collect_garbage(), 1);
+        assert_eq!(space.slots.read().len(), 0);
+    }
+
+    #[test]
+    fn per_type_locking() {
+        let space = Arc::new(TreeObjectSpace::new());
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+
+        // a guard of a type blocks neither the readers of the type nor other types
+        let guard = space.get_object_entry_ref::<TestStruct>().unwrap();
+        let space_clone = space.clone();
+        let handle = thread::spawn(move || {
+            space_clone.write::<i64>(3);
+            (
+                space_clone.take::<i64>(),
+                space_clone.try_read::<TestStruct>().unwrap().count,
+            )
+        });
+        assert_eq!(handle.join().unwrap(), (3, 3));
+        drop(guard);
+
+        // writers and blocking takers are not starved by readers hammering the same type
+        let stop = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let space = space.clone();
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    let mut reads = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        reads += space.read_all::<i64>().count();
+                        space.try_read::<bool>();
+                    }
+                    reads
+                })
+            })
+            .collect();
+        let takers: Vec<_> = (0..4)
+            .map(|_| {
+                let space = space.clone();
+                thread::spawn(move || (0..250).map(|_| space.take::<i64>()).sum::<i64>())
+            })
+            .collect();
+        let writers: Vec<_> = (0..4)
+            .map(|i| {
+                let space = space.clone();
+                thread::spawn(move || {
+                    for j in 0..250 {
+                        space.write::<i64>(i * 250 + j);
+                        space.write(j % 2 == 0);
+                    }
+                })
+            })
+            .collect();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let taken: i64 = takers.into_iter().map(|taker| taker.join().unwrap()).sum();
+        stop.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(taken, (0..1000).sum::<i64>());
+        assert_eq!(space.try_read::<i64>(), None);
+        assert_eq!(space.take_all::<bool>().count(), 1000);
+    }
+
+    #[test]
+    fn read_arc() {
+        let space = TreeObjectSpace::new();
+        assert!(space.read_all_arc::<TestStruct>().is_empty());
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+        space.write(TestStruct {
+            count: 5,
+            name: String::from("Duane"),
+        });
+
+        let first = space.read_all_arc::<TestStruct>();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[1].name, String::from("Duane"));
+
+        space.try_take_by_value::<TestStruct>("count", &3i64);
+        let second = space.read_all_arc::<TestStruct>();
+        assert_eq!(second.len(), 1);
+        assert!(Arc::ptr_eq(&first[1], &second[0]));
+        assert!(Arc::ptr_eq(&second[0], &space.read_arc::<TestStruct>()));
+    }
+
+    #[test]
+    fn eval() {
+        let space = Arc::new(TreeObjectSpace::new());
+        let handles: Vec<_> = (0..4)
+            .map(|i| space.eval(move || TestStruct {
+                count: i,
+                name: i.to_string(),
+            }))
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut counts: Vec<i32> = space.take_all::<TestStruct>().map(|s| s.count).collect();
+        counts.sort();
+        assert_eq!(counts, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn pipe() {
+        let space = Arc::new(TreeObjectSpace::new());
+        let pipe = space.pipe::<i32, TestStruct, _>(3, |count| TestStruct {
+            count,
+            name: count.to_string(),
+        });
+        for i in 0..10 {
+            space.write(i);
+        }
+
+        let mut counts: Vec<i32> = (0..10).map(|_| space.take::<TestStruct>().count).collect();
+        counts.sort();
+        assert_eq!(counts, (0..10).collect::<Vec<_>>());
+
+        pipe.stop();
+        space.write(10);
+        assert_eq!(space.try_read::<i32>(), Some(10));
+        assert_eq!(space.try_read::<TestStruct>(), None);
+    }
+
+    #[test]
+    fn starvation_watchdog() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let handler_reports = reports.clone();
+        let space = Arc::new(
+            TreeObjectSpace::builder()
+                .starvation_watchdog(Duration::from_millis(20), move |call| {
+                    handler_reports.lock().unwrap().push(call.clone())
+                })
+                .build(),
+        );
+        space.write(TestStruct {
+            count: 2,
+            name: String::from("Tuan"),
+        });
+
+        let consumer_space = space.clone();
+        let consumer =
+            thread::spawn(move || consumer_space.take_by_value::<TestStruct>("count", &3i64));
+        while reports.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Duane"),
+        });
+        assert_eq!(consumer.join().unwrap().name, String::from("Duane"));
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].operation, "take_by_value");
+        assert!(reports[0].type_name.ends_with("TestStruct"));
+        assert_eq!(reports[0].field, Some(String::from("count")));
+        assert_eq!(reports[0].predicate, Some(String::from("== 3")));
+        assert_eq!(reports[0].count, 1);
+        assert!(reports[0].waited >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn type_registry() {
+        let space = TreeObjectSpace::new();
+        space.write::<i64>(3);
+        space.register_type::<TestStruct>("tests::TestStruct");
+        assert_eq!(space.stable_name::<TestStruct>(), "tests::TestStruct");
+        assert_eq!(space.stable_name::<i64>(), "i64");
+        assert!(space.write_json("tests::TestStruct", serde_json::Value::Null).is_none());
+
+        space.write(TestStruct {
+            count: 2,
+            name: String::from("Tuan"),
+        });
+        assert_eq!(space.type_names(), vec!["i64", "tests::TestStruct"]);
+        let mut obj = serde_json::to_value(TestStruct {
+            count: 3,
+            name: String::from("Duane"),
+        }).unwrap();
+        assert!(space.write_json("tests::TestStruct", obj.clone()).is_some());
+        assert!(space.write_json("i64", serde_json::Value::from(5)).is_some());
+        assert_eq!(space.try_take_by_value::<TestStruct>("count", &3i64).unwrap().name, "Duane");
+        assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![3, 5]);
+        assert_eq!(space.metrics()[1].type_name, "tests::TestStruct");
+
+        // renaming a type moves it to its new name
+        space.register_type::<TestStruct>("TestStruct");
+        assert!(space.write_json("tests::TestStruct", obj.clone()).is_none());
+        obj["count"] = serde_json::Value::from(4);
+        assert!(space.write_json("TestStruct", obj).is_some());
+        assert_eq!(space.type_names(), vec!["TestStruct", "i64"]);
+        let counts: Vec<_> = space
+            .read_all_json("TestStruct")
+            .iter()
+            .map(|obj| obj["count"].clone())
+            .collect();
+        assert_eq!(counts, vec![serde_json::Value::from(2), serde_json::Value::from(4)]);
+        assert!(space.read_all_json("tests::TestStruct").is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "already registered for another type")]
+    fn type_registry_conflict() {
+        let space = TreeObjectSpace::new();
+        space.register_type::<TestStruct>("task");
+        space.register_type::<i64>("task");
+    }
+
+    #[test]
+    fn dump_waiters() {
+        assert!(TreeObjectSpace::new().dump_waiters().is_empty());
+
+        let space = Arc::new(TreeObjectSpace::builder().diagnostics().build());
+        space.write(TestStruct {
+            count: 2,
+            name: String::from("Tuan"),
+        });
+        let taker_space = space.clone();
+        let taker = thread::spawn(move || taker_space.take_by_value::<TestStruct>("count", &3i64));
+        while space.dump_waiters().is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(Duration::from_millis(10));
+        let reader_space = space.clone();
+        let reader = thread::spawn(move || reader_space.read::<String>());
+        while space.dump_waiters().len() < 2 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let waiters = space.dump_waiters();
+        assert_eq!(waiters[0].operation, "take_by_value");
+        assert!(waiters[0].type_name.ends_with("TestStruct"));
+        assert_eq!(waiters[0].field, Some(String::from("count")));
+        assert_eq!(waiters[0].predicate, Some(String::from("== 3")));
+        assert_eq!(waiters[0].count, 1);
+        assert!(waiters[0].waited >= Duration::from_millis(10));
+        assert_eq!(waiters[1].operation, "read");
+        assert_eq!(waiters[1].count, 0);
+        assert!(waiters[1].waited <= waiters[0].waited);
+
+        space.write(String::from("done"));
+        reader.join().unwrap();
+        assert_eq!(space.dump_waiters().len(), 1);
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Duane"),
+        });
+        taker.join().unwrap();
+        assert!(space.dump_waiters().is_empty());
+    }
+
+    #[test]
+    fn lock_hold_times() {
+        let space = TreeObjectSpace::new();
+        assert!(space.lock_hold_times::<TestStruct>().is_empty());
+        space.hash_index::<TestStruct>(&["name"]);
+        for i in 0..3 {
+            space.write(TestStruct {
+                count: i,
+                name: i.to_string(),
+            });
+        }
+        space.try_take_by_value::<TestStruct>("count", &1i64);
+        space.try_take_by_value::<TestStruct>("count", &5i64);
+
+        let hold_times = space.lock_hold_times::<TestStruct>();
+        assert_eq!(
+            hold_times.keys().cloned().collect::<Vec<_>>(),
+            vec!["hash_index", "try_take_by_value", "write"]
+        );
+        assert_eq!(hold_times["write"].count, 3);
+        assert_eq!(hold_times["try_take_by_value"].count, 2);
+        let write = hold_times["write"];
+        assert!(write.max <= write.total);
+        assert!(write.mean() <= write.max);
+    }
+
+    #[test]
+    fn metrics() {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use server::metrics::MetricsServer;
+
+        let space = Arc::new(TreeObjectSpace::new());
+        assert!(space.metrics().is_empty());
+        for i in 0..3 {
+            space.write(TestStruct {
+                count: i,
+                name: i.to_string(),
+            });
+        }
+        space.write_with_ttl::<i64>(3, Duration::from_millis(1));
+        space.try_read::<TestStruct>();
+        space.read_all::<TestStruct>().count();
+        space.try_take::<TestStruct>();
+
+        let waiting = space.clone();
+        let waiter = thread::spawn(move || waiting.take::<String>());
+        while space.metrics().iter().all(|metrics| metrics.blocked == 0) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(Duration::from_millis(5));
+
+        let metrics = space.metrics();
+        assert_eq!(
+            metrics.iter().map(|metrics| metrics.type_name).collect::<Vec<_>>(),
+            vec!["alloc::string::String", "i64", type_name::<TestStruct>()]
+        );
+        assert_eq!((metrics[0].blocked, metrics[0].size), (1, 0));
+        assert_eq!((metrics[1].writes, metrics[1].expired, metrics[1].size), (1, 1, 0));
+        let structs = &metrics[2];
+        assert_eq!((structs.writes, structs.reads, structs.takes), (3, 2, 1));
+        assert_eq!((structs.blocked, structs.size), (0, 2));
+        assert_eq!(structs.latencies["write"].count, 3);
+        assert_eq!(structs.latencies["try_take"].count, 1);
+
+        space.write(String::from("done"));
+        waiter.join().unwrap();
+        let metrics = space.metrics();
+        assert_eq!(metrics[0].blocked, 0);
+        assert_eq!(metrics[0].waits["take"].count, 1);
+
+        let server = MetricsServer::bind(space.clone(), "127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        server.spawn();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("# TYPE object_space_writes_total counter"));
+        assert!(response.contains("object_space_takes_total{type=\"alloc::string::String\"} 1"));
+        assert!(response.contains("object_space_wait_seconds_count{type=\"alloc::string::String\",operation=\"take\"} 1"));
+    }
+
+    #[test]
+    fn list_types() {
+        let space = TreeObjectSpace::new();
+        assert!(space.list_types().is_empty());
+        space.index_fields::<CompoundStruct>(&["person.name", "gpa"]);
+        space.hash_index::<CompoundStruct>(&["person.name"]);
+        for i in 0..10 {
+            space.write(CompoundStruct {
+                person: TestStruct {
+                    count: i,
+                    name: format!("person {}", i),
+                },
+                gpa: 3.0,
+            });
+        }
+        space.write(String::from("hello"));
+
+        let types = space.list_types();
+        assert_eq!(types.len(), 2);
+        let info = types.iter().find(|info| info.type_name.ends_with("CompoundStruct")).unwrap();
+        assert_eq!(info.count, 10);
+        assert_eq!(
+            info.indexed_fields.iter().map(|(name, &kind)| (name.as_str(), kind)).collect::<Vec<_>>(),
+            vec![("gpa", FieldKind::Float), ("person.name", FieldKind::String)]
+        );
+        assert_eq!(info.hashed_fields.iter().collect::<Vec<_>>(), vec!["person.name"]);
+        let memory = info.memory;
+        assert!(memory > 10 * "person 0".len());
+
+        for _ in 0..5 {
+            space.take::<CompoundStruct>();
+        }
+        let types = space.list_types();
+        let info = types.iter().find(|info| info.type_name.ends_with("CompoundStruct")).unwrap();
+        assert_eq!(info.count, 5);
+        assert!(info.memory < memory);
+        let info = types.iter().find(|info| info.type_name.ends_with("String")).unwrap();
+        assert_eq!(info.indexed_fields[""], FieldKind::String);
+    }
+
+    #[test]
+    fn compact() {
+        let space = TreeObjectSpace::new();
+        space.create_index::<TestStruct>(&["count", "name"]);
+        for i in 0..1000 {
+            space.write(TestStruct {
+                count: i,
+                name: format!("person {}", i % 10),
+            });
+        }
+        space.take_all_by_range::<TestStruct, _>("count", 10i64..).count();
+
+        // the buckets of the values taken are gone
+        let plan = space.explain_query::<TestStruct, _>("count", 0i64..);
+        assert_eq!((plan.buckets, plan.candidates, plan.total), (10, 10, 10));
+        assert_eq!(space.try_read_nearest::<TestStruct>("count", &500i64).map(|s| s.count), Some(9));
+
+        assert!(space.compact() > 0);
+        assert_eq!(space.compact(), 0);
+        assert_eq!(
+            space.try_read_by_value::<TestStruct>("name", &String::from("person 3")),
+            Some(TestStruct {
+                count: 3,
+                name: String::from("person 3"),
+            })
+        );
+        assert_eq!(space.read_all::<TestStruct>().count(), 10);
+    }
+
+    #[test]
+    fn explain_query() {
+        #[derive(Serialize, Deserialize)]
+        struct Post {
+            id: i64,
+            author: String,
+            tags: Vec<String>,
+            score: f64,
+        }
+
+        let space = TreeObjectSpace::new();
+        let plan = space.explain_query::<Post, _>("id", 0i64..);
+        assert_eq!((plan.indexed, plan.kind, plan.total), (true, None, 0));
+
+        space.index_fields::<Post>(&["id", "author", "tags[]"]);
+        space.hash_index::<Post>(&["author"]);
+        space.set_collation::<Post>("tags[]", Collation::CaseInsensitive);
+        for id in 0..6 {
+            space.write(Post {
+                id,
+                author: format!("author {}", id % 2),
+                tags: vec![String::from("rust"), format!("tag {}", id)],
+                score: 1.0,
+            });
+        }
+
+        let plan = space.explain_query::<Post, _>("id", 2i64..4);
+        assert_eq!(
+            plan,
+            QueryPlan {
+                field: String::from("id"),
+                indexed: true,
+                hashed: false,
+                kind: Some(FieldKind::Int),
+                buckets: 2,
+                candidates: 2,
+                total: 6,
+            }
+        );
+        // the range is normalized by the collation of the field, and a struct counted once per element
+        let plan = space.explain_query::<Post, _>("tags[]", String::from("RUST")..String::from("TAH"));
+        assert_eq!((plan.buckets, plan.candidates), (7, 12));
+
+        let plan = space.explain_query::<Post, _>("author", String::from("a")..);
+        assert!(plan.hashed);
+        assert_eq!((plan.buckets, plan.candidates), (0, 0));
+        let plan = space.explain_query::<Post, _>("score", 0.0..);
+        assert!(!plan.indexed);
+        assert_eq!((plan.kind, plan.candidates), (None, 0));
+
+        // explaining a query is not a lookup
+        assert_eq!(space.metrics()[0].reads, 0);
+    }
+
+    #[test]
+    fn template() {
+        let space = TreeObjectSpace::new();
+        for (i, name) in ["alice", "bob", "carol", "dave"].iter().enumerate() {
+            space.write(CompoundStruct {
+                person: TestStruct {
+                    count: i as i32,
+                    name: name.to_string(),
+                },
+                gpa: 2.0 + i as f64 * 0.5,
+            });
+        }
+        let names = |query: Query| {
+            let mut names: Vec<_> = space
+                .read_all_by_query::<CompoundStruct>(&query)
+                .map(|s| s.person.name)
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(names(template!{ "person.name": "bob", gpa: _ }), vec!["bob"]);
+        assert_eq!(names(template!{ "person.count": 1..=2, }), vec!["bob", "carol"]);
+        assert_eq!(names(template!{ "person.count": ..2, gpa: 2.5.. }), vec!["bob"]);
+        assert_eq!(names(template!{ gpa: ..=3.0, "person.name": ("a".to_owned() + "lice") }), vec!["alice"]);
+        let min = 1;
+        assert_eq!(names(template!{ "person.count": (min + 1).. }), vec!["carol", "dave"]);
+        assert_eq!(template!{ "person.count": _ }, Query::And(Vec::new()));
+
+        let dave = space.take_by_query::<CompoundStruct>(&template!{ "person.count": 3, gpa: 3.5 });
+        assert_eq!(dave.person.name, "dave");
+    }
+
+    #[test]
+    fn typed_fields() {
+        fields! {
+            Compound for CompoundStruct {
+                Count: i64 = "person.count",
+                Name: String = "person.name",
+                Gpa: f64 = "gpa",
+            }
+        }
+
+        let space = TreeObjectSpace::new();
+        for i in 0..4 {
+            space.write(CompoundStruct {
+                person: TestStruct {
+                    count: i,
+                    name: format!("person {}", i),
+                },
+                gpa: f64::from(i),
+            });
+        }
+        assert_eq!(Compound::Name.name(), "person.name");
+
+        assert_eq!(space.lookup(Compound::Count).read_all_by_range(1..3).count(), 2);
+        assert_eq!(
+            space.lookup(Compound::Name).try_read_by_value(&String::from("person 2")).map(|s| s.gpa),
+            Some(2.0)
+        );
+        assert_eq!(space.lookup(Compound::Gpa).take_by_range(2.5..).person.count, 3);
+        assert_eq!(space.lookup(Compound::Count).take_all_by_value(&0).count(), 1);
+        assert!(space.lookup(Compound::Count).try_take_by_range(..=0).is_none());
+        assert_eq!(space.read_all::<CompoundStruct>().count(), 2);
+    }
+
+    #[test]
+    fn dyn_object_space() {
+        let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+        let remote = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+        server.spawn();
+        let local = TreeObjectSpace::new();
+        local.register_type::<TestStruct>("person");
+        assert!(DynObjectSpace::write_json(&local, "person", serde_json::json!({})).is_none());
+        local.write(TestStruct {
+            count: 4,
+            name: String::from("Tuan"),
+        });
+        local.try_take::<TestStruct>();
+
+        let spaces: Vec<Box<DynObjectSpace>> = vec![Box::new(local), Box::new(remote)];
+        for space in spaces {
+            for i in 0..4 {
+                let person = serde_json::json!({ "count": i, "name": format!("person {}", i) });
+                assert!(space.write_json("person", person).is_some());
+            }
+            assert_eq!(space.read_all_json("person").len(), 4);
+            assert_eq!(
+                space.try_read_json_by_query("person", &field("count").eq(2i64)),
+                Some(serde_json::json!({ "count": 2, "name": "person 2" }))
+            );
+            assert_eq!(space.read_all_json_by_query("person", &field("count").lt(2i64)).len(), 2);
+            assert_eq!(space.take_all_json_by_query("person", &field("count").ge(2i64)).len(), 2);
+            assert!(space.try_take_json_by_query("person", &field("count").ge(2i64)).is_none());
+            assert!(space.try_take_json("person").is_some());
+            assert!(space.try_read_json("person").is_some());
+            assert_eq!(space.take_all_json("person").len(), 1);
+            assert!(space.try_read_json("person").is_none());
+            assert!(space.try_read_json("unknown").is_none());
+        }
+    }
+
+    #[test]
+    fn snapshot() {
+        let space = Arc::new(TreeObjectSpace::new());
+        space.set_collation::<TestStruct>("name", Collation::CaseInsensitive);
+        for i in 0..100 {
+            space.write(TestStruct {
+                count: i,
+                name: format!("Person {}", i % 2),
+            });
+        }
+
+        let snapshot = space.snapshot::<TestStruct>();
+        let writer_space = space.clone();
+        let writer = thread::spawn(move || {
+            for i in 100..200 {
+                writer_space.write(TestStruct {
+                    count: i,
+                    name: String::from("person 0"),
+                });
+                writer_space.try_take::<TestStruct>();
+            }
+        });
+        for _ in 0..10 {
+            assert_eq!(snapshot.len(), 100);
+            assert_eq!(snapshot.iter().map(|s| s.count).sum::<i32>(), 4950);
+            assert_eq!(snapshot.count_by_query(&field("name").eq("PERSON 0")), 50);
+        }
+        writer.join().unwrap();
+
+        let query = field("count").lt(10i64).and(field("name").eq("person 1"));
+        assert_eq!(snapshot.read_all_by_query(&query).count(), 5);
+        assert_eq!(snapshot.clone().try_read_by_query(&field("count").eq(99i64)).map(|s| s.count), Some(99));
+        assert_eq!(space.read_all::<TestStruct>().count(), 100);
+        assert!(space.snapshot::<i64>().is_empty());
+    }
+
+    #[test]
+    fn fork() {
+        let space = TreeObjectSpace::new();
+        space.set_policy::<TestStruct>(OrderingPolicy::Fifo);
+        space.set_capacity::<TestStruct>(3, Overflow::EvictOldest);
+        space.index_fields::<TestStruct>(&["count"]);
+        for i in 0..3 {
+            space.write(TestStruct {
+                count: i,
+                name: format!("person {}", i),
+            });
+        }
+        space.write_value("order", serde_json::json!({ "id": 1 }));
+
+        let fork = space.fork();
+        fork.write(TestStruct {
+            count: 3,
+            name: String::from("person 3"),
+        });
+        // the oldest struct of the fork is evicted, and the others are taken in the order they were written
+        assert_eq!(fork.take::<TestStruct>().count, 1);
+        assert_eq!(fork.try_read_by_value::<TestStruct>("count", &3i64).map(|s| s.count), Some(3));
+        assert!(fork.try_read_by_value_checked::<TestStruct>("name", &String::from("person 2")).is_err());
+        assert_eq!(fork.take_all_json("order"), vec![serde_json::json!({ "id": 1 })]);
+
+        assert_eq!(
+            space.read_all::<TestStruct>().map(|s| s.count).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(space.read_all_json("order").len(), 1);
+        assert_eq!(fork.read_all::<TestStruct>().count(), 2);
+    }
+
+    #[test]
+    fn write_value() {
+        let space = TreeObjectSpace::new();
+        space.write(TestStruct {
+            count: 1,
+            name: String::from("Tuan"),
+        });
+        space.register_type::<TestStruct>("person");
+
+        // a document written under the name of a type is a struct of that type
+        space.write_value("person", serde_json::json!({ "count": 2, "name": "Hoang" }));
+        assert_eq!(space.read_all::<TestStruct>().count(), 2);
+
+        for i in 0..4 {
+            space.write_value("order", serde_json::json!({ "id": i, "item": format!("item {}", i) }));
+        }
+        assert!(space.try_read::<serde_json::Value>().is_none());
+        assert_eq!(space.read_all_json("order").len(), 4);
+        assert_eq!(
+            space.try_read_json_by_query("order", &field("item").eq("item 2")),
+            Some(serde_json::json!({ "id": 2, "item": "item 2" }))
+        );
+        assert_eq!(space.take_all_json_by_query("order", &field("id").lt(2i64)).len(), 2);
+        assert_eq!(space.collect_garbage(), 0);
+        assert_eq!(space.take_all_json("order").len(), 2);
+        assert_eq!(space.collect_garbage(), 1);
+        assert!(space.try_take_json("order").is_none());
+
+        // a document whose field holds another kind of value than those written before is rejected
+        let mismatch = |result: Result<ObjectHandle, SpaceError>| match result {
+            Err(SpaceError::Mismatch(_)) => true,
+            _ => false,
+        };
+        space.write_value("point", serde_json::json!({ "x": 1 }));
+        assert!(mismatch(space.try_write_value("point", serde_json::json!({ "x": 1.5 }))));
+        space.write_value("label", serde_json::json!({ "x": "a", "y": true }));
+        assert!(mismatch(space.try_write_value("label", serde_json::json!({ "x": 2, "y": true }))));
+        assert!(mismatch(space.try_write_value("label", serde_json::json!({ "x": "b", "y": [1] }))));
+        let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            space.write_value("label", serde_json::json!({ "x": 2 }))
+        }));
+        assert!(result.is_err());
+        // nothing of the rejected documents was indexed
+        assert_eq!(space.read_all_json("label"), vec![serde_json::json!({ "x": "a", "y": true })]);
+        assert_eq!(space.read_all_json_by_query("label", &field("y").eq(true)).len(), 1);
+        assert!(space.try_write_value("label", serde_json::json!({ "x": "b" })).is_ok());
+        assert_eq!(space.read_all_json("point"), vec![serde_json::json!({ "x": 1 })]);
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn tracing_spans() {
+        use std::fmt::Debug;
+        use std::sync::atomic::AtomicU64;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record as Values};
+        use tracing::{Event, Metadata, Subscriber};
+
+        // records the name and fields of every span and event
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>, Arc<AtomicU64>);
+
+        struct Fields(String);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &Debug) {
+                self.0 += &format!(" {}={:?}", field.name(), value);
+            }
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes) -> Id {
+                let mut fields = Fields(span.metadata().name().to_owned());
+                span.record(&mut fields);
+                self.0.lock().unwrap().push(fields.0);
+                Id::from_u64(self.1.fetch_add(1, Ordering::SeqCst) + 1)
+            }
+
+            fn record(&self, _: &Id, _: &Values) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event) {
+                let mut fields = Fields(String::from("event"));
+                event.record(&mut fields);
+                self.0.lock().unwrap().push(fields.0);
+            }
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder = Recorder::default();
+        let space = TreeObjectSpace::new();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            space.write(TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            });
+            space.try_read::<TestStruct>();
+            let timeout = Duration::from_millis(5);
+            assert_eq!(space.take_by_value_timeout::<TestStruct>("count", &5i64, timeout), None);
+        });
+
+        let records = recorder.0.lock().unwrap();
+        let type_name = type_name::<TestStruct>();
+        for expected in &[
+            format!("operation type_name={:?}", type_name),
+            String::from("operation=\"write\""),
+            String::from("event message=lookup"),
+            format!(
+                "blocking type_name={:?} type_id={:?} operation=\"take_by_value_timeout\" field=\"count\" predicate=\"== 5\"",
+                type_name,
+                TypeId::of::<TestStruct>()
+            ),
+            String::from("event message=waiting for a struct"),
+            String::from("event message=returned blocked=true"),
+        ] {
+            assert!(
+                records.iter().any(|record| record.contains(expected.as_str())),
+                "{} not in {:?}",
+                expected,
+                records
+            );
+        }
+    }
+
+    #[test]
+    fn spin_then_block() {
+        let space = Arc::new(
+            TreeObjectSpace::builder()
+                .wait_strategy(WaitStrategy::SpinThenBlock(Duration::from_millis(20)))
+                .build(),
+        );
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || {
+            let first = consumer_space.take_by_value::<TestStruct>("count", &3i64);
+            // the producer is slower than the spinning phase
+            let second = consumer_space.take_by_value::<TestStruct>("count", &5i64);
+            (first.name, second.name)
+        });
+
+        space.write(TestStruct {
+            count: 1,
+            name: String::from("Tuan"),
+        });
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Duane"),
+        });
+        thread::sleep(Duration::from_millis(50));
+        space.write(TestStruct {
+            count: 5,
+            name: String::from("Tom"),
+        });
+
+        assert_eq!(
+            consumer.join().unwrap(),
+            (String::from("Duane"), String::from("Tom"))
+        );
+    }
+
+    #[test]
+    fn blocking_timeout() {
+        let space = Arc::new(TreeObjectSpace::new());
+        let timeout = Duration::from_millis(20);
+        let started = Instant::now();
+        assert_eq!(space.take_by_value_timeout::<TestStruct>("count", &3i64, timeout), None);
+        assert!(started.elapsed() >= timeout);
+
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || {
+            consumer_space.take_by_range_timeout::<TestStruct, _>(
+                "count",
+                2i64..4i64,
+                Duration::from_secs(10),
+            )
+        });
+        space.write(TestStruct {
+            count: 1,
+            name: String::from("Tuan"),
+        });
+        thread::sleep(Duration::from_millis(20));
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Duane"),
+        });
+
+        assert_eq!(consumer.join().unwrap().unwrap().name, String::from("Duane"));
+        assert_eq!(space.read_timeout::<TestStruct>(timeout).unwrap().count, 1);
+        assert_eq!(space.take_timeout::<TestStruct>(timeout).unwrap().count, 1);
+        assert_eq!(space.take_timeout::<TestStruct>(timeout), None);
+    }
+
+    #[test]
+    fn take_async() {
+        struct CountingWaker(AtomicUsize);
+
+        impl ::std::task::Wake for CountingWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
 
-                    Box::new(val_iter.into_iter().filter_map(|item| from_value(deflatten(item)).ok()))
-                }
+        let space = TreeObjectSpace::new();
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
 
-                fn read_by_range<T, R>(&self, field: &str, range: R) -> T
-                where
-                    for<'de> T: Serialize + Deserialize<'de> + 'static,
-                    R: RangeBounds<$ty> + Clone,
-                {
-                    self.add_entry(TypeId::of::<T>());
-                    let &(ref lock, ref cvar) = &*self.get_lock::<T>().unwrap().clone();
-                    let value;
-                    {
-                        let mut fetched = lock.lock().unwrap();
-                        loop {
-                            let result = match self.get_object_entry_ref::<T>() {
-                                Some(entry) => entry.get_by_range::<_>(field, range.clone()),
-                                _ => None,
-                            };
-                            if let Some(item) = result {
-                                value = item;
-                                break;
-                            }
-                            fetched = cvar.wait(fetched).unwrap();
-                        }
-                    }
-                    from_value(deflatten(value)).unwrap()
-                }
+        let mut future = space.take_async::<TestStruct>();
+        assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+        // polling again does not register the waker twice
+        assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+        assert!(!space.drop_type::<TestStruct>());
 
-                fn try_take_by_range<T, R>(&self, field: &str, range: R) -> Option<T>
-                where
-                    for<'de> T: Serialize + Deserialize<'de> + 'static,
-                    R: RangeBounds<$ty> + Clone,
-                {
-                    let value = match self.get_object_entry_mut::<T>() {
-                        Some(mut entry) => entry.remove_by_range::<_>(field, range),
-                        _ => None,
-                    };
-                    match value {
-                        Some(val) => from_value(deflatten(val)).ok(),
-                        _ => None,
-                    }
-                }
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            Pin::new(&mut future).poll(&mut cx),
+            Poll::Ready(TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            })
+        );
+        assert_eq!(space.try_read::<TestStruct>(), None);
+    }
 
-                fn take_all_by_range<'a, T, R>(
-                    &'a self,
-                    field: &str,
-                    range: R,
-                ) -> Box<Iterator<Item = T> + 'a>
-                where
-                    for<'de> T: Deserialize<'de> + 'static,
-                    R: RangeBounds<$ty> + Clone,
-                {
-                    let val_iter = match self.get_object_entry_mut::<T>() {
-                        Some(mut ent) => ent.remove_all_by_range::<_>(field, range),
-                        None => Vec::new(),
-                    };
+    #[test]
+    fn blocking_backoff() {
+        let space = Arc::new(TreeObjectSpace::new());
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || {
+            consumer_space.take_by_query::<TestStruct>(&field("count").eq(-1))
+        });
 
-                    Box::new(
-                        val_iter
-                            .into_iter()
-                            .filter_map(|item| from_value(deflatten(item)).ok())
-                    )
-                }
+        for i in 0..1000 {
+            space.write(TestStruct {
+                count: i,
+                name: i.to_string(),
+            });
+        }
+        space.write(TestStruct {
+            count: -1,
+            name: String::from("Tuan"),
+        });
+        assert_eq!(consumer.join().unwrap().name, String::from("Tuan"));
 
-                fn take_by_range<T, R>(&self, field: &str, range: R) -> T
-                where
-                    for<'de> T: Serialize + Deserialize<'de> + 'static,
-                    R: RangeBounds<$ty> + Clone,
-                {
-                    self.add_entry(TypeId::of::<T>());
-                    let &(ref lock, ref cvar) = &*self.get_lock::<T>().unwrap().clone();
-                    let value;
-                    {
-                        let mut fetched = lock.lock().unwrap();
-                        loop {
-                            let result = match self.get_object_entry_mut::<T>() {
-                                Some(mut entry) => entry.remove_by_range::<_>(field, range.clone()),
-                                _ => None,
-                            };
-                            if let Some(item) = result {
-                                value = item;
-                                break;
-                            }
-                            fetched = cvar.wait(fetched).unwrap();
-                        }
-                    }
-                    from_value(deflatten(value)).unwrap()
-                }
-            }
-        )*
-    };
-}
+        // the consumer looks up writes in batches instead of once per write
+        let lookups = space.lock_hold_times::<TestStruct>()["take_by_query"].count;
+        assert!(lookups < 200);
+    }
 
-macro_rules! object_key{
-    ($($ty:ty)*) => {
-        $(
-            impl ValueLookupObjectSpace<$ty> for TreeObjectSpace {
-                fn try_read_by_value<T>(&self, field: &str, key: &$ty) -> Option<T>
-                where
-                    for<'de> T: Serialize + Deserialize<'de> + 'static,
-                {
-                    let value = match self.get_object_entry_ref::<T>() {
-                        Some(entry) => entry.get_by_value(field, key),
-                        _ => None,
-                    };
-                    match value {
-                        Some(val) => from_value(deflatten(val)).ok(),
-                        _ => None,
-                    }
-                }
+    #[test]
+    fn wait_for_matching_struct() {
+        let space = Arc::new(TreeObjectSpace::new());
+        let value_space = space.clone();
+        let by_value =
+            thread::spawn(move || value_space.take_by_value::<TestStruct>("count", &-1i64));
+        let range_space = space.clone();
+        let by_range = thread::spawn(move || {
+            range_space.read_by_range::<TestStruct, _>("name", String::from("Z")..)
+        });
+        let timeout = space.take_by_range_timeout::<TestStruct, _>(
+            "count",
+            2000i64..,
+            Duration::from_millis(10),
+        );
+        assert_eq!(timeout, None);
 
-                fn read_all_by_value<'a, T>(&'a self, field: &str, key: &$ty) -> Box<Iterator<Item = T> + 'a>
-                where
-                    for<'de> T: Deserialize<'de> + 'static,
-                {
-                    let val_iter: Vec<_> = match self.get_object_entry_ref::<T>() {
-                        Some(ent) => ent.get_all_by_value(field, key).collect(),
-                        None => Vec::new(),
-                    };
+        for i in 0..1000 {
+            space.write(TestStruct {
+                count: i,
+                name: i.to_string(),
+            });
+        }
+        space.write(TestStruct {
+            count: -1,
+            name: String::from("Tuan"),
+        });
+        space.write(TestStruct {
+            count: 1000,
+            name: String::from("Zoe"),
+        });
+        assert_eq!(by_value.join().unwrap().name, String::from("Tuan"));
+        assert_eq!(by_range.join().unwrap().count, 1000);
 
-                    Box::new(val_iter.into_iter().filter_map(|item| from_value(deflatten(item)).ok()))
-                }
+        // the calls are only woken up by the struct they are waiting for
+        let lookups = space.lock_hold_times::<TestStruct>()["take_by_value"].count;
+        assert!(lookups <= 2);
+    }
 
-                fn read_by_value<T>(&self, field: &str, key: &$ty) -> T
-                where
-                    for<'de> T: Serialize + Deserialize<'de> + 'static,
-                {
-                    self.add_entry(TypeId::of::<T>());
-                    let &(ref lock, ref cvar) = &*self.get_lock::<T>().unwrap().clone();
-                    let value;
-                    {
-                        let mut fetched = lock.lock().unwrap();
-                        loop {
-                            let result = match self.get_object_entry_ref::<T>() {
-                                Some(entry) => entry.get_by_value(field, key),
-                                _ => None,
-                            };
-                            if let Some(item) = result {
-                                value = item;
-                                break;
-                            }
-                            fetched = cvar.wait(fetched).unwrap();
-                        }
-                    }
-                    from_value(deflatten(value)).unwrap()
-                }
+    #[test]
+    fn write_all() {
+        let space = Arc::new(TreeObjectSpace::new());
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || consumer_space.take_by_value::<TestStruct>("count", &2i64));
 
-                fn try_take_by_value<T>(&self, field: &str, key: &$ty) -> Option<T>
-                where
-                    for<'de> T: Serialize + Deserialize<'de> + 'static,
-                {
-                    let value = match self.get_object_entry_mut::<T>() {
-                        Some(mut entry) => entry.remove_by_value(field, key),
-                        _ => None,
-                    };
-                    match value {
-                        Some(val) => from_value(deflatten(val)).ok(),
-                        _ => None,
-                    }
-                }
+        space.write_all((0..3).map(|i| TestStruct {
+            count: i,
+            name: i.to_string(),
+        }));
+        space.write_all(Vec::<TestStruct>::new());
+        assert_eq!(consumer.join().unwrap().name, String::from("2"));
 
-                fn take_all_by_value<'a, T>(
-                    &'a self,
-                    field: &str,
-                    key: &$ty,
-                ) -> Box<Iterator<Item = T> + 'a>
-                where
-                    for<'de> T: Deserialize<'de> + 'static,
-                {
-                    let val_iter = match self.get_object_entry_mut::<T>() {
-                        Some(mut ent) => ent.remove_all_by_value(field, key),
-                        None => Vec::new(),
-                    };
+        let mut counts: Vec<i32> = space.read_all::<TestStruct>().map(|s| s.count).collect();
+        counts.sort();
+        assert_eq!(counts, vec![0, 1]);
+        assert_eq!(space.lock_hold_times::<TestStruct>()["write_all"].count, 2);
+    }
 
-                    Box::new(
-                        val_iter
-                            .into_iter()
-                            .filter_map(|item| from_value(deflatten(item)).ok())
-                    )
-                }
+    #[test]
+    fn with_read() {
+        let space = TreeObjectSpace::new();
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.5,
+        });
+        assert_eq!(
+            space.with_read::<CompoundStruct, _, _>(|c| (c.person.count, c.person.name.clone(), c.gpa)),
+            Some((3, String::from("Tuan"), 3.5))
+        );
 
-                fn take_by_value<T>(&self, field: &str, key: &$ty) -> T
-                where
-                    for<'de> T: Serialize + Deserialize<'de> + 'static,
-                {
-                    self.add_entry(TypeId::of::<T>());
-                    let &(ref lock, ref cvar) = &*self.get_lock::<T>().unwrap().clone();
-                    let value;
-                    {
-                        let mut fetched = lock.lock().unwrap();
-                        loop {
-                            let result = match self.get_object_entry_mut::<T>() {
-                                Some(mut entry) => entry.remove_by_value(field, key),
-                                _ => None,
-                            };
-                            if let Some(item) = result {
-                                value = item;
-                                break;
-                            }
-                            fetched = cvar.wait(fetched).unwrap();
-                        }
-                    }
-                    from_value(deflatten(value)).unwrap()
+        space.write(Some(TestEnum::Int(3)));
+        assert_eq!(
+            space.with_read::<Option<TestEnum>, _, _>(|e| *e == Some(TestEnum::Int(3))),
+            Some(true)
+        );
+        space.take_all::<Option<TestEnum>>().count();
+        space.write::<Option<TestEnum>>(None);
+        assert_eq!(space.with_read::<Option<TestEnum>, _, _>(Option::is_none), Some(true));
+
+        space.write(TestEnum::Struct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+        assert_eq!(
+            space.with_read::<TestEnum, _, _>(|e| {
+                *e == TestEnum::Struct {
+                    count: 3,
+                    name: String::from("Tuan"),
                 }
-            }
-        )*
-    };
-}
+            }),
+            Some(true)
+        );
+    }
 
-object_range!{i64 String f64}
-object_key!{i64 String bool f64}
+    #[test]
+    fn large_unsigned_field() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Id {
+            id: u64,
+            name: String,
+        }
 
-mod tests {
-    use super::*;
+        let space = TreeObjectSpace::new();
+        space.write(Id {
+            id: u64::MAX,
+            name: String::from("Tuan"),
+        });
+        assert_eq!(
+            space.try_take_by_value::<Id>("name", &String::from("Tuan")),
+            Some(Id {
+                id: u64::MAX,
+                name: String::from("Tuan"),
+            })
+        );
+        assert_eq!(space.try_read::<Id>(), None);
 
-    #[derive(Serialize, Deserialize, PartialEq, Debug)]
-    struct TestStruct {
-        count: i32,
-        name: String,
+        match space.try_write(u128::MAX) {
+            Err(SpaceError::Serialization(_)) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        assert_eq!(space.try_read::<u128>(), None);
+        space.write(5u128);
+        assert_eq!(space.try_read_by_value::<u128>("", &5i128), Some(5));
     }
 
-    #[derive(Serialize, Deserialize, PartialEq, Debug)]
-    struct CompoundStruct {
-        person: TestStruct,
-        gpa: f64,
+    #[test]
+    fn strict_schema() {
+        let space = TreeObjectSpace::new();
+        space.strict_schema::<TestEnum>();
+        space.write(TestEnum::Int(3));
+        space.write(TestEnum::Int(5));
+        let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            space.write(TestEnum::String(String::from("Tuan")))
+        }));
+        assert!(result.is_err());
+        assert_eq!(space.read_all::<TestEnum>().count(), 2);
+        assert_eq!(space.schema::<TestEnum>().unwrap().get("Int"), Some(FieldKind::Int));
+
+        space.write(String::from("Hello World"));
+        space.write(String::from("Hello Again"));
+        assert_eq!(
+            space.schema::<String>().unwrap().fields().collect::<Vec<_>>(),
+            vec![("", FieldKind::String)]
+        );
     }
 
-    #[derive(Serialize, Deserialize, PartialEq, Debug)]
-    enum TestEnum {
-        String(String),
-        Int(i32),
-        Struct { count: i32, name: String },
+    #[test]
+    fn json_schema() {
+        let space = TreeObjectSpace::new();
+        assert_eq!(space.json_schema::<CompoundStruct>(), None);
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.5,
+        });
+        let schema = space.json_schema::<CompoundStruct>().unwrap();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["gpa"]["type"], "number");
+        assert_eq!(schema["properties"]["person"]["properties"]["count"]["type"], "integer");
+        assert_eq!(schema["properties"]["person"]["required"][1], "name");
+
+        space.write::<i64>(3);
+        assert_eq!(space.json_schema::<i64>().unwrap()["type"], "integer");
     }
 
     #[test]
-    fn try_read() {
+    fn try_read_by_range() {
         let space = TreeObjectSpace::new();
-        assert_eq!(space.try_read::<String>(), None);
-        space.write(String::from("Hello World"));
+        assert_eq!(space.try_read_by_range::<i64, _>("", 2i64..4i64), None);
+        space.write::<i64>(3);
+        space.write::<i64>(5);
+
+        assert_eq!(space.try_read_by_range::<i64, _>("", 2i64..4i64), Some(3));
+        assert_ne!(space.try_read_by_range::<i64, _>("", 2i64..4i64), None);
+
         space.write(TestStruct {
             count: 3,
             name: String::from("Tuan"),
         });
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 3,
-                name: String::from("Tuan"),
-            },
-            gpa: 3.0,
+        space.write(TestStruct {
+            count: 5,
+            name: String::from("Duane"),
         });
 
         assert_eq!(
-            space.try_read::<String>(),
-            Some(String::from("Hello World"))
-        );
-        assert_ne!(space.try_read::<String>(), None);
-
-        assert_eq!(
-            space.try_read::<TestStruct>(),
+            space.try_read_by_range::<TestStruct, _>("count", 2i64..4i64),
             Some(TestStruct {
                 count: 3,
                 name: String::from("Tuan"),
             })
         );
+        assert!(
+            space
+                .try_read_by_range::<TestStruct, _>("count", 2i64..4i64)
+                .is_some()
+        );
+
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 5,
+                name: String::from("Duane"),
+            },
+            gpa: 3.0,
+        });
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.5,
+        });
 
         assert_eq!(
-            space.try_read::<CompoundStruct>(),
+            space.try_read_by_range::<CompoundStruct, _>("person.count", 2i64..4i64),
             Some(CompoundStruct {
                 person: TestStruct {
                     count: 3,
                     name: String::from("Tuan"),
                 },
-                gpa: 3.0
+                gpa: 3.5
             })
         );
-        assert!(space.try_read::<CompoundStruct>().is_some());
+        assert!(
+            space
+                .try_read_by_range::<CompoundStruct, _>("person.count", 2i64..4i64)
+                .is_some()
+        );
     }
 
     #[test]
-    fn try_take() {
+    fn try_take_by_range() {
         let space = TreeObjectSpace::new();
-        assert_eq!(space.try_take::<String>(), None);
-        space.write(String::from("Hello World"));
+        assert_eq!(space.try_take_by_range::<i64, _>("", 2i64..4i64), None);
+        space.write::<i64>(3);
+        space.write::<i64>(5);
+        assert_eq!(space.try_take_by_range::<i64, _>("", 2i64..4i64), Some(3));
+        assert_eq!(space.try_take_by_range::<i64, _>("", 2i64..4i64), None);
+
         space.write(TestStruct {
             count: 3,
             name: String::from("Tuan"),
         });
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 3,
-                name: String::from("Tuan"),
-            },
-            gpa: 3.5,
+        space.write(TestStruct {
+            count: 5,
+            name: String::from("Duane"),
         });
 
         assert_eq!(
-            space.try_take::<String>(),
-            Some(String::from("Hello World"))
-        );
-        assert_eq!(space.try_take::<String>(), None);
-
-        assert_eq!(
-            space.try_take::<TestStruct>(),
+            space.try_take_by_range::<TestStruct, _>("count", 2i64..4i64),
             Some(TestStruct {
                 count: 3,
                 name: String::from("Tuan"),
             })
         );
-        assert_eq!(space.try_take::<TestStruct>(), None);
+        assert!(
+            space
+                .try_take_by_range::<TestStruct, _>("count", 2i64..4i64)
+                .is_none()
+        );
+
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.0,
+        });
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 5,
+                name: String::from("Duane"),
+            },
+            gpa: 3.5,
+        });
 
         assert_eq!(
-            space.try_take::<CompoundStruct>(),
+            space.try_take_by_range::<CompoundStruct, _>("gpa", 3.0..3.5),
             Some(CompoundStruct {
                 person: TestStruct {
                     count: 3,
                     name: String::from("Tuan"),
                 },
-                gpa: 3.5
+                gpa: 3.0
             })
         );
-        assert!(space.try_take::<CompoundStruct>().is_none());
+        assert!(
+            space
+                .try_take_by_range::<CompoundStruct, _>("person.count", 2i64..4i64)
+                .is_none()
+        );
     }
 
     #[test]
-    fn read_all() {
+    fn read_all_by_range() {
         let space = TreeObjectSpace::new();
-        assert_eq!(space.read_all::<String>().count(), 0);
-        space.write("Hello".to_string());
-        space.write("World".to_string());
+        space.write::<i64>(3);
+        space.write::<i64>(5);
+        assert_eq!(space.read_all_by_range::<i64, _>("", 2i64..4i64).count(), 1);
+        assert_eq!(space.read_all_by_range::<i64, _>("", 2i64..4i64).count(), 1);
+
         space.write(TestStruct {
             count: 3,
             name: String::from("Tuan"),
         });
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Minh"),
+        });
+
         space.write(TestStruct {
             count: 5,
             name: String::from("Duane"),
         });
 
         assert_eq!(
-            space.read_all::<String>().collect::<Vec<String>>(),
-            vec!["Hello", "World"]
+            space
+                .read_all_by_range::<TestStruct, _>("count", 2i64..4i64)
+                .count(),
+            2
+        );
+        assert_eq!(
+            space
+                .read_all_by_range::<TestStruct, _>("count", 2i64..4i64)
+                .count(),
+            2
         );
-        assert_ne!(space.read_all::<String>().count(), 0);
 
-        assert_eq!(space.read_all::<TestStruct>().count(), 2);
-        assert_eq!(space.read_all::<TestStruct>().count(), 2);
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 5,
+                name: String::from("Duane"),
+            },
+            gpa: 4.0,
+        });
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.0,
+        });
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Minh"),
+            },
+            gpa: 3.0,
+        });
+
+        assert_eq!(
+            space
+                .read_all_by_range::<CompoundStruct, _>("gpa", 2.5..4.0)
+                .count(),
+            2
+        );
+        assert_eq!(
+            space
+                .read_all_by_range::<CompoundStruct, _>("person.count", 2i64..4i64)
+                .count(),
+            2
+        );
     }
 
     #[test]
-    fn take_all() {
+    fn take_all_by_range() {
         let space = TreeObjectSpace::new();
-        assert_eq!(space.take_all::<String>().count(), 0);
-        space.write("Hello".to_string());
-        space.write("World".to_string());
+        space.write::<i64>(3);
+        space.write::<i64>(5);
+        assert_eq!(space.take_all_by_range::<i64, _>("", 2i64..4i64).count(), 1);
+        assert_eq!(space.take_all_by_range::<i64, _>("", 2i64..4i64).count(), 0);
+
         space.write(TestStruct {
             count: 3,
             name: String::from("Tuan"),
         });
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Minh"),
+        });
+
         space.write(TestStruct {
             count: 5,
             name: String::from("Duane"),
         });
 
-        assert_eq!(space.take_all::<String>().count(), 2);
-        assert_eq!(space.take_all::<String>().count(), 0);
+        assert_eq!(
+            space
+                .take_all_by_range::<TestStruct, _>("count", 2i64..4i64)
+                .count(),
+            2
+        );
+        assert_eq!(
+            space
+                .take_all_by_range::<TestStruct, _>("count", 2i64..4i64)
+                .count(),
+            0
+        );
+        assert_eq!(
+            space
+                .take_all_by_range::<TestStruct, _>("count", 4i64..)
+                .count(),
+            1
+        );
 
-        assert_eq!(space.take_all::<TestStruct>().count(), 2);
-        assert_eq!(space.take_all::<TestStruct>().count(), 0);
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 5,
+                name: String::from("Duane"),
+            },
+            gpa: 3.5,
+        });
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            },
+            gpa: 3.0,
+        });
+        space.write(CompoundStruct {
+            person: TestStruct {
+                count: 3,
+                name: String::from("Minh"),
+            },
+            gpa: 3.0,
+        });
+
+        assert_eq!(
+            space
+                .take_all_by_range::<CompoundStruct, _>("gpa", 2.5..3.5)
+                .count(),
+            2
+        );
+        assert_eq!(
+            space
+                .take_all_by_range::<CompoundStruct, _>("person.count", 2i64..4i64)
+                .count(),
+            0
+        );
+        assert_eq!(
+            space
+                .take_all_by_range::<CompoundStruct, _>("gpa", 3.5..)
+                .count(),
+            1
+        );
     }
 
     #[test]
-    fn try_read_by_range() {
+    fn try_read_by_value() {
         let space = TreeObjectSpace::new();
-        assert_eq!(space.try_read_by_range::<i64, _>("", 2..4), None);
+        assert_eq!(space.try_read_by_value::<i64>("", &3i64), None);
         space.write::<i64>(3);
         space.write::<i64>(5);
 
-        assert_eq!(space.try_read_by_range::<i64, _>("", 2..4), Some(3));
-        assert_ne!(space.try_read_by_range::<i64, _>("", 2..4), None);
+        assert_eq!(space.try_read_by_value::<i64>("", &3i64), Some(3));
+        assert_eq!(space.try_read_by_value::<i64>("", &2i64), None);
 
         space.write(TestStruct {
             count: 3,
@@ -1033,80 +10429,68 @@ mod tests {
         });
 
         assert_eq!(
-            space.try_read_by_range::<TestStruct, _>("count", 2..4),
+            space.try_read_by_value::<TestStruct>("count", &3i64),
             Some(TestStruct {
                 count: 3,
                 name: String::from("Tuan"),
             })
         );
-        assert!(
-            space
-                .try_read_by_range::<TestStruct, _>("count", 2..4)
-                .is_some()
-        );
+        assert!(space.try_read_by_value::<TestStruct>("count", &3i64).is_some());
 
         space.write(CompoundStruct {
             person: TestStruct {
                 count: 5,
                 name: String::from("Duane"),
             },
-            gpa: 3.0,
+            gpa: 4.0,
         });
         space.write(CompoundStruct {
             person: TestStruct {
                 count: 3,
                 name: String::from("Tuan"),
             },
-            gpa: 3.5,
+            gpa: 3.0,
         });
 
         assert_eq!(
-            space.try_read_by_range::<CompoundStruct, _>("person.count", 2..4),
+            space.try_read_by_value::<CompoundStruct>("person.count", &3i64),
             Some(CompoundStruct {
                 person: TestStruct {
                     count: 3,
                     name: String::from("Tuan"),
                 },
-                gpa: 3.5
+                gpa: 3.0
             })
         );
         assert!(
             space
-                .try_read_by_range::<CompoundStruct, _>("person.count", 2..4)
+                .try_read_by_value::<CompoundStruct>("gpa", &3.0)
                 .is_some()
         );
     }
 
     #[test]
-    fn try_take_by_range() {
+    fn try_take_by_value() {
         let space = TreeObjectSpace::new();
-        assert_eq!(space.try_take_by_range::<i64, _>("", 2..4), None);
+        assert_eq!(space.try_take_by_value::<i64>("", &3i64), None);
         space.write::<i64>(3);
         space.write::<i64>(5);
-        assert_eq!(space.try_take_by_range::<i64, _>("", 2..4), Some(3));
-        assert_eq!(space.try_take_by_range::<i64, _>("", 2..4), None);
+        assert_eq!(space.try_take_by_value::<i64>("", &4i64), None);
+        assert_eq!(space.try_take_by_value::<i64>("", &3i64), Some(3));
+        assert_eq!(space.try_take_by_value::<i64>("", &3i64), None);
 
         space.write(TestStruct {
             count: 3,
             name: String::from("Tuan"),
         });
-        space.write(TestStruct {
-            count: 5,
-            name: String::from("Duane"),
-        });
-
         assert_eq!(
-            space.try_take_by_range::<TestStruct, _>("count", 2..4),
+            space.try_take_by_value::<TestStruct>("count", &3i64),
             Some(TestStruct {
                 count: 3,
                 name: String::from("Tuan"),
             })
         );
-        assert!(
-            space
-                .try_take_by_range::<TestStruct, _>("count", 2..4)
-                .is_none()
-        );
+        assert!(space.try_take_by_value::<TestStruct>("count", &3i64).is_none());
 
         space.write(CompoundStruct {
             person: TestStruct {
@@ -1115,16 +10499,9 @@ mod tests {
             },
             gpa: 3.0,
         });
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 5,
-                name: String::from("Duane"),
-            },
-            gpa: 3.5,
-        });
 
         assert_eq!(
-            space.try_take_by_range::<CompoundStruct, _>("gpa", 3.0..3.5),
+            space.try_take_by_value::<CompoundStruct>("person.count", &3i64),
             Some(CompoundStruct {
                 person: TestStruct {
                     count: 3,
@@ -1135,18 +10512,18 @@ mod tests {
         );
         assert!(
             space
-                .try_take_by_range::<CompoundStruct, _>("person.count", 2..4)
+                .try_take_by_value::<CompoundStruct>("gpa", &3.0)
                 .is_none()
         );
     }
 
     #[test]
-    fn read_all_by_range() {
+    fn read_all_by_value() {
         let space = TreeObjectSpace::new();
         space.write::<i64>(3);
         space.write::<i64>(5);
-        assert_eq!(space.read_all_by_range::<i64, _>("", 2..4).count(), 1);
-        assert_eq!(space.read_all_by_range::<i64, _>("", 2..4).count(), 1);
+        assert_eq!(space.read_all_by_value::<i64>("", &3i64).count(), 1);
+        assert_eq!(space.read_all_by_value::<i64>("", &4i64).count(), 0);
 
         space.write(TestStruct {
             count: 3,
@@ -1163,16 +10540,12 @@ mod tests {
         });
 
         assert_eq!(
-            space
-                .read_all_by_range::<TestStruct, _>("count", 2..4)
-                .count(),
+            space.read_all_by_value::<TestStruct>("count", &3i64).count(),
             2
         );
         assert_eq!(
-            space
-                .read_all_by_range::<TestStruct, _>("count", 2..4)
-                .count(),
-            2
+            space.read_all_by_value::<TestStruct>("count", &4i64).count(),
+            0
         );
 
         space.write(CompoundStruct {
@@ -1194,30 +10567,36 @@ mod tests {
                 count: 3,
                 name: String::from("Minh"),
             },
-            gpa: 3.0,
+            gpa: 3.5,
         });
 
         assert_eq!(
             space
-                .read_all_by_range::<CompoundStruct, _>("gpa", 2.5..4.0)
+                .read_all_by_value::<CompoundStruct>("person.count", &3i64)
                 .count(),
             2
         );
         assert_eq!(
             space
-                .read_all_by_range::<CompoundStruct, _>("person.count", 2..4)
+                .read_all_by_value::<CompoundStruct>("person.count", &4i64)
                 .count(),
-            2
+            0
+        );
+        assert_eq!(
+            space
+                .read_all_by_value::<CompoundStruct>("gpa", &4.0)
+                .count(),
+            1
         );
     }
 
     #[test]
-    fn take_all_by_range() {
+    fn take_all_by_value() {
         let space = TreeObjectSpace::new();
         space.write::<i64>(3);
         space.write::<i64>(5);
-        assert_eq!(space.take_all_by_range::<i64, _>("", 2..4).count(), 1);
-        assert_eq!(space.take_all_by_range::<i64, _>("", 2..4).count(), 0);
+        assert_eq!(space.take_all_by_value::<i64>("", &3i64).count(), 1);
+        assert_eq!(space.take_all_by_value::<i64>("", &4i64).count(), 0);
 
         space.write(TestStruct {
             count: 3,
@@ -1234,21 +10613,15 @@ mod tests {
         });
 
         assert_eq!(
-            space
-                .take_all_by_range::<TestStruct, _>("count", 2..4)
-                .count(),
+            space.take_all_by_value::<TestStruct>("count", &3i64).count(),
             2
         );
         assert_eq!(
-            space
-                .take_all_by_range::<TestStruct, _>("count", 2..4)
-                .count(),
+            space.take_all_by_value::<TestStruct>("count", &3i64).count(),
             0
         );
         assert_eq!(
-            space
-                .take_all_by_range::<TestStruct, _>("count", 4..)
-                .count(),
+            space.take_all_by_value::<TestStruct>("count", &5i64).count(),
             1
         );
 
@@ -1257,7 +10630,7 @@ mod tests {
                 count: 5,
                 name: String::from("Duane"),
             },
-            gpa: 3.5,
+            gpa: 4.0,
         });
         space.write(CompoundStruct {
             person: TestStruct {
@@ -1276,280 +10649,933 @@ mod tests {
 
         assert_eq!(
             space
-                .take_all_by_range::<CompoundStruct, _>("gpa", 2.5..3.5)
+                .take_all_by_value::<CompoundStruct>("gpa", &3.0)
                 .count(),
             2
         );
         assert_eq!(
             space
-                .take_all_by_range::<CompoundStruct, _>("person.count", 2..4)
+                .take_all_by_value::<CompoundStruct>("person.count", &3i64)
                 .count(),
             0
         );
         assert_eq!(
             space
-                .take_all_by_range::<CompoundStruct, _>("gpa", 3.5..)
+                .take_all_by_value::<CompoundStruct>("person.count", &5i64)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn value_lookup_signed_zero() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct P {
+            f: f64,
+        }
+
+        let space = TreeObjectSpace::new();
+        space.write(P { f: -0.0 });
+        assert_eq!(space.try_read_by_value::<P>("f", &0.0), Some(P { f: -0.0 }));
+        space.write::<f64>(0.0);
+        assert_eq!(space.try_take_by_value::<f64>("", &-0.0), Some(0.0));
+    }
+
+    #[test]
+    fn value_lookup_after_take() {
+        let space = TreeObjectSpace::new();
+        for i in 0..100 {
+            space.write(TestStruct {
+                count: i,
+                name: i.to_string(),
+            });
+        }
+        assert_eq!(space.try_read_by_value::<TestStruct>("count", &100i64), None);
+        assert_eq!(
+            space.try_read_by_value::<TestStruct>("name", &String::from("Tuan")),
+            None
+        );
+
+        assert_eq!(
+            space.take_all_by_value::<TestStruct>("count", &42i64).count(),
+            1
+        );
+        assert_eq!(space.try_read_by_value::<TestStruct>("count", &42i64), None);
+        assert_eq!(
+            space.try_take_by_value::<TestStruct>("name", &String::from("42")),
+            None
+        );
+        assert!(space.try_read_by_value::<TestStruct>("count", &43i64).is_some());
+        assert!(
+            space
+                .try_take_by_value::<TestStruct>("name", &String::from("43"))
+                .is_some()
+        );
+
+        space.write(TestStruct {
+            count: 42,
+            name: String::from("42"),
+        });
+        assert!(space.try_read_by_value::<TestStruct>("count", &42i64).is_some());
+    }
+
+    #[test]
+    fn hash_index() {
+        let space = TreeObjectSpace::new();
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+        space.hash_index::<TestStruct>(&["name"]);
+        space.write(TestStruct {
+            count: 5,
+            name: String::from("Duane"),
+        });
+
+        assert_eq!(
+            space.try_read_by_value::<TestStruct>("name", &String::from("Tuan")),
+            Some(TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            })
+        );
+        assert_eq!(
+            space
+                .take_all_by_value::<TestStruct>("name", &String::from("Duane"))
                 .count(),
             1
         );
+        assert_eq!(
+            space.try_read_by_range::<TestStruct, _>("count", 2i64..4i64),
+            Some(TestStruct {
+                count: 3,
+                name: String::from("Tuan"),
+            })
+        );
+
+        assert_eq!(space.take_all::<TestStruct>().count(), 1);
+        space.write(TestStruct {
+            count: 4,
+            name: String::from("Minh"),
+        });
+        assert!(
+            space
+                .try_take_by_value::<TestStruct>("name", &String::from("Minh"))
+                .is_some()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn hash_index_range_lookup() {
+        let space = TreeObjectSpace::new();
+        space.hash_index::<TestStruct>(&["count"]);
+        space.write(TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        });
+        space.try_read_by_range::<TestStruct, _>("count", 2i64..4i64);
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Task {
+        start: i64,
+        end: i64,
+    }
+
+    #[test]
+    fn read_all_covering() {
+        let space = TreeObjectSpace::new();
+        space.write(Task { start: 0, end: 10 });
+        space.write(Task { start: 5, end: 20 });
+        assert_eq!(space.read_all_covering::<Task>(("start", "end"), &7).count(), 2);
+
+        space.interval_index::<Task>(("start", "end"));
+        for i in 0..50 {
+            space.write(Task {
+                start: i * 10,
+                end: i * 10 + 5,
+            });
+        }
+
+        assert_eq!(space.read_all_covering::<Task>(("start", "end"), &7).count(), 2);
+        assert_eq!(space.read_all_covering::<Task>(("start", "end"), &3).count(), 2);
+        assert_eq!(space.read_all_covering::<Task>(("start", "end"), &15).count(), 1);
+        assert_eq!(space.read_all_covering::<Task>(("start", "end"), &20).count(), 1);
+        assert_eq!(space.read_all_covering::<Task>(("start", "end"), &495).count(), 0);
+        assert_eq!(
+            space.try_read_covering::<Task>(("start", "end"), &492),
+            Some(Task {
+                start: 490,
+                end: 495
+            })
+        );
+    }
+
+    #[test]
+    fn take_all_covering() {
+        let space = TreeObjectSpace::new();
+        space.interval_index::<Task>(("start", "end"));
+        for i in 0..50 {
+            space.write(Task { start: i, end: 50 });
+        }
+
+        assert_eq!(space.take_all_covering::<Task>(("start", "end"), &9).count(), 10);
+        assert_eq!(space.take_all_covering::<Task>(("start", "end"), &9).count(), 0);
+        assert_eq!(
+            space.try_take_covering::<Task>(("start", "end"), &20),
+            Some(Task { start: 10, end: 50 })
+        );
+        assert_eq!(space.read_all::<Task>().count(), 39);
+        assert_eq!(space.take_all_covering::<Task>(("start", "end"), &49).count(), 39);
+        assert_eq!(space.try_read::<Task>(), None);
+
+        space.write(Task { start: 1, end: 2 });
+        assert_eq!(
+            space.take_covering::<Task>(("start", "end"), &1),
+            Task { start: 1, end: 2 }
+        );
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Place {
+        name: String,
+        location: Point,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+
+    #[test]
+    fn read_all_in_rect() {
+        let space = TreeObjectSpace::new();
+        let fields = ("location.x", "location.y");
+        space.spatial_index::<Place>(fields);
+        for i in -20..20 {
+            for j in -20..20 {
+                space.write(Place {
+                    name: format!("{},{}", i, j),
+                    location: Point {
+                        x: f64::from(i) / 2.0,
+                        y: f64::from(j) / 2.0,
+                    },
+                });
+            }
+        }
+
+        assert_eq!(
+            space
+                .read_all_in_rect::<Place, _>(fields, (0.0..=1.0, 0.0..=1.0))
+                .count(),
+            9
+        );
+        assert_eq!(
+            space
+                .read_all_in_rect::<Place, _>(fields, (-1.0..0.0, -1.0..0.0))
+                .count(),
+            4
+        );
+        assert_eq!(
+            space
+                .read_all_in_rect::<Place, _>(fields, (9.6..=100.0, -100.0..=100.0))
+                .count(),
+            0
+        );
+        assert_eq!(
+            space
+                .try_read_in_rect::<Place, _>(fields, (-0.2..0.2, 9.4..9.6))
+                .map(|place| place.name),
+            Some(String::from("0,19"))
+        );
+    }
+
+    #[test]
+    fn take_all_in_rect() {
+        let space = TreeObjectSpace::new();
+        let fields = ("location.x", "location.y");
+        for i in 0..50 {
+            space.write(Place {
+                name: i.to_string(),
+                location: Point {
+                    x: f64::from(i),
+                    y: f64::from(i),
+                },
+            });
+        }
+        assert_eq!(
+            space
+                .read_all_in_rect::<Place, _>(fields, (10.0..20.0, 15.0..25.0))
+                .count(),
+            5
+        );
+
+        space.spatial_index::<Place>(fields);
+        assert_eq!(
+            space
+                .take_all_in_rect::<Place, _>(fields, (10.0..20.0, 15.0..25.0))
+                .count(),
+            5
+        );
+        assert_eq!(
+            space
+                .take_all_in_rect::<Place, _>(fields, (10.0..20.0, 15.0..25.0))
+                .count(),
+            0
+        );
+        assert_eq!(
+            space
+                .take_all_in_rect::<Place, _>(fields, (..100.0, ..100.0))
+                .count(),
+            45
+        );
+        assert_eq!(space.try_read::<Place>(), None);
+    }
+
+    #[test]
+    fn try_take_nearest() {
+        let space = TreeObjectSpace::new();
+        assert_eq!(space.try_take_nearest::<CompoundStruct>("gpa", &3.0), None);
+        for gpa in &[2.0, 3.5, 3.9] {
+            space.write(CompoundStruct {
+                person: TestStruct {
+                    count: 3,
+                    name: String::from("Tuan"),
+                },
+                gpa: *gpa,
+            });
+        }
+
+        assert_eq!(
+            space
+                .try_read_nearest::<CompoundStruct>("gpa", &3.0)
+                .map(|s| s.gpa),
+            Some(3.5)
+        );
+        assert_eq!(
+            space
+                .try_take_nearest::<CompoundStruct>("gpa", &3.0)
+                .map(|s| s.gpa),
+            Some(3.5)
+        );
+        assert_eq!(
+            space
+                .try_take_nearest::<CompoundStruct>("gpa", &3.0)
+                .map(|s| s.gpa),
+            Some(3.9)
+        );
+        assert_eq!(
+            space
+                .try_take_nearest::<CompoundStruct>("person.count", &100)
+                .map(|s| s.gpa),
+            Some(2.0)
+        );
+        assert_eq!(space.try_take_nearest::<CompoundStruct>("gpa", &3.0), None);
+
+        space.write::<i64>(i64::MIN);
+        space.write::<i64>(i64::MAX);
+        assert_eq!(space.try_read_nearest::<i64>("", &-1), Some(i64::MIN));
+        assert_eq!(space.try_read_nearest::<i64>("", &0), Some(i64::MAX));
+    }
+
+    #[test]
+    fn query_or() {
+        let space = TreeObjectSpace::new();
+        for &(count, name) in &[(3, "Tuan"), (5, "Duane"), (3, "Tom"), (7, "Tuan")] {
+            space.write(CompoundStruct {
+                person: TestStruct {
+                    count,
+                    name: String::from(name),
+                },
+                gpa: f64::from(count),
+            });
+        }
+
+        let query = field("person.count")
+            .eq(3)
+            .or(field("person.name").eq("Tuan"));
+        let mut result: Vec<(i32, String)> = space
+            .read_all_by_query::<CompoundStruct>(&query)
+            .map(|s| (s.person.count, s.person.name))
+            .collect();
+        result.sort();
+        assert_eq!(
+            result,
+            vec![
+                (3, String::from("Tom")),
+                (3, String::from("Tuan")),
+                (7, String::from("Tuan")),
+            ]
+        );
+
+        let query = field("gpa").ge(6.0).or(field("person.name").eq("Nobody"));
+        assert_eq!(
+            space.try_take_by_query::<CompoundStruct>(&query).map(|s| s.person.count),
+            Some(7)
+        );
+        assert_eq!(space.try_take_by_query::<CompoundStruct>(&query), None);
+        assert_eq!(
+            space.take_all_by_query::<CompoundStruct>(&field("person.count").between(3, 6)).count(),
+            3
+        );
+        assert_eq!(space.try_read::<CompoundStruct>(), None);
+    }
+
+    #[test]
+    fn query_and() {
+        let space = Arc::new(TreeObjectSpace::new());
+        for &(count, name) in &[(3, "Tuan"), (5, "Tuan"), (3, "Tom")] {
+            space.write(TestStruct {
+                count,
+                name: String::from(name),
+            });
+        }
+
+        let fields = [("count", FieldValue::from(3)), ("name", FieldValue::from("Tuan"))];
+        assert_eq!(space.read_all_by_match::<TestStruct>(&fields).count(), 1);
+        let query = field("name")
+            .eq("Tuan")
+            .and(field("count").eq(5).or(field("count").eq(7)));
+        assert_eq!(space.try_take_by_query::<TestStruct>(&query).map(|s| s.count), Some(5));
+        assert_eq!(space.try_take_by_query::<TestStruct>(&query), None);
+
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || {
+            let fields = [("count", FieldValue::from(7)), ("name", FieldValue::from("Tom"))];
+            consumer_space.take_by_match::<TestStruct>(&fields)
+        });
+        space.write(TestStruct {
+            count: 7,
+            name: String::from("Tuan"),
+        });
+        space.write(TestStruct {
+            count: 7,
+            name: String::from("Tom"),
+        });
+        assert_eq!(consumer.join().unwrap().name, String::from("Tom"));
+        assert_eq!(space.read_all::<TestStruct>().count(), 3);
+    }
+
+    #[test]
+    fn pattern_lookup() {
+        let space = Arc::new(TreeObjectSpace::new());
+        for (count, name) in ["Tuan", "Tu", "Tom", "Tu*n", "Túan", "Ttu"].iter().enumerate() {
+            space.write(TestStruct {
+                count: count as i32,
+                name: String::from(*name),
+            });
+        }
+
+        assert_eq!(space.read_all_by_prefix::<TestStruct>("name", "Tu").count(), 3);
+        assert_eq!(space.read_all_by_prefix::<TestStruct>("name", "").count(), 6);
+        assert_eq!(space.read_all_by_prefix::<TestStruct>("name", "Tú").count(), 1);
+        assert_eq!(space.read_all_by_pattern::<TestStruct>("name", "T*n").count(), 3);
+        assert_eq!(space.read_all_by_pattern::<TestStruct>("name", "T?").count(), 1);
+        assert_eq!(space.read_all_by_pattern::<TestStruct>("name", "*u*").count(), 4);
+        assert_eq!(
+            space.try_read_by_pattern::<TestStruct>("name", "Tu\\*n").map(|s| s.count),
+            Some(3)
+        );
+        assert_eq!(
+            space.try_take_by_pattern::<TestStruct>("name", "Tu").map(|s| s.count),
+            Some(1)
+        );
+        assert_eq!(space.take_all_by_prefix::<TestStruct>("name", "Tu").count(), 2);
+        assert_eq!(space.read_all::<TestStruct>().count(), 3);
+
+        let consumer_space = space.clone();
+        let consumer =
+            thread::spawn(move || consumer_space.take_by_prefix::<TestStruct>("name", "Ng"));
+        space.write(TestStruct {
+            count: 10,
+            name: String::from("Nguyen"),
+        });
+        assert_eq!(consumer.join().unwrap().count, 10);
+        assert_eq!(space.read_all::<TestStruct>().count(), 3);
     }
 
     #[test]
-    fn try_read_by_value() {
-        let space = TreeObjectSpace::new();
-        assert_eq!(space.try_read_by_value::<i64>("", &3), None);
-        space.write::<i64>(3);
-        space.write::<i64>(5);
+    fn checkout() {
+        let space = Arc::new(TreeObjectSpace::new());
+        space.set_policy::<TestStruct>(OrderingPolicy::Fifo);
+        let handles: Vec<_> = (0..3)
+            .map(|count| {
+                space.write(TestStruct {
+                    count,
+                    name: count.to_string(),
+                })
+            })
+            .collect();
+
+        // a worker panicking while processing a struct does not lose it
+        let worker_space = space.clone();
+        let worker = thread::spawn(move || {
+            let task = worker_space.checkout::<TestStruct>(Duration::from_secs(60));
+            assert_eq!(worker_space.try_read_by_value::<TestStruct>("count", &0i64), None);
+            panic!("cannot process task {}", task.count);
+        });
+        assert!(worker.join().is_err());
+        let counts = || space.read_all::<TestStruct>().map(|s| s.count).collect::<Vec<_>>();
+        assert_eq!(counts(), vec![0, 1, 2]);
+        assert_eq!(space.try_read_by_handle::<TestStruct>(handles[0]).map(|s| s.count), Some(0));
 
-        assert_eq!(space.try_read_by_value::<i64>("", &3), Some(3));
-        assert_eq!(space.try_read_by_value::<i64>("", &2), None);
+        let task = space.checkout::<TestStruct>(Duration::from_secs(60));
+        assert_eq!(task.handle(), handles[0]);
+        assert!(task.commit());
+        assert_eq!(counts(), vec![1, 2]);
 
+        // a stuck worker loses its struct to another one once its lease ends
+        let stuck = space.checkout::<TestStruct>(Duration::from_millis(50));
+        let other = space.try_checkout::<TestStruct>(Duration::from_secs(60)).unwrap();
+        assert_eq!((stuck.count, other.count), (1, 2));
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || consumer_space.take::<TestStruct>().count);
+        assert_eq!(consumer.join().unwrap(), 1);
+        assert!(!stuck.commit());
+        other.release();
+        assert_eq!(counts(), vec![2]);
+
+        // taking every struct leaves those checked out
         space.write(TestStruct {
             count: 3,
-            name: String::from("Tuan"),
-        });
-        space.write(TestStruct {
-            count: 5,
-            name: String::from("Duane"),
+            name: String::from("3"),
         });
+        let task = space.checkout::<TestStruct>(Duration::from_secs(60));
+        assert_eq!(space.take_all::<TestStruct>().map(|s| s.count).collect::<Vec<_>>(), vec![3]);
+        drop(task);
+        assert_eq!(counts(), vec![2]);
+    }
 
-        assert_eq!(
-            space.try_read_by_value::<TestStruct>("count", &3),
-            Some(TestStruct {
-                count: 3,
-                name: String::from("Tuan"),
-            })
-        );
-        assert!(space.try_read_by_value::<TestStruct>("count", &3).is_some());
-
-        space.write(CompoundStruct {
-            person: TestStruct {
+    #[test]
+    fn object_handle() {
+        let space = TreeObjectSpace::new();
+        let tuan = || TestStruct {
+            count: 3,
+            name: String::from("Tuan"),
+        };
+        let first = space.write(tuan());
+        let second = space.write(tuan());
+        let other = space.write_with_ttl(
+            TestStruct {
                 count: 5,
                 name: String::from("Duane"),
             },
-            gpa: 4.0,
-        });
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 3,
-                name: String::from("Tuan"),
-            },
-            gpa: 3.0,
-        });
-
-        assert_eq!(
-            space.try_read_by_value::<CompoundStruct>("person.count", &3),
-            Some(CompoundStruct {
-                person: TestStruct {
-                    count: 3,
-                    name: String::from("Tuan"),
-                },
-                gpa: 3.0
-            })
-        );
-        assert!(
-            space
-                .try_read_by_value::<CompoundStruct>("gpa", &3.0)
-                .is_some()
+            Duration::from_secs(60),
         );
+        assert_ne!(first, second);
+
+        // equal structs are told apart by their handle
+        assert_eq!(space.try_take_by_handle::<TestStruct>(second), Some(tuan()));
+        assert_eq!(space.try_read_by_handle::<TestStruct>(second), None);
+        assert_eq!(space.try_take_by_handle::<TestStruct>(second), None);
+        assert_eq!(space.try_read_by_handle::<TestStruct>(first), Some(tuan()));
+        assert_eq!(space.read_all::<TestStruct>().count(), 2);
+        assert_eq!(space.try_read_by_value::<TestStruct>("count", &3i64), Some(tuan()));
+
+        // a struct taken by value could not be taken again by handle
+        assert_eq!(space.try_take_by_value::<TestStruct>("name", &String::from("Duane")).map(|s| s.count), Some(5));
+        assert_eq!(space.try_take_by_handle::<TestStruct>(other), None);
+
+        // an updated struct gets a new handle
+        assert!(space.update_by_value::<TestStruct, _>("count", &3i64, |s| TestStruct { count: 4, ..s }));
+        assert_eq!(space.try_read_by_handle::<TestStruct>(first), None);
+
+        let space = Arc::new(space);
+        let handle = space.eval(|| 7i64).join().unwrap();
+        assert_eq!(space.try_take_by_handle::<i64>(handle), Some(7));
     }
 
     #[test]
-    fn try_take_by_value() {
-        let space = TreeObjectSpace::new();
-        assert_eq!(space.try_take_by_value::<i64>("", &3), None);
-        space.write::<i64>(3);
-        space.write::<i64>(5);
-        assert_eq!(space.try_take_by_value::<i64>("", &4), None);
-        assert_eq!(space.try_take_by_value::<i64>("", &3), Some(3));
-        assert_eq!(space.try_take_by_value::<i64>("", &3), None);
+    fn ordering_policy() {
+        let space = Arc::new(TreeObjectSpace::new());
+        let write_all = |space: &TreeObjectSpace| {
+            for count in 0..6 {
+                space.write(TestStruct {
+                    count,
+                    name: count.to_string(),
+                });
+            }
+        };
+        let counts = |structs: Box<Iterator<Item = TestStruct>>| structs.map(|s| s.count).collect::<Vec<_>>();
 
-        space.write(TestStruct {
-            count: 3,
-            name: String::from("Tuan"),
+        write_all(&space);
+        // structs taken from the middle must not change the order of the others
+        assert_eq!(space.try_take_by_value::<TestStruct>("count", &1i64).map(|s| s.count), Some(1));
+        space.set_policy::<TestStruct>(OrderingPolicy::Fifo);
+        assert_eq!(space.try_take_by_value::<TestStruct>("count", &3i64).map(|s| s.count), Some(3));
+        assert_eq!(counts(space.read_all::<TestStruct>()), vec![0, 2, 4, 5]);
+        assert_eq!(space.read::<TestStruct>().count, 0);
+        assert_eq!(space.take::<TestStruct>().count, 0);
+        assert_eq!(space.try_take::<TestStruct>().map(|s| s.count), Some(2));
+        assert_eq!(counts(space.take_all::<TestStruct>()), vec![4, 5]);
+
+        space.set_policy::<TestStruct>(OrderingPolicy::Lifo);
+        write_all(&space);
+        assert_eq!(space.try_take_by_value::<TestStruct>("count", &2i64).map(|s| s.count), Some(2));
+        assert_eq!(counts(space.read_all::<TestStruct>()), vec![5, 4, 3, 1, 0]);
+        assert_eq!(space.try_read::<TestStruct>().map(|s| s.count), Some(5));
+        assert_eq!(space.take::<TestStruct>().count, 5);
+        assert_eq!(space.take::<TestStruct>().count, 4);
+        assert_eq!(counts(space.take_all::<TestStruct>()), vec![3, 1, 0]);
+
+        space.set_policy::<TestStruct>(OrderingPolicy::Fifo);
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || {
+            (0..6).map(|_| consumer_space.take::<TestStruct>().count).collect::<Vec<_>>()
         });
+        write_all(&space);
+        assert_eq!(consumer.join().unwrap(), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn ordered_lookup() {
+        let space = Arc::new(TreeObjectSpace::new());
+        for (count, name) in [(3, "Tuan"), (1, "Duane"), (3, "Tom"), (7, "Nguyen")].iter() {
+            space.write(TestStruct {
+                count: *count,
+                name: String::from(*name),
+            });
+        }
+
+        assert_eq!(space.try_read_min_by::<TestStruct>("count").map(|s| s.count), Some(1));
+        assert_eq!(space.try_read_max_by::<TestStruct>("name").map(|s| s.count), Some(3));
+        assert_eq!(space.try_take_max_by::<TestStruct>("count").map(|s| s.count), Some(7));
+        assert_eq!(space.try_take_min_by::<TestStruct>("count").map(|s| s.count), Some(1));
+        // the oldest of equal values is taken first
         assert_eq!(
-            space.try_take_by_value::<TestStruct>("count", &3),
-            Some(TestStruct {
-                count: 3,
-                name: String::from("Tuan"),
-            })
+            space.take_min_by::<TestStruct>("count").name,
+            String::from("Tuan")
         );
-        assert!(space.try_take_by_value::<TestStruct>("count", &3).is_none());
+        assert_eq!(space.take_max_by::<TestStruct>("count").name, String::from("Tom"));
+        assert_eq!(space.try_take_min_by::<TestStruct>("count"), None);
 
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 3,
-                name: String::from("Tuan"),
-            },
-            gpa: 3.0,
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || consumer_space.take_max_by::<TestStruct>("count"));
+        space.write(TestStruct {
+            count: 10,
+            name: String::from("Zoe"),
+        });
+        assert_eq!(consumer.join().unwrap().count, 10);
+    }
+
+    #[test]
+    fn priority_take() {
+        let space = Arc::new(TreeObjectSpace::new());
+        for count in [2, 9, 5].iter() {
+            space.write(TestStruct {
+                count: *count,
+                name: count.to_string(),
+            });
+        }
+        let (sender, receiver) = channel();
+        let worker_space = space.clone();
+        let worker = thread::spawn(move || {
+            for _ in 0..4 {
+                let task = worker_space.take_ordered::<TestStruct>("count", Order::Descending);
+                sender.send(task.count).unwrap();
+            }
+        });
+        assert_eq!(receiver.iter().take(3).collect::<Vec<_>>(), vec![9, 5, 2]);
+
+        space.write(TestStruct {
+            count: 1,
+            name: String::from("last"),
         });
+        worker.join().unwrap();
+        assert_eq!(receiver.recv().unwrap(), 1);
 
         assert_eq!(
-            space.try_take_by_value::<CompoundStruct>("person.count", &3),
-            Some(CompoundStruct {
-                person: TestStruct {
-                    count: 3,
-                    name: String::from("Tuan"),
-                },
-                gpa: 3.0
-            })
+            space.take_ordered_timeout::<TestStruct>("count", Order::Ascending, Duration::from_millis(10)),
+            None
         );
-        assert!(
+        for count in [4, 1, 6].iter() {
+            space.write(TestStruct {
+                count: *count,
+                name: count.to_string(),
+            });
+        }
+        assert_eq!(
             space
-                .try_take_by_value::<CompoundStruct>("gpa", &3.0)
-                .is_none()
+                .read_all_ordered::<TestStruct>("count", Order::Ascending)
+                .map(|s| s.count)
+                .collect::<Vec<_>>(),
+            vec![1, 4, 6]
         );
+        assert_eq!(space.take_ordered::<TestStruct>("count", Order::Ascending).count, 1);
     }
 
     #[test]
-    fn read_all_by_value() {
+    fn checked_lookup() {
         let space = TreeObjectSpace::new();
-        space.write::<i64>(3);
-        space.write::<i64>(5);
-        assert_eq!(space.read_all_by_value::<i64>("", &3).count(), 1);
-        assert_eq!(space.read_all_by_value::<i64>("", &4).count(), 0);
+        assert_eq!(space.try_read_by_value_checked::<TestStruct>("count", &3i64), Ok(None));
 
+        space.hash_index::<TestStruct>(&["name"]);
         space.write(TestStruct {
             count: 3,
             name: String::from("Tuan"),
         });
-        space.write(TestStruct {
-            count: 3,
-            name: String::from("Minh"),
-        });
-
-        space.write(TestStruct {
-            count: 5,
-            name: String::from("Duane"),
-        });
-
         assert_eq!(
-            space.read_all_by_value::<TestStruct>("count", &3).count(),
-            2
+            space
+                .try_read_by_value_checked::<TestStruct>("count", &3i64)
+                .map(|s| s.map(|s| s.name)),
+            Ok(Some(String::from("Tuan")))
         );
         assert_eq!(
-            space.read_all_by_value::<TestStruct>("count", &4).count(),
-            0
+            space.try_read_by_value_checked::<TestStruct>("cuont", &3i64),
+            Err(SpaceError::NoSuchField(String::from("cuont")))
         );
-
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 5,
-                name: String::from("Duane"),
-            },
-            gpa: 4.0,
-        });
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 3,
-                name: String::from("Tuan"),
-            },
-            gpa: 3.0,
-        });
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 3,
-                name: String::from("Minh"),
-            },
-            gpa: 3.5,
-        });
-
         assert_eq!(
             space
-                .read_all_by_value::<CompoundStruct>("person.count", &3)
-                .count(),
-            2
+                .read_all_by_value_checked::<TestStruct>("count", &String::from("3"))
+                .err(),
+            Some(SpaceError::WrongType {
+                field: String::from("count"),
+                kind: FieldKind::Int,
+                key: FieldKind::String,
+            })
+        );
+        assert_eq!(
+            space.try_take_by_range_checked::<TestStruct, _>("count", 0.0..f64::NAN),
+            Err(SpaceError::NaN)
         );
         assert_eq!(
             space
-                .read_all_by_value::<CompoundStruct>("person.count", &4)
-                .count(),
-            0
+                .take_all_by_range_checked::<TestStruct, _>("name", String::from("T")..)
+                .err(),
+            Some(SpaceError::HashIndexed(String::from("name")))
+        );
+        assert_eq!(space.try_take_by_value_checked::<TestStruct>("name", &FieldValue::Null), Ok(None));
+
+        space.index_fields::<TestStruct>(&["name"]);
+        assert_eq!(
+            space.try_take_by_range_checked::<TestStruct, _>("count", 0i64..),
+            Err(SpaceError::NotIndexed(String::from("count")))
         );
         assert_eq!(
             space
-                .read_all_by_value::<CompoundStruct>("gpa", &4.0)
-                .count(),
-            1
+                .take_all_by_value_checked::<TestStruct>("name", &String::from("Tuan"))
+                .map(|found| found.count()),
+            Ok(1)
         );
     }
 
     #[test]
-    fn take_all_by_value() {
-        let space = TreeObjectSpace::new();
-        space.write::<i64>(3);
-        space.write::<i64>(5);
-        assert_eq!(space.take_all_by_value::<i64>("", &3).count(), 1);
-        assert_eq!(space.take_all_by_value::<i64>("", &4).count(), 0);
+    fn collation() {
+        let space = Arc::new(TreeObjectSpace::new());
+        space.set_collation::<TestStruct>("name", Collation::CaseInsensitive);
+        for (count, name) in ["Tuan", "TOM", "nguyen"].iter().enumerate() {
+            space.write(TestStruct {
+                count: count as i32,
+                name: String::from(*name),
+            });
+        }
 
-        space.write(TestStruct {
-            count: 3,
-            name: String::from("Tuan"),
-        });
-        space.write(TestStruct {
-            count: 3,
-            name: String::from("Minh"),
-        });
+        assert_eq!(
+            space.try_read_by_value::<TestStruct>("name", &String::from("tuan")).map(|s| s.name),
+            Some(String::from("Tuan"))
+        );
+        assert_eq!(
+            space.read_all_by_range::<TestStruct, _>("name", String::from("N")..String::from("U")).count(),
+            3
+        );
+        assert_eq!(space.read_all_by_prefix::<TestStruct>("name", "T").count(), 2);
+        assert_eq!(space.read_all_by_pattern::<TestStruct>("name", "*Y?N").count(), 1);
+        assert_eq!(space.read_all_by_query::<TestStruct>(&field("name").eq("tom")).count(), 1);
+        assert_eq!(space.read_all_by_value::<TestStruct>("count", &1i64).count(), 1);
+        assert_eq!(
+            space.try_take_by_value::<TestStruct>("name", &String::from("NGUYEN")).map(|s| s.count),
+            Some(2)
+        );
 
+        let consumer_space = space.clone();
+        let consumer =
+            thread::spawn(move || consumer_space.take_by_value::<TestStruct>("name", &String::from("ZOE")));
         space.write(TestStruct {
-            count: 5,
-            name: String::from("Duane"),
+            count: 10,
+            name: String::from("Zoe"),
         });
+        assert_eq!(consumer.join().unwrap().count, 10);
+
+        space.set_collation::<TestStruct>("name", Collation::Binary);
+        assert_eq!(space.try_read_by_value::<TestStruct>("name", &String::from("tuan")), None);
+        assert_eq!(space.read_all_by_prefix::<TestStruct>("name", "T").count(), 2);
+        assert_eq!(space.read_all_by_prefix::<TestStruct>("name", "t").count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "chrono")]
+    fn time_range_lookup() {
+        use chrono::{Duration as TimeDelta, TimeZone, Utc};
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Event {
+            name: String,
+            at: String,
+            stamp: i64,
+        }
+
+        let space = Arc::new(TreeObjectSpace::new());
+        space.set_collation::<Event>("at", Collation::Timestamp);
+        space.set_collation::<Event>("stamp", Collation::Timestamp);
+        for &(name, at, stamp) in &[
+            ("a", "2018-01-01T02:00:00+02:00", 1514764800),
+            ("b", "2018-01-01T00:00:00.5Z", 1514764801),
+            ("c", "2018-01-01T01:00:00Z", 1514768400),
+        ] {
+            space.write(Event {
+                name: String::from(name),
+                at: String::from(at),
+                stamp,
+            });
+        }
+        let midnight = Utc.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap();
+        let names = |found: Box<Iterator<Item = Event>>| found.map(|event| event.name).collect::<Vec<_>>();
 
+        // times are compared as instants, whatever their offset or fraction of seconds
         assert_eq!(
-            space.take_all_by_value::<TestStruct>("count", &3).count(),
-            2
+            names(space.read_all_by_range::<Event, _>("at", midnight..midnight + TimeDelta::seconds(1))),
+            vec!["a", "b"]
         );
         assert_eq!(
-            space.take_all_by_value::<TestStruct>("count", &3).count(),
-            0
+            space.try_read_by_range::<Event, _>("at", midnight + TimeDelta::milliseconds(1)..).map(|e| e.name),
+            Some(String::from("b"))
         );
         assert_eq!(
-            space.take_all_by_value::<TestStruct>("count", &5).count(),
-            1
+            names(space.read_all_by_range::<Event, _>("stamp", midnight + TimeDelta::seconds(1)..)),
+            vec!["b", "c"]
+        );
+        assert_eq!(
+            space.try_read_by_value::<Event>("at", &String::from("2018-01-01T00:00:00Z")).map(|e| e.name),
+            Some(String::from("a"))
         );
+        assert!(space.try_read_by_range_checked::<Event, _>("at", ..midnight).unwrap().is_none());
 
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 5,
-                name: String::from("Duane"),
-            },
-            gpa: 4.0,
+        let later = midnight + TimeDelta::days(1);
+        let waiter = {
+            let space = space.clone();
+            thread::spawn(move || space.take_by_range::<Event, _>("stamp", later..).name)
+        };
+        thread::sleep(Duration::from_millis(50));
+        space.write(Event {
+            name: String::from("d"),
+            at: String::from("2018-01-02T00:00:00Z"),
+            stamp: later.timestamp(),
         });
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 3,
-                name: String::from("Tuan"),
-            },
-            gpa: 3.0,
+        assert_eq!(waiter.join().unwrap(), "d");
+    }
+
+    #[test]
+    #[cfg(feature = "uuid")]
+    fn uuid_value_lookup() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Message {
+            id: Uuid,
+            reply_to: Option<Uuid>,
+        }
+
+        let space = TreeObjectSpace::new();
+        let first = Uuid::from_u128(0x936d_a01f_9abd_4d9d_80c7_02af_85c8_22a8);
+        let second = Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+        space.write(Message {
+            id: first,
+            reply_to: None,
         });
-        space.write(CompoundStruct {
-            person: TestStruct {
-                count: 3,
-                name: String::from("Minh"),
-            },
-            gpa: 3.0,
+        space.write(Message {
+            id: second,
+            reply_to: Some(first),
         });
 
+        assert_eq!(space.try_read_by_value::<Message>("id", &second).map(|m| m.reply_to), Some(Some(first)));
+        assert_eq!(space.read_all_by_value::<Message>("reply_to", &first).count(), 1);
+        // uuids are stored as their string form
         assert_eq!(
-            space
-                .take_all_by_value::<CompoundStruct>("gpa", &3.0)
-                .count(),
-            2
-        );
-        assert_eq!(
-            space
-                .take_all_by_value::<CompoundStruct>("person.count", &3)
-                .count(),
-            0
-        );
-        assert_eq!(
-            space
-                .take_all_by_value::<CompoundStruct>("person.count", &5)
-                .count(),
-            1
+            space.try_read_by_value::<Message>("id", &first.to_string()).map(|m| m.id),
+            Some(first)
         );
+        assert_eq!(space.try_take_by_value::<Message>("id", &first).map(|m| m.id), Some(first));
+        assert_eq!(space.try_read_by_value::<Message>("id", &first), None);
+        assert_eq!(space.take_by_value::<Message>("reply_to", &first).id, second);
+    }
+
+    #[test]
+    fn bytes_not_indexed() {
+        extern crate serde_bytes;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Attachment {
+            name: String,
+            #[serde(with = "serde_bytes")]
+            data: Vec<u8>,
+            plain: Vec<u8>,
+        }
+
+        let space = TreeObjectSpace::new();
+        let data: Vec<u8> = (0..10_000).map(|i| (i % 256) as u8).collect();
+        space.write(Attachment {
+            name: String::from("blob"),
+            data: data.clone(),
+            plain: vec![7],
+        });
+
+        let schema = space.schema::<Attachment>().unwrap();
+        assert_eq!(schema.get("data"), Some(FieldKind::Bytes));
+        assert_eq!(schema.get("plain"), Some(FieldKind::Seq));
+        // the elements of a plain `Vec<u8>` are indexed, the bytes are not
+        assert!(space.try_read_by_value::<Attachment>("plain[]", &7i64).is_some());
+        assert!(space.try_read_by_value::<Attachment>("data[]", &7i64).is_none());
+        match space.try_read_by_value_checked::<Attachment>("data", &7i64) {
+            Err(SpaceError::WrongType { kind, .. }) => assert_eq!(kind, FieldKind::Bytes),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert_eq!(space.try_take_by_value::<Attachment>("name", &String::from("blob")).unwrap().data, data);
+        assert!(space.try_read::<Attachment>().is_none());
+    }
+
+    #[test]
+    fn spill_to_disk() {
+        use std::env;
+        use std::process;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Frame {
+            id: i64,
+            pixels: Vec<u8>,
+        }
+
+        let dir = env::temp_dir().join(format!("object-space-spill-{}", process::id()));
+        let files = || fs::read_dir(&dir).map_or(0, |files| files.count());
+        let space = TreeObjectSpace::builder().spill_to_disk(dir.clone(), 1024).build();
+        space.index_fields::<Frame>(&["id"]);
+        space.write(Frame {
+            id: 1,
+            pixels: vec![1; 16],
+        });
+        assert_eq!(files(), 0);
+        for id in 2..4 {
+            space.write(Frame {
+                id,
+                pixels: vec![id as u8; 4096],
+            });
+        }
+        assert_eq!(files(), 2);
+
+        // lookups find spilled structs by their fields, which stay in memory
+        assert_eq!(space.try_read_by_value::<Frame>("id", &2i64).unwrap().pixels, vec![2; 4096]);
+        assert_eq!(space.read_all_by_range::<Frame, _>("id", 1i64..).count(), 3);
+        assert!(space.update_by_value::<Frame, _>("id", &1i64, |frame| Frame {
+            pixels: vec![5; 2048],
+            ..frame
+        }));
+        assert_eq!(files(), 3);
+
+        assert_eq!(space.take_by_value::<Frame>("id", &3i64).pixels, vec![3; 4096]);
+        assert_eq!(files(), 2);
+        drop(space);
+        assert_eq!(files(), 0);
+        let _ = fs::remove_dir(&dir);
     }
 
     #[test]
@@ -1559,15 +11585,15 @@ mod tests {
         space.write(TestEnum::Int(4));
         assert_eq!(space.read::<TestEnum>(), TestEnum::Int(4));
         assert_eq!(
-            space.try_read_by_value::<TestEnum>("Int", &4),
+            space.try_read_by_value::<TestEnum>("Int", &4i64),
             Some(TestEnum::Int(4))
         );
         assert_eq!(
-            space.try_read_by_value::<TestEnum>("Struct.count", &4),
+            space.try_read_by_value::<TestEnum>("Struct.count", &4i64),
             None
         );
         assert_eq!(
-            space.try_read_by_range::<TestEnum, _>("Struct.count", 3..5),
+            space.try_read_by_range::<TestEnum, _>("Struct.count", 3i64..5i64),
             None
         );
 
@@ -1576,14 +11602,14 @@ mod tests {
             name: String::from("Tuan"),
         });
         assert_eq!(
-            space.read_by_value::<TestEnum>("Struct.count", &4),
+            space.read_by_value::<TestEnum>("Struct.count", &4i64),
             TestEnum::Struct {
                 count: 4,
                 name: String::from("Tuan")
             }
         );
         assert_eq!(
-            space.take_by_range::<TestEnum, _>("Struct.count", 3..5),
+            space.take_by_range::<TestEnum, _>("Struct.count", 3i64..5i64),
             TestEnum::Struct {
                 count: 4,
                 name: String::from("Tuan")
@@ -0,0 +1,419 @@
+use std::fmt;
+use std::ops::Bound;
+
+use ordered_float::NotNaN;
+use serde_json::Value;
+
+use entry::record::Field;
+
+/// A value which the field of a struct is compared to in a `Query`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FieldValue {
+    /// The field is null, e.g: an `Option` which is `None`.
+    Null,
+    Bool(bool),
+    Int(i64),
+    /// An integer too large to fit in an `i64`, e.g: a large `u64` id.
+    BigInt(i128),
+    Float(f64),
+    Str(String),
+}
+
+impl FieldValue {
+    pub(crate) fn to_field(&self) -> Field {
+        match *self {
+            FieldValue::Null => Field::Null,
+            FieldValue::Bool(b) => Field::from(b),
+            FieldValue::Int(i) => Field::from(i),
+            FieldValue::BigInt(i) => Field::from(i),
+            FieldValue::Float(f) => Field::from(f),
+            FieldValue::Str(ref s) => Field::from(s.clone()),
+        }
+    }
+
+    /// Return the value of a basic field sent as JSON, e.g: by a client in another language.
+    pub(crate) fn from_json(value: Value) -> Option<FieldValue> {
+        match value {
+            Value::Null => Some(FieldValue::Null),
+            Value::Bool(b) => Some(FieldValue::Bool(b)),
+            Value::Number(n) => n.as_i64()
+                .map(FieldValue::Int)
+                .or_else(|| n.as_u64().map(FieldValue::from))
+                .or_else(|| n.as_f64().map(FieldValue::Float)),
+            Value::String(s) => Some(FieldValue::Str(s)),
+            Value::Array(_) | Value::Object(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FieldValue::Null => write!(f, "null"),
+            FieldValue::Bool(b) => write!(f, "{}", b),
+            FieldValue::Int(i) => write!(f, "{}", i),
+            FieldValue::BigInt(i) => write!(f, "{}", i),
+            FieldValue::Float(x) => write!(f, "{:?}", x),
+            FieldValue::Str(ref s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+impl From<FieldValue> for Field {
+    fn from(value: FieldValue) -> Self {
+        value.to_field()
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(b: bool) -> Self {
+        FieldValue::Bool(b)
+    }
+}
+
+impl From<i32> for FieldValue {
+    fn from(i: i32) -> Self {
+        FieldValue::Int(i64::from(i))
+    }
+}
+
+impl From<i64> for FieldValue {
+    fn from(i: i64) -> Self {
+        FieldValue::Int(i)
+    }
+}
+
+impl From<u64> for FieldValue {
+    fn from(u: u64) -> Self {
+        FieldValue::from(i128::from(u))
+    }
+}
+
+impl From<i128> for FieldValue {
+    fn from(i: i128) -> Self {
+        if i >= i128::from(i64::MIN) && i <= i128::from(i64::MAX) {
+            FieldValue::Int(i as i64)
+        } else {
+            FieldValue::BigInt(i)
+        }
+    }
+}
+
+impl From<f64> for FieldValue {
+    fn from(f: f64) -> Self {
+        FieldValue::Float(f)
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(s: String) -> Self {
+        FieldValue::Str(s)
+    }
+}
+
+impl<'a> From<&'a str> for FieldValue {
+    fn from(s: &'a str) -> Self {
+        FieldValue::Str(s.to_owned())
+    }
+}
+
+/// A condition on the fields of a struct, built by starting with `field`.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::field;
+/// let query = field("count").eq(3).or(field("name").eq("Tuan"));
+/// assert_eq!(query.to_string(), "count == 3 || name == \"Tuan\"");
+///
+/// let query = field("finished").eq(false).and(field("priority").gt(5));
+/// assert_eq!(query.to_string(), "finished == false && priority in 5..");
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Query {
+    /// The field is equal to the value.
+    Eq(String, FieldValue),
+    /// The field is within the bounds.
+    Range(String, Bound<FieldValue>, Bound<FieldValue>),
+    /// Any of the queries is satisfied.
+    Or(Vec<Query>),
+    /// All of the queries are satisfied.
+    And(Vec<Query>),
+}
+
+impl Query {
+    /// Return a query satisfied by structs satisfying either `self` or `other`.
+    pub fn or(self, other: Query) -> Query {
+        let mut queries = match self {
+            Query::Or(queries) => queries,
+            query => vec![query],
+        };
+        match other {
+            Query::Or(others) => queries.extend(others),
+            query => queries.push(query),
+        }
+        Query::Or(queries)
+    }
+
+    /// Return a query satisfied by structs satisfying both `self` and `other`.
+    pub fn and(self, other: Query) -> Query {
+        let mut queries = match self {
+            Query::And(queries) => queries,
+            query => vec![query],
+        };
+        match other {
+            Query::And(others) => queries.extend(others),
+            query => queries.push(query),
+        }
+        Query::And(queries)
+    }
+
+    /// Return a query satisfied by structs whose fields all equal the specified values.
+    pub fn matching(fields: &[(&str, FieldValue)]) -> Query {
+        Query::And(
+            fields
+                .iter()
+                .map(|&(name, ref value)| Query::Eq(name.to_owned(), value.clone()))
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Query::Eq(ref field, ref value) => write!(f, "{} == {}", field, value),
+            Query::Range(ref field, ref start, ref end) => {
+                write!(f, "{} in ", field)?;
+                if let Bound::Included(ref start) | Bound::Excluded(ref start) = *start {
+                    write!(f, "{}", start)?;
+                }
+                match *end {
+                    Bound::Included(ref end) => write!(f, "..={}", end),
+                    Bound::Excluded(ref end) => write!(f, "..{}", end),
+                    Bound::Unbounded => write!(f, ".."),
+                }
+            }
+            Query::Or(ref queries) => {
+                for (i, query) in queries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " || ")?;
+                    }
+                    write!(f, "{}", query)?;
+                }
+                Ok(())
+            }
+            Query::And(ref queries) => {
+                for (i, query) in queries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " && ")?;
+                    }
+                    match *query {
+                        Query::Or(_) => write!(f, "({})", query)?,
+                        _ => write!(f, "{}", query)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Start building a query on a field, e.g: `"person.name"`.
+pub fn field(name: &str) -> FieldCondition {
+    FieldCondition {
+        name: name.to_owned(),
+    }
+}
+
+/// A field which a condition is about to be put on. See `field`.
+pub struct FieldCondition {
+    name: String,
+}
+
+impl FieldCondition {
+    pub fn eq<V: Into<FieldValue>>(self, value: V) -> Query {
+        Query::Eq(self.name, value.into())
+    }
+
+    pub fn lt<V: Into<FieldValue>>(self, value: V) -> Query {
+        Query::Range(self.name, Bound::Unbounded, Bound::Excluded(value.into()))
+    }
+
+    pub fn le<V: Into<FieldValue>>(self, value: V) -> Query {
+        Query::Range(self.name, Bound::Unbounded, Bound::Included(value.into()))
+    }
+
+    pub fn gt<V: Into<FieldValue>>(self, value: V) -> Query {
+        Query::Range(self.name, Bound::Excluded(value.into()), Bound::Unbounded)
+    }
+
+    pub fn ge<V: Into<FieldValue>>(self, value: V) -> Query {
+        Query::Range(self.name, Bound::Included(value.into()), Bound::Unbounded)
+    }
+
+    /// The field is at least `start` and less than `end`.
+    pub fn between<V: Into<FieldValue>>(self, start: V, end: V) -> Query {
+        Query::Range(
+            self.name,
+            Bound::Included(start.into()),
+            Bound::Excluded(end.into()),
+        )
+    }
+}
+
+/// Build a query from a template of the fields of a struct, as in the tuple spaces of Linda.
+///
+/// Each field of the template is either an actual, which the field of a struct must equal,
+/// a range, which the field must be within, or the formal `_`, which any value matches.
+/// Fields are named by identifiers, or by strings for nested fields, e.g: `"person.name"`.
+/// The bounds of a range are literals or identifiers: other expressions are parenthesized, e.g: `(-5)..5`.
+///
+/// The template is a `Query`, answered by the indices of all its fields,
+/// so it is looked up by the methods of `QueryObjectSpace`.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate object_space;
+/// #[macro_use]
+/// extern crate serde_derive;
+/// # use object_space::{TreeObjectSpace, ObjectSpace, QueryObjectSpace};
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct Task {
+///     finished: bool,
+///     start: i64,
+///     end: i64,
+/// }
+///
+/// # fn main() {
+/// let space = TreeObjectSpace::new();
+/// space.write(Task { finished: false, start: 0, end: 150 });
+/// space.write(Task { finished: false, start: 20, end: 50 });
+///
+/// let limit = 100;
+/// let task = space.take_by_query::<Task>(&template!{ finished: false, start: _, end: 0..limit });
+/// assert_eq!(task.end, 50);
+/// assert_eq!(
+///     template!{ finished: false, start: _, end: 0..100 }.to_string(),
+///     "finished == false && end in 0..100"
+/// );
+/// # }
+/// ```
+#[macro_export]
+macro_rules! template {
+    (@munch [$($queries:expr),*];) => {
+        $crate::Query::And(vec![$($queries),*])
+    };
+    (@munch [$($queries:expr),*]; , $($rest:tt)*) => {
+        template!(@munch [$($queries),*]; $($rest)*)
+    };
+    (@munch [$($queries:expr),*]; $name:tt : _ , $($rest:tt)*) => {
+        template!(@munch [$($queries),*]; $($rest)*)
+    };
+    (@munch [$($queries:expr),*]; $name:tt : $start:tt ..= $end:tt , $($rest:tt)*) => {
+        template!(@munch [$($queries,)* template!(@range $name, Included($start), Included($end))]; $($rest)*)
+    };
+    (@munch [$($queries:expr),*]; $name:tt : $start:tt .. $end:tt , $($rest:tt)*) => {
+        template!(@munch [$($queries,)* template!(@range $name, Included($start), Excluded($end))]; $($rest)*)
+    };
+    (@munch [$($queries:expr),*]; $name:tt : $start:tt .. , $($rest:tt)*) => {
+        template!(@munch [$($queries,)* template!(@range $name, Included($start), Unbounded)]; $($rest)*)
+    };
+    (@munch [$($queries:expr),*]; $name:tt : ..= $end:tt , $($rest:tt)*) => {
+        template!(@munch [$($queries,)* template!(@range $name, Unbounded, Included($end))]; $($rest)*)
+    };
+    (@munch [$($queries:expr),*]; $name:tt : .. $end:tt , $($rest:tt)*) => {
+        template!(@munch [$($queries,)* template!(@range $name, Unbounded, Excluded($end))]; $($rest)*)
+    };
+    (@munch [$($queries:expr),*]; $name:tt : $value:expr , $($rest:tt)*) => {
+        template!(@munch [$($queries,)* $crate::Query::Eq(
+            String::from(template!(@name $name)),
+            $crate::FieldValue::from($value),
+        )]; $($rest)*)
+    };
+    (@range $name:tt, $start:ident $(($start_value:expr))*, $end:ident $(($end_value:expr))*) => {
+        $crate::Query::Range(
+            String::from(template!(@name $name)),
+            ::std::ops::Bound::$start $(($crate::FieldValue::from($start_value)))*,
+            ::std::ops::Bound::$end $(($crate::FieldValue::from($end_value)))*,
+        )
+    };
+    (@name $name:ident) => {
+        stringify!($name)
+    };
+    (@name $name:expr) => {
+        $name
+    };
+    ($($fields:tt)*) => {
+        template!(@munch []; $($fields)* ,)
+    };
+}
+
+/// The bounds of a range query, converted to the type of the key of the index.
+pub(crate) enum TypedBounds {
+    Int((Bound<i128>, Bound<i128>)),
+    Float((Bound<NotNaN<f64>>, Bound<NotNaN<f64>>)),
+    Str((Bound<String>, Bound<String>)),
+}
+
+impl TypedBounds {
+    /// Panic if the bounds are of different types, or of a type which could not be ordered.
+    pub(crate) fn new(start: &Bound<FieldValue>, end: &Bound<FieldValue>) -> Self {
+        let value = match (start, end) {
+            (&Bound::Included(ref value), _) | (&Bound::Excluded(ref value), _) => value,
+            (_, &Bound::Included(ref value)) | (_, &Bound::Excluded(ref value)) => value,
+            _ => panic!("Range query must have at least one bound"),
+        };
+        match *value {
+            FieldValue::Int(_) | FieldValue::BigInt(_) => {
+                TypedBounds::Int((map_bound(start, int_key), map_bound(end, int_key)))
+            }
+            FieldValue::Float(_) => TypedBounds::Float((
+                map_bound(start, float_key),
+                map_bound(end, float_key),
+            )),
+            FieldValue::Str(_) => TypedBounds::Str((
+                map_bound(start, |v| match *v {
+                    FieldValue::Str(ref s) => Some(s.clone()),
+                    _ => None,
+                }),
+                map_bound(end, |v| match *v {
+                    FieldValue::Str(ref s) => Some(s.clone()),
+                    _ => None,
+                }),
+            )),
+            FieldValue::Bool(_) => panic!("Range query is not supported on bool fields"),
+            FieldValue::Null => panic!("Range query is not supported on null values"),
+        }
+    }
+}
+
+fn int_key(value: &FieldValue) -> Option<i128> {
+    match *value {
+        FieldValue::Int(i) => Some(i128::from(i)),
+        FieldValue::BigInt(i) => Some(i),
+        _ => None,
+    }
+}
+
+fn float_key(value: &FieldValue) -> Option<NotNaN<f64>> {
+    match *value {
+        FieldValue::Float(f) => Some(NotNaN::new(f).expect("NaN value is not accepted")),
+        _ => None,
+    }
+}
+
+fn map_bound<T, F>(bound: &Bound<FieldValue>, f: F) -> Bound<T>
+where
+    F: Fn(&FieldValue) -> Option<T>,
+{
+    let convert = |value| f(value).expect("Bounds of a range query must be of the same type");
+    match *bound {
+        Bound::Included(ref value) => Bound::Included(convert(value)),
+        Bound::Excluded(ref value) => Bound::Excluded(convert(value)),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
@@ -0,0 +1,66 @@
+use std::any::{type_name, TypeId};
+use std::collections::HashMap;
+
+/// The stable names of types, which identify them across binaries and versions, unlike their `TypeId`.
+///
+/// A type is named by `std::any::type_name` unless another name has been registered for it,
+/// e.g: so that a type keeps its name when moved to another module or crate.
+#[derive(Clone, Default)]
+pub struct TypeRegistry {
+    names: HashMap<TypeId, &'static str>,
+    ids: HashMap<&'static str, TypeId>,
+}
+
+impl TypeRegistry {
+    /// Name type T, instead of its default name or the name registered so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is already registered for another type.
+    pub fn register<T>(&mut self, name: &'static str)
+    where
+        T: 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        if self.ids.get(name).is_some_and(|&id| id != type_id) {
+            panic!("Type name `{}` is already registered for another type", name);
+        }
+        if let Some(previous) = self.names.insert(type_id, name) {
+            self.ids.remove(previous);
+        }
+        self.ids.insert(name, type_id);
+    }
+
+    /// Make type T found by its name, registering its default name if it has none.
+    pub fn add<T>(&mut self) -> &'static str
+    where
+        T: 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        if let Some(name) = self.names.get(&type_id) {
+            return name;
+        }
+        let name = type_name::<T>();
+        // a type registered with the default name of another keeps it
+        if !self.ids.contains_key(name) {
+            self.names.insert(type_id, name);
+            self.ids.insert(name, type_id);
+        }
+        name
+    }
+
+    pub fn name<T>(&self) -> &'static str
+    where
+        T: 'static,
+    {
+        self.names
+            .get(&TypeId::of::<T>())
+            .cloned()
+            .unwrap_or_else(type_name::<T>)
+    }
+
+    /// Return the type with the specified name, if it has been registered or added.
+    pub fn type_id(&self, name: &str) -> Option<TypeId> {
+        self.ids.get(name).cloned()
+    }
+}
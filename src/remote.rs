@@ -0,0 +1,1227 @@
+use std::cmp::min;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::{Bound, RangeBounds};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{self, Value};
+
+use entry::handle::ObjectHandle;
+use entry::record::Record;
+use entry::subscription::Subscription;
+use entry::Entry;
+use error::SpaceError;
+use object_space::{
+    DynObjectSpace, ObjectSpace, QueryObjectSpace, RangeLookupObjectSpace, TreeObjectSpace, ValueLookupObjectSpace,
+};
+use query::{FieldValue, Query};
+use registry::TypeRegistry;
+
+// a client sending a larger message is disconnected
+pub(crate) const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+// how often a blocked lookup looks for structs written by the threads of the space rather than through the server
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A request sent by a `RemoteObjectSpace` to a `SpaceServer`.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum Request {
+    Write {
+        type_name: String,
+        objs: Vec<Value>,
+    },
+    Lookup {
+        type_name: String,
+        // None matches every struct of the type
+        query: Option<Query>,
+        take: bool,
+        mode: Mode,
+        // fail with a `SpaceError` instead of panicking if the query could not be answered
+        checked: bool,
+    },
+}
+
+/// How many structs a lookup returns, and whether it waits for them.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum Mode {
+    One,
+    All,
+    Sample(usize),
+    /// Wait for a struct, up to the timeout if any.
+    Block(Option<Duration>),
+    /// The struct with the handle, if it is still there.
+    Handle(ObjectHandle),
+}
+
+/// Why a request failed.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum Failure {
+    /// A checked lookup could not be answered.
+    Space(SpaceError),
+    /// The request panicked, or the structs could not be converted.
+    Message(String),
+}
+
+pub(crate) type Response = Result<Vec<Value>, Failure>;
+
+/// Send a message, prefixed by its length as a big-endian `u32`.
+fn send<W, M>(writer: &mut W, message: &M) -> io::Result<()>
+where
+    W: Write,
+    M: Serialize,
+{
+    let bytes = serde_json::to_vec(message)?;
+    if bytes.len() > u32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "message is too large to be sent",
+        ));
+    }
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+/// Receive a message sent with `send`, or None if the connection was closed in between messages.
+/// A message longer than `max_len` bytes is rejected before it is read.
+fn receive<R, M>(reader: &mut R, max_len: usize) -> io::Result<Option<M>>
+where
+    R: Read,
+    M: DeserializeOwned,
+{
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message is too large"));
+    }
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(Some(serde_json::from_slice(&bytes)?))
+}
+
+/// A server sharing the structs of a `TreeObjectSpace` with `RemoteObjectSpace` clients over TCP.
+///
+/// Types are told apart by their name, e.g: `my_crate::Task`, or the name registered with `register_type`,
+/// so clients should share the definitions of the types they exchange.
+/// Structs written by clients to a type the space holds no entry for are kept as documents,
+/// like those written with `TreeObjectSpace::write_value`.
+/// Every message is a JSON document prefixed by its length, and a client sending a request
+/// larger than 16 MiB is disconnected.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::{ObjectSpace, RemoteObjectSpace, SpaceServer};
+/// let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+/// let addr = server.local_addr().unwrap();
+/// server.spawn();
+///
+/// let producer = RemoteObjectSpace::connect(addr).unwrap();
+/// let consumer = RemoteObjectSpace::connect(addr).unwrap();
+/// producer.write(String::from("Hello World"));
+/// assert_eq!(consumer.take::<String>(), String::from("Hello World"));
+/// ```
+pub struct SpaceServer {
+    listener: TcpListener,
+    pub(crate) store: Arc<Store>,
+}
+
+impl SpaceServer {
+    /// Listen for clients on the specified address, with a space of its own.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<SpaceServer> {
+        SpaceServer::bind_space(addr, Arc::new(TreeObjectSpace::new()))
+    }
+
+    /// Listen for clients on the specified address, sharing the structs of an existing space,
+    /// e.g: so that local threads and remote clients exchange structs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use object_space::{ObjectSpace, RemoteObjectSpace, SpaceServer, TreeObjectSpace};
+    /// let space = Arc::new(TreeObjectSpace::new());
+    /// space.write::<i64>(3);
+    /// let server = SpaceServer::bind_space("127.0.0.1:0", space.clone()).unwrap();
+    /// let remote = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+    /// server.spawn();
+    ///
+    /// assert_eq!(remote.take::<i64>(), 3);
+    /// remote.write::<i64>(5);
+    /// assert_eq!(space.take::<i64>(), 5);
+    /// ```
+    pub fn bind_space<A: ToSocketAddrs>(addr: A, space: Arc<TreeObjectSpace>) -> io::Result<SpaceServer> {
+        Ok(SpaceServer {
+            listener: TcpListener::bind(addr)?,
+            store: Arc::new(Store::new(space)),
+        })
+    }
+
+    /// Return the space whose structs are shared with the clients.
+    pub fn space(&self) -> &Arc<TreeObjectSpace> {
+        &self.store.space
+    }
+
+    /// Return the address the server listens on, e.g: after binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve clients, each on its own thread. The call blocks forever unless listening fails.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let store = self.store.clone();
+            thread::spawn(move || {
+                // a client which disconnects or sends garbage only loses its own connection
+                let _ = serve(&store, stream);
+            });
+        }
+        Ok(())
+    }
+
+    /// Serve clients on a background thread.
+    pub fn spawn(self) -> JoinHandle<io::Result<()>> {
+        thread::spawn(move || self.run())
+    }
+}
+
+fn serve(store: &Store, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream.try_clone()?);
+    while let Some(request) = receive::<_, Request>(&mut reader, MAX_MESSAGE_LEN)? {
+        let taken = match request {
+            Request::Lookup {
+                ref type_name,
+                take: true,
+                ..
+            } => Some(type_name.clone()),
+            _ => None,
+        };
+        let response = store.respond_while(request, &|| is_connected(&stream));
+        if let Err(e) = send(&mut writer, &response) {
+            // the structs taken for a client which left are written back rather than lost
+            if let (Some(type_name), Ok(objs)) = (taken, response) {
+                store.put_back(&type_name, objs);
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Return false if the peer has closed the connection.
+/// Must only be called while the peer waits for a response, as it peeks at the next request.
+fn is_connected(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+    let mut byte = [0; 1];
+    let connected = match stream.peek(&mut byte) {
+        Ok(0) => false,
+        Ok(_) => true,
+        Err(e) => e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::Interrupted,
+    };
+    let _ = stream.set_nonblocking(false);
+    connected
+}
+
+/// The space shared by a server, whose types are named by the clients.
+pub(crate) struct Store {
+    space: Arc<TreeObjectSpace>,
+    // notified whenever structs are written through the server, so that blocked lookups look again
+    written: (Mutex<()>, Condvar),
+}
+
+impl Store {
+    pub(crate) fn new(space: Arc<TreeObjectSpace>) -> Self {
+        Store {
+            space,
+            written: (Mutex::new(()), Condvar::new()),
+        }
+    }
+
+    /// Notify a subscription of the structs of a type written from now on.
+    pub(crate) fn subscribe(&self, type_name: String, subscription: Subscription) {
+        self.space.subscribe_named(&type_name, subscription);
+    }
+
+    /// Answer a request. A bad request, e.g: a range over bool fields,
+    /// fails without bringing the server down.
+    pub(crate) fn respond(&self, request: Request) -> Response {
+        self.respond_while(request, &|| true)
+    }
+
+    /// Answer a request, like `respond`, but stop waiting for a struct once `connected` returns false.
+    pub(crate) fn respond_while(&self, request: Request, connected: &Fn() -> bool) -> Response {
+        panic::catch_unwind(AssertUnwindSafe(|| self.handle(request, connected))).unwrap_or_else(|cause| {
+            Err(Failure::Message(cause
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| cause.downcast_ref::<&str>().map(|s| (*s).to_owned()))
+                .unwrap_or_else(|| String::from("request failed"))))
+        })
+    }
+
+    /// Write back structs taken for a client which could not receive them.
+    pub(crate) fn put_back(&self, type_name: &str, objs: Vec<Value>) {
+        for obj in objs {
+            let _ = self.space.try_write_value(type_name, obj);
+        }
+        self.notify_written();
+    }
+
+    fn notify_written(&self) {
+        let &(ref lock, ref cvar) = &self.written;
+        let _written = lock.lock().unwrap_or_else(|e| e.into_inner());
+        cvar.notify_all();
+    }
+
+    fn handle(&self, request: Request, connected: &Fn() -> bool) -> Response {
+        match request {
+            Request::Write { type_name, objs } => {
+                let mut handles = Vec::new();
+                let mut written = Ok(());
+                for obj in objs {
+                    match self.space.try_write_value(&type_name, obj) {
+                        Ok(handle) => handles.push(to_value(&handle)),
+                        Err(error) => {
+                            written = Err(Failure::Space(error));
+                            break;
+                        }
+                    }
+                }
+                // structs written before a failure are kept, so waiters are woken up for them
+                self.notify_written();
+                written.map(|()| handles)
+            }
+            Request::Lookup {
+                type_name,
+                query,
+                take,
+                mode,
+                checked,
+            } => {
+                let query = query.as_ref();
+                let records = match mode {
+                    Mode::Block(timeout) => self.wait_for(&type_name, query, take, checked, timeout, connected),
+                    mode => self.lookup(&type_name, query, take, checked, &mode),
+                }.map_err(Failure::Space)?;
+                records
+                    .iter()
+                    .map(|record| {
+                        record
+                            .to_json()
+                            .map_err(|e| Failure::Space(SpaceError::Serialization(e.to_string())))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    fn lookup(
+        &self,
+        type_name: &str,
+        query: Option<&Query>,
+        take: bool,
+        checked: bool,
+        mode: &Mode,
+    ) -> Result<Vec<Arc<Record>>, SpaceError> {
+        let check = |entry: &Entry| match (checked, query) {
+            (true, Some(query)) => entry.check_query(query),
+            _ => Ok(()),
+        };
+        let found = if take {
+            self.space.take_named(type_name, "remote_take", |entry| {
+                check(entry).map(|()| remove(entry, query, mode))
+            })
+        } else {
+            self.space
+                .read_named(type_name, |entry| check(entry).map(|()| get(entry, query, mode)))
+        };
+        found.unwrap_or_else(|| Ok(Vec::new()))
+    }
+
+    /// Wait for a struct satisfying the query, up to the timeout if any,
+    /// until the space is closed or the client is no longer connected.
+    fn wait_for(
+        &self,
+        type_name: &str,
+        query: Option<&Query>,
+        take: bool,
+        checked: bool,
+        timeout: Option<Duration>,
+        connected: &Fn() -> bool,
+    ) -> Result<Vec<Arc<Record>>, SpaceError> {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let &(ref lock, ref cvar) = &self.written;
+        // held while looking up, so that no write through the server is missed
+        let mut written = lock.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let found = self.lookup(type_name, query, take, checked, &Mode::One)?;
+            if !found.is_empty() {
+                return Ok(found);
+            }
+            if self.space.is_closed() {
+                return Err(SpaceError::Closed);
+            }
+            // structs written to the space by its own threads are only seen at the next poll
+            let mut wait = POLL_INTERVAL;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(Vec::new());
+                }
+                wait = min(wait, deadline - now);
+            }
+            // a struct taken for a client which left would be lost
+            if !connected() {
+                return Ok(Vec::new());
+            }
+            written = cvar.wait_timeout(written, wait).unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+}
+
+fn get(entry: &Entry, query: Option<&Query>, mode: &Mode) -> Vec<Arc<Record>> {
+    match (mode, query) {
+        (&Mode::All, None) => entry.get_all().collect(),
+        (&Mode::All, Some(query)) => entry.get_all_by_query(query),
+        (&Mode::Sample(count), _) => entry.get_sample(count),
+        (&Mode::Handle(handle), _) => entry.get_by_index(handle.index()).into_iter().collect(),
+        (_, None) => entry.get().into_iter().collect(),
+        (_, Some(query)) => entry.get_by_query(query).into_iter().collect(),
+    }
+}
+
+fn remove(entry: &mut Entry, query: Option<&Query>, mode: &Mode) -> Vec<Arc<Record>> {
+    match (mode, query) {
+        (&Mode::All, None) => entry.remove_all(),
+        (&Mode::All, Some(query)) => entry.remove_all_by_query(query),
+        (&Mode::Sample(count), _) => entry.get_sample(count),
+        (&Mode::Handle(handle), _) => entry.remove_by_index(handle.index()).into_iter().collect(),
+        (_, None) => entry.remove().into_iter().collect(),
+        (_, Some(query)) => entry.remove_by_query(query).into_iter().collect(),
+    }
+}
+
+/// An `ObjectSpace` whose structs are held by a `SpaceServer`, possibly on another machine.
+///
+/// A connection is opened for every concurrent call, and kept open for later calls.
+/// The operations of `ObjectSpace` panic if the server could not be reached,
+/// while `try_write`, `try_read_checked`, `try_take_checked` and the `_checked` lookups
+/// return `SpaceError::Remote` instead.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::{ObjectSpace, RemoteObjectSpace, SpaceServer, ValueLookupObjectSpace};
+/// let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+/// let space = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+/// server.spawn();
+///
+/// space.write::<i64>(3);
+/// space.write::<i64>(5);
+/// assert_eq!(space.try_take_by_value::<i64>("", &5i64), Some(5));
+/// assert_eq!(space.read_all::<i64>().collect::<Vec<_>>(), vec![3]);
+/// ```
+pub struct RemoteObjectSpace {
+    addr: SocketAddr,
+    idle: Mutex<Vec<TcpStream>>,
+    registry: RwLock<TypeRegistry>,
+}
+
+impl RemoteObjectSpace {
+    /// Connect to the `SpaceServer` listening on the specified address.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<RemoteObjectSpace> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(RemoteObjectSpace {
+            addr: stream.peer_addr()?,
+            idle: Mutex::new(vec![stream]),
+            registry: RwLock::default(),
+        })
+    }
+
+    /// Name type T, so that it is matched by this name on the server instead of its full Rust name,
+    /// like `TreeObjectSpace::register_type`. Clients built from different binaries share the structs
+    /// of the types they name alike.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is already registered for another type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{ObjectSpace, RemoteObjectSpace, SpaceServer};
+    /// let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+    /// let producer = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+    /// let consumer = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+    /// server.spawn();
+    ///
+    /// producer.register_type::<i64>("counter");
+    /// consumer.register_type::<u64>("counter");
+    /// producer.write::<i64>(3);
+    /// assert_eq!(consumer.try_take::<u64>(), Some(3));
+    /// ```
+    pub fn register_type<T>(&self, name: &'static str)
+    where
+        T: 'static,
+    {
+        self.registry.write().unwrap().register::<T>(name);
+    }
+
+    fn type_name<T>(&self) -> String
+    where
+        T: 'static,
+    {
+        self.registry.read().unwrap().name::<T>().to_owned()
+    }
+
+    /// Send a request and return the response, or `SpaceError::Remote` if the server could not be reached
+    /// or failed to answer.
+    fn call(&self, request: &Request) -> Result<Vec<Value>, SpaceError> {
+        let response = self.exchange(request).map_err(|e| SpaceError::Remote(e.to_string()))?;
+        match response {
+            Ok(values) => Ok(values),
+            Err(Failure::Space(error)) => Err(error),
+            Err(Failure::Message(message)) => Err(SpaceError::Remote(message)),
+        }
+    }
+
+    fn exchange(&self, request: &Request) -> io::Result<Response> {
+        let idle = self.idle.lock().unwrap().pop();
+        let mut stream = match idle {
+            Some(stream) => stream,
+            None => TcpStream::connect(self.addr)?,
+        };
+        send(&mut stream, request)?;
+        let response = receive(&mut stream, u32::MAX as usize)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by the server")
+        })?;
+        // a connection which failed is dropped rather than used again
+        self.idle.lock().unwrap().push(stream);
+        Ok(response)
+    }
+
+    /// Write a struct of type T, or return an error instead of panicking,
+    /// e.g: `SpaceError::Remote` if the server could not be reached.
+    /// The server never waits for room in a type whose capacity blocks writers, like `TreeObjectSpace::try_write`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use object_space::{RemoteObjectSpace, SpaceServer};
+    /// let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+    /// let space = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+    /// server.spawn();
+    ///
+    /// assert!(space.try_write::<i64>(3).is_ok());
+    /// ```
+    pub fn try_write<T>(&self, obj: T) -> Result<ObjectHandle, SpaceError>
+    where
+        T: Serialize + 'static,
+    {
+        let handle = self.try_send_all(Some(obj))?
+            .pop()
+            .ok_or_else(|| SpaceError::Remote(String::from("no handle returned by the space server")))?;
+        serde_json::from_value(handle).map_err(|e| SpaceError::Remote(e.to_string()))
+    }
+
+    /// Like `try_read`, but return an error instead of panicking, e.g: `SpaceError::Remote`
+    /// if the server could not be reached, or `SpaceError::Serialization` if the struct found
+    /// could not be read as T.
+    pub fn try_read_checked<T>(&self) -> Result<Option<T>, SpaceError>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        Ok(self.find(None, false, Mode::One, true)?.pop())
+    }
+
+    /// Like `try_take`, but return an error instead of panicking, e.g: `SpaceError::Remote`
+    /// if the server could not be reached, or `SpaceError::Serialization` if the struct taken
+    /// could not be read as T.
+    pub fn try_take_checked<T>(&self) -> Result<Option<T>, SpaceError>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        Ok(self.find(None, true, Mode::One, true)?.pop())
+    }
+
+    /// Write the structs and return their handles.
+    fn send_all<T, I>(&self, objs: I) -> Vec<Value>
+    where
+        T: Serialize + 'static,
+        I: IntoIterator<Item = T>,
+    {
+        self.try_send_all(objs).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn try_send_all<T, I>(&self, objs: I) -> Result<Vec<Value>, SpaceError>
+    where
+        T: Serialize + 'static,
+        I: IntoIterator<Item = T>,
+    {
+        let objs = objs.into_iter()
+            .map(|obj| serde_json::to_value(&obj).map_err(|e| SpaceError::Serialization(e.to_string())))
+            .collect::<Result<_, _>>()?;
+        let request = Request::Write {
+            type_name: self.type_name::<T>(),
+            objs,
+        };
+        self.call(&request)
+    }
+
+    fn lookup<T>(&self, query: Option<Query>, take: bool, mode: Mode) -> Vec<T>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        self.find(query, take, mode, false)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Look up the structs satisfying the query, or return why the query could not be answered.
+    fn lookup_checked<T>(&self, query: Query, take: bool, mode: Mode) -> Result<Vec<T>, SpaceError>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        self.find(Some(query), take, mode, true)
+    }
+
+    fn find<T>(&self, query: Option<Query>, take: bool, mode: Mode, checked: bool) -> Result<Vec<T>, SpaceError>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        self.find_json(self.type_name::<T>(), query, take, mode, checked)?
+            .into_iter()
+            .map(|value| serde_json::from_value(value).map_err(|e| SpaceError::Serialization(e.to_string())))
+            .collect()
+    }
+
+    fn find_json(
+        &self,
+        type_name: String,
+        query: Option<Query>,
+        take: bool,
+        mode: Mode,
+        checked: bool,
+    ) -> Result<Vec<Value>, SpaceError> {
+        let request = Request::Lookup {
+            type_name,
+            query,
+            take,
+            mode,
+            checked,
+        };
+        self.call(&request)
+    }
+
+    fn lookup_json(&self, type_name: &str, query: Option<&Query>, take: bool, mode: Mode) -> Vec<Value> {
+        self.find_json(type_name.to_owned(), query.cloned(), take, mode, false)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+fn to_value<T: Serialize>(obj: &T) -> Value {
+    serde_json::to_value(obj).expect("struct cannot be serialized")
+}
+
+impl ObjectSpace for RemoteObjectSpace {
+    fn write<T>(&self, obj: T) -> ObjectHandle
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let handle = self.send_all(Some(obj)).pop().expect("no handle returned by the space server");
+        serde_json::from_value(handle).expect("handle cannot be deserialized")
+    }
+
+    fn write_all<T, I>(&self, objs: I)
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        I: IntoIterator<Item = T>,
+    {
+        self.send_all(objs);
+    }
+
+    fn try_read<T>(&self) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(None, false, Mode::One).pop()
+    }
+
+    fn read_all<'a, T>(&'a self) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        Box::new(self.lookup(None, false, Mode::All).into_iter())
+    }
+
+    fn read_sample<'a, T>(&'a self, count: usize) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        Box::new(self.lookup(None, false, Mode::Sample(count)).into_iter())
+    }
+
+    fn read<T>(&self) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(None, false, Mode::Block(None)).pop().unwrap()
+    }
+
+    fn read_timeout<T>(&self, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(None, false, Mode::Block(Some(timeout))).pop()
+    }
+
+    fn try_take<T>(&self) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(None, true, Mode::One).pop()
+    }
+
+    fn take_all<'a, T>(&'a self) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        Box::new(self.lookup(None, true, Mode::All).into_iter())
+    }
+
+    fn take<T>(&self) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(None, true, Mode::Block(None)).pop().unwrap()
+    }
+
+    fn take_timeout<T>(&self, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(None, true, Mode::Block(Some(timeout))).pop()
+    }
+
+    fn try_read_by_handle<T>(&self, handle: ObjectHandle) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(None, false, Mode::Handle(handle)).pop()
+    }
+
+    fn try_take_by_handle<T>(&self, handle: ObjectHandle) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(None, true, Mode::Handle(handle)).pop()
+    }
+}
+
+impl DynObjectSpace for RemoteObjectSpace {
+    fn write_json(&self, type_name: &str, obj: Value) -> Option<ObjectHandle> {
+        Some(self.write_value(type_name, obj))
+    }
+
+    /// The server keeps the structs of every type by name, so that a type need not be known to it.
+    fn write_value(&self, type_name: &str, value: Value) -> ObjectHandle {
+        let request = Request::Write {
+            type_name: type_name.to_owned(),
+            objs: vec![value],
+        };
+        let handle = self.call(&request).unwrap_or_else(|error| panic!("{}", error)).pop();
+        serde_json::from_value(handle.expect("no handle returned by the space server"))
+            .expect("handle cannot be deserialized")
+    }
+
+    fn try_read_json(&self, type_name: &str) -> Option<Value> {
+        self.lookup_json(type_name, None, false, Mode::One).pop()
+    }
+
+    fn read_all_json(&self, type_name: &str) -> Vec<Value> {
+        self.lookup_json(type_name, None, false, Mode::All)
+    }
+
+    fn try_take_json(&self, type_name: &str) -> Option<Value> {
+        self.lookup_json(type_name, None, true, Mode::One).pop()
+    }
+
+    fn take_all_json(&self, type_name: &str) -> Vec<Value> {
+        self.lookup_json(type_name, None, true, Mode::All)
+    }
+
+    fn try_read_json_by_query(&self, type_name: &str, query: &Query) -> Option<Value> {
+        self.lookup_json(type_name, Some(query), false, Mode::One).pop()
+    }
+
+    fn read_all_json_by_query(&self, type_name: &str, query: &Query) -> Vec<Value> {
+        self.lookup_json(type_name, Some(query), false, Mode::All)
+    }
+
+    fn try_take_json_by_query(&self, type_name: &str, query: &Query) -> Option<Value> {
+        self.lookup_json(type_name, Some(query), true, Mode::One).pop()
+    }
+
+    fn take_all_json_by_query(&self, type_name: &str, query: &Query) -> Vec<Value> {
+        self.lookup_json(type_name, Some(query), true, Mode::All)
+    }
+}
+
+impl QueryObjectSpace for RemoteObjectSpace {
+    fn try_read_by_query<T>(&self, query: &Query) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(Some(query.clone()), false, Mode::One).pop()
+    }
+
+    fn read_all_by_query<'a, T>(&'a self, query: &Query) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        Box::new(self.lookup(Some(query.clone()), false, Mode::All).into_iter())
+    }
+
+    fn read_by_query<T>(&self, query: &Query) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(Some(query.clone()), false, Mode::Block(None))
+            .pop()
+            .unwrap()
+    }
+
+    fn try_take_by_query<T>(&self, query: &Query) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(Some(query.clone()), true, Mode::One).pop()
+    }
+
+    fn take_all_by_query<'a, T>(&'a self, query: &Query) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Deserialize<'de> + 'static,
+    {
+        Box::new(self.lookup(Some(query.clone()), true, Mode::All).into_iter())
+    }
+
+    fn take_by_query<T>(&self, query: &Query) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.lookup(Some(query.clone()), true, Mode::Block(None))
+            .pop()
+            .unwrap()
+    }
+}
+
+fn range_query<U, R>(field: &str, range: &R) -> Query
+where
+    U: Clone + Into<FieldValue>,
+    R: RangeBounds<U>,
+{
+    let convert = |bound: Bound<&U>| match bound {
+        Bound::Included(value) => Bound::Included(value.clone().into()),
+        Bound::Excluded(value) => Bound::Excluded(value.clone().into()),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    Query::Range(
+        field.to_owned(),
+        convert(range.start_bound()),
+        convert(range.end_bound()),
+    )
+}
+
+macro_rules! remote_range{
+    ($($ty:ident)*) => {
+        $(
+            impl RangeLookupObjectSpace<$ty> for RemoteObjectSpace {
+                fn try_read_by_range<T, R>(&self, field: &str, range: R) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.lookup(Some(range_query(field, &range)), false, Mode::One).pop()
+                }
+
+                fn read_all_by_range<'a, T, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    Box::new(self.lookup(Some(range_query(field, &range)), false, Mode::All).into_iter())
+                }
+
+                fn read_by_range<T, R>(&self, field: &str, range: R) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.lookup(Some(range_query(field, &range)), false, Mode::Block(None))
+                        .pop()
+                        .unwrap()
+                }
+
+                fn read_by_range_timeout<T, R>(&self, field: &str, range: R, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.lookup(Some(range_query(field, &range)), false, Mode::Block(Some(timeout)))
+                        .pop()
+                }
+
+                fn try_take_by_range<T, R>(&self, field: &str, range: R) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.lookup(Some(range_query(field, &range)), true, Mode::One).pop()
+                }
+
+                fn take_all_by_range<'a, T, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    Box::new(self.lookup(Some(range_query(field, &range)), true, Mode::All).into_iter())
+                }
+
+                fn take_by_range<T, R>(&self, field: &str, range: R) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.lookup(Some(range_query(field, &range)), true, Mode::Block(None))
+                        .pop()
+                        .unwrap()
+                }
+
+                fn take_by_range_timeout<T, R>(&self, field: &str, range: R, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.lookup(Some(range_query(field, &range)), true, Mode::Block(Some(timeout)))
+                        .pop()
+                }
+
+                fn try_read_by_range_checked<T, R>(&self, field: &str, range: R) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    Ok(self.lookup_checked(range_query(field, &range), false, Mode::One)?.pop())
+                }
+
+                fn read_all_by_range_checked<'a, T, R>(
+                    &'a self,
+                    field: &str,
+                    range: R,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let found = self.lookup_checked(range_query(field, &range), false, Mode::All)?;
+                    Ok(Box::new(found.into_iter()))
+                }
+
+                fn try_take_by_range_checked<T, R>(&self, field: &str, range: R) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    Ok(self.lookup_checked(range_query(field, &range), true, Mode::One)?.pop())
+                }
+
+                fn take_all_by_range_checked<'a, T, R>(
+                    &'a self,
+                    field: &str,
+                    range: R,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let found = self.lookup_checked(range_query(field, &range), true, Mode::All)?;
+                    Ok(Box::new(found.into_iter()))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! remote_key{
+    ($($ty:ty)*) => {
+        $(
+            impl ValueLookupObjectSpace<$ty> for RemoteObjectSpace {
+                fn try_read_by_value<T>(&self, field: &str, key: &$ty) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    self.lookup(Some(query), false, Mode::One).pop()
+                }
+
+                fn read_all_by_value<'a, T>(&'a self, field: &str, key: &$ty) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    Box::new(self.lookup(Some(query), false, Mode::All).into_iter())
+                }
+
+                fn read_by_value<T>(&self, field: &str, key: &$ty) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    self.lookup(Some(query), false, Mode::Block(None)).pop().unwrap()
+                }
+
+                fn read_by_value_timeout<T>(&self, field: &str, key: &$ty, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    self.lookup(Some(query), false, Mode::Block(Some(timeout))).pop()
+                }
+
+                fn try_take_by_value<T>(&self, field: &str, key: &$ty) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    self.lookup(Some(query), true, Mode::One).pop()
+                }
+
+                fn take_all_by_value<'a, T>(&'a self, field: &str, key: &$ty) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    Box::new(self.lookup(Some(query), true, Mode::All).into_iter())
+                }
+
+                fn take_by_value<T>(&self, field: &str, key: &$ty) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    self.lookup(Some(query), true, Mode::Block(None)).pop().unwrap()
+                }
+
+                fn take_by_value_timeout<T>(&self, field: &str, key: &$ty, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    self.lookup(Some(query), true, Mode::Block(Some(timeout))).pop()
+                }
+
+                fn try_read_by_value_checked<T>(&self, field: &str, key: &$ty) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    Ok(self.lookup_checked(query, false, Mode::One)?.pop())
+                }
+
+                fn read_all_by_value_checked<'a, T>(
+                    &'a self,
+                    field: &str,
+                    key: &$ty,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    Ok(Box::new(self.lookup_checked(query, false, Mode::All)?.into_iter()))
+                }
+
+                fn try_take_by_value_checked<T>(&self, field: &str, key: &$ty) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    Ok(self.lookup_checked(query, true, Mode::One)?.pop())
+                }
+
+                fn take_all_by_value_checked<'a, T>(
+                    &'a self,
+                    field: &str,
+                    key: &$ty,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let query = Query::Eq(field.to_owned(), key.clone().into());
+                    Ok(Box::new(self.lookup_checked(query, true, Mode::All)?.into_iter()))
+                }
+            }
+        )*
+    };
+}
+
+remote_range!{i64 u64 i128 String f64}
+remote_key!{i64 u64 i128 String bool f64 FieldValue}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct TestStruct {
+        count: i32,
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct CompoundStruct {
+        person: TestStruct,
+        gpa: f64,
+    }
+
+    #[test]
+    fn oversized_request() {
+        let mut bytes = u32::MAX.to_be_bytes().to_vec();
+        bytes.extend_from_slice(b"{}");
+        let error = receive::<_, Request>(&mut Cursor::new(bytes), MAX_MESSAGE_LEN).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn shared_space() {
+        let space = Arc::new(TreeObjectSpace::new());
+        let server = SpaceServer::bind_space("127.0.0.1:0", space.clone()).unwrap();
+        let addr = server.local_addr().unwrap();
+        server.spawn();
+
+        // a blocked client sees the structs written by the threads of the space
+        let remote = RemoteObjectSpace::connect(addr).unwrap();
+        let taker = thread::spawn(move || remote.take::<i64>());
+        thread::sleep(Duration::from_millis(20));
+        space.write::<i64>(3);
+        assert_eq!(taker.join().unwrap(), 3);
+
+        // a client which leaves while blocked takes nothing
+        {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let request = Request::Lookup {
+                type_name: String::from("i64"),
+                query: None,
+                take: true,
+                mode: Mode::Block(None),
+                checked: false,
+            };
+            send(&mut stream, &request).unwrap();
+        }
+        thread::sleep(2 * POLL_INTERVAL);
+        space.write::<i64>(5);
+        thread::sleep(2 * POLL_INTERVAL);
+        assert_eq!(space.try_take::<i64>(), Some(5));
+    }
+
+    #[test]
+    fn wide_integer_keys() {
+        let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+        let space = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+        server.spawn();
+        space.write_all(vec![3u64, u64::MAX]);
+        assert_eq!(space.try_read_by_value::<u64>("", &u64::MAX), Some(u64::MAX));
+        assert_eq!(
+            space.read_all_by_range::<u64, _>("", (1u64 << 63)..).collect::<Vec<_>>(),
+            vec![u64::MAX]
+        );
+        assert_eq!(space.try_take_by_range::<u64, _>("", ..=3i128), Some(3));
+    }
+
+    #[test]
+    fn unreachable_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let space = RemoteObjectSpace::connect(listener.local_addr().unwrap()).unwrap();
+        drop(listener.accept().unwrap());
+        drop(listener);
+
+        assert!(match space.try_write::<i64>(3) {
+            Err(SpaceError::Remote(_)) => true,
+            _ => false,
+        });
+        assert!(match space.try_take_checked::<i64>() {
+            Err(SpaceError::Remote(_)) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn remote_space() {
+        let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        server.spawn();
+        let space = Arc::new(RemoteObjectSpace::connect(addr).unwrap());
+
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || {
+            consumer_space.take_by_range::<CompoundStruct, _>("person.count", 2i64..4)
+        });
+        space.write_all(vec![
+            CompoundStruct {
+                person: TestStruct {
+                    count: 1,
+                    name: String::from("Tuan"),
+                },
+                gpa: 3.0,
+            },
+            CompoundStruct {
+                person: TestStruct {
+                    count: 3,
+                    name: String::from("Duane"),
+                },
+                gpa: 3.5,
+            },
+        ]);
+        assert_eq!(consumer.join().unwrap().person.name, String::from("Duane"));
+
+        let other = RemoteObjectSpace::connect(addr).unwrap();
+        assert_eq!(
+            other
+                .try_read_by_value::<CompoundStruct>("person.name", &String::from("Tuan"))
+                .map(|s| s.gpa),
+            Some(3.0)
+        );
+        assert_eq!(other.try_read::<TestStruct>(), None);
+        assert_eq!(
+            other.take_timeout::<TestStruct>(Duration::from_millis(10)),
+            None
+        );
+        assert_eq!(
+            other
+                .try_take_by_value_checked::<CompoundStruct>("person.nmae", &String::from("Tuan"))
+                .err(),
+            Some(SpaceError::NoSuchField(String::from("person.nmae")))
+        );
+        assert_eq!(other.take_all::<CompoundStruct>().count(), 1);
+        assert_eq!(space.try_take::<CompoundStruct>(), None);
+
+        let handle = space.write(String::from("Tuan"));
+        assert_eq!(other.try_read_by_handle::<String>(handle), Some(String::from("Tuan")));
+        assert_eq!(other.try_take_by_handle::<String>(handle), Some(String::from("Tuan")));
+        assert_eq!(space.try_take_by_handle::<String>(handle), None);
+    }
+}
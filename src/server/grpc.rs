@@ -0,0 +1,450 @@
+//! A gRPC interface to a space, for clients written in other languages.
+//!
+//! The service is defined by `proto/object_space.proto`, from which clients could be generated,
+//! e.g: with `grpcio-tools` in Python. Structs are exchanged as JSON documents, and their type is
+//! told apart by its full Rust name, e.g: `my_crate::Task`, like the structs of a `SpaceServer`.
+
+use std::convert::Infallible;
+use std::future::{self, Future, Ready};
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::ops::Bound;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use prost::Message;
+use serde_json::{self, Value};
+use tokio::net;
+use tokio::runtime::{Builder, Handle};
+use tokio::task;
+use tonic::body::BoxBody;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, BoxFuture, Service};
+use tonic::server::{Grpc, NamedService, UnaryService};
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::Status;
+
+use object_space::TreeObjectSpace;
+use query::{FieldValue, Query};
+use remote::{Failure, Mode, Request, SpaceServer, Store};
+
+/// Structs of a type to write, as JSON documents.
+#[derive(Clone, PartialEq, Message)]
+pub struct WriteRequest {
+    #[prost(string, tag = "1")]
+    pub type_name: String,
+    #[prost(string, repeated, tag = "2")]
+    pub objs: Vec<String>,
+}
+
+/// The handles of the structs written, in the order of the request.
+#[derive(Clone, PartialEq, Message)]
+pub struct WriteResponse {
+    #[prost(uint64, repeated, tag = "1")]
+    pub handles: Vec<u64>,
+}
+
+/// A lookup of the structs of a type, by the value or range of a field if any.
+#[derive(Clone, PartialEq, Message)]
+pub struct LookupRequest {
+    #[prost(string, tag = "1")]
+    pub type_name: String,
+    /// The field looked up, or an empty string for a primitive type such as `i64`.
+    #[prost(string, tag = "2")]
+    pub field: String,
+    /// The JSON value of the field. Takes precedence over `start` and `end`.
+    #[prost(string, optional, tag = "3")]
+    pub value: Option<String>,
+    /// The JSON lower bound of the field, included.
+    #[prost(string, optional, tag = "4")]
+    pub start: Option<String>,
+    /// The JSON upper bound of the field, excluded.
+    #[prost(string, optional, tag = "5")]
+    pub end: Option<String>,
+    /// Return every struct found instead of a single one.
+    #[prost(bool, tag = "6")]
+    pub all: bool,
+    /// Wait for a struct if none is found, up to `timeout_ms` if set. Not allowed with `all`.
+    #[prost(bool, tag = "7")]
+    pub block: bool,
+    #[prost(uint64, optional, tag = "8")]
+    pub timeout_ms: Option<u64>,
+}
+
+/// The structs found, as JSON documents. Empty if no struct was found.
+#[derive(Clone, PartialEq, Message)]
+pub struct LookupResponse {
+    #[prost(string, repeated, tag = "1")]
+    pub objs: Vec<String>,
+}
+
+/// A server answering the calls of the `object_space.ObjectSpace` gRPC service.
+///
+/// A server built with `SpaceServer::grpc` shares the structs of the `SpaceServer`,
+/// so that clients written in other languages exchange structs with `RemoteObjectSpace` clients.
+/// Every call is answered on a thread of its own, as blocking lookups could wait for a struct.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::{ObjectSpace, RemoteObjectSpace, SpaceServer};
+/// let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+/// let grpc = server.grpc("127.0.0.1:0").unwrap();
+/// let space = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+/// println!("gRPC clients could connect to {}", grpc.local_addr().unwrap());
+/// grpc.spawn();
+/// server.spawn();
+///
+/// space.write(String::from("Hello World"));
+/// ```
+pub struct GrpcServer {
+    listener: TcpListener,
+    store: Arc<Store>,
+}
+
+impl GrpcServer {
+    /// Listen for gRPC clients on the specified address, with a space of its own.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<GrpcServer> {
+        Ok(GrpcServer {
+            listener: TcpListener::bind(addr)?,
+            store: Arc::new(Store::new(Arc::new(TreeObjectSpace::new()))),
+        })
+    }
+
+    /// Return the address the server listens on, e.g: after binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve clients. The call blocks forever unless listening fails.
+    pub fn run(self) -> io::Result<()> {
+        let runtime = Builder::new_multi_thread().enable_all().build()?;
+        // the listener is registered with the runtime it is used on
+        let _context = runtime.enter();
+        self.listener.set_nonblocking(true)?;
+        let incoming = TcpIncoming::from_listener(net::TcpListener::from_std(self.listener)?, true, None)
+            .map_err(io::Error::other)?;
+        let service = ObjectSpaceService { store: self.store };
+        runtime
+            .block_on(Server::builder().add_service(service).serve_with_incoming(incoming))
+            .map_err(io::Error::other)
+    }
+
+    /// Serve clients on a background thread.
+    pub fn spawn(self) -> JoinHandle<io::Result<()>> {
+        thread::spawn(move || self.run())
+    }
+}
+
+impl SpaceServer {
+    /// Listen for gRPC clients on the specified address, sharing the structs of this server.
+    pub fn grpc<A: ToSocketAddrs>(&self, addr: A) -> io::Result<GrpcServer> {
+        Ok(GrpcServer {
+            listener: TcpListener::bind(addr)?,
+            store: self.store.clone(),
+        })
+    }
+}
+
+#[derive(Clone)]
+struct ObjectSpaceService {
+    store: Arc<Store>,
+}
+
+impl NamedService for ObjectSpaceService {
+    const NAME: &'static str = "object_space.ObjectSpace";
+}
+
+impl Service<http::Request<BoxBody>> for ObjectSpaceService {
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        let store = self.store.clone();
+        match request.uri().path() {
+            "/object_space.ObjectSpace/Write" => unary(request, WriteMethod(store)),
+            "/object_space.ObjectSpace/Read" => unary(request, LookupMethod { store, take: false }),
+            "/object_space.ObjectSpace/Take" => unary(request, LookupMethod { store, take: true }),
+            path => {
+                let status = Status::unimplemented(format!("no method {}", path));
+                Box::pin(future::ready(Ok(status.into_http())))
+            }
+        }
+    }
+}
+
+/// Answer a unary call on a blocking thread, as answering could wait for a struct.
+fn unary<M, S>(request: http::Request<BoxBody>, method: S) -> BoxFuture<http::Response<BoxBody>, Infallible>
+where
+    M: Message + Default + Send + 'static,
+    S: UnaryService<M> + Send + 'static,
+    S::Response: Message + Send + 'static,
+{
+    let runtime = Handle::current();
+    let answer = task::spawn_blocking(move || {
+        let mut grpc = Grpc::new(ProstCodec::<S::Response, M>::default());
+        runtime.block_on(grpc.unary(method, request))
+    });
+    Box::pin(Answer(answer))
+}
+
+/// The response to a call answered on a blocking thread.
+struct Answer(task::JoinHandle<http::Response<BoxBody>>);
+
+impl Future for Answer {
+    type Output = Result<http::Response<BoxBody>, Infallible>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx).map(|answered| {
+            Ok(answered.unwrap_or_else(|e| Status::internal(e.to_string()).into_http()))
+        })
+    }
+}
+
+struct WriteMethod(Arc<Store>);
+
+impl UnaryService<WriteRequest> for WriteMethod {
+    type Response = WriteResponse;
+    type Future = Ready<Result<tonic::Response<WriteResponse>, Status>>;
+
+    fn call(&mut self, request: tonic::Request<WriteRequest>) -> Self::Future {
+        future::ready(write(&self.0, request.into_inner()).map(tonic::Response::new).map_err(|status| *status))
+    }
+}
+
+struct LookupMethod {
+    store: Arc<Store>,
+    take: bool,
+}
+
+impl UnaryService<LookupRequest> for LookupMethod {
+    type Response = LookupResponse;
+    type Future = Ready<Result<tonic::Response<LookupResponse>, Status>>;
+
+    fn call(&mut self, request: tonic::Request<LookupRequest>) -> Self::Future {
+        future::ready(
+            lookup(&self.store, request.into_inner(), self.take)
+                .map(tonic::Response::new)
+                .map_err(|status| *status),
+        )
+    }
+}
+
+/// The helpers of the methods box their `Status`, which is unboxed once returned by the method.
+fn write(store: &Store, request: WriteRequest) -> Result<WriteResponse, Box<Status>> {
+    let objs = request
+        .objs
+        .iter()
+        .map(|obj| parse(obj))
+        .collect::<Result<Vec<Value>, Box<Status>>>()?;
+    let handles = store
+        .respond(Request::Write {
+            type_name: request.type_name,
+            objs,
+        })
+        .map_err(to_status)?;
+    Ok(WriteResponse {
+        handles: handles.iter().filter_map(Value::as_u64).collect(),
+    })
+}
+
+fn lookup(store: &Store, request: LookupRequest, take: bool) -> Result<LookupResponse, Box<Status>> {
+    let query = match (&request.value, &request.start, &request.end) {
+        (&Some(ref value), _, _) => Some(Query::Eq(request.field.clone(), field_value(value)?)),
+        (&None, &None, &None) => None,
+        (&None, start, end) => Some(Query::Range(
+            request.field.clone(),
+            bound(start, Bound::Included)?,
+            bound(end, Bound::Excluded)?,
+        )),
+    };
+    let mode = match (request.all, request.block) {
+        (true, true) => return Err(Box::new(Status::invalid_argument("a lookup of every struct could not block"))),
+        (true, false) => Mode::All,
+        (false, true) => Mode::Block(request.timeout_ms.map(Duration::from_millis)),
+        (false, false) => Mode::One,
+    };
+    let objs = store
+        .respond(Request::Lookup {
+            type_name: request.type_name,
+            query,
+            take,
+            mode,
+            checked: true,
+        })
+        .map_err(to_status)?;
+    Ok(LookupResponse {
+        objs: objs.iter().map(Value::to_string).collect(),
+    })
+}
+
+fn parse(json: &str) -> Result<Value, Box<Status>> {
+    serde_json::from_str(json)
+        .map_err(|e| Box::new(Status::invalid_argument(format!("{} is not valid JSON: {}", json, e))))
+}
+
+/// Return the value of a basic field encoded as JSON, e.g: `3` or `"urgent"`.
+fn field_value(json: &str) -> Result<FieldValue, Box<Status>> {
+    FieldValue::from_json(parse(json)?)
+        .ok_or_else(|| Box::new(Status::invalid_argument(format!("{} is not the value of a basic field", json))))
+}
+
+fn bound(json: &Option<String>, kind: fn(FieldValue) -> Bound<FieldValue>) -> Result<Bound<FieldValue>, Box<Status>> {
+    match *json {
+        Some(ref json) => field_value(json).map(kind),
+        None => Ok(Bound::Unbounded),
+    }
+}
+
+fn to_status(failure: Failure) -> Status {
+    match failure {
+        Failure::Space(e) => Status::invalid_argument(e.to_string()),
+        Failure::Message(message) => Status::internal(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::type_name;
+
+    use object_space::{ObjectSpace, ValueLookupObjectSpace};
+    use remote::RemoteObjectSpace;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct TestStruct {
+        count: i32,
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct CompoundStruct {
+        person: TestStruct,
+        gpa: f64,
+    }
+
+    #[test]
+    fn grpc_server() {
+        use tokio::runtime::Runtime;
+        use tonic::client::Grpc;
+        use tonic::codec::ProstCodec;
+        use tonic::codegen::http::uri::PathAndQuery;
+        use tonic::transport::{Channel, Endpoint};
+        use tonic::{Code, Request, Status};
+
+        fn call<Req, Res>(runtime: &Runtime, client: &mut Grpc<Channel>, method: &'static str, request: Req) -> Result<Res, Status>
+        where
+            Req: prost::Message + Send + 'static,
+            Res: prost::Message + Default + Send + 'static,
+        {
+            runtime.block_on(client.ready()).unwrap();
+            runtime
+                .block_on(client.unary(
+                    Request::new(request),
+                    PathAndQuery::from_static(method),
+                    ProstCodec::default(),
+                ))
+                .map(|response| response.into_inner())
+        }
+
+        let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+        let grpc = server.grpc("127.0.0.1:0").unwrap();
+        let addr = grpc.local_addr().unwrap();
+        let space = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+        grpc.spawn();
+        server.spawn();
+
+        let runtime = Runtime::new().unwrap();
+        let channel = runtime
+            .block_on(Endpoint::from_shared(format!("http://{}", addr)).unwrap().connect())
+            .unwrap();
+        let mut client = Grpc::new(channel);
+        let lookup = |field: &str| LookupRequest {
+            type_name: String::from(type_name::<CompoundStruct>()),
+            field: field.to_owned(),
+            ..Default::default()
+        };
+
+        let written: WriteResponse = call(
+            &runtime,
+            &mut client,
+            "/object_space.ObjectSpace/Write",
+            WriteRequest {
+                type_name: String::from(type_name::<CompoundStruct>()),
+                objs: vec![
+                    String::from(r#"{"person": {"count": 1, "name": "Tuan"}, "gpa": 3.0}"#),
+                    String::from(r#"{"person": {"count": 3, "name": "Duane"}, "gpa": 3.5}"#),
+                ],
+            },
+        ).unwrap();
+        assert_eq!(written.handles.len(), 2);
+        assert_eq!(
+            space
+                .try_read_by_value::<CompoundStruct>("person.name", &String::from("Tuan"))
+                .map(|s| s.gpa),
+            Some(3.0)
+        );
+
+        let read: LookupResponse = call(
+            &runtime,
+            &mut client,
+            "/object_space.ObjectSpace/Read",
+            LookupRequest {
+                value: Some(String::from(r#""Duane""#)),
+                ..lookup("person.name")
+            },
+        ).unwrap();
+        assert_eq!(read.objs.len(), 1);
+        let duane: CompoundStruct = serde_json::from_str(&read.objs[0]).unwrap();
+        assert_eq!(duane.gpa, 3.5);
+
+        let taken: LookupResponse = call(
+            &runtime,
+            &mut client,
+            "/object_space.ObjectSpace/Take",
+            LookupRequest {
+                start: Some(String::from("0")),
+                end: Some(String::from("2")),
+                all: true,
+                ..lookup("person.count")
+            },
+        ).unwrap();
+        assert_eq!(taken.objs.len(), 1);
+        assert_eq!(space.read_all::<CompoundStruct>().count(), 1);
+
+        space.write::<i64>(5);
+        let blocked: LookupResponse = call(
+            &runtime,
+            &mut client,
+            "/object_space.ObjectSpace/Take",
+            LookupRequest {
+                type_name: String::from("i64"),
+                block: true,
+                timeout_ms: Some(1000),
+                ..Default::default()
+            },
+        ).unwrap();
+        assert_eq!(blocked.objs, vec![String::from("5")]);
+
+        let missing = call::<_, LookupResponse>(
+            &runtime,
+            &mut client,
+            "/object_space.ObjectSpace/Read",
+            LookupRequest {
+                value: Some(String::from("true")),
+                ..lookup("nmae")
+            },
+        );
+        assert_eq!(missing.unwrap_err().code(), Code::InvalidArgument);
+    }
+}
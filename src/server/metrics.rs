@@ -0,0 +1,180 @@
+//! An HTTP endpoint exporting the metrics of a space in the Prometheus text format.
+//!
+//! Every metric is labelled by the full Rust name of its type, e.g: `type="my_crate::Task"`,
+//! and latencies are also labelled by operation, e.g: `operation="take"`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use entry::stats::{HoldTime, TypeMetrics};
+use object_space::TreeObjectSpace;
+
+// a client slower than this to send its request is disconnected
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A server answering `GET /metrics` with the metrics of a space, for Prometheus to scrape.
+///
+/// # Example
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use object_space::{ObjectSpace, TreeObjectSpace};
+/// # use object_space::server::metrics::MetricsServer;
+/// let space = Arc::new(TreeObjectSpace::new());
+/// let server = MetricsServer::bind(space.clone(), "127.0.0.1:0").unwrap();
+/// println!("Prometheus could scrape http://{}/metrics", server.local_addr().unwrap());
+/// server.spawn();
+///
+/// space.write::<i64>(3);
+/// assert!(space.prometheus_metrics().contains("object_space_writes_total{type=\"i64\"} 1"));
+/// ```
+pub struct MetricsServer {
+    listener: TcpListener,
+    space: Arc<TreeObjectSpace>,
+}
+
+impl MetricsServer {
+    /// Listen for scrapes of the metrics of the space on the specified address.
+    pub fn bind<A: ToSocketAddrs>(space: Arc<TreeObjectSpace>, addr: A) -> io::Result<MetricsServer> {
+        Ok(MetricsServer {
+            listener: TcpListener::bind(addr)?,
+            space,
+        })
+    }
+
+    /// Return the address the server listens on, e.g: after binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve clients one at a time. The call blocks forever unless listening fails.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            // a client which disconnects or breaks the protocol only loses its own connection
+            let _ = serve(&self.space, stream?);
+        }
+        Ok(())
+    }
+
+    /// Serve clients on a background thread.
+    pub fn spawn(self) -> JoinHandle<io::Result<()>> {
+        thread::spawn(move || self.run())
+    }
+}
+
+impl TreeObjectSpace {
+    /// Return the metrics of every type in the space in the Prometheus text format,
+    /// as served by `MetricsServer`.
+    pub fn prometheus_metrics(&self) -> String {
+        render(&self.metrics())
+    }
+}
+
+/// Answer a single request, then close the connection.
+fn serve(space: &TreeObjectSpace, stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers are of no use, but are read so that the client is not reset
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", space.prometheus_metrics()),
+        (Some("GET"), _) => ("404 Not Found", String::from("Not Found\n")),
+        _ => ("405 Method Not Allowed", String::from("Method Not Allowed\n")),
+    };
+    let mut writer = &stream;
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+fn render(metrics: &[TypeMetrics]) -> String {
+    let mut text = String::new();
+    counter(&mut text, metrics, "writes_total", "Structs written.", |m| m.writes);
+    counter(&mut text, metrics, "reads_total", "Lookups leaving the structs in the space.", |m| m.reads);
+    counter(&mut text, metrics, "takes_total", "Structs removed by an operation.", |m| m.takes);
+    counter(&mut text, metrics, "expired_total", "Structs removed because their lease ended.", |m| m.expired);
+    counter(&mut text, metrics, "evicted_total", "Structs removed to make room, as their type was full.", |m| m.evicted);
+    gauge(&mut text, metrics, "blocked_calls", "Blocking calls waiting for a struct.", |m| m.blocked);
+    gauge(&mut text, metrics, "structs", "Structs in the space.", |m| m.size);
+    gauge(&mut text, metrics, "memory_bytes", "Estimated memory taken by the structs and their indices.", |m| m.memory);
+    summary(
+        &mut text,
+        metrics,
+        "operation_seconds",
+        "Time operations took, including waiting for the write guard.",
+        |m| &m.latencies,
+    );
+    summary(
+        &mut text,
+        metrics,
+        "wait_seconds",
+        "Time blocking calls took, including waiting for a struct.",
+        |m| &m.waits,
+    );
+    text
+}
+
+fn header(text: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(text, "# HELP object_space_{} {}", name, help);
+    let _ = writeln!(text, "# TYPE object_space_{} {}", name, kind);
+}
+
+fn counter<F>(text: &mut String, metrics: &[TypeMetrics], name: &str, help: &str, value: F)
+where
+    F: Fn(&TypeMetrics) -> u64,
+{
+    header(text, name, help, "counter");
+    for m in metrics {
+        let _ = writeln!(text, "object_space_{}{{type=\"{}\"}} {}", name, escape(m.type_name), value(m));
+    }
+}
+
+fn gauge<F>(text: &mut String, metrics: &[TypeMetrics], name: &str, help: &str, value: F)
+where
+    F: Fn(&TypeMetrics) -> usize,
+{
+    header(text, name, help, "gauge");
+    for m in metrics {
+        let _ = writeln!(text, "object_space_{}{{type=\"{}\"}} {}", name, escape(m.type_name), value(m));
+    }
+}
+
+/// Write the count and total time of every operation, without quantiles.
+fn summary<F>(text: &mut String, metrics: &[TypeMetrics], name: &str, help: &str, times: F)
+where
+    F: for<'a> Fn(&'a TypeMetrics) -> &'a BTreeMap<&'static str, HoldTime>,
+{
+    header(text, name, help, "summary");
+    for m in metrics {
+        for (operation, time) in times(m) {
+            let labels = format!("type=\"{}\",operation=\"{}\"", escape(m.type_name), operation);
+            let _ = writeln!(text, "object_space_{}_sum{{{}}} {}", name, labels, time.total.as_secs_f64());
+            let _ = writeln!(text, "object_space_{}_count{{{}}} {}", name, labels, time.count);
+        }
+    }
+}
+
+/// Escape a label value, e.g: a type name containing quotes.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,6 @@
+//! Servers exposing a space to clients which are not `RemoteObjectSpace`.
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod metrics;
+pub mod websocket;
@@ -0,0 +1,714 @@
+//! A WebSocket interface to a space, for browser clients such as live dashboards.
+//!
+//! Clients send commands as JSON text messages, e.g: `{"op": "read_all", "type_name": "my_crate::Task"}`,
+//! and receive events as JSON text messages, e.g: `{"event": "read", "type_name": "my_crate::Task", "objs": [..]}`.
+//! Types are told apart by their full Rust name, like the structs of a `SpaceServer`.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use serde_json::{self, Value};
+
+use entry::record::Record;
+use entry::subscription::Subscription;
+use object_space::TreeObjectSpace;
+use query::{FieldValue, Query};
+use remote::{Failure, Mode, Request, SpaceServer, Store, MAX_MESSAGE_LEN};
+
+// appended to the key of a client to accept its handshake, as specified by RFC 6455
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// the most bytes of headers a client could send in its handshake
+const MAX_HANDSHAKE_LEN: u64 = 8 * 1024;
+
+// the most frames waiting to be sent to a client, before its subscriptions are dropped
+const MAX_QUEUED_FRAMES: usize = 1024;
+
+const CONTINUATION: u8 = 0x0;
+pub(crate) const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+pub(crate) const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+/// A command sent by a client.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Command {
+    /// Receive a `written` event for every struct of the type written from now on,
+    /// or only for those whose field is equal to the value.
+    Subscribe {
+        type_name: String,
+        #[serde(default)]
+        field: String,
+        value: Option<Value>,
+    },
+    /// Stop receiving the structs of the type.
+    Unsubscribe { type_name: String },
+    /// Return a struct of the type, like `try_read`, whose field is equal to the value if any.
+    TryRead {
+        type_name: String,
+        #[serde(default)]
+        field: String,
+        value: Option<Value>,
+    },
+    /// Return every struct of the type, like `read_all`, whose field is equal to the value if any.
+    ReadAll {
+        type_name: String,
+        #[serde(default)]
+        field: String,
+        value: Option<Value>,
+    },
+}
+
+/// An event sent to a client.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event {
+    /// The structs found by a `try_read` or `read_all`, sent in the order of the commands.
+    Read { type_name: String, objs: Vec<Value> },
+    /// A struct of a type subscribed to was written.
+    Written { type_name: String, obj: Value },
+    /// A command could not be answered.
+    Error { message: String },
+}
+
+/// A frame of a WebSocket message.
+pub(crate) struct Frame {
+    pub fin: bool,
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+/// A server letting WebSocket clients, e.g: browser dashboards, subscribe to the structs written
+/// to a space and read them without blocking.
+///
+/// A server built with `SpaceServer::websocket` shares the structs of the `SpaceServer`,
+/// so that a dashboard could monitor the structs exchanged by `RemoteObjectSpace` clients.
+///
+/// A client sends commands as JSON text messages:
+///
+/// - `{"op": "subscribe", "type_name": "my_crate::Task"}` to receive every `Task` written from now on.
+///   With `"field": "status", "value": "done"`, only those whose `status` is `"done"` are received.
+/// - `{"op": "unsubscribe", "type_name": "my_crate::Task"}`
+/// - `{"op": "try_read", "type_name": "my_crate::Task"}` and `{"op": "read_all", "type_name": "my_crate::Task"}`,
+///   optionally with a `field` and `value` as well.
+///
+/// The server answers with JSON text messages: `{"event": "read", "type_name": .., "objs": [..]}`
+/// for every lookup, in the order of the commands, `{"event": "written", "type_name": .., "obj": ..}`
+/// for every struct subscribed to, and `{"event": "error", "message": ..}` for a command which
+/// could not be answered.
+///
+/// Messages are limited in size like the requests of a `SpaceServer`.
+/// A client which falls so far behind that its events pile up loses its subscriptions.
+///
+/// # Example
+///
+/// ```
+/// # use object_space::{ObjectSpace, RemoteObjectSpace, SpaceServer};
+/// let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+/// let websocket = server.websocket("127.0.0.1:0").unwrap();
+/// let space = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+/// println!("dashboards could connect to ws://{}", websocket.local_addr().unwrap());
+/// websocket.spawn();
+/// server.spawn();
+///
+/// space.write(String::from("Hello World"));
+/// ```
+pub struct WebSocketServer {
+    listener: TcpListener,
+    store: Arc<Store>,
+}
+
+impl WebSocketServer {
+    /// Listen for WebSocket clients on the specified address, with a space of its own.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<WebSocketServer> {
+        Ok(WebSocketServer {
+            listener: TcpListener::bind(addr)?,
+            store: Arc::new(Store::new(Arc::new(TreeObjectSpace::new()))),
+        })
+    }
+
+    /// Return the address the server listens on, e.g: after binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve clients, each on its own thread. The call blocks forever unless listening fails.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let store = self.store.clone();
+            thread::spawn(move || {
+                // a client which disconnects or breaks the protocol only loses its own connection
+                let _ = serve(&store, stream);
+            });
+        }
+        Ok(())
+    }
+
+    /// Serve clients on a background thread.
+    pub fn spawn(self) -> JoinHandle<io::Result<()>> {
+        thread::spawn(move || self.run())
+    }
+}
+
+impl SpaceServer {
+    /// Listen for WebSocket clients on the specified address, sharing the structs of this server.
+    pub fn websocket<A: ToSocketAddrs>(&self, addr: A) -> io::Result<WebSocketServer> {
+        Ok(WebSocketServer {
+            listener: TcpListener::bind(addr)?,
+            store: self.store.clone(),
+        })
+    }
+}
+
+fn serve(store: &Store, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    if !handshake(&mut reader, &mut writer)? {
+        return Ok(());
+    }
+
+    // frames are sent by a thread of their own, so that a slow client never holds up writers of the space
+    let (frames, outgoing) = sync_channel::<(u8, Vec<u8>)>(MAX_QUEUED_FRAMES);
+    let sender = thread::spawn(move || -> io::Result<()> {
+        for (opcode, payload) in outgoing {
+            write_frame(&mut writer, opcode, &payload, None)?;
+            if opcode == CLOSE {
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    let mut subscriptions = HashMap::new();
+    let received = receive(store, &mut reader, &frames, &mut subscriptions);
+    for active in subscriptions.values().flat_map(|flags: &Vec<Arc<AtomicBool>>| flags.iter()) {
+        active.store(false, Ordering::SeqCst);
+    }
+    // the sender stops after the close frame, even if subscriptions still hold the channel
+    let _ = frames.send((CLOSE, Vec::new()));
+    let sent = sender.join().unwrap_or(Ok(()));
+    received.and(sent)
+}
+
+/// Answer the messages of a client until it closes the connection.
+fn receive<R: Read>(
+    store: &Store,
+    reader: &mut R,
+    frames: &SyncSender<(u8, Vec<u8>)>,
+    subscriptions: &mut HashMap<String, Vec<Arc<AtomicBool>>>,
+) -> io::Result<()> {
+    let mut message = Vec::new();
+    while let Some(frame) = read_frame(reader)? {
+        match frame.opcode {
+            TEXT | BINARY | CONTINUATION => {
+                message.extend_from_slice(&frame.payload);
+                if message.len() > MAX_MESSAGE_LEN {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "message is too large"));
+                }
+                if frame.fin {
+                    let event = execute(store, &message, frames, subscriptions)
+                        .unwrap_or_else(|message| Some(Event::Error { message }));
+                    if let Some(event) = event {
+                        let _ = frames.send(text(&event));
+                    }
+                    message.clear();
+                }
+            }
+            PING => {
+                let _ = frames.send((PONG, frame.payload));
+            }
+            PONG => (),
+            _ => return Ok(()),
+        }
+    }
+    Ok(())
+}
+
+/// Carry out a command, and return the event answering it if any.
+fn execute(
+    store: &Store,
+    message: &[u8],
+    frames: &SyncSender<(u8, Vec<u8>)>,
+    subscriptions: &mut HashMap<String, Vec<Arc<AtomicBool>>>,
+) -> Result<Option<Event>, String> {
+    let command = serde_json::from_slice(message).map_err(|e| format!("Invalid command: {}", e))?;
+    match command {
+        Command::Subscribe {
+            type_name,
+            field,
+            value,
+        } => {
+            let condition = match value {
+                Some(value) => Some((field, key(value)?.to_field())),
+                None => None,
+            };
+            let active = Arc::new(AtomicBool::new(true));
+            let (flag, frames, name) = (active.clone(), frames.clone(), type_name.clone());
+            let subscription = Subscription::new(condition, move |obj: &Record| {
+                if !flag.load(Ordering::SeqCst) {
+                    return false;
+                }
+                // a subscription must not block the writer of the struct
+                match obj.to_json() {
+                    Ok(obj) => frames
+                        .try_send(text(&Event::Written {
+                            type_name: name.clone(),
+                            obj,
+                        }))
+                        .is_ok(),
+                    Err(_) => true,
+                }
+            });
+            store.subscribe(type_name.clone(), subscription);
+            subscriptions.entry(type_name).or_default().push(active);
+            Ok(None)
+        }
+        Command::Unsubscribe { type_name } => {
+            for active in subscriptions.remove(&type_name).unwrap_or_default() {
+                active.store(false, Ordering::SeqCst);
+            }
+            Ok(None)
+        }
+        Command::TryRead {
+            type_name,
+            field,
+            value,
+        } => read(store, type_name, field, value, Mode::One).map(Some),
+        Command::ReadAll {
+            type_name,
+            field,
+            value,
+        } => read(store, type_name, field, value, Mode::All).map(Some),
+    }
+}
+
+fn read(store: &Store, type_name: String, field: String, value: Option<Value>, mode: Mode) -> Result<Event, String> {
+    let query = match value {
+        Some(value) => Some(Query::Eq(field, key(value)?)),
+        None => None,
+    };
+    let objs = store
+        .respond(Request::Lookup {
+            type_name: type_name.clone(),
+            query,
+            take: false,
+            mode,
+            checked: true,
+        })
+        .map_err(|failure| match failure {
+            Failure::Space(e) => e.to_string(),
+            Failure::Message(message) => message,
+        })?;
+    Ok(Event::Read { type_name, objs })
+}
+
+fn key(value: Value) -> Result<FieldValue, String> {
+    let json = value.to_string();
+    FieldValue::from_json(value).ok_or_else(|| format!("{} is not the value of a basic field", json))
+}
+
+fn text(event: &Event) -> (u8, Vec<u8>) {
+    (TEXT, serde_json::to_vec(event).unwrap())
+}
+
+/// Read the opening handshake of a client, and accept it if it asks for a WebSocket.
+fn handshake<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<bool> {
+    let mut reader = reader.take(MAX_HANDSHAKE_LEN);
+    let mut key = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            if reader.limit() == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "handshake is too large"));
+            }
+            return Ok(false);
+        }
+        let header = line.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(colon) = header.find(':') {
+            if header[..colon].trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(header[colon + 1..].trim().to_owned());
+            }
+        }
+    }
+    let accepted = key.is_some();
+    match key {
+        Some(key) => write!(
+            writer,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(&key)
+        )?,
+        None => writer.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")?,
+    }
+    writer.flush()?;
+    Ok(accepted)
+}
+
+/// Return the value of `Sec-WebSocket-Accept` answering the key sent by a client.
+pub(crate) fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()))
+}
+
+/// Read a frame, or return None if the connection was closed in between frames.
+pub(crate) fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<Frame>> {
+    let mut head = [0; 2];
+    match reader.read_exact(&mut head) {
+        Ok(()) => (),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    if len > MAX_MESSAGE_LEN as u64 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame is too large"));
+    }
+    let mut mask = None;
+    if head[1] & 0x80 != 0 {
+        let mut key = [0; 4];
+        reader.read_exact(&mut key)?;
+        mask = Some(key);
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    if let Some(mask) = mask {
+        apply_mask(&mut payload, mask);
+    }
+    Ok(Some(Frame {
+        fin: head[0] & 0x80 != 0,
+        opcode: head[0] & 0x0F,
+        payload,
+    }))
+}
+
+/// Write a whole message as a single frame. Frames sent by clients must be masked.
+pub(crate) fn write_frame<W: Write>(writer: &mut W, opcode: u8, payload: &[u8], mask: Option<[u8; 4]>) -> io::Result<()> {
+    let masked = if mask.is_some() { 0x80 } else { 0 };
+    writer.write_all(&[0x80 | opcode])?;
+    match payload.len() {
+        len if len < 126 => writer.write_all(&[masked | len as u8])?,
+        len if len <= u16::MAX as usize => {
+            writer.write_all(&[masked | 126])?;
+            writer.write_all(&(len as u16).to_be_bytes())?;
+        }
+        len => {
+            writer.write_all(&[masked | 127])?;
+            writer.write_all(&(len as u64).to_be_bytes())?;
+        }
+    }
+    match mask {
+        Some(mask) => {
+            let mut payload = payload.to_vec();
+            apply_mask(&mut payload, mask);
+            writer.write_all(&mask)?;
+            writer.write_all(&payload)?;
+        }
+        None => writer.write_all(payload)?,
+    }
+    writer.flush()
+}
+
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// Return the SHA-1 digest of the bytes, which the handshake is made of.
+fn sha1(bytes: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(bytes.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let next = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = next;
+        }
+        for (value, added) in state.iter_mut().zip(&[a, b, c, d, e]) {
+            *value = value.wrapping_add(*added);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(&state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, &byte)| group | u32::from(byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::type_name;
+
+    use object_space::ObjectSpace;
+    use remote::RemoteObjectSpace;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct TestStruct {
+        count: i32,
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct CompoundStruct {
+        person: TestStruct,
+        gpa: f64,
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn handshake_vectors() {
+        // the examples of FIPS 180-2
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            hex(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+        // the examples of RFC 4648
+        let encoded: Vec<String> = ["", "f", "fo", "foo", "foob", "fooba", "foobar"]
+            .iter()
+            .map(|s| base64(s.as_bytes()))
+            .collect();
+        assert_eq!(encoded, vec!["", "Zg==", "Zm8=", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "Zm9vYmFy"]);
+        // the example of RFC 6455
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        let mut response = Vec::new();
+        let request = "GET / HTTP/1.1\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+        assert!(handshake(&mut request.as_bytes(), &mut response).unwrap());
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        let mut response = Vec::new();
+        assert!(!handshake(&mut &b"GET / HTTP/1.1\r\n\r\n"[..], &mut response).unwrap());
+        assert!(response.starts_with(b"HTTP/1.1 400"));
+
+        let endless = vec![b'a'; 2 * MAX_HANDSHAKE_LEN as usize];
+        let error = handshake(&mut &endless[..], &mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn frames() {
+        // the examples of RFC 6455, section 5.7
+        let unmasked = [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+        let masked = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        let mut written = Vec::new();
+        write_frame(&mut written, TEXT, b"Hello", None).unwrap();
+        assert_eq!(written, unmasked);
+        written.clear();
+        write_frame(&mut written, TEXT, b"Hello", Some([0x37, 0xfa, 0x21, 0x3d])).unwrap();
+        assert_eq!(written, masked);
+        for bytes in &[&unmasked[..], &masked[..]] {
+            let frame = read_frame(&mut &bytes[..]).unwrap().unwrap();
+            assert!(frame.fin);
+            assert_eq!(frame.opcode, TEXT);
+            assert_eq!(frame.payload, b"Hello");
+        }
+
+        let fragmented = [0x01, 0x03, 0x48, 0x65, 0x6c, 0x80, 0x02, 0x6c, 0x6f];
+        let mut reader = &fragmented[..];
+        let first = read_frame(&mut reader).unwrap().unwrap();
+        assert_eq!((first.fin, first.opcode, &first.payload[..]), (false, TEXT, &b"Hel"[..]));
+        let last = read_frame(&mut reader).unwrap().unwrap();
+        assert_eq!((last.fin, last.opcode, &last.payload[..]), (true, CONTINUATION, &b"lo"[..]));
+        assert!(read_frame(&mut reader).unwrap().is_none());
+
+        // payloads of 256 bytes and 64 KiB take a 16-bit and a 64-bit length
+        let lengths = [
+            (256, vec![0x82, 0x7E, 0x01, 0x00]),
+            (65536, vec![0x82, 0x7F, 0, 0, 0, 0, 0, 1, 0, 0]),
+        ];
+        for &(len, ref head) in &lengths {
+            let payload = vec![7; len];
+            let mut written = Vec::new();
+            write_frame(&mut written, BINARY, &payload, None).unwrap();
+            assert_eq!(&written[..head.len()], &head[..]);
+            assert_eq!(read_frame(&mut &written[..]).unwrap().unwrap().payload, payload);
+        }
+
+        let oversized = [0x82, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        let error = read_frame(&mut &oversized[..]).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn fragmented_command() {
+        let store = Store::new(Arc::new(TreeObjectSpace::new()));
+        let command = br#"{"op": "try_read", "type_name": "i64"}"#;
+        let mut bytes = Vec::new();
+        let (head, tail) = command.split_at(10);
+        bytes.extend_from_slice(&[0x01, 0x80 | head.len() as u8, 1, 2, 3, 4]);
+        bytes.extend(head.iter().enumerate().map(|(i, byte)| byte ^ [1, 2, 3, 4][i % 4]));
+        // a ping may come in between the fragments of a message
+        write_frame(&mut bytes, PING, b"ping", Some([5, 6, 7, 8])).unwrap();
+        bytes.extend_from_slice(&[0x80, 0x80 | tail.len() as u8, 1, 2, 3, 4]);
+        bytes.extend(tail.iter().enumerate().map(|(i, byte)| byte ^ [1, 2, 3, 4][i % 4]));
+
+        let (frames, outgoing) = sync_channel(MAX_QUEUED_FRAMES);
+        receive(&store, &mut &bytes[..], &frames, &mut HashMap::new()).unwrap();
+        assert_eq!(outgoing.try_recv().unwrap(), (PONG, b"ping".to_vec()));
+        let (opcode, payload) = outgoing.try_recv().unwrap();
+        assert_eq!(opcode, TEXT);
+        let event: Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(event["event"], "read");
+        assert_eq!(event["objs"].as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn websocket_server() {
+        let server = SpaceServer::bind("127.0.0.1:0").unwrap();
+        let websocket = server.websocket("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(websocket.local_addr().unwrap()).unwrap();
+        let space = RemoteObjectSpace::connect(server.local_addr().unwrap()).unwrap();
+        websocket.spawn();
+        server.spawn();
+
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        write!(
+            writer,
+            "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        ).unwrap();
+        let mut response = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            response.push(line.trim().to_owned());
+        }
+        assert_eq!(response[0], "HTTP/1.1 101 Switching Protocols");
+        assert!(response.contains(&String::from("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")));
+
+        let name = type_name::<CompoundStruct>();
+        let mut command = |json: String| {
+            write_frame(&mut writer, TEXT, json.as_bytes(), Some([1, 2, 3, 4])).unwrap();
+        };
+        let mut event = || -> serde_json::Value {
+            let frame = read_frame(&mut reader).unwrap().unwrap();
+            assert_eq!(frame.opcode, TEXT);
+            serde_json::from_slice(&frame.payload).unwrap()
+        };
+
+        command(format!(r#"{{"op": "subscribe", "type_name": "{}", "field": "person.count", "value": 3}}"#, name));
+        command(format!(r#"{{"op": "try_read", "type_name": "{}"}}"#, name));
+        let read = event();
+        assert_eq!(read["event"], "read");
+        assert_eq!(read["type_name"], name);
+        assert_eq!(read["objs"].as_array().unwrap().len(), 0);
+
+        space.write_all(vec![
+            CompoundStruct {
+                person: TestStruct {
+                    count: 1,
+                    name: String::from("Tuan"),
+                },
+                gpa: 3.0,
+            },
+            CompoundStruct {
+                person: TestStruct {
+                    count: 3,
+                    name: String::from("Duane"),
+                },
+                gpa: 3.5,
+            },
+        ]);
+        let written = event();
+        assert_eq!(written["event"], "written");
+        assert_eq!(written["obj"]["person"]["name"], "Duane");
+
+        command(format!(r#"{{"op": "read_all", "type_name": "{}", "field": "person.name", "value": "Tuan"}}"#, name));
+        let read = event();
+        assert_eq!(read["objs"].as_array().unwrap().len(), 1);
+        assert_eq!(read["objs"][0]["gpa"], 3.0);
+
+        command(format!(r#"{{"op": "read_all", "type_name": "{}", "field": "nmae", "value": "Tuan"}}"#, name));
+        assert_eq!(event()["event"], "error");
+        command(String::from(r#"{"op": "take"}"#));
+        assert_eq!(event()["event"], "error");
+
+        command(format!(r#"{{"op": "unsubscribe", "type_name": "{}"}}"#, name));
+        command(String::from(r#"{"op": "try_read", "type_name": "i64"}"#));
+        assert_eq!(event()["objs"].as_array().unwrap().len(), 0);
+        assert_eq!(space.read_all::<CompoundStruct>().count(), 2);
+
+        write_frame(&mut writer, CLOSE, &[], Some([1, 2, 3, 4])).unwrap();
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap().opcode, CLOSE);
+    }
+}
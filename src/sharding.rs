@@ -0,0 +1,749 @@
+use std::any::TypeId;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::RangeBounds;
+use std::time::Duration;
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use codec::Codec;
+use entry::handle::ObjectHandle;
+use entry::record::{Field, Record};
+use error::SpaceError;
+use helpers::{poll, sample_indices};
+use object_space::{ObjectSpace, RangeLookupObjectSpace, TreeObjectSpace, ValueLookupObjectSpace};
+use query::FieldValue;
+
+/// The shards which could hold the structs looked up.
+#[derive(Clone, Copy)]
+enum Route {
+    One(usize),
+    All,
+}
+
+/// An `ObjectSpace` partitioning its structs across several `TreeObjectSpace`, called shards,
+/// so that threads using different shards never contend.
+///
+/// The structs of a type are partitioned by the value of a field declared with `shard_by`.
+/// Lookups by the value of that field, e.g: `take_by_value`, only look up the shard of the value,
+/// and wait on it like on any space, while other lookups of the type look up every shard in turn.
+/// Blocking calls looking up every shard notice a new struct after a short delay rather than right away.
+/// The structs of a type which is not sharded all live in the same shard, chosen by their type.
+///
+/// # Example
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate object_space;
+/// # use object_space::{ObjectSpace, ShardedObjectSpace, ValueLookupObjectSpace};
+/// #[derive(Serialize, Deserialize)]
+/// struct Task {
+///     start: i64,
+///     end: i64,
+/// }
+///
+/// # fn main() {
+/// let space = ShardedObjectSpace::new(4);
+/// space.shard_by::<Task>("start");
+/// for start in 0..10 {
+///     space.write(Task { start, end: start + 1 });
+/// }
+///
+/// // looks up a single shard
+/// assert_eq!(space.take_by_value::<Task>("start", &3i64).end, 4);
+/// // looks up every shard
+/// assert_eq!(space.read_all::<Task>().count(), 9);
+/// # }
+/// ```
+pub struct ShardedObjectSpace {
+    shards: Vec<TreeObjectSpace>,
+    // the field the structs of a type are partitioned by, by type
+    keys: RwLock<HashMap<TypeId, String>>,
+}
+
+impl ShardedObjectSpace {
+    /// Return a space made of `count` shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is 0.
+    pub fn new(count: usize) -> ShardedObjectSpace {
+        assert!(count > 0, "a space needs at least one shard");
+        ShardedObjectSpace {
+            shards: (0..count).map(|_| TreeObjectSpace::new()).collect(),
+            keys: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Partition the structs of type T by the value of a basic field, e.g: `"start"` or `"person.id"`.
+    /// The structs of type T already in the space are moved to the shard of their value.
+    ///
+    /// A struct whose field is missing, or is not basic, is kept in the shard of its type.
+    /// This should be called before the type is used by other threads.
+    pub fn shard_by<T>(&self, field: &str)
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.keys.write().insert(TypeId::of::<T>(), field.to_owned());
+        let moved: Vec<T> = self.shards
+            .iter()
+            .flat_map(|shard| shard.take_all::<T>())
+            .collect();
+        self.write_all(moved);
+    }
+
+    /// Return the shards, e.g: to configure a type on every shard with `set_policy`.
+    pub fn shards(&self) -> &[TreeObjectSpace] {
+        &self.shards
+    }
+
+    /// Return the shard holding the structs of type T if it is not sharded.
+    fn home<T: 'static>(&self) -> usize {
+        let mut hasher = DefaultHasher::new();
+        TypeId::of::<T>().hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /// Return the shard a struct of type T is written to.
+    fn shard_of<T>(&self, obj: &T) -> usize
+    where
+        T: Serialize + 'static,
+    {
+        let value = match self.keys.read().get(&TypeId::of::<T>()) {
+            Some(field) => Record::new(obj, Codec::Json).ok().and_then(|record| record.get(field).cloned()),
+            None => None,
+        };
+        match value {
+            Some(ref value) if *value != Field::Seq && *value != Field::Bytes => self.shard_of_value(value),
+            _ => self.home::<T>(),
+        }
+    }
+
+    fn shard_of_value(&self, value: &Field) -> usize {
+        (hash_field(value) % self.shards.len() as u64) as usize
+    }
+
+    /// Return the shards which could hold the structs of type T.
+    fn route<T: 'static>(&self) -> Route {
+        if self.keys.read().contains_key(&TypeId::of::<T>()) {
+            Route::All
+        } else {
+            Route::One(self.home::<T>())
+        }
+    }
+
+    /// Return the shards which could hold the structs of type T whose field is equal to the value.
+    fn route_by_value<T: 'static>(&self, field: &str, value: Field) -> Route {
+        match self.keys.read().get(&TypeId::of::<T>()) {
+            Some(key) if key == field && value != Field::Seq && value != Field::Bytes => Route::One(self.shard_of_value(&value)),
+            Some(_) => Route::All,
+            None => Route::One(self.home::<T>()),
+        }
+    }
+
+    fn select(&self, route: Route) -> &[TreeObjectSpace] {
+        match route {
+            Route::One(position) => &self.shards[position..position + 1],
+            Route::All => &self.shards,
+        }
+    }
+
+    /// Return the first struct found by `lookup` in the shards of the route.
+    fn find<T, F>(&self, route: Route, lookup: F) -> Option<T>
+    where
+        F: FnMut(&TreeObjectSpace) -> Option<T>,
+    {
+        self.select(route).iter().find_map(lookup)
+    }
+
+    /// Return the first struct found by `lookup` in the shards of the route,
+    /// or the first error if a shard could not answer it.
+    fn find_checked<T, F>(&self, route: Route, mut lookup: F) -> Result<Option<T>, SpaceError>
+    where
+        F: FnMut(&TreeObjectSpace) -> Result<Option<T>, SpaceError>,
+    {
+        for shard in self.select(route) {
+            if let Some(found) = lookup(shard)? {
+                return Ok(Some(found));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Return the structs found by `lookup` in every shard of the route, looked up right away.
+    fn collect_checked<'a, T, F>(
+        &'a self,
+        route: Route,
+        mut lookup: F,
+    ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+    where
+        T: 'a,
+        F: FnMut(&'a TreeObjectSpace) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>,
+    {
+        let mut found = Vec::new();
+        for shard in self.select(route) {
+            found.extend(lookup(shard)?);
+        }
+        Ok(Box::new(found.into_iter()))
+    }
+
+    /// Wait for a struct with `block` if the route leads to a single shard.
+    /// Otherwise, look up every shard with `lookup` until a struct is found or the timeout has passed.
+    fn wait<T, B, F>(&self, route: Route, timeout: Option<Duration>, block: B, mut lookup: F) -> Option<T>
+    where
+        B: FnOnce(&TreeObjectSpace) -> Option<T>,
+        F: FnMut(&TreeObjectSpace) -> Option<T>,
+    {
+        match route {
+            Route::One(position) => block(&self.shards[position]),
+            Route::All => poll(timeout, || self.shards.iter().find_map(&mut lookup)),
+        }
+    }
+
+    /// Return a handle identifying the shard as well as the struct within it.
+    fn encode_handle(&self, position: usize, handle: ObjectHandle) -> ObjectHandle {
+        ObjectHandle::new(handle.index() * self.shards.len() as u64 + position as u64)
+    }
+
+    /// Return the shard a handle was returned by, and the handle of the struct within it.
+    fn decode_handle(&self, handle: ObjectHandle) -> (&TreeObjectSpace, ObjectHandle) {
+        let count = self.shards.len() as u64;
+        (
+            &self.shards[(handle.index() % count) as usize],
+            ObjectHandle::new(handle.index() / count),
+        )
+    }
+}
+
+/// Hash the value of a field, such that an integer and a float of equal value hash alike,
+/// as they are found by the keys of one another.
+fn hash_field(value: &Field) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match *value {
+        Field::Null | Field::Seq | Field::Bytes => 0u8.hash(&mut hasher),
+        Field::Bool(b) => (1u8, b).hash(&mut hasher),
+        Field::Str(ref s) => (2u8, s).hash(&mut hasher),
+        Field::Int(_) | Field::BigInt(_) | Field::Float(_) => {
+            // -0.0 and 0.0 are equal
+            let number = value.as_f64().unwrap() + 0.0;
+            (3u8, number.to_bits()).hash(&mut hasher)
+        }
+    }
+    hasher.finish()
+}
+
+impl ObjectSpace for ShardedObjectSpace {
+    fn write<T>(&self, obj: T) -> ObjectHandle
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let position = self.shard_of(&obj);
+        let handle = self.shards[position].write(obj);
+        self.encode_handle(position, handle)
+    }
+
+    fn write_all<T, I>(&self, objs: I)
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+        I: IntoIterator<Item = T>,
+    {
+        let mut batches: Vec<Vec<T>> = self.shards.iter().map(|_| Vec::new()).collect();
+        for obj in objs {
+            let position = self.shard_of(&obj);
+            batches[position].push(obj);
+        }
+        for (shard, batch) in self.shards.iter().zip(batches) {
+            if !batch.is_empty() {
+                shard.write_all(batch);
+            }
+        }
+    }
+
+    fn try_read<T>(&self) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.find(self.route::<T>(), |shard| shard.try_read::<T>())
+    }
+
+    fn read_all<'a, T>(&'a self) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        Box::new(
+            self.select(self.route::<T>())
+                .iter()
+                .flat_map(|shard| shard.read_all::<T>()),
+        )
+    }
+
+    fn read_sample<'a, T>(&'a self, count: usize) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        // each shard contributes its own sample, which is then sampled again
+        let mut samples: Vec<Option<T>> = self.select(self.route::<T>())
+            .iter()
+            .flat_map(|shard| shard.read_sample::<T>(count))
+            .map(Some)
+            .collect();
+        let chosen: Vec<T> = sample_indices(samples.len(), count)
+            .into_iter()
+            .filter_map(|i| samples[i].take())
+            .collect();
+        Box::new(chosen.into_iter())
+    }
+
+    fn read<T>(&self) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait(
+            self.route::<T>(),
+            None,
+            |shard| Some(shard.read::<T>()),
+            |shard| shard.try_read::<T>(),
+        ).unwrap()
+    }
+
+    fn read_timeout<T>(&self, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait(
+            self.route::<T>(),
+            Some(timeout),
+            |shard| shard.read_timeout::<T>(timeout),
+            |shard| shard.try_read::<T>(),
+        )
+    }
+
+    fn try_take<T>(&self) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.find(self.route::<T>(), |shard| shard.try_take::<T>())
+    }
+
+    fn take_all<'a, T>(&'a self) -> Box<Iterator<Item = T> + 'a>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        // every shard is emptied right away, rather than when the iterator gets to it
+        let taken: Vec<T> = self.select(self.route::<T>())
+            .iter()
+            .flat_map(|shard| shard.take_all::<T>())
+            .collect();
+        Box::new(taken.into_iter())
+    }
+
+    fn take<T>(&self) -> T
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait(
+            self.route::<T>(),
+            None,
+            |shard| Some(shard.take::<T>()),
+            |shard| shard.try_take::<T>(),
+        ).unwrap()
+    }
+
+    fn take_timeout<T>(&self, timeout: Duration) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        self.wait(
+            self.route::<T>(),
+            Some(timeout),
+            |shard| shard.take_timeout::<T>(timeout),
+            |shard| shard.try_take::<T>(),
+        )
+    }
+
+    fn try_read_by_handle<T>(&self, handle: ObjectHandle) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let (shard, handle) = self.decode_handle(handle);
+        shard.try_read_by_handle::<T>(handle)
+    }
+
+    fn try_take_by_handle<T>(&self, handle: ObjectHandle) -> Option<T>
+    where
+        for<'de> T: Serialize + Deserialize<'de> + 'static,
+    {
+        let (shard, handle) = self.decode_handle(handle);
+        shard.try_take_by_handle::<T>(handle)
+    }
+}
+
+macro_rules! sharded_range{
+    ($($ty:ident)*) => {
+        $(
+            impl RangeLookupObjectSpace<$ty> for ShardedObjectSpace {
+                fn try_read_by_range<T, R>(&self, field: &str, range: R) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.find(self.route::<T>(), |shard| shard.try_read_by_range::<T, _>(field, range.clone()))
+                }
+
+                fn read_all_by_range<'a, T, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let found: Vec<T> = self.select(self.route::<T>())
+                        .iter()
+                        .flat_map(|shard| shard.read_all_by_range::<T, _>(field, range.clone()))
+                        .collect();
+                    Box::new(found.into_iter())
+                }
+
+                fn read_by_range<T, R>(&self, field: &str, range: R) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.wait(
+                        self.route::<T>(),
+                        None,
+                        |shard| Some(shard.read_by_range::<T, _>(field, range.clone())),
+                        |shard| shard.try_read_by_range::<T, _>(field, range.clone()),
+                    ).unwrap()
+                }
+
+                fn read_by_range_timeout<T, R>(&self, field: &str, range: R, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.wait(
+                        self.route::<T>(),
+                        Some(timeout),
+                        |shard| shard.read_by_range_timeout::<T, _>(field, range.clone(), timeout),
+                        |shard| shard.try_read_by_range::<T, _>(field, range.clone()),
+                    )
+                }
+
+                fn try_take_by_range<T, R>(&self, field: &str, range: R) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.find(self.route::<T>(), |shard| shard.try_take_by_range::<T, _>(field, range.clone()))
+                }
+
+                fn take_all_by_range<'a, T, R>(&'a self, field: &str, range: R) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    let taken: Vec<T> = self.select(self.route::<T>())
+                        .iter()
+                        .flat_map(|shard| shard.take_all_by_range::<T, _>(field, range.clone()))
+                        .collect();
+                    Box::new(taken.into_iter())
+                }
+
+                fn take_by_range<T, R>(&self, field: &str, range: R) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.wait(
+                        self.route::<T>(),
+                        None,
+                        |shard| Some(shard.take_by_range::<T, _>(field, range.clone())),
+                        |shard| shard.try_take_by_range::<T, _>(field, range.clone()),
+                    ).unwrap()
+                }
+
+                fn take_by_range_timeout<T, R>(&self, field: &str, range: R, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.wait(
+                        self.route::<T>(),
+                        Some(timeout),
+                        |shard| shard.take_by_range_timeout::<T, _>(field, range.clone(), timeout),
+                        |shard| shard.try_take_by_range::<T, _>(field, range.clone()),
+                    )
+                }
+
+                fn try_read_by_range_checked<T, R>(&self, field: &str, range: R) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.find_checked(self.route::<T>(), |shard| {
+                        shard.try_read_by_range_checked::<T, _>(field, range.clone())
+                    })
+                }
+
+                fn read_all_by_range_checked<'a, T, R>(
+                    &'a self,
+                    field: &str,
+                    range: R,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.collect_checked(self.route::<T>(), |shard| {
+                        shard.read_all_by_range_checked::<T, _>(field, range.clone())
+                    })
+                }
+
+                fn try_take_by_range_checked<T, R>(&self, field: &str, range: R) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.find_checked(self.route::<T>(), |shard| {
+                        shard.try_take_by_range_checked::<T, _>(field, range.clone())
+                    })
+                }
+
+                fn take_all_by_range_checked<'a, T, R>(
+                    &'a self,
+                    field: &str,
+                    range: R,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                    R: RangeBounds<$ty> + Clone,
+                {
+                    self.collect_checked(self.route::<T>(), |shard| {
+                        shard.take_all_by_range_checked::<T, _>(field, range.clone())
+                    })
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! sharded_key{
+    ($($ty:ty)*) => {
+        $(
+            impl ValueLookupObjectSpace<$ty> for ShardedObjectSpace {
+                fn try_read_by_value<T>(&self, field: &str, key: &$ty) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let route = self.route_by_value::<T>(field, Field::from(key.clone()));
+                    self.find(route, |shard| shard.try_read_by_value::<T>(field, key))
+                }
+
+                fn read_all_by_value<'a, T>(&'a self, field: &str, key: &$ty) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let route = self.route_by_value::<T>(field, Field::from(key.clone()));
+                    let found: Vec<T> = self.select(route)
+                        .iter()
+                        .flat_map(|shard| shard.read_all_by_value::<T>(field, key))
+                        .collect();
+                    Box::new(found.into_iter())
+                }
+
+                fn read_by_value<T>(&self, field: &str, key: &$ty) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    self.wait(
+                        self.route_by_value::<T>(field, Field::from(key.clone())),
+                        None,
+                        |shard| Some(shard.read_by_value::<T>(field, key)),
+                        |shard| shard.try_read_by_value::<T>(field, key),
+                    ).unwrap()
+                }
+
+                fn read_by_value_timeout<T>(&self, field: &str, key: &$ty, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    self.wait(
+                        self.route_by_value::<T>(field, Field::from(key.clone())),
+                        Some(timeout),
+                        |shard| shard.read_by_value_timeout::<T>(field, key, timeout),
+                        |shard| shard.try_read_by_value::<T>(field, key),
+                    )
+                }
+
+                fn try_take_by_value<T>(&self, field: &str, key: &$ty) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let route = self.route_by_value::<T>(field, Field::from(key.clone()));
+                    self.find(route, |shard| shard.try_take_by_value::<T>(field, key))
+                }
+
+                fn take_all_by_value<'a, T>(&'a self, field: &str, key: &$ty) -> Box<Iterator<Item = T> + 'a>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let route = self.route_by_value::<T>(field, Field::from(key.clone()));
+                    let taken: Vec<T> = self.select(route)
+                        .iter()
+                        .flat_map(|shard| shard.take_all_by_value::<T>(field, key))
+                        .collect();
+                    Box::new(taken.into_iter())
+                }
+
+                fn take_by_value<T>(&self, field: &str, key: &$ty) -> T
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    self.wait(
+                        self.route_by_value::<T>(field, Field::from(key.clone())),
+                        None,
+                        |shard| Some(shard.take_by_value::<T>(field, key)),
+                        |shard| shard.try_take_by_value::<T>(field, key),
+                    ).unwrap()
+                }
+
+                fn take_by_value_timeout<T>(&self, field: &str, key: &$ty, timeout: Duration) -> Option<T>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    self.wait(
+                        self.route_by_value::<T>(field, Field::from(key.clone())),
+                        Some(timeout),
+                        |shard| shard.take_by_value_timeout::<T>(field, key, timeout),
+                        |shard| shard.try_take_by_value::<T>(field, key),
+                    )
+                }
+
+                fn try_read_by_value_checked<T>(&self, field: &str, key: &$ty) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let route = self.route_by_value::<T>(field, Field::from(key.clone()));
+                    self.find_checked(route, |shard| shard.try_read_by_value_checked::<T>(field, key))
+                }
+
+                fn read_all_by_value_checked<'a, T>(
+                    &'a self,
+                    field: &str,
+                    key: &$ty,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let route = self.route_by_value::<T>(field, Field::from(key.clone()));
+                    self.collect_checked(route, |shard| shard.read_all_by_value_checked::<T>(field, key))
+                }
+
+                fn try_take_by_value_checked<T>(&self, field: &str, key: &$ty) -> Result<Option<T>, SpaceError>
+                where
+                    for<'de> T: Serialize + Deserialize<'de> + 'static,
+                {
+                    let route = self.route_by_value::<T>(field, Field::from(key.clone()));
+                    self.find_checked(route, |shard| shard.try_take_by_value_checked::<T>(field, key))
+                }
+
+                fn take_all_by_value_checked<'a, T>(
+                    &'a self,
+                    field: &str,
+                    key: &$ty,
+                ) -> Result<Box<Iterator<Item = T> + 'a>, SpaceError>
+                where
+                    for<'de> T: Deserialize<'de> + 'static,
+                {
+                    let route = self.route_by_value::<T>(field, Field::from(key.clone()));
+                    self.collect_checked(route, |shard| shard.take_all_by_value_checked::<T>(field, key))
+                }
+            }
+        )*
+    };
+}
+
+sharded_range!{i64 u64 i128 String f64}
+sharded_key!{i64 u64 i128 String bool f64 FieldValue}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct TestStruct {
+        count: i32,
+        name: String,
+    }
+
+    #[test]
+    fn sharded_space() {
+        let space = Arc::new(ShardedObjectSpace::new(4));
+        space.write::<i64>(3);
+        space.write::<i64>(5);
+        // a type which is not sharded lives in a single shard
+        assert_eq!(
+            space.shards().iter().filter(|shard| shard.try_read::<i64>().is_some()).count(),
+            1
+        );
+        assert_eq!(space.try_take_by_value::<i64>("", &5i64), Some(5));
+
+        space.write(TestStruct {
+            count: 0,
+            name: String::from("Tuan"),
+        });
+        space.shard_by::<TestStruct>("count");
+        let handles: Vec<ObjectHandle> = (1..20)
+            .map(|count| {
+                space.write(TestStruct {
+                    count,
+                    name: format!("#{}", count),
+                })
+            })
+            .collect();
+        assert!(space.shards().iter().all(|shard| shard.try_read::<TestStruct>().is_some()));
+        for count in 0..20i64 {
+            let holding = space
+                .shards()
+                .iter()
+                .filter(|shard| shard.try_read_by_value::<TestStruct>("count", &count).is_some())
+                .count();
+            assert_eq!(holding, 1);
+        }
+        assert_eq!(space.read_all::<TestStruct>().count(), 20);
+        assert_eq!(space.read_all_by_range::<TestStruct, _>("count", 5i64..10).count(), 5);
+        assert_eq!(
+            space.try_read_by_value::<TestStruct>("name", &String::from("Tuan")).map(|s| s.count),
+            Some(0)
+        );
+        assert_eq!(space.try_read_by_handle::<TestStruct>(handles[6]).map(|s| s.count), Some(7));
+
+        assert_eq!(space.take_by_value::<TestStruct>("count", &7i64).name, "#7");
+        assert_eq!(space.try_take_by_handle::<TestStruct>(handles[6]), None);
+        assert_eq!(space.take_by_value_timeout::<TestStruct>("count", &7i64, Duration::from_millis(10)), None);
+        assert!(space.try_read_by_value_checked::<TestStruct>("nmae", &1i64).is_err());
+
+        let consumer_space = space.clone();
+        let consumer = thread::spawn(move || consumer_space.take_by_value::<TestStruct>("count", &42i64));
+        let other_space = space.clone();
+        let other = thread::spawn(move || other_space.take_by_value::<TestStruct>("name", &String::from("#42")));
+        thread::sleep(Duration::from_millis(10));
+        space.write(TestStruct {
+            count: 42,
+            name: String::from("#42"),
+        });
+        space.write(TestStruct {
+            count: 42,
+            name: String::from("#42"),
+        });
+        assert_eq!(consumer.join().unwrap().count, 42);
+        assert_eq!(other.join().unwrap().count, 42);
+        assert_eq!(space.take_all::<TestStruct>().count(), 19);
+        assert_eq!(space.take_timeout::<TestStruct>(Duration::from_millis(10)), None);
+    }
+}
@@ -0,0 +1,76 @@
+//! Spans and events describing the operations of a `TreeObjectSpace`,
+//! emitted with `tracing` if the crate is built with the `tracing` feature, and compiled out otherwise.
+//!
+//! Operations holding the write guard of a type, e.g: `write` or `take`, are traced as `operation` spans,
+//! blocking calls as `blocking` spans, and lookups leaving the structs in the space as `lookup` events.
+
+use std::any::TypeId;
+use std::time::Duration;
+
+#[cfg(feature = "tracing")]
+pub use tracing::span::EnteredSpan as Span;
+
+/// A span doing nothing, as the crate is built without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub struct Span;
+
+/// Enter the span of an operation holding the write guard of a type, until the guard is released.
+#[cfg(feature = "tracing")]
+pub fn operation(type_name: &'static str, type_id: TypeId, operation: &'static str) -> Span {
+    tracing::trace_span!("operation", type_name, type_id = ?type_id, operation).entered()
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub fn operation(_: &'static str, _: TypeId, _: &'static str) -> Span {
+    Span
+}
+
+/// Record a lookup of the structs of a type leaving them in the space.
+#[cfg(feature = "tracing")]
+pub fn lookup(type_name: &'static str, type_id: TypeId) {
+    tracing::trace!(type_name, type_id = ?type_id, "lookup");
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub fn lookup(_: &'static str, _: TypeId) {}
+
+/// Enter the span of a blocking call, until it returns.
+/// The field and predicate are those reported to the watchdog, e.g: `count` and `== 3`.
+#[cfg(feature = "tracing")]
+pub fn blocking(
+    type_name: &'static str,
+    type_id: TypeId,
+    operation: &'static str,
+    field: Option<&str>,
+    predicate: Option<&str>,
+) -> Span {
+    tracing::debug_span!("blocking", type_name, type_id = ?type_id, operation, field, predicate).entered()
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub fn blocking(_: &'static str, _: TypeId, _: &'static str, _: Option<&str>, _: Option<&str>) -> Span {
+    Span
+}
+
+/// Record that a blocking call found no struct, and starts waiting for one to be written.
+#[cfg(feature = "tracing")]
+pub fn waiting() {
+    tracing::debug!("waiting for a struct");
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub fn waiting() {}
+
+/// Record that a blocking call returns, and whether it had to wait.
+#[cfg(feature = "tracing")]
+pub fn returned(blocked: bool, waited: Duration) {
+    tracing::debug!(blocked, waited = ?waited, "returned");
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub fn returned(_: bool, _: Duration) {}
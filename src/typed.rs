@@ -0,0 +1,211 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+use serde::{Deserialize, Serialize};
+
+use object_space::{RangeLookupObjectSpace, TreeObjectSpace, ValueLookupObjectSpace};
+
+/// A field of the structs of type T, whose values are looked up by keys of type K.
+/// Fields are usually declared with `fields!`.
+///
+/// Looking a field up with `TreeObjectSpace::lookup` only accepts keys and ranges of type K,
+/// so that a key of the wrong type is a compile error instead of a panic.
+pub struct TypedField<T, K> {
+    name: &'static str,
+    marker: PhantomData<fn() -> (T, K)>,
+}
+
+impl<T, K> TypedField<T, K> {
+    /// Declare the field named `name`, e.g: `"person.name"`.
+    pub const fn new(name: &'static str) -> Self {
+        TypedField {
+            name,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T, K> Clone for TypedField<T, K> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, K> Copy for TypedField<T, K> {}
+
+impl<T, K> fmt::Debug for TypedField<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TypedField").field(&self.name).finish()
+    }
+}
+
+/// Declare the fields of a struct which are looked up, and the type of their keys,
+/// as the associated constants of a new type.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate object_space;
+/// #[macro_use]
+/// extern crate serde_derive;
+/// # use object_space::{TreeObjectSpace, ObjectSpace};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Task {
+///     finished: bool,
+///     start: i64,
+///     owner: String,
+/// }
+///
+/// fields! {
+///     pub TaskField for Task {
+///         Finished: bool = "finished",
+///         Start: i64 = "start",
+///         Owner: String = "owner",
+///     }
+/// }
+///
+/// # fn main() {
+/// let space = TreeObjectSpace::new();
+/// space.write(Task { finished: false, start: 5, owner: String::from("tuan") });
+///
+/// let task = space.lookup(TaskField::Start).take_by_range(0..10);
+/// assert_eq!(task.owner, "tuan");
+/// assert!(space.lookup(TaskField::Finished).try_read_by_value(&false).is_none());
+/// # }
+/// ```
+///
+/// A key of the wrong type does not compile:
+///
+/// ```compile_fail
+/// # #[macro_use]
+/// # extern crate object_space;
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # use object_space::TreeObjectSpace;
+/// # #[derive(Serialize, Deserialize)]
+/// # struct Task {
+/// #     start: i64,
+/// # }
+/// # fields! {
+/// #     TaskField for Task {
+/// #         Start: i64 = "start",
+/// #     }
+/// # }
+/// # fn main() {
+/// let space = TreeObjectSpace::new();
+/// space.lookup(TaskField::Start).try_take_by_range(String::from("a")..);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! fields {
+    ($vis:vis $fields:ident for $ty:ty { $($field:ident : $key:ty = $name:expr),* $(,)* }) => {
+        $vis struct $fields;
+
+        #[allow(non_upper_case_globals)]
+        impl $fields {
+            $(
+                pub const $field: $crate::TypedField<$ty, $key> = $crate::TypedField::new($name);
+            )*
+        }
+    };
+}
+
+/// The lookups of the structs of type T by a field whose keys are of type K,
+/// returned by `TreeObjectSpace::lookup`.
+///
+/// The lookups are those of `RangeLookupObjectSpace` and `ValueLookupObjectSpace`.
+pub struct FieldLookup<'a, T, K> {
+    space: &'a TreeObjectSpace,
+    field: TypedField<T, K>,
+}
+
+impl<'a, T, K> FieldLookup<'a, T, K> {
+    pub(crate) fn new(space: &'a TreeObjectSpace, field: TypedField<T, K>) -> Self {
+        FieldLookup { space, field }
+    }
+}
+
+impl<'a, T, K> FieldLookup<'a, T, K>
+where
+    for<'de> T: Serialize + Deserialize<'de> + 'static,
+    TreeObjectSpace: RangeLookupObjectSpace<K>,
+{
+    pub fn try_read_by_range<R>(&self, range: R) -> Option<T>
+    where
+        R: RangeBounds<K> + Clone,
+    {
+        self.space.try_read_by_range::<T, R>(self.field.name, range)
+    }
+
+    pub fn read_all_by_range<R>(&self, range: R) -> Box<Iterator<Item = T> + 'a>
+    where
+        R: RangeBounds<K> + Clone,
+    {
+        self.space.read_all_by_range::<T, R>(self.field.name, range)
+    }
+
+    pub fn read_by_range<R>(&self, range: R) -> T
+    where
+        R: RangeBounds<K> + Clone,
+    {
+        self.space.read_by_range::<T, R>(self.field.name, range)
+    }
+
+    pub fn try_take_by_range<R>(&self, range: R) -> Option<T>
+    where
+        R: RangeBounds<K> + Clone,
+    {
+        self.space.try_take_by_range::<T, R>(self.field.name, range)
+    }
+
+    pub fn take_all_by_range<R>(&self, range: R) -> Box<Iterator<Item = T> + 'a>
+    where
+        R: RangeBounds<K> + Clone,
+    {
+        self.space.take_all_by_range::<T, R>(self.field.name, range)
+    }
+
+    pub fn take_by_range<R>(&self, range: R) -> T
+    where
+        R: RangeBounds<K> + Clone,
+    {
+        self.space.take_by_range::<T, R>(self.field.name, range)
+    }
+}
+
+impl<'a, T, K> FieldLookup<'a, T, K>
+where
+    for<'de> T: Serialize + Deserialize<'de> + 'static,
+    TreeObjectSpace: ValueLookupObjectSpace<K>,
+{
+    pub fn try_read_by_value(&self, key: &K) -> Option<T> {
+        self.space.try_read_by_value::<T>(self.field.name, key)
+    }
+
+    pub fn read_all_by_value(&self, key: &K) -> Box<Iterator<Item = T> + 'a> {
+        self.space.read_all_by_value::<T>(self.field.name, key)
+    }
+
+    pub fn read_by_value(&self, key: &K) -> T {
+        self.space.read_by_value::<T>(self.field.name, key)
+    }
+
+    pub fn try_take_by_value(&self, key: &K) -> Option<T> {
+        self.space.try_take_by_value::<T>(self.field.name, key)
+    }
+
+    pub fn take_all_by_value(&self, key: &K) -> Box<Iterator<Item = T> + 'a> {
+        self.space.take_all_by_value::<T>(self.field.name, key)
+    }
+
+    pub fn take_by_value(&self, key: &K) -> T {
+        self.space.take_by_value::<T>(self.field.name, key)
+    }
+}
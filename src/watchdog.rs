@@ -0,0 +1,90 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Debug;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A blocking call which has been waiting for a struct longer than the threshold of the watchdog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockedCall {
+    /// The name of the type of the awaited struct.
+    pub type_name: &'static str,
+    /// The name of the blocking operation, e.g: `take_by_value`.
+    pub operation: &'static str,
+    /// The field, or pair of fields, the operation looks up.
+    pub field: Option<String>,
+    /// The condition the field must satisfy, e.g: `== 3` or `in 2..10`.
+    pub predicate: Option<String>,
+    /// How long the call has been waiting.
+    pub waited: Duration,
+    /// The number of structs of the type in the space when the call was flagged.
+    pub count: usize,
+}
+
+impl fmt::Display for BlockedCall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}::<{}>", self.operation, self.type_name)?;
+        if let Some(ref field) = self.field {
+            write!(f, " on {}", field)?;
+        }
+        if let Some(ref predicate) = self.predicate {
+            write!(f, " {}", predicate)?;
+        }
+        write!(
+            f,
+            " has been waiting for {:?}, with {} structs of the type in the space",
+            self.waited, self.count
+        )
+    }
+}
+
+/// Report blocking calls waiting longer than a threshold, e.g: because nobody produces their type.
+pub struct Watchdog {
+    pub threshold: Duration,
+    pub handler: Box<Fn(&BlockedCall) + Send + Sync>,
+}
+
+/// The blocking calls currently waiting, tracked when the space is built in diagnostics mode.
+#[derive(Default)]
+pub struct WaiterRegistry {
+    next: AtomicUsize,
+    waiting: Mutex<HashMap<usize, (BlockedCall, TypeId, Instant)>>,
+}
+
+impl WaiterRegistry {
+    /// Track a call waiting on a type since the specified time, until it is unregistered.
+    pub fn register(&self, call: BlockedCall, type_id: TypeId, since: Instant) -> usize {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        self.waiting.lock().unwrap().insert(id, (call, type_id, since));
+        id
+    }
+
+    pub fn unregister(&self, id: usize) {
+        self.waiting.lock().unwrap().remove(&id);
+    }
+
+    /// Return the calls waiting, with the type they wait on and the time they started waiting.
+    pub fn waiting(&self) -> Vec<(BlockedCall, TypeId, Instant)> {
+        self.waiting.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// Describe a range the way it would be written in Rust, e.g: `2..10`.
+pub fn describe_range<U, R>(range: &R) -> String
+where
+    U: Debug,
+    R: RangeBounds<U>,
+{
+    let start = match range.start_bound() {
+        Bound::Included(start) | Bound::Excluded(start) => format!("{:?}", start),
+        Bound::Unbounded => String::new(),
+    };
+    match range.end_bound() {
+        Bound::Included(end) => format!("{}..={:?}", start, end),
+        Bound::Excluded(end) => format!("{}..{:?}", start, end),
+        Bound::Unbounded => format!("{}..", start),
+    }
+}